@@ -0,0 +1,185 @@
+// A manual QA checklist: every key below drives one WindowT method, and the
+// events it causes are printed along with the getters afterwards, so a
+// mismatch between what was asked for and what the platform reports shows
+// up right away. Only the portable API is used, so this builds wherever a
+// backend does.
+
+use std::{thread, time::Duration};
+
+use nwin::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    prelude::*,
+    FullscreenType, KeyboardScancode, Theme, UserAttentionType, WindowButtons,
+};
+
+const HINTS: &[(&str, &str)] = &[
+    ("F", "toggle borderless fullscreen"),
+    ("M", "maximize"),
+    ("N", "minimize"),
+    ("O", "normalize"),
+    ("R", "toggle resizeable"),
+    ("T", "cycle theme preference"),
+    ("B", "cycle enabled buttons"),
+    ("U", "request attention (informational)"),
+    ("I", "request attention (critical), after 2s"),
+    ("H", "hide, then show after 2s"),
+    ("P", "move to (100, 100)"),
+    ("L", "rename"),
+    ("D", "request a redraw"),
+    ("1", "resize to 320x240"),
+    ("2", "resize to 1024x768"),
+    ("3", "limit to 400x300..800x600"),
+    ("4", "remove the size limits"),
+    ("Esc", "quit"),
+];
+
+fn dump(window: &Window) {
+    println!(
+        "  title={:?} position={:?} size={:?} outer={:?} at {:?}",
+        window.title(),
+        window.position(),
+        window.inner_size(),
+        window.outer_size(),
+        window.outer_position(),
+    );
+    println!(
+        "  visible={} focused={} resizeable={} minimized={} maximized={} normalized={}",
+        window.visible(),
+        window.focused(),
+        window.resizeable(),
+        window.minimized(),
+        window.maximized(),
+        window.normalized(),
+    );
+    println!(
+        "  fullscreen={:?} theme={:?} preference={:?}",
+        window.fullscreen_type(),
+        window.theme(),
+        window.theme_preference(),
+    );
+    println!(
+        "  min={}x{} max={}x{} buttons={:?} scale={} refresh={:?}",
+        window.min_width(),
+        window.min_height(),
+        window.max_width(),
+        window.max_height(),
+        window.enabled_buttons(),
+        window.scale_factor(),
+        window.current_refresh_rate_millihertz(),
+    );
+}
+
+// Returns what was done, or None for keys with nothing bound to them.
+fn act(window: &mut Window, key: KeyboardScancode, renames: &mut u32) -> Option<&'static str> {
+    match key {
+        KeyboardScancode::F => {
+            window.toggle_fullscreen(FullscreenType::Borderless);
+        }
+        KeyboardScancode::M => window.maximize(),
+        KeyboardScancode::N => window.minimize(),
+        KeyboardScancode::O => window.normalize(),
+        KeyboardScancode::R => window.set_resizeable(!window.resizeable()),
+        KeyboardScancode::T => {
+            let next = match window.theme_preference() {
+                None => Some(Theme::Light),
+                Some(Theme::Light) => Some(Theme::Dark),
+                Some(Theme::Dark) => None,
+            };
+            window.set_theme_preference(next);
+        }
+        KeyboardScancode::B => {
+            let buttons = window.enabled_buttons();
+            let next = if buttons.contains(WindowButtons::MAXIMIZE) {
+                buttons - WindowButtons::MAXIMIZE
+            } else if buttons.contains(WindowButtons::MINIMIZE) {
+                buttons - WindowButtons::MINIMIZE
+            } else if buttons.contains(WindowButtons::CLOSE) {
+                buttons - WindowButtons::CLOSE
+            } else {
+                WindowButtons::all()
+            };
+            window.set_enabled_buttons(next);
+        }
+        KeyboardScancode::U => window.request_user_attention(UserAttentionType::Informational),
+        // Attention is only drawn to windows that aren't focused, so there
+        // is time to switch away first.
+        KeyboardScancode::I => {
+            thread::sleep(Duration::from_secs(2));
+            window.request_user_attention(UserAttentionType::Critical);
+        }
+        KeyboardScancode::H => {
+            window.hide();
+            thread::sleep(Duration::from_secs(2));
+            window.show();
+        }
+        KeyboardScancode::P => window.set_position(PhysicalPosition::new(100, 100)),
+        KeyboardScancode::L => {
+            *renames += 1;
+            window.set_title(&format!("nwin kitchen sink (renamed {renames}x)"));
+        }
+        KeyboardScancode::D => window.request_redraw(),
+        KeyboardScancode::Key1 => {
+            window.request_inner_size(PhysicalSize::new(320, 240));
+        }
+        KeyboardScancode::Key2 => {
+            window.request_inner_size(PhysicalSize::new(1024, 768));
+        }
+        KeyboardScancode::Key3 => {
+            window.set_min_size(400, 300);
+            window.set_max_size(800, 600);
+        }
+        KeyboardScancode::Key4 => {
+            window.set_min_size(1, 1);
+            window.set_max_size(u32::MAX, u32::MAX);
+        }
+        _ => return None,
+    }
+    HINTS
+        .iter()
+        .find(|(name, _)| name.parse() == Ok(key))
+        .map(|(_, hint)| *hint)
+}
+
+fn main() {
+    let mut window = WindowBuilder::new()
+        .with_title("nwin kitchen sink (see the terminal for keys)")
+        .with_inner_size(PhysicalSize::new(640, 480))
+        .with_visible(true)
+        .build()
+        .expect("failed to create a window");
+    let mut event_loop = EventLoop::new();
+    event_loop.bind(&mut window);
+
+    for (key, hint) in HINTS {
+        println!("{key:>3}  {hint}");
+    }
+    println!("initial state:");
+    dump(&window);
+
+    let mut renames = 0;
+    loop {
+        let Some((id, event)) = event_loop.next_event() else {
+            thread::sleep(Duration::from_millis(5));
+            continue;
+        };
+        println!("{id}: {event:?}");
+        match event {
+            WindowEvent::KeyDown {
+                logical_scancode: KeyboardScancode::Esc,
+                ..
+            }
+            | WindowEvent::CloseRequested => break,
+            WindowEvent::KeyDown {
+                logical_scancode,
+                repeat: false,
+                ..
+            } => {
+                if let Some(action) = act(&mut window, logical_scancode, &mut renames) {
+                    println!("after {logical_scancode} ({action}):");
+                    dump(&window);
+                }
+            }
+            _ => {}
+        }
+    }
+}
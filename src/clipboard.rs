@@ -0,0 +1,326 @@
+use crate::{platform::platform_impl, RgbaImage};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClipboardError {
+    // The image is empty, or its data isn't width * height * 4 bytes.
+    InvalidImage,
+    Os,
+}
+
+// Replaces the clipboard's contents. On X11 the image is served to other
+// applications from a background thread until something else is copied.
+pub fn set_image(image: RgbaImage) -> Result<(), ClipboardError> {
+    if image.width == 0
+        || image.height == 0
+        || image.data.len() as u64 != image.width as u64 * image.height as u64 * 4
+    {
+        return Err(ClipboardError::InvalidImage);
+    }
+    platform_impl::set_clipboard_image(image)
+}
+
+// None if the clipboard holds no image, or none in a format nwin can read.
+pub fn get_image() -> Option<RgbaImage> {
+    platform_impl::clipboard_image()
+}
+
+pub(crate) fn encode_png(image: &RgbaImage) -> Vec<u8> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // set_image checked the size, and writing to a Vec can't fail.
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&image.data).unwrap();
+    writer.finish().unwrap();
+    png
+}
+
+pub(crate) fn decode_png(bytes: &[u8]) -> Option<RgbaImage> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Palettes, low bit depths and tRNS come out as plain 8-bit channels.
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).ok()?;
+    buf.truncate(info.buffer_size());
+    let data = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return None,
+    };
+    Some(RgbaImage {
+        width: info.width,
+        height: info.height,
+        data,
+    })
+}
+
+const BITMAPV5HEADER_SIZE: usize = 124;
+const BITMAPFILEHEADER_SIZE: usize = 14;
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const LCS_SRGB: u32 = u32::from_be_bytes(*b"sRGB");
+const LCS_GM_IMAGES: u32 = 4;
+
+// A BITMAPV5HEADER followed by bottom-up BGRA rows, as CF_DIBV5 holds it.
+// Bottom-up because that's what most readers of DIBs handle.
+pub(crate) fn encode_dib(image: &RgbaImage) -> Vec<u8> {
+    let mut dib = Vec::with_capacity(BITMAPV5HEADER_SIZE + image.data.len());
+    dib.extend((BITMAPV5HEADER_SIZE as u32).to_le_bytes());
+    dib.extend((image.width as i32).to_le_bytes());
+    dib.extend((image.height as i32).to_le_bytes());
+    dib.extend(1u16.to_le_bytes());
+    dib.extend(32u16.to_le_bytes());
+    dib.extend(BI_BITFIELDS.to_le_bytes());
+    dib.extend((image.data.len() as u32).to_le_bytes());
+    // Resolution and palette size.
+    dib.extend([0; 16]);
+    for mask in [0x00FF_0000u32, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000] {
+        dib.extend(mask.to_le_bytes());
+    }
+    dib.extend(LCS_SRGB.to_le_bytes());
+    // Endpoints and gamma, unused for sRGB.
+    dib.extend([0; 48]);
+    dib.extend(LCS_GM_IMAGES.to_le_bytes());
+    // No profile.
+    dib.extend([0; 12]);
+    for row in image.data.chunks_exact(image.width as usize * 4).rev() {
+        for p in row.chunks_exact(4) {
+            dib.extend([p[2], p[1], p[0], p[3]]);
+        }
+    }
+    dib
+}
+
+pub(crate) fn encode_bmp(image: &RgbaImage) -> Vec<u8> {
+    let dib = encode_dib(image);
+    let mut bmp = Vec::with_capacity(BITMAPFILEHEADER_SIZE + dib.len());
+    bmp.extend(b"BM");
+    bmp.extend(((BITMAPFILEHEADER_SIZE + dib.len()) as u32).to_le_bytes());
+    bmp.extend([0; 4]);
+    bmp.extend(((BITMAPFILEHEADER_SIZE + BITMAPV5HEADER_SIZE) as u32).to_le_bytes());
+    bmp.extend(dib);
+    bmp
+}
+
+pub(crate) fn decode_bmp(bytes: &[u8]) -> Option<RgbaImage> {
+    if bytes.get(..2)? != b"BM" {
+        return None;
+    }
+    let offset = u32_at(bytes, 10)? as usize;
+    decode_dib_at(
+        bytes.get(BITMAPFILEHEADER_SIZE..)?,
+        Some(offset.checked_sub(BITMAPFILEHEADER_SIZE)?),
+    )
+}
+
+// Takes any header from BITMAPINFOHEADER on, with 24-bit or 32-bit pixels.
+pub(crate) fn decode_dib(bytes: &[u8]) -> Option<RgbaImage> {
+    decode_dib_at(bytes, None)
+}
+
+// Without a file header, the pixels follow the header, the masks of a
+// BITMAPINFOHEADER and the palette.
+fn decode_dib_at(bytes: &[u8], pixels: Option<usize>) -> Option<RgbaImage> {
+    let header_size = u32_at(bytes, 0)? as usize;
+    if header_size < 40 || bytes.len() < header_size {
+        return None;
+    }
+    let width = i32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    let height = i32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    let bit_count = u16::from_le_bytes(bytes[14..16].try_into().unwrap());
+    let compression = u32_at(bytes, 16)?;
+    let colors_used = u32_at(bytes, 32)? as usize;
+    if width <= 0 || height == 0 || height == i32::MIN {
+        return None;
+    }
+    let (width, top_down) = (width as usize, height < 0);
+    let height = height.unsigned_abs() as usize;
+
+    let (masks, masks_size) = match (bit_count, compression) {
+        (24 | 32, BI_RGB) => ([0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0], 0),
+        (32, BI_BITFIELDS) if header_size == 40 => {
+            let m = |i: usize| u32_at(bytes, 40 + i * 4);
+            ([m(0)?, m(1)?, m(2)?, 0], 12)
+        }
+        (32, BI_BITFIELDS) => {
+            let m = |i: usize| u32_at(bytes, 40 + i * 4);
+            let alpha = if header_size >= 56 { m(3)? } else { 0 };
+            ([m(0)?, m(1)?, m(2)?, alpha], 0)
+        }
+        _ => return None,
+    };
+    let pixels = pixels.unwrap_or(header_size + masks_size + colors_used.checked_mul(4)?);
+
+    let stride = (width.checked_mul(bit_count as usize)?.checked_add(31)? / 32).checked_mul(4)?;
+    let rows = bytes.get(pixels..)?;
+    if rows.len() < stride.checked_mul(height)? {
+        return None;
+    }
+    let bytes_per_pixel = bit_count as usize / 8;
+    let mut data = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let row = if top_down { y } else { height - 1 - y };
+        let row = &rows[row * stride..][..width * bytes_per_pixel];
+        for p in row.chunks_exact(bytes_per_pixel) {
+            let px = match *p {
+                [b, g, r] => u32::from_le_bytes([b, g, r, 0]),
+                [b, g, r, a] => u32::from_le_bytes([b, g, r, a]),
+                _ => unreachable!(),
+            };
+            data.extend(masks.map(|mask| channel(px, mask)));
+        }
+    }
+    // Plenty of writers leave the alpha of 32-bit DIBs at zero, meaning
+    // opaque rather than invisible.
+    if masks[3] == 0 || data.chunks_exact(4).all(|p| p[3] == 0) {
+        data.chunks_exact_mut(4).for_each(|p| p[3] = 255);
+    }
+    Some(RgbaImage {
+        width: width as u32,
+        height: height as u32,
+        data,
+    })
+}
+
+// Scales the masked bits to 0..=255.
+fn channel(px: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let bits = (mask >> mask.trailing_zeros()).trailing_ones();
+    let value = (px & mask) >> mask.trailing_zeros();
+    if bits >= 8 {
+        (value >> (bits - 8)) as u8
+    } else {
+        (value * 255 / ((1 << bits) - 1)) as u8
+    }
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(at..at + 4)?.try_into().unwrap(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> RgbaImage {
+        // Three columns, so 24-bit rows need padding.
+        RgbaImage {
+            width: 3,
+            height: 2,
+            data: vec![
+                255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 255, 0, //
+                10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120,
+            ],
+        }
+    }
+
+    #[test]
+    fn png_round_trips() {
+        assert_eq!(decode_png(&encode_png(&image())), Some(image()));
+    }
+
+    #[test]
+    fn dib_round_trips() {
+        let dib = encode_dib(&image());
+        assert_eq!(dib.len(), BITMAPV5HEADER_SIZE + 3 * 2 * 4);
+        assert_eq!(decode_dib(&dib), Some(image()));
+        assert_eq!(decode_bmp(&encode_bmp(&image())), Some(image()));
+    }
+
+    #[test]
+    fn dib_rows_are_bottom_up_bgra() {
+        let dib = encode_dib(&image());
+        assert_eq!(dib[BITMAPV5HEADER_SIZE..][..4], [30, 20, 10, 40]);
+    }
+
+    fn info_header(width: i32, height: i32, bit_count: u16, compression: u32) -> Vec<u8> {
+        let mut dib = Vec::new();
+        dib.extend(40u32.to_le_bytes());
+        dib.extend(width.to_le_bytes());
+        dib.extend(height.to_le_bytes());
+        dib.extend(1u16.to_le_bytes());
+        dib.extend(bit_count.to_le_bytes());
+        dib.extend(compression.to_le_bytes());
+        dib.extend([0; 20]);
+        dib
+    }
+
+    #[test]
+    fn decodes_padded_24_bit_rows() {
+        let mut dib = info_header(1, -2, 24, BI_RGB);
+        dib.extend([1, 2, 3, 0xEE, 4, 5, 6, 0xEE]);
+        let image = decode_dib(&dib).unwrap();
+        assert_eq!(image.data, [3, 2, 1, 255, 6, 5, 4, 255]);
+    }
+
+    #[test]
+    fn decodes_bitfields_after_an_info_header() {
+        let mut dib = info_header(1, 1, 32, BI_BITFIELDS);
+        // 5-6-5 packed into the low half.
+        for mask in [0xF800u32, 0x07E0, 0x001F] {
+            dib.extend(mask.to_le_bytes());
+        }
+        dib.extend(0xF81Fu32.to_le_bytes());
+        assert_eq!(decode_dib(&dib).unwrap().data, [255, 0, 255, 255]);
+    }
+
+    #[test]
+    fn zero_alpha_means_opaque() {
+        let mut dib = info_header(2, 1, 32, BI_RGB);
+        dib.extend([1, 2, 3, 0, 4, 5, 6, 0]);
+        assert_eq!(decode_dib(&dib).unwrap().data, [3, 2, 1, 255, 6, 5, 4, 255]);
+    }
+
+    #[test]
+    fn malformed_images_are_rejected() {
+        let dib = encode_dib(&image());
+        for len in 0..dib.len() {
+            assert_eq!(decode_dib(&dib[..len]), None);
+        }
+        let bmp = encode_bmp(&image());
+        for len in 0..bmp.len() {
+            assert_eq!(decode_bmp(&bmp[..len]), None);
+        }
+        // The pixels are all there once only the 12-byte IEND is missing.
+        let png = encode_png(&image());
+        for len in 0..png.len() - 12 {
+            assert_eq!(decode_png(&png[..len]), None);
+        }
+        assert_eq!(decode_dib(&info_header(1, 1, 8, BI_RGB)), None);
+        assert_eq!(decode_dib(&info_header(0, 1, 32, BI_RGB)), None);
+        assert_eq!(decode_dib(&info_header(1, i32::MIN, 32, BI_RGB)), None);
+        assert_eq!(decode_dib(&info_header(i32::MAX, 1, 32, BI_RGB)), None);
+    }
+
+    #[test]
+    fn invalid_images_are_not_set() {
+        let mut short = image();
+        short.data.pop();
+        assert_eq!(set_image(short), Err(ClipboardError::InvalidImage));
+        assert_eq!(
+            set_image(RgbaImage::default()),
+            Err(ClipboardError::InvalidImage)
+        );
+    }
+
+    #[cfg(not(any(windows, all(unix, feature = "x11"))))]
+    #[test]
+    fn headless_clipboard_round_trips() {
+        set_image(image()).unwrap();
+        assert_eq!(get_image(), Some(image()));
+    }
+}
@@ -0,0 +1,315 @@
+// Physical values are in device pixels, which is what every backend works in.
+// Logical values are physical ones divided by the scale factor, so they stay
+// the same size on screen across monitors.
+//
+// Converting to an integer type rounds to the nearest value, with halves
+// rounded away from zero, and saturates at the bounds of the type. A logical
+// size of 100.5 at scale 1.0 becomes 101 physical pixels, never 100.
+
+pub trait Pixel: Copy + Into<f64> {
+    fn from_f64(f: f64) -> Self;
+
+    fn cast<P: Pixel>(self) -> P {
+        P::from_f64(self.into())
+    }
+}
+
+macro_rules! impl_pixel_int {
+    ($($t:ty),*) => {
+        $(impl Pixel for $t {
+            fn from_f64(f: f64) -> Self {
+                f.round() as $t
+            }
+        })*
+    };
+}
+
+impl_pixel_int!(u8, u16, u32, i8, i16, i32);
+
+impl Pixel for f32 {
+    fn from_f64(f: f64) -> Self {
+        f as f32
+    }
+}
+
+impl Pixel for f64 {
+    fn from_f64(f: f64) -> Self {
+        f
+    }
+}
+
+// Scale factors must be finite and positive.
+pub fn validate_scale_factor(scale_factor: f64) -> bool {
+    scale_factor.is_sign_positive() && scale_factor.is_normal()
+}
+
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhysicalSize<P> {
+    pub width: P,
+    pub height: P,
+}
+
+impl<P> PhysicalSize<P> {
+    pub const fn new(width: P, height: P) -> Self {
+        Self { width, height }
+    }
+}
+
+impl<P: Pixel> PhysicalSize<P> {
+    pub fn to_logical<X: Pixel>(&self, scale_factor: f64) -> LogicalSize<X> {
+        assert!(validate_scale_factor(scale_factor));
+        LogicalSize::new(
+            self.width.into() / scale_factor,
+            self.height.into() / scale_factor,
+        )
+        .cast()
+    }
+
+    pub fn cast<X: Pixel>(&self) -> PhysicalSize<X> {
+        PhysicalSize::new(self.width.cast(), self.height.cast())
+    }
+}
+
+impl<P> From<(P, P)> for PhysicalSize<P> {
+    fn from((width, height): (P, P)) -> Self {
+        Self::new(width, height)
+    }
+}
+
+impl<P> From<PhysicalSize<P>> for (P, P) {
+    fn from(size: PhysicalSize<P>) -> Self {
+        (size.width, size.height)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LogicalSize<P> {
+    pub width: P,
+    pub height: P,
+}
+
+impl<P> LogicalSize<P> {
+    pub const fn new(width: P, height: P) -> Self {
+        Self { width, height }
+    }
+}
+
+impl<P: Pixel> LogicalSize<P> {
+    pub fn to_physical<X: Pixel>(&self, scale_factor: f64) -> PhysicalSize<X> {
+        assert!(validate_scale_factor(scale_factor));
+        PhysicalSize::new(
+            self.width.into() * scale_factor,
+            self.height.into() * scale_factor,
+        )
+        .cast()
+    }
+
+    pub fn cast<X: Pixel>(&self) -> LogicalSize<X> {
+        LogicalSize::new(self.width.cast(), self.height.cast())
+    }
+}
+
+impl<P> From<(P, P)> for LogicalSize<P> {
+    fn from((width, height): (P, P)) -> Self {
+        Self::new(width, height)
+    }
+}
+
+impl<P> From<LogicalSize<P>> for (P, P) {
+    fn from(size: LogicalSize<P>) -> Self {
+        (size.width, size.height)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhysicalPosition<P> {
+    pub x: P,
+    pub y: P,
+}
+
+impl<P> PhysicalPosition<P> {
+    pub const fn new(x: P, y: P) -> Self {
+        Self { x, y }
+    }
+}
+
+impl<P: Pixel> PhysicalPosition<P> {
+    pub fn to_logical<X: Pixel>(&self, scale_factor: f64) -> LogicalPosition<X> {
+        assert!(validate_scale_factor(scale_factor));
+        LogicalPosition::new(self.x.into() / scale_factor, self.y.into() / scale_factor).cast()
+    }
+
+    pub fn cast<X: Pixel>(&self) -> PhysicalPosition<X> {
+        PhysicalPosition::new(self.x.cast(), self.y.cast())
+    }
+}
+
+impl<P> From<(P, P)> for PhysicalPosition<P> {
+    fn from((x, y): (P, P)) -> Self {
+        Self::new(x, y)
+    }
+}
+
+impl<P> From<PhysicalPosition<P>> for (P, P) {
+    fn from(position: PhysicalPosition<P>) -> Self {
+        (position.x, position.y)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LogicalPosition<P> {
+    pub x: P,
+    pub y: P,
+}
+
+impl<P> LogicalPosition<P> {
+    pub const fn new(x: P, y: P) -> Self {
+        Self { x, y }
+    }
+}
+
+impl<P: Pixel> LogicalPosition<P> {
+    pub fn to_physical<X: Pixel>(&self, scale_factor: f64) -> PhysicalPosition<X> {
+        assert!(validate_scale_factor(scale_factor));
+        PhysicalPosition::new(self.x.into() * scale_factor, self.y.into() * scale_factor).cast()
+    }
+
+    pub fn cast<X: Pixel>(&self) -> LogicalPosition<X> {
+        LogicalPosition::new(self.x.cast(), self.y.cast())
+    }
+}
+
+impl<P> From<(P, P)> for LogicalPosition<P> {
+    fn from((x, y): (P, P)) -> Self {
+        Self::new(x, y)
+    }
+}
+
+impl<P> From<LogicalPosition<P>> for (P, P) {
+    fn from(position: LogicalPosition<P>) -> Self {
+        (position.x, position.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halves_round_away_from_zero() {
+        assert_eq!(u32::from_f64(100.5), 101);
+        assert_eq!(u32::from_f64(100.499_999), 100);
+        assert_eq!(u32::from_f64(101.5), 102);
+        assert_eq!(i32::from_f64(-0.5), -1);
+        assert_eq!(i32::from_f64(-1.5), -2);
+        assert_eq!(i32::from_f64(-1.499_999), -1);
+        assert_eq!(u8::from_f64(0.5), 1);
+        assert_eq!(i16::from_f64(2.5), 3);
+    }
+
+    #[test]
+    fn integers_saturate() {
+        assert_eq!(u8::from_f64(300.0), u8::MAX);
+        assert_eq!(u32::from_f64(-3.0), 0);
+        assert_eq!(u32::from_f64(f64::INFINITY), u32::MAX);
+        assert_eq!(i8::from_f64(-200.0), i8::MIN);
+        assert_eq!(i32::from_f64(f64::NEG_INFINITY), i32::MIN);
+        assert_eq!(i32::from_f64(f64::NAN), 0);
+    }
+
+    #[test]
+    fn sizes_round_to_the_nearest_pixel() {
+        let logical = LogicalSize::new(100.5, 99.5);
+        assert_eq!(logical.to_physical::<u32>(1.0), PhysicalSize::new(101, 100));
+        assert_eq!(
+            LogicalSize::new(640.0, 480.0).to_physical::<u32>(1.25),
+            PhysicalSize::new(800, 600)
+        );
+        // 801 / 1.25 = 640.8
+        assert_eq!(
+            PhysicalSize::new(801u32, 601).to_logical::<u32>(1.25),
+            LogicalSize::new(641, 481)
+        );
+        assert_eq!(
+            PhysicalSize::new(3u32, 1).to_logical::<u32>(2.0),
+            LogicalSize::new(2, 1)
+        );
+        assert_eq!(
+            PhysicalSize::new(3u32, 1).to_logical::<f64>(2.0),
+            LogicalSize::new(1.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn positions_round_away_from_zero() {
+        assert_eq!(
+            LogicalPosition::new(-10.25, 10.25).to_physical::<i32>(2.0),
+            PhysicalPosition::new(-21, 21)
+        );
+        assert_eq!(
+            PhysicalPosition::new(-3i32, 3).to_logical::<i32>(2.0),
+            LogicalPosition::new(-2, 2)
+        );
+        assert_eq!(
+            PhysicalPosition::new(-3i32, 3).to_logical::<f64>(2.0),
+            LogicalPosition::new(-1.5, 1.5)
+        );
+    }
+
+    #[test]
+    fn whole_scale_factors_round_trip() {
+        for scale in [1.0, 2.0, 3.0] {
+            for width in [0u32, 1, 2, 799, 800, 1919, 3840] {
+                let size = PhysicalSize::new(width, width + 1);
+                let logical = size.to_logical::<f64>(scale);
+                assert_eq!(logical.to_physical::<u32>(scale), size);
+            }
+        }
+    }
+
+    #[test]
+    fn casts_round_like_conversions() {
+        assert_eq!(
+            PhysicalSize::new(1.5f64, 2.4).cast::<u32>(),
+            PhysicalSize::new(2, 2)
+        );
+        assert_eq!(
+            LogicalPosition::new(-1.5f32, 0.49).cast::<i32>(),
+            LogicalPosition::new(-2, 0)
+        );
+        assert_eq!(
+            PhysicalSize::new(7u32, 9).cast::<f64>(),
+            PhysicalSize::new(7.0, 9.0)
+        );
+    }
+
+    #[test]
+    fn scale_factors_must_be_finite_and_positive() {
+        assert!(validate_scale_factor(1.0));
+        assert!(validate_scale_factor(0.75));
+        assert!(!validate_scale_factor(0.0));
+        assert!(!validate_scale_factor(-1.0));
+        assert!(!validate_scale_factor(f64::NAN));
+        assert!(!validate_scale_factor(f64::INFINITY));
+        assert!(!validate_scale_factor(f64::MIN_POSITIVE / 2.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn converting_with_a_bad_scale_factor_panics() {
+        PhysicalSize::new(1u32, 1).to_logical::<f64>(0.0);
+    }
+
+    #[test]
+    fn tuples_convert_both_ways() {
+        let size: PhysicalSize<u32> = (3, 4).into();
+        assert_eq!(size, PhysicalSize::new(3, 4));
+        assert_eq!(<(u32, u32)>::from(size), (3, 4));
+        let position: LogicalPosition<f64> = (1.5, -2.0).into();
+        assert_eq!(<(f64, f64)>::from(position), (1.5, -2.0));
+    }
+}
@@ -0,0 +1,446 @@
+use std::{fmt, str::FromStr};
+
+use crate::{platform::platform_impl, KeyboardScancode, Modifiers};
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct KeyCombo {
+    pub key: KeyboardScancode,
+    pub modifiers: Modifiers,
+}
+
+impl KeyCombo {
+    pub fn new(key: KeyboardScancode, modifiers: Modifiers) -> Self {
+        Self { key, modifiers }
+    }
+
+    // Accepts strings like "Ctrl+Shift+S": any number of modifiers in any
+    // order followed by exactly one key, case-insensitive.
+    pub fn parse(s: &str) -> Result<Self, KeyComboParseError> {
+        let mut parts = s.split('+').map(str::trim).peekable();
+        let mut modifiers = Modifiers::empty();
+        let mut key = None;
+        while let Some(part) = parts.next() {
+            if part.is_empty() {
+                return Err(KeyComboParseError::Empty);
+            }
+            if parts.peek().is_some() {
+                let m = modifier_from_name(part)
+                    .ok_or_else(|| KeyComboParseError::UnknownModifier(part.to_owned()))?;
+                if modifiers.contains(m) {
+                    return Err(KeyComboParseError::DuplicateModifier(part.to_owned()));
+                }
+                modifiers |= m;
+            } else {
+                key = Some(
+                    key_from_name(part)
+                        .ok_or_else(|| KeyComboParseError::UnknownKey(part.to_owned()))?,
+                );
+            }
+        }
+        key.map(|key| Self::new(key, modifiers))
+            .ok_or(KeyComboParseError::Empty)
+    }
+
+    // Folds right-hand modifiers onto their left-hand counterparts and drops
+    // the lock keys, so that combos compare the way users think of them.
+    pub fn normalized(self) -> Self {
+        Self::new(self.key, normalize_modifiers(self.modifiers))
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = KeyComboParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = normalize_modifiers(self.modifiers);
+        if !m.is_empty() {
+            write!(f, "{}+", m)?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+// The canonical name, which parses back to the same key.
+impl fmt::Display for KeyboardScancode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = KEY_NAMES
+            .iter()
+            .find(|(_, k)| k == self)
+            .map(|(n, _)| *n)
+            .unwrap_or("?");
+        f.write_str(name)
+    }
+}
+
+impl FromStr for KeyboardScancode {
+    type Err = KeyComboParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        key_from_name(s.trim()).ok_or_else(|| KeyComboParseError::UnknownKey(s.to_owned()))
+    }
+}
+
+// Sides are merged, as in "Ctrl+Shift", and active locks follow the
+// modifiers, as in "Shift+CapsLock". No modifiers give an empty string.
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = normalize_modifiers(*self);
+        let names = [
+            (m.contains(Modifiers::LCTRL), "Ctrl"),
+            (m.contains(Modifiers::LALT), "Alt"),
+            (m.contains(Modifiers::LSHIFT), "Shift"),
+            (m.contains(Modifiers::LSYS), "Sys"),
+            (self.contains(Modifiers::CAPSLOCK), "CapsLock"),
+            (self.contains(Modifiers::NUMLOCK), "NumLock"),
+            (self.contains(Modifiers::SCRLOCK), "ScrollLock"),
+        ];
+        let mut first = true;
+        for (_, name) in names.into_iter().filter(|(on, _)| *on) {
+            if !first {
+                f.write_str("+")?;
+            }
+            f.write_str(name)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for KeyCombo {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for KeyCombo {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        Self::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyComboParseError {
+    Empty,
+    UnknownModifier(String),
+    DuplicateModifier(String),
+    UnknownKey(String),
+}
+
+impl fmt::Display for KeyComboParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("empty key combination"),
+            Self::UnknownModifier(m) => write!(f, "unknown modifier `{}`", m),
+            Self::DuplicateModifier(m) => write!(f, "modifier `{}` given twice", m),
+            Self::UnknownKey(k) => write!(f, "unknown key `{}`", k),
+        }
+    }
+}
+
+impl std::error::Error for KeyComboParseError {}
+
+pub(crate) fn normalize_modifiers(m: Modifiers) -> Modifiers {
+    let mut out = Modifiers::empty();
+    for (l, r) in [
+        (Modifiers::LCTRL, Modifiers::RCTRL),
+        (Modifiers::LALT, Modifiers::RALT),
+        (Modifiers::LSHIFT, Modifiers::RSHIFT),
+        (Modifiers::LSYS, Modifiers::RSYS),
+    ] {
+        if m.intersects(l | r) {
+            out |= l;
+        }
+    }
+    out
+}
+
+fn modifier_from_name(name: &str) -> Option<Modifiers> {
+    let m = match name.to_ascii_lowercase().as_str() {
+        "ctrl" | "control" => Modifiers::LCTRL,
+        "alt" | "option" => Modifiers::LALT,
+        "shift" => Modifiers::LSHIFT,
+        "sys" | "super" | "win" | "meta" | "cmd" => Modifiers::LSYS,
+        _ => return None,
+    };
+    Some(m)
+}
+
+fn key_from_name(name: &str) -> Option<KeyboardScancode> {
+    use KeyboardScancode::*;
+    let alias = match name.to_ascii_lowercase().as_str() {
+        "escape" => Some(Esc),
+        "`" | "~" | "grave" => Some(Tilde),
+        "-" | "minus" => Some(Hyphen),
+        "=" => Some(Equals),
+        "[" => Some(OpenBracket),
+        "]" => Some(CloseBracket),
+        "\\" => Some(BackSlash),
+        ";" => Some(Semicolon),
+        "'" => Some(Apostrophe),
+        "," => Some(Comma),
+        "." => Some(Period),
+        "/" | "slash" => Some(ForwardSlash),
+        "return" => Some(Enter),
+        "delete" => Some(Del),
+        "ins" => Some(Insert),
+        "pageup" => Some(PgUp),
+        "pagedown" => Some(PgDn),
+        "up" => Some(ArrowUp),
+        "down" => Some(ArrowDown),
+        "left" => Some(ArrowLeft),
+        "right" => Some(ArrowRight),
+        "printscreen" => Some(PrtScSysRq),
+        "pause" => Some(PauseBreak),
+        _ => None,
+    };
+    alias.or_else(|| {
+        KEY_NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, k)| *k)
+    })
+}
+
+// Canonical names, used both for display and parsing.
+pub(crate) const KEY_NAMES: &[(&str, KeyboardScancode)] = {
+    use KeyboardScancode::*;
+    &[
+        ("Esc", Esc),
+        ("F1", F1),
+        ("F2", F2),
+        ("F3", F3),
+        ("F4", F4),
+        ("F5", F5),
+        ("F6", F6),
+        ("F7", F7),
+        ("F8", F8),
+        ("F9", F9),
+        ("F10", F10),
+        ("F11", F11),
+        ("F12", F12),
+        ("PrtSc", PrtScSysRq),
+        ("ScrLk", ScrLk),
+        ("Break", PauseBreak),
+        ("Tilde", Tilde),
+        ("1", Key1),
+        ("2", Key2),
+        ("3", Key3),
+        ("4", Key4),
+        ("5", Key5),
+        ("6", Key6),
+        ("7", Key7),
+        ("8", Key8),
+        ("9", Key9),
+        ("0", Key0),
+        ("Hyphen", Hyphen),
+        ("Equals", Equals),
+        ("Backspace", Backspace),
+        ("Insert", Insert),
+        ("Home", Home),
+        ("PgUp", PgUp),
+        ("NumLk", NumLk),
+        ("NumSlash", NumSlash),
+        ("NumAsterisk", NumAsterisk),
+        ("NumHyphen", NumHyphen),
+        ("Tab", Tab),
+        ("Q", Q),
+        ("W", W),
+        ("E", E),
+        ("R", R),
+        ("T", T),
+        ("Y", Y),
+        ("U", U),
+        ("I", I),
+        ("O", O),
+        ("P", P),
+        ("OpenBracket", OpenBracket),
+        ("CloseBracket", CloseBracket),
+        ("BackSlash", BackSlash),
+        ("Del", Del),
+        ("End", End),
+        ("PgDn", PgDn),
+        ("Num7", Num7),
+        ("Num8", Num8),
+        ("Num9", Num9),
+        ("NumPlus", NumPlus),
+        ("CapsLk", CapsLk),
+        ("A", A),
+        ("S", S),
+        ("D", D),
+        ("F", F),
+        ("G", G),
+        ("H", H),
+        ("J", J),
+        ("K", K),
+        ("L", L),
+        ("Semicolon", Semicolon),
+        ("Apostrophe", Apostrophe),
+        ("Enter", Enter),
+        ("Num4", Num4),
+        ("Num5", Num5),
+        ("Num6", Num6),
+        ("LShift", LShift),
+        ("Z", Z),
+        ("X", X),
+        ("C", C),
+        ("V", V),
+        ("B", B),
+        ("N", N),
+        ("M", M),
+        ("Comma", Comma),
+        ("Period", Period),
+        ("ForwardSlash", ForwardSlash),
+        ("RShift", RShift),
+        ("ArrowUp", ArrowUp),
+        ("Num1", Num1),
+        ("Num2", Num2),
+        ("Num3", Num3),
+        ("NumEnter", NumEnter),
+        ("LCtrl", LCtrl),
+        ("LSys", LSys),
+        ("LAlt", LAlt),
+        ("Space", Space),
+        ("RAlt", RAlt),
+        ("RSys", RSys),
+        ("Fn", Fn),
+        ("Menu", Menu),
+        ("RCtrl", RCtrl),
+        ("ArrowLeft", ArrowLeft),
+        ("ArrowDown", ArrowDown),
+        ("ArrowRight", ArrowRight),
+        ("Num0", Num0),
+        ("NumPeriod", NumPeriod),
+    ]
+};
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct HotkeyId(pub(crate) u32);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HotkeyError {
+    AlreadyRegistered,
+    UnsupportedKey,
+    Os,
+}
+
+// Hotkeys belong to the registering thread and are reported by the event loop
+// pumped on it as `WindowEvent::GlobalHotkey`. They stay registered until
+// unregistered; `EventLoop::register_hotkey` ties one to the loop instead.
+pub fn register(combo: KeyCombo) -> Result<HotkeyId, HotkeyError> {
+    platform_impl::register_hotkey(combo)
+}
+
+pub fn unregister(id: HotkeyId) {
+    platform_impl::unregister_hotkey(id)
+}
+
+pub fn unregister_all() {
+    platform_impl::unregister_all_hotkeys()
+}
+
+// The hotkeys an event loop registered, released when it's dropped so the
+// loop never takes down registrations made by anyone else on the thread.
+#[derive(Debug, Default)]
+pub(crate) struct OwnedHotkeys(Vec<HotkeyId>);
+
+impl OwnedHotkeys {
+    pub(crate) fn register(&mut self, combo: KeyCombo) -> Result<HotkeyId, HotkeyError> {
+        let id = register(combo)?;
+        self.0.push(id);
+        Ok(id)
+    }
+
+    pub(crate) fn unregister(&mut self, id: HotkeyId) {
+        self.0.retain(|&owned| owned != id);
+        unregister(id);
+    }
+}
+
+impl Drop for OwnedHotkeys {
+    fn drop(&mut self) {
+        for id in self.0.drain(..) {
+            unregister(id);
+        }
+    }
+}
+
+pub(crate) fn next_hotkey() -> Option<HotkeyId> {
+    platform_impl::next_hotkey()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifiers_display_merged_and_in_a_fixed_order() {
+        let cases = [
+            (Modifiers::empty(), ""),
+            (Modifiers::LCTRL, "Ctrl"),
+            (Modifiers::RCTRL | Modifiers::LCTRL, "Ctrl"),
+            (Modifiers::RSHIFT | Modifiers::LCTRL, "Ctrl+Shift"),
+            (
+                Modifiers::RSYS | Modifiers::LSHIFT | Modifiers::RALT | Modifiers::LCTRL,
+                "Ctrl+Alt+Shift+Sys",
+            ),
+            (Modifiers::CAPSLOCK | Modifiers::LSHIFT, "Shift+CapsLock"),
+            (
+                Modifiers::SCRLOCK | Modifiers::NUMLOCK | Modifiers::CAPSLOCK,
+                "CapsLock+NumLock+ScrollLock",
+            ),
+        ];
+        for (modifiers, shown) in cases {
+            assert_eq!(modifiers.to_string(), shown, "{modifiers:?}");
+        }
+    }
+
+    #[test]
+    fn keys_display_their_canonical_name() {
+        assert_eq!(KeyboardScancode::S.to_string(), "S");
+        assert_eq!(KeyboardScancode::Num0.to_string(), "Num0");
+        assert_eq!(KeyboardScancode::ArrowLeft.to_string(), "ArrowLeft");
+        for (name, key) in KEY_NAMES {
+            assert_eq!(key.to_string().parse(), Ok(*key), "{name}");
+        }
+    }
+
+    #[test]
+    fn combos_display_as_they_parse() {
+        let combo = KeyCombo::new(KeyboardScancode::S, Modifiers::RSHIFT | Modifiers::LCTRL);
+        assert_eq!(combo.to_string(), "Ctrl+Shift+S");
+        // Parsing gives the left-hand modifiers.
+        assert_eq!(
+            KeyCombo::parse(&combo.to_string()),
+            Ok(KeyCombo::new(
+                KeyboardScancode::S,
+                Modifiers::LSHIFT | Modifiers::LCTRL
+            ))
+        );
+        assert_eq!(
+            KeyCombo::new(KeyboardScancode::F1, Modifiers::empty()).to_string(),
+            "F1"
+        );
+    }
+
+    #[test]
+    fn parse_errors_name_the_culprit() {
+        let cases = [
+            ("", "empty key combination"),
+            ("Hyper+S", "unknown modifier `Hyper`"),
+            ("Ctrl+Ctrl+S", "modifier `Ctrl` given twice"),
+            ("Ctrl+Nope", "unknown key `Nope`"),
+        ];
+        for (s, message) in cases {
+            assert_eq!(KeyCombo::parse(s).unwrap_err().to_string(), message, "{s}");
+        }
+    }
+}
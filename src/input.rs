@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    hotkeys::{normalize_modifiers, KeyCombo},
+    KeyboardScancode, Modifiers, WindowEvent,
+};
+
+// Maps key combinations to application actions. Feed it every event from the
+// loop so it can track the held modifiers and keys; `process` then reports the
+// action bound to a combo when its terminal key goes down.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActionMap<A> {
+    bindings: HashMap<KeyCombo, A>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    key_repeat: bool,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Modifiers::empty"))]
+    modifiers: Modifiers,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    held: HashSet<KeyboardScancode>,
+}
+
+impl<A> Default for ActionMap<A> {
+    fn default() -> Self {
+        Self {
+            bindings: HashMap::new(),
+            key_repeat: false,
+            modifiers: Modifiers::empty(),
+            held: HashSet::new(),
+        }
+    }
+}
+
+impl<A> ActionMap<A> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Whether auto-repeated key downs trigger their action again. Off by
+    // default.
+    pub fn set_key_repeat(&mut self, key_repeat: bool) {
+        self.key_repeat = key_repeat;
+    }
+
+    pub fn key_repeat(&self) -> bool {
+        self.key_repeat
+    }
+
+    pub fn bind(&mut self, combo: KeyCombo, action: A) -> Option<A> {
+        self.bindings.insert(combo.normalized(), action)
+    }
+
+    pub fn unbind(&mut self, combo: KeyCombo) -> Option<A> {
+        self.bindings.remove(&combo.normalized())
+    }
+
+    // Moves the action bound to `from` onto `to`, returning whatever `to` was
+    // bound to before. Does nothing if `from` is unbound.
+    pub fn rebind(&mut self, from: KeyCombo, to: KeyCombo) -> Option<A> {
+        let action = self.unbind(from)?;
+        self.bind(to, action)
+    }
+
+    pub fn get(&self, combo: KeyCombo) -> Option<&A> {
+        self.bindings.get(&combo.normalized())
+    }
+
+    pub fn combo_for(&self, action: &A) -> Option<KeyCombo>
+    where
+        A: PartialEq,
+    {
+        self.bindings
+            .iter()
+            .find(|(_, a)| *a == action)
+            .map(|(c, _)| *c)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (KeyCombo, &A)> {
+        self.bindings.iter().map(|(c, a)| (*c, a))
+    }
+
+    pub fn clear(&mut self) {
+        self.bindings.clear();
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    pub fn process(&mut self, event: &WindowEvent) -> Option<&A> {
+        match *event {
+            WindowEvent::ModifiersChanged(m) => {
+                self.modifiers = m;
+                None
+            }
+            WindowEvent::KeyDown {
+                logical_scancode, ..
+            } => {
+                let repeat = !self.held.insert(logical_scancode);
+                if repeat && !self.key_repeat {
+                    return None;
+                }
+                let combo = KeyCombo::new(logical_scancode, normalize_modifiers(self.modifiers));
+                self.bindings.get(&combo)
+            }
+            WindowEvent::KeyUp {
+                logical_scancode, ..
+            } => {
+                self.held.remove(&logical_scancode);
+                None
+            }
+            // Key ups that happen while another window has focus are never
+            // seen, so start from a clean slate instead of reporting repeats.
+            WindowEvent::Focused(false) | WindowEvent::Destroyed => {
+                self.held.clear();
+                self.modifiers = Modifiers::empty();
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    enum Action {
+        Save,
+        Quit,
+    }
+
+    fn combo(s: &str) -> KeyCombo {
+        KeyCombo::parse(s).unwrap()
+    }
+
+    fn key_down(key: KeyboardScancode) -> WindowEvent {
+        WindowEvent::KeyDown {
+            logical_scancode: key,
+            physical_scancode: Some(key),
+            character: None,
+            unshifted_char: None,
+            text: None,
+            repeat: false,
+        }
+    }
+
+    fn key_up(key: KeyboardScancode) -> WindowEvent {
+        WindowEvent::KeyUp {
+            logical_scancode: key,
+            physical_scancode: Some(key),
+        }
+    }
+
+    #[test]
+    fn lookup_ignores_modifier_order_and_side() {
+        let mut map = ActionMap::new();
+        assert_eq!(map.bind(combo("Ctrl+Shift+S"), Action::Save), None);
+        assert_eq!(map.get(combo("shift+control+s")), Some(&Action::Save));
+        assert_eq!(
+            map.get(KeyCombo::new(
+                KeyboardScancode::S,
+                Modifiers::RCTRL | Modifiers::LSHIFT | Modifiers::CAPSLOCK
+            )),
+            Some(&Action::Save)
+        );
+        assert_eq!(map.get(combo("Ctrl+S")), None);
+        assert_eq!(map.combo_for(&Action::Save), Some(combo("Ctrl+Shift+S")));
+        assert_eq!(map.combo_for(&Action::Quit), None);
+    }
+
+    #[test]
+    fn rebinding_moves_the_action() {
+        let mut map = ActionMap::new();
+        map.bind(combo("Ctrl+S"), Action::Save);
+        map.bind(combo("Ctrl+Q"), Action::Quit);
+        assert_eq!(
+            map.rebind(combo("Ctrl+S"), combo("Ctrl+Q")),
+            Some(Action::Quit)
+        );
+        assert_eq!(map.get(combo("Ctrl+S")), None);
+        assert_eq!(map.get(combo("Ctrl+Q")), Some(&Action::Save));
+        assert_eq!(map.rebind(combo("Ctrl+S"), combo("Alt+S")), None);
+        assert_eq!(map.get(combo("Alt+S")), None);
+        assert_eq!(map.unbind(combo("Ctrl+Q")), Some(Action::Save));
+        assert_eq!(map.iter().count(), 0);
+    }
+
+    #[test]
+    fn actions_fire_on_the_terminal_key_with_modifiers_held() {
+        let mut map = ActionMap::new();
+        map.bind(combo("Ctrl+S"), Action::Save);
+        assert_eq!(map.process(&key_down(KeyboardScancode::S)), None);
+        map.process(&key_up(KeyboardScancode::S));
+
+        map.process(&WindowEvent::ModifiersChanged(Modifiers::RCTRL));
+        assert_eq!(map.modifiers(), Modifiers::RCTRL);
+        assert_eq!(
+            map.process(&key_down(KeyboardScancode::S)),
+            Some(&Action::Save)
+        );
+        map.process(&key_up(KeyboardScancode::S));
+
+        // Bindings match exactly, so an extra modifier doesn't count.
+        map.process(&WindowEvent::ModifiersChanged(
+            Modifiers::LCTRL | Modifiers::LSHIFT,
+        ));
+        assert_eq!(map.process(&key_down(KeyboardScancode::S)), None);
+    }
+
+    #[test]
+    fn repeats_are_suppressed_unless_enabled() {
+        let mut map = ActionMap::new();
+        map.bind(combo("F5"), Action::Save);
+        assert!(!map.key_repeat());
+        assert_eq!(
+            map.process(&key_down(KeyboardScancode::F5)),
+            Some(&Action::Save)
+        );
+        assert_eq!(map.process(&key_down(KeyboardScancode::F5)), None);
+
+        map.set_key_repeat(true);
+        assert_eq!(
+            map.process(&key_down(KeyboardScancode::F5)),
+            Some(&Action::Save)
+        );
+
+        map.set_key_repeat(false);
+        map.process(&key_up(KeyboardScancode::F5));
+        assert_eq!(
+            map.process(&key_down(KeyboardScancode::F5)),
+            Some(&Action::Save)
+        );
+    }
+
+    #[test]
+    fn losing_focus_forgets_held_keys_and_modifiers() {
+        let mut map = ActionMap::new();
+        map.bind(combo("Ctrl+S"), Action::Save);
+        map.bind(combo("S"), Action::Quit);
+        map.process(&WindowEvent::ModifiersChanged(Modifiers::LCTRL));
+        map.process(&key_down(KeyboardScancode::S));
+        map.process(&WindowEvent::Focused(false));
+        assert_eq!(map.modifiers(), Modifiers::empty());
+        assert_eq!(
+            map.process(&key_down(KeyboardScancode::S)),
+            Some(&Action::Quit)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn bindings_round_trip_through_serde() {
+        use serde_test::{assert_de_tokens, assert_tokens, Token};
+
+        // ActionMap has no PartialEq, so compare what's serialized.
+        #[derive(Debug, serde::Serialize, serde::Deserialize)]
+        #[serde(transparent)]
+        struct Map(ActionMap<Action>);
+
+        impl PartialEq for Map {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.key_repeat() == other.0.key_repeat()
+                    && self.0.iter().count() == other.0.iter().count()
+                    && self.0.iter().all(|(c, a)| other.0.get(c) == Some(a))
+            }
+        }
+
+        let mut map = ActionMap::new();
+        map.bind(combo("Shift+Ctrl+S"), Action::Save);
+        map.set_key_repeat(true);
+        let tokens = [
+            Token::Struct {
+                name: "ActionMap",
+                len: 2,
+            },
+            Token::Str("bindings"),
+            Token::Map { len: Some(1) },
+            Token::Str("Ctrl+Shift+S"),
+            Token::UnitVariant {
+                name: "Action",
+                variant: "Save",
+            },
+            Token::MapEnd,
+            Token::Str("key_repeat"),
+            Token::Bool(true),
+            Token::StructEnd,
+        ];
+        assert_tokens(&Map(map), &tokens);
+
+        // key_repeat may be left out.
+        let mut map = ActionMap::new();
+        map.bind(combo("Q"), Action::Quit);
+        assert_de_tokens(
+            &Map(map),
+            &[
+                Token::Struct {
+                    name: "ActionMap",
+                    len: 1,
+                },
+                Token::Str("bindings"),
+                Token::Map { len: Some(1) },
+                Token::Str("q"),
+                Token::UnitVariant {
+                    name: "Action",
+                    variant: "Quit",
+                },
+                Token::MapEnd,
+                Token::StructEnd,
+            ],
+        );
+    }
+}
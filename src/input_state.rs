@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+
+use crate::{KeyboardScancode, Modifiers};
+
+// Key and modifier bookkeeping shared by the backends, which only translate
+// their native events into physical key transitions and forward what comes
+// back. Held modifiers follow their keys, lock keys toggle on the initial
+// press only, and losing focus releases everything but the locks.
+#[derive(Clone, Debug)]
+pub(crate) struct KeyStateTracker {
+    modifiers: Modifiers,
+    held: HashSet<KeyboardScancode>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct KeyTransition {
+    pub(crate) repeat: bool,
+    // The new set, only when it differs from the previous one.
+    pub(crate) modifiers_changed: Option<Modifiers>,
+}
+
+impl Default for KeyStateTracker {
+    fn default() -> Self {
+        Self {
+            modifiers: Modifiers::empty(),
+            held: HashSet::new(),
+        }
+    }
+}
+
+impl KeyStateTracker {
+    pub(crate) fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    pub(crate) fn is_held(&self, key: KeyboardScancode) -> bool {
+        self.held.contains(&key)
+    }
+
+    // `repeat` is the platform's own auto-repeat flag, if it has one; a key
+    // that goes down while already held counts as a repeat either way.
+    pub(crate) fn key(&mut self, key: KeyboardScancode, down: bool, repeat: bool) -> KeyTransition {
+        let old = self.modifiers;
+        let repeat = if down {
+            !self.held.insert(key) || repeat
+        } else {
+            self.held.remove(&key);
+            false
+        };
+
+        if let Some(m) = modifier(key) {
+            if LOCKS.contains(m) {
+                if down && !repeat {
+                    self.modifiers ^= m;
+                }
+            } else if down {
+                self.modifiers |= m;
+            } else {
+                self.modifiers &= !m;
+            }
+        }
+
+        KeyTransition {
+            repeat,
+            modifiers_changed: (self.modifiers != old).then_some(self.modifiers),
+        }
+    }
+
+    // For lock state changed without a key press, e.g. by
+    // keyboard::set_lock_state.
+    pub(crate) fn set_lock(&mut self, lock: Modifiers, enabled: bool) -> Option<Modifiers> {
+        let old = self.modifiers;
+        self.modifiers.set(lock, enabled);
+        (self.modifiers != old).then_some(self.modifiers)
+    }
+
+    // Key ups that happen while another window has focus are never seen.
+    pub(crate) fn focus_lost(&mut self) -> Option<Modifiers> {
+        let old = self.modifiers;
+        self.held.clear();
+        self.modifiers &= LOCKS;
+        (self.modifiers != old).then_some(self.modifiers)
+    }
+}
+
+const LOCKS: Modifiers = Modifiers::CAPSLOCK
+    .union(Modifiers::NUMLOCK)
+    .union(Modifiers::SCRLOCK);
+
+fn modifier(key: KeyboardScancode) -> Option<Modifiers> {
+    let m = match key {
+        KeyboardScancode::LShift => Modifiers::LSHIFT,
+        KeyboardScancode::RShift => Modifiers::RSHIFT,
+        KeyboardScancode::LCtrl => Modifiers::LCTRL,
+        KeyboardScancode::RCtrl => Modifiers::RCTRL,
+        KeyboardScancode::LAlt => Modifiers::LALT,
+        KeyboardScancode::RAlt => Modifiers::RALT,
+        KeyboardScancode::LSys => Modifiers::LSYS,
+        KeyboardScancode::RSys => Modifiers::RSYS,
+        KeyboardScancode::CapsLk => Modifiers::CAPSLOCK,
+        KeyboardScancode::NumLk => Modifiers::NUMLOCK,
+        KeyboardScancode::ScrLk => Modifiers::SCRLOCK,
+        _ => return None,
+    };
+    Some(m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyboardScancode::*;
+
+    // Feeds (key, down, platform repeat flag) and collects the sets that
+    // would be sent as ModifiersChanged.
+    fn feed(
+        tracker: &mut KeyStateTracker,
+        keys: &[(KeyboardScancode, bool, bool)],
+    ) -> Vec<Modifiers> {
+        keys.iter()
+            .filter_map(|&(key, down, repeat)| tracker.key(key, down, repeat).modifiers_changed)
+            .collect()
+    }
+
+    #[test]
+    fn held_modifiers_follow_their_keys() {
+        let mut tracker = KeyStateTracker::default();
+        let changes = feed(
+            &mut tracker,
+            &[
+                (LShift, true, false),
+                (LShift, true, true),
+                (LShift, true, true),
+                (RCtrl, true, false),
+                (LShift, false, false),
+                (RCtrl, false, false),
+            ],
+        );
+        assert_eq!(
+            changes,
+            [
+                Modifiers::LSHIFT,
+                Modifiers::LSHIFT | Modifiers::RCTRL,
+                Modifiers::RCTRL,
+                Modifiers::empty(),
+            ]
+        );
+    }
+
+    #[test]
+    fn both_sides_are_tracked_separately() {
+        let mut tracker = KeyStateTracker::default();
+        feed(&mut tracker, &[(LAlt, true, false), (RAlt, true, false)]);
+        assert_eq!(tracker.modifiers(), Modifiers::LALT | Modifiers::RALT);
+        assert_eq!(
+            feed(&mut tracker, &[(LAlt, false, false)]),
+            [Modifiers::RALT]
+        );
+    }
+
+    #[test]
+    fn locks_toggle_on_the_first_press_only() {
+        let mut tracker = KeyStateTracker::default();
+        let changes = feed(
+            &mut tracker,
+            &[
+                (CapsLk, true, false),
+                (CapsLk, true, true),
+                (CapsLk, true, true),
+                (CapsLk, false, false),
+                (CapsLk, true, false),
+                (CapsLk, false, false),
+            ],
+        );
+        assert_eq!(changes, [Modifiers::CAPSLOCK, Modifiers::empty()]);
+
+        feed(&mut tracker, &[(NumLk, true, false), (NumLk, false, false)]);
+        assert_eq!(
+            feed(&mut tracker, &[(ScrLk, true, false)]),
+            [Modifiers::NUMLOCK | Modifiers::SCRLOCK]
+        );
+    }
+
+    #[test]
+    fn a_held_key_going_down_again_is_a_repeat() {
+        let mut tracker = KeyStateTracker::default();
+        // X11 doesn't flag repeats.
+        assert!(!tracker.key(CapsLk, true, false).repeat);
+        let again = tracker.key(CapsLk, true, false);
+        assert!(again.repeat);
+        assert_eq!(again.modifiers_changed, None);
+        assert_eq!(tracker.modifiers(), Modifiers::CAPSLOCK);
+
+        assert!(!tracker.key(A, true, false).repeat);
+        assert!(tracker.key(A, true, false).repeat);
+        assert!(!tracker.key(A, false, false).repeat);
+        assert!(!tracker.is_held(A));
+        assert!(!tracker.key(A, true, false).repeat);
+    }
+
+    #[test]
+    fn other_keys_change_nothing() {
+        let mut tracker = KeyStateTracker::default();
+        let transition = tracker.key(Enter, true, false);
+        assert_eq!(
+            transition,
+            KeyTransition {
+                repeat: false,
+                modifiers_changed: None,
+            }
+        );
+        assert!(tracker.is_held(Enter));
+        assert_eq!(tracker.modifiers(), Modifiers::empty());
+    }
+
+    #[test]
+    fn losing_focus_releases_all_but_the_locks() {
+        let mut tracker = KeyStateTracker::default();
+        feed(
+            &mut tracker,
+            &[
+                (LCtrl, true, false),
+                (A, true, false),
+                (CapsLk, true, false),
+            ],
+        );
+        assert_eq!(tracker.focus_lost(), Some(Modifiers::CAPSLOCK));
+        assert!(!tracker.is_held(A));
+        assert!(!tracker.is_held(CapsLk));
+        assert_eq!(tracker.focus_lost(), None);
+        // CapsLk's key up was missed, so the next press is a fresh one.
+        assert_eq!(
+            feed(&mut tracker, &[(CapsLk, true, false)]),
+            [Modifiers::empty()]
+        );
+    }
+
+    #[test]
+    fn locks_set_elsewhere_are_reported_once() {
+        let mut tracker = KeyStateTracker::default();
+        assert_eq!(
+            tracker.set_lock(Modifiers::NUMLOCK, true),
+            Some(Modifiers::NUMLOCK)
+        );
+        assert_eq!(tracker.set_lock(Modifiers::NUMLOCK, true), None);
+        assert_eq!(
+            tracker.set_lock(Modifiers::NUMLOCK, false),
+            Some(Modifiers::empty())
+        );
+    }
+}
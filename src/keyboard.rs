@@ -0,0 +1,37 @@
+use crate::{platform::platform_impl, Modifiers};
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum LockKey {
+    CapsLock,
+    NumLock,
+    ScrollLock,
+}
+
+impl LockKey {
+    pub(crate) fn modifier(self) -> Modifiers {
+        match self {
+            LockKey::CapsLock => Modifiers::CAPSLOCK,
+            LockKey::NumLock => Modifiers::NUMLOCK,
+            LockKey::ScrollLock => Modifiers::SCRLOCK,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LockError {
+    // The keyboard has no such lock, e.g. Scroll Lock on most X11 layouts.
+    Unsupported,
+    Os,
+}
+
+// Sets a lock for the whole system, e.g. to match a guest's keyboard. The
+// windows only see it as ModifiersChanged; no KeyDown or KeyUp is reported
+// for the toggle.
+pub fn set_lock_state(lock: LockKey, enabled: bool) -> Result<(), LockError> {
+    platform_impl::set_lock_state(lock, enabled)
+}
+
+// None where the state can't be read.
+pub fn lock_state(lock: LockKey) -> Option<bool> {
+    platform_impl::lock_state(lock)
+}
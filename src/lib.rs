@@ -1,22 +1,64 @@
 #![allow(clippy::bool_comparison, clippy::iter_nth_zero, dead_code)]
 
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
     marker::PhantomData,
-    sync::{Arc, RwLock},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, RwLock,
+    },
+    task::Waker,
+    time::{Duration, Instant},
 };
 
 use bitflags::bitflags;
+use dpi::{PhysicalPosition, PhysicalSize};
 
+pub mod clipboard;
+pub mod dpi;
+pub mod hotkeys;
+pub mod input;
+mod input_state;
+pub mod keyboard;
 pub mod platform;
+pub mod prelude;
+pub mod single_instance;
+#[cfg(any(windows, all(unix, feature = "x11")))]
+pub mod ui_thread;
+
+#[cfg(any(windows, all(unix, feature = "x11")))]
+pub use ui_thread::spawn_ui_thread;
+
+// Passed to the AT-SPI adapter so assistive technology can identify the
+// toolkit behind nwin windows.
+#[cfg(feature = "accesskit")]
+pub const ACCESSKIT_TOOLKIT_NAME: &str = "nwin";
+#[cfg(feature = "accesskit")]
+pub const ACCESSKIT_TOOLKIT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Copy, Clone, Debug, Hash, Default, PartialEq, Eq)]
 pub struct WindowId(pub u64);
 
+// The native handle in hex, tagged with what kind of handle it is, e.g.
+// "HWND 0x1a2b" or "XID 0x3c00007".
+impl fmt::Display for WindowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:#x}",
+            platform::platform_impl::WINDOW_ID_KIND,
+            self.0
+        )
+    }
+}
+
 bitflags! {
     #[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
     pub struct WindowButtons: u8 {
-        const CLOSE = 0x00;
+        const CLOSE = 0x04;
         const MINIMIZE = 0x01;
         const MAXIMIZE = 0x02;
     }
@@ -51,11 +93,207 @@ pub enum Theme {
     Dark,
 }
 
+// What happens after a window reports CloseRequested.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CloseAction {
+    Destroy,
+    Hide,
+    #[default]
+    Nothing,
+}
+
+// Run by the backends once CloseRequested has been sent, outside of any
+// window lock. Does nothing once every handle to the window has been dropped.
+pub(crate) fn apply_close_action(id: WindowId) {
+    let Some(mut window) = platform::platform_impl::Window::from_id(id) else {
+        return;
+    };
+    match window.close_action() {
+        CloseAction::Destroy => window.destroy_window(),
+        CloseAction::Hide => {
+            window.hide();
+            window
+                .sender()
+                .write()
+                .unwrap()
+                .send(id, WindowEvent::HiddenToBackground);
+        }
+        CloseAction::Nothing => {}
+    }
+}
+
+// Tightly packed 8-bit RGBA, rows top to bottom.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DragPayload {
+    Files(Vec<PathBuf>),
+    Text(String),
+}
+
+// How the target took a drag started with WindowT::start_drag. Drops the
+// target refused count as cancelled.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DragOutcome {
+    #[default]
+    Cancelled,
+    Copied,
+    Moved,
+    Linked,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SystemSettings {
+    pub double_click_time: Duration,
+    pub double_click_area: (u32, u32),
+    pub caret_blink_interval: Option<Duration>,
+    pub drag_threshold: (u32, u32),
+    pub wheel_scroll_lines: u32,
+    // The user asked for fewer animations; skip transitions or make them
+    // instant.
+    pub reduced_motion: bool,
+    pub high_contrast: bool,
+}
+
+impl Default for SystemSettings {
+    fn default() -> Self {
+        Self {
+            double_click_time: Duration::from_millis(500),
+            double_click_area: (4, 4),
+            caret_blink_interval: Some(Duration::from_millis(530)),
+            drag_threshold: (4, 4),
+            wheel_scroll_lines: 3,
+            reduced_motion: false,
+            high_contrast: false,
+        }
+    }
+}
+
+// Every window of this process that still has a live handle, in no
+// particular order. Pair with Window::from_id to get at the windows.
+pub fn windows() -> Vec<WindowId> {
+    platform::platform_impl::windows()
+}
+
+pub fn system_settings() -> SystemSettings {
+    platform::platform_impl::system_settings()
+}
+
+// The theme the user picked for applications, or None when the platform
+// doesn't say. On X11 it's guessed from the XSETTINGS theme name.
+pub fn system_theme() -> Option<Theme> {
+    platform::platform_impl::system_theme()
+}
+
+// How win32 scales the process's windows. X11 has no per-process setting.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DpiMode {
+    #[default]
+    PerMonitorV2,
+    SystemAware,
+    // Bitmap-stretched by the system, e.g. for legacy GDI content.
+    Unaware,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DpiError {
+    // A window has already been created.
+    TooLate,
+    // Refused by the OS, e.g. because the manifest already sets a mode.
+    Os,
+}
+
+// Set by the backends once they create a window; the DPI mode can't change
+// from then on.
+pub(crate) static WINDOW_CREATED: AtomicBool = AtomicBool::new(false);
+
+pub fn init_dpi(mode: DpiMode) -> Result<(), DpiError> {
+    if WINDOW_CREATED.load(Ordering::Relaxed) {
+        return Err(DpiError::TooLate);
+    }
+    platform::platform_impl::init_dpi(mode)
+}
+
+// NWIN_SCALE_FACTOR_OVERRIDE forces every window's scale factor, e.g. for
+// tests. The OS's own changes (WM_DPICHANGED) are then not reported.
+pub(crate) fn scale_factor_override() -> Option<f64> {
+    std::env::var("NWIN_SCALE_FACTOR_OVERRIDE")
+        .ok()?
+        .parse()
+        .ok()
+        .filter(|f| dpi::validate_scale_factor(*f))
+}
+
+// When Resized is sent, for renderers that can't recreate their swapchain on
+// every step of a drag resize. Only the last size is ever held back.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ResizePolicy {
+    #[default]
+    Live,
+    // Once the user lets go. X11 doesn't say when that is, so there it's
+    // once no new size has arrived for RESIZE_SETTLE.
+    OnRelease,
+    // At most one per interval.
+    Throttled(Duration),
+}
+
+const RESIZE_SETTLE: Duration = Duration::from_millis(100);
+
+// Stays the same for as long as the monitor is connected, and usually across
+// sessions too: the monitor's device interface path on win32, and the output
+// name (e.g. "DP-1") on X11. Meant for remembering which monitor a window was
+// on.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MonitorId(pub String);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Monitor {
+    pub id: MonitorId,
+    pub name: String,
+    pub position: PhysicalPosition<i32>,
+    pub size: PhysicalSize<u32>,
+    pub refresh_rate_millihertz: Option<u32>,
+    pub primary: bool,
+}
+
+// The monitors currently showing part of the desktop.
+pub fn monitors() -> Vec<Monitor> {
+    platform::platform_impl::monitors()
+}
+
 pub trait WindowT {
     fn id(&self) -> WindowId;
     fn request_redraw(&mut self);
+    fn request_redraw_continuous(&mut self, continuous: bool);
     fn width(&self) -> u32;
     fn height(&self) -> u32;
+    fn inner_size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.width(), self.height())
+    }
+    // When the last key, button, motion or wheel event for this window was
+    // sent, whether or not it has been drained yet.
+    fn last_interaction(&self) -> Option<Instant>
+    where
+        Self: WindowTExt,
+    {
+        self.sender().read().unwrap().last_interaction
+    }
+    fn set_resize_event_policy(&mut self, policy: ResizePolicy)
+    where
+        Self: WindowTExt,
+    {
+        let id = self.id();
+        let sender = self.sender();
+        let mut sender = sender.write().unwrap();
+        sender.resize_policy = policy;
+        sender.flush_resize(id);
+    }
     fn set_width(&mut self, width: u32);
     fn set_height(&mut self, height: u32);
     fn min_width(&self) -> u32;
@@ -66,12 +304,39 @@ pub trait WindowT {
     fn max_height(&self) -> u32;
     fn set_max_width(&mut self, width: u32);
     fn set_max_height(&mut self, height: u32);
+    fn set_min_size(&mut self, width: u32, height: u32) {
+        self.set_min_width(width);
+        self.set_min_height(height);
+    }
+    fn set_max_size(&mut self, width: u32, height: u32) {
+        self.set_max_width(width);
+        self.set_max_height(height);
+    }
+    // Returns the size actually applied when the platform reports it right
+    // away, or None when it will arrive later as a Resized event.
+    fn request_inner_size(&mut self, size: PhysicalSize<u32>) -> Option<PhysicalSize<u32>>;
     fn title(&self) -> String;
+    fn set_title(&mut self, title: &str);
+    // Where the last Moved event put the window.
+    fn position(&self) -> PhysicalPosition<i32>;
+    // The move is reported as Moved once the platform has made it.
+    fn set_position(&mut self, position: PhysicalPosition<i32>);
+    // Including the frame and title bar around the window. X11 window
+    // managers report these asynchronously, so they're only right once the
+    // window has been mapped and framed.
+    fn outer_size(&self) -> PhysicalSize<u32>;
+    fn outer_position(&self) -> PhysicalPosition<i32>;
     fn visible(&self) -> bool;
+    // Hiding counts as losing focus without being reported as Focused(false),
+    // so it can't be mistaken for the user switching away, and nothing about
+    // focus is reported while hidden. The size state is kept: minimizing or
+    // maximizing a hidden window takes effect once it's shown.
     fn hide(&mut self);
     fn show(&mut self);
     fn resizeable(&self) -> bool;
     fn set_resizeable(&mut self, resizeable: bool);
+    // What was last passed to set_enabled_buttons. Maximize is also turned
+    // off while the window can't change size, without touching this.
     fn enabled_buttons(&self) -> WindowButtons;
     fn set_enabled_buttons(&mut self, buttons: WindowButtons);
     fn minimized(&self) -> bool;
@@ -86,22 +351,258 @@ pub trait WindowT {
             || self.fullscreen_type() == FullscreenType::Exclusive
     }
     fn set_fullscreen(&mut self, fullscreen: FullscreenType);
+    // Switches between `kind` and windowed, returning the new state. The
+    // transition has finished by the time set_fullscreen returns, so calls
+    // from a repeating F11 just alternate.
+    fn toggle_fullscreen(&mut self, kind: FullscreenType) -> FullscreenType {
+        self.set_fullscreen(toggled_fullscreen(self.fullscreen_type(), kind));
+        self.fullscreen_type()
+    }
     fn focus(&mut self);
+    // Asks the window manager to activate the window. Unlike `focus`, the
+    // request may be declined under focus-stealing prevention, in which case
+    // the window flashes for attention instead.
+    fn request_activation(&mut self);
+    // Restack the window among its siblings without activating it.
+    fn raise(&mut self);
+    fn lower(&mut self);
+    // Whether the window is kept above normal windows, which raise and lower
+    // don't change.
+    fn is_topmost(&self) -> bool;
+    // Lets files, URLs and text be dragged onto the window, reported as
+    // DroppedFile, DroppedUri and DroppedText. Off by default.
+    fn set_accepts_drops(&mut self, accept: bool);
+    // Drags `payload` out of the window, returning once it's dropped or
+    // cancelled. Meant to be called while a mouse button is held, from a
+    // MouseButtonDown or CursorMoved handler.
+    fn start_drag(
+        &mut self,
+        payload: DragPayload,
+    ) -> Result<DragOutcome, platform::platform_impl::OsError>;
     fn focused(&self) -> bool;
     fn request_user_attention(&mut self, attention: UserAttentionType);
+    // The theme in effect: the preference if one is set, the system's
+    // otherwise. ThemeChanged reports system changes while following it.
     fn theme(&self) -> Theme;
-    fn set_theme(&mut self, theme: Theme);
+    fn set_theme(&mut self, theme: Theme) {
+        self.set_theme_preference(Some(theme));
+    }
+    // None follows the system theme, which new windows do.
+    fn theme_preference(&self) -> Option<Theme>;
+    fn set_theme_preference(&mut self, theme: Option<Theme>);
+    fn close_action(&self) -> CloseAction;
+    fn set_close_action(&mut self, action: CloseAction);
+    // Mainly for borderless windows, which don't get one from the system.
+    // Turning it off puts a decorated window back to its usual shadow. On X11
+    // shadows are up to the compositor, so this does nothing there.
+    fn set_shadow(&mut self, shadow: bool);
+    // See WindowBuilder::with_background_color. None goes back to the system
+    // default. The window is repainted with the new color.
+    fn set_background_color(&mut self, color: Option<(u8, u8, u8)>);
+    // The refresh rate of the monitor the window is mostly on, if known.
+    fn current_refresh_rate_millihertz(&self) -> Option<u32>;
+    // Physical pixels per logical pixel.
+    fn scale_factor(&self) -> f64;
+    // Reads back the client area. On X11 without a compositor, parts of the
+    // window covered by other windows come back undefined.
+    fn capture(&self) -> Result<RgbaImage, platform::platform_impl::OsError>;
 }
 
 pub trait WindowTExt {
     fn sender(&self) -> Arc<RwLock<EventSender>>;
+    // Queues a synthetic event addressed to this window. It is delivered in
+    // order with the OS events, and EventLoop::is_synthetic reports it.
+    fn send_event(&self, event: WindowEvent)
+    where
+        Self: WindowT,
+    {
+        self.sender()
+            .write()
+            .unwrap()
+            .send_synthetic(self.id(), event);
+    }
+    // For windows living in a loop nwin doesn't own, such as a plugin GUI
+    // whose host dispatches the messages. Returns what has been translated
+    // for this window since the last call, without pumping the win32 message
+    // queue. An X11 window has a connection of its own that no host pumps, so
+    // there its pending events are handled first. Only works while the window
+    // isn't bound to an EventLoop, which would receive the events instead.
+    fn poll_events(&mut self) -> Vec<WindowEvent>
+    where
+        Self: WindowT,
+    {
+        self.id().dispatch_pending();
+        let sender = self.sender();
+        let mut sender = sender.write().unwrap();
+        sender.flush_resize(self.id());
+        sender.take_queued()
+    }
+}
+
+// Size limits follow "last call wins": a new minimum above the current maximum
+// drags the maximum up with it and vice versa. Zero is raised to 1, since
+// neither platform can have an empty window.
+pub(crate) fn limit_min(min: u32, max: u32) -> (u32, u32) {
+    let min = min.max(1);
+    (min, max.max(min))
+}
+
+pub(crate) fn limit_max(min: u32, max: u32) -> (u32, u32) {
+    let max = max.max(1);
+    (min.min(max), max)
+}
+
+// The buttons to actually show: maximize means nothing for a window whose
+// size can't change.
+pub(crate) fn effective_buttons(
+    buttons: WindowButtons,
+    resizeable: bool,
+    min: (u32, u32),
+    max: (u32, u32),
+) -> WindowButtons {
+    if !resizeable || min == max {
+        buttons - WindowButtons::MAXIMIZE
+    } else {
+        buttons
+    }
+}
+
+// Any fullscreen state toggles back to windowed, even if it's not `kind`.
+pub(crate) fn toggled_fullscreen(current: FullscreenType, kind: FullscreenType) -> FullscreenType {
+    if current == FullscreenType::NotFullscreen {
+        kind
+    } else {
+        FullscreenType::NotFullscreen
+    }
+}
+
+// A wheel movement of `notches`, with the system scrolling `lines` per notch.
+pub(crate) fn wheel_scroll(notches: f32, lines: u32) -> WindowEvent {
+    WindowEvent::MouseWheelScroll {
+        raw_delta: notches,
+        scaled: notches * lines as f32,
+    }
+}
+
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
 }
 
 pub(crate) trait WindowIdExt {
     fn next_event(&self);
+    // Handles what the window has pending, without running the OS pump.
+    fn dispatch_pending(&self);
+    fn sender(&self) -> Option<Arc<RwLock<EventSender>>>;
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+// The time between frames at a refresh rate in millihertz. Continuous redraws
+// fall back to 60 Hz when the rate is unknown.
+pub(crate) fn frame_interval(refresh_rate: Option<u32>) -> Duration {
+    Duration::from_secs(1000) / refresh_rate.filter(|r| *r > 0).unwrap_or(60_000)
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct RedrawScheduler {
+    interval: Option<Duration>,
+    next: Option<Instant>,
+    // A one-off redraw asked for since the last poll.
+    requested: bool,
+}
+
+impl RedrawScheduler {
+    pub(crate) fn set_interval(&mut self, interval: Option<Duration>) {
+        self.interval = interval.map(|i| i.max(Duration::from_millis(1)));
+        self.next = interval.map(|_| Instant::now());
+    }
+
+    pub(crate) fn continuous(&self) -> bool {
+        self.interval.is_some()
+    }
+
+    // Requests made before the next poll all come out as one redraw.
+    pub(crate) fn request(&mut self) {
+        self.requested = true;
+    }
+
+    // Returns whether a redraw is due, skipping any frames missed while the
+    // loop wasn't pumping so at most one redraw is reported per interval.
+    pub(crate) fn poll(&mut self, now: Instant) -> bool {
+        let requested = std::mem::take(&mut self.requested);
+        let (Some(interval), Some(next)) = (self.interval, self.next) else {
+            return requested;
+        };
+        if now < next {
+            return requested;
+        }
+        let missed = now.duration_since(next).as_nanos() / interval.as_nanos();
+        self.next = Some(next + interval * (missed as u32 + 1));
+        true
+    }
+}
+
+// Turns successive monitor lists into connect and disconnect events. A
+// monitor whose mode changed keeps its id, so it isn't reported again.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MonitorTracker {
+    known: Vec<Monitor>,
+}
+
+impl MonitorTracker {
+    pub(crate) fn new(monitors: Vec<Monitor>) -> Self {
+        Self { known: monitors }
+    }
+
+    pub(crate) fn update(&mut self, monitors: Vec<Monitor>) -> Vec<WindowEvent> {
+        let mut events = self
+            .known
+            .iter()
+            .filter(|m| !monitors.iter().any(|n| n.id == m.id))
+            .map(|m| WindowEvent::MonitorDisconnected(m.id.clone()))
+            .collect::<Vec<_>>();
+        events.extend(
+            monitors
+                .iter()
+                .filter(|m| !self.known.iter().any(|k| k.id == m.id))
+                .cloned()
+                .map(WindowEvent::MonitorConnected),
+        );
+        self.known = monitors;
+        events
+    }
+}
+
+// Remembers the last refresh rate reported for a window, so moving within a
+// monitor or between monitors with the same rate doesn't repeat the event.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct RefreshRateTracker {
+    last: Option<u32>,
+}
+
+impl RefreshRateTracker {
+    // Returns the rate to report, if it changed. An unknown rate keeps the
+    // last one.
+    pub(crate) fn update(&mut self, rate: Option<u32>) -> Option<u32> {
+        match rate {
+            Some(rate) if self.last != Some(rate) => {
+                self.last = Some(rate);
+                Some(rate)
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn last(&self) -> Option<u32> {
+        self.last
+    }
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum KeyboardScancode {
     Esc,
     F1,
@@ -205,7 +706,10 @@ pub enum KeyboardScancode {
     Space,
     RAlt,
     RSys,
+    // Handled by the keyboard's firmware, so it never reaches the OS and is
+    // never reported. It only exists to complete the layout.
     Fn,
+    Menu,
     RCtrl,
     ArrowLeft,
     ArrowDown,
@@ -214,7 +718,17 @@ pub enum KeyboardScancode {
     NumPeriod,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+// The kind of device driving the cursor.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PointerKind {
+    #[default]
+    Mouse,
+    Touch,
+    Pen,
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum MouseScancode {
     LClick,
     RClick,
@@ -225,7 +739,7 @@ pub enum MouseScancode {
 }
 
 bitflags! {
-    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
     #[non_exhaustive]
     pub struct Modifiers: u16 {
         const LCTRL = 0x0001;
@@ -243,60 +757,191 @@ bitflags! {
 }
 
 bitflags! {
-    #[derive(Copy, Clone, Debug)]
+    #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
     #[non_exhaustive]
-    pub struct MouseButtons: u8 {
+    pub struct MouseButtons: u32 {
         const LCLICK = 0x01;
         const RCLICK = 0x02;
         const MCLICK = 0x04;
         const BUTTON_4 = 0x08;
         const BUTTON_5 = 0x10;
+        const BUTTON_6 = 0x20;
+        const BUTTON_7 = 0x40;
+        const BUTTON_8 = 0x80;
+        const BUTTON_9 = 0x100;
+        const BUTTON_10 = 0x200;
+        const BUTTON_11 = 0x400;
+        const BUTTON_12 = 0x800;
+        const BUTTON_13 = 0x1000;
+        const BUTTON_14 = 0x2000;
+        const BUTTON_15 = 0x4000;
+        const BUTTON_16 = 0x8000;
+        const BUTTON_17 = 0x10000;
+        const BUTTON_18 = 0x20000;
+        const BUTTON_19 = 0x40000;
+        const BUTTON_20 = 0x80000;
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+impl MouseScancode {
+    // Buttons are numbered from 1, with 4 and 5 being the usual back/forward
+    // side buttons.
+    pub(crate) fn from_number(n: u8) -> Self {
+        match n {
+            1 => Self::LClick,
+            2 => Self::RClick,
+            3 => Self::MClick,
+            4 => Self::Button4,
+            5 => Self::Button5,
+            n => Self::ButtonN(n),
+        }
+    }
+
+    pub(crate) fn number(self) -> u8 {
+        match self {
+            Self::LClick => 1,
+            Self::RClick => 2,
+            Self::MClick => 3,
+            Self::Button4 => 4,
+            Self::Button5 => 5,
+            Self::ButtonN(n) => n,
+        }
+    }
+}
+
+impl MouseButtons {
+    // Empty for buttons past BUTTON_20, which aren't tracked.
+    pub(crate) fn from_scancode(k: MouseScancode) -> Self {
+        match k.number() {
+            n @ 1..=20 => Self::from_bits_retain(1 << (n - 1)),
+            _ => Self::empty(),
+        }
+    }
+
+    pub(crate) fn scancodes(self) -> impl Iterator<Item = MouseScancode> {
+        (1..=20)
+            .map(MouseScancode::from_number)
+            .filter(move |k| self.contains(Self::from_scancode(*k)))
+    }
+}
+
+// Closing works the same on every backend. CloseRequested is only sent when
+// the user asks to close the window (close button, Alt+F4, WM_DELETE_WINDOW),
+// and is followed by whatever the window's CloseAction says, which by default
+// is nothing. Dropping the last Window handle destroys the window, which is
+// reported by exactly one Destroyed, and no events for that WindowId are
+// delivered after it.
+#[derive(Clone, Debug, PartialEq)]
 #[non_exhaustive]
 pub enum WindowEvent {
     Created,
-    Resized {
-        width: u32,
-        height: u32,
-    },
-    Moved {
-        x: u32,
-        y: u32,
-    },
+    Resized(PhysicalSize<u32>),
+    Moved(PhysicalPosition<i32>),
     CloseRequested,
+    // Sent after CloseRequested when the window's CloseAction is Hide.
+    HiddenToBackground,
     Destroyed,
+    // Not sent while the window is hidden; see WindowT::hide.
     Focused(bool),
     ThemeChanged(Theme),
+    // Modifier and lock keys get a KeyDown and KeyUp like any other key,
+    // followed by the ModifiersChanged they cause.
     #[non_exhaustive]
     KeyDown {
         logical_scancode: KeyboardScancode,
         physical_scancode: Option<KeyboardScancode>,
+        // The first character of `text`.
         character: Option<char>,
         unshifted_char: Option<char>,
+        // Everything the key typed. Usually one character, but a dead key
+        // that doesn't combine with the next key types both.
+        text: Option<String>,
+        // Auto-repeat while the key is held. Only the final release of a held
+        // key produces a KeyUp.
+        repeat: bool,
     },
     #[non_exhaustive]
     KeyUp {
         logical_scancode: KeyboardScancode,
         physical_scancode: Option<KeyboardScancode>,
     },
-    CursorMoved {
-        x: f64,
-        y: f64,
-    },
+    CursorMoved(PhysicalPosition<f64>),
+    // Sent before the first CursorMoved or button event from a different
+    // kind of device than the last one. Windows start out assuming a mouse.
+    PointerKindChanged(PointerKind),
     MouseButtonDown(MouseScancode),
     MouseButtonUp(MouseScancode),
-    MouseWheelScroll(f32),
+    // `raw_delta` is in wheel notches, fractional on high-resolution wheels;
+    // `scaled` is the same movement in lines, per the system scroll setting.
+    #[non_exhaustive]
+    MouseWheelScroll {
+        raw_delta: f32,
+        scaled: f32,
+    },
     ModifiersChanged(Modifiers),
-    UnrecoverableError,
+    ActivationRequested {
+        args: Vec<String>,
+    },
+    // From request_redraw, request_redraw_continuous or damage reported by
+    // the OS. Everything asked for before a pump comes out as one event.
+    RedrawRequested,
+    DismissRequested,
+    SizeStateChanged(WindowSizeState),
+    GlobalHotkey(hotkeys::HotkeyId),
+    // From EventLoop::set_timer. Timers belong to the loop, so they're
+    // reported against the default id.
+    Timer(TimerId),
+    SystemSettingsChanged,
+    // One per dropped file; a drop of several files sends several events.
+    DroppedFile(PathBuf),
+    DroppedUri(String),
+    DroppedText(String),
+    // In millihertz. Sent when the window moves to a monitor with a
+    // different rate or the monitor's mode changes; the first one reports
+    // the initial rate.
+    RefreshRateChanged(u32),
+    // The window moved to a monitor with a different scale factor, or the
+    // user changed the monitor's scaling.
+    ScaleFactorChanged(f64),
+    // Sent to every window when a monitor is plugged in or unplugged.
+    MonitorConnected(Monitor),
+    MonitorDisconnected(MonitorId),
+    // A handler or hook panicked while processing this window's events. The
+    // window ignores further input; the payload is the panic message. A
+    // panicking EventLoopProxy closure is reported against the default id.
+    UnrecoverableError(String),
+}
+
+impl WindowEvent {
+    // Whether the event comes from the user interacting with the window.
+    pub fn is_user_input(&self) -> bool {
+        matches!(
+            self,
+            WindowEvent::KeyDown { .. }
+                | WindowEvent::KeyUp { .. }
+                | WindowEvent::CursorMoved(_)
+                | WindowEvent::MouseButtonDown(_)
+                | WindowEvent::MouseButtonUp(_)
+                | WindowEvent::MouseWheelScroll { .. }
+        )
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct EventSender {
     receiver: Option<Arc<RwLock<EventReceiver>>>,
-    queued_evs: VecDeque<WindowEvent>,
+    // Events sent before a loop is bound, with whether they're synthetic.
+    queued_evs: VecDeque<(WindowEvent, bool)>,
+    destroyed: bool,
+    // Set when the bound loop is dropped; events are discarded until the
+    // next bind instead of queueing up for a loop that will never drain them.
+    unbound: bool,
+    last_interaction: Option<Instant>,
+    resize_policy: ResizePolicy,
+    in_size_move: bool,
+    held_resize: Option<PhysicalSize<u32>>,
+    resize_received: Option<Instant>,
+    resize_sent: Option<Instant>,
 }
 
 impl EventSender {
@@ -304,28 +949,145 @@ impl EventSender {
         Self {
             receiver: None,
             queued_evs: VecDeque::new(),
+            destroyed: false,
+            unbound: false,
+            last_interaction: None,
+            resize_policy: ResizePolicy::default(),
+            in_size_move: false,
+            held_resize: None,
+            resize_received: None,
+            resize_sent: None,
         }
     }
 
     pub(crate) fn with_receiver(receiver: Arc<RwLock<EventReceiver>>) -> Self {
         Self {
             receiver: Some(receiver),
-            queued_evs: VecDeque::new(),
+            ..Self::new()
         }
     }
 
     pub(crate) fn bind(&mut self, receiver: Arc<RwLock<EventReceiver>>) {
         self.receiver = Some(receiver);
+        self.unbound = false;
+    }
+
+    // Only unbinds from `receiver`, so a window that has since been bound to
+    // another loop stays with it.
+    pub(crate) fn unbind(&mut self, receiver: &Arc<RwLock<EventReceiver>>) {
+        if self
+            .receiver
+            .as_ref()
+            .is_some_and(|r| Arc::ptr_eq(r, receiver))
+        {
+            self.receiver = None;
+            self.unbound = true;
+        }
+    }
+
+    fn take_queued(&mut self) -> Vec<WindowEvent> {
+        self.queued_evs.drain(..).map(|(ev, _)| ev).collect()
     }
 
     pub(crate) fn send(&mut self, id: WindowId, ev: WindowEvent) {
+        self.push(id, ev, false);
+    }
+
+    pub(crate) fn send_synthetic(&mut self, id: WindowId, ev: WindowEvent) {
+        self.push(id, ev, true);
+    }
+
+    fn push(&mut self, id: WindowId, ev: WindowEvent, synthetic: bool) {
+        // Destroyed can be reported both by the OS and by dropping the
+        // handle; only the first one counts.
+        if self.destroyed {
+            return;
+        }
+        if let WindowEvent::Resized(size) = ev {
+            if !synthetic && self.hold_resize(size) {
+                return;
+            }
+        }
+        self.deliver(id, ev, synthetic);
+    }
+
+    // Whether the resize policy holds back a Resized for `size`, which then
+    // replaces whatever was held before.
+    fn hold_resize(&mut self, size: PhysicalSize<u32>) -> bool {
+        let now = Instant::now();
+        self.resize_received = Some(now);
+        let hold = match self.resize_policy {
+            ResizePolicy::Live => false,
+            ResizePolicy::OnRelease => {
+                self.in_size_move || !platform::platform_impl::REPORTS_SIZE_MOVE
+            }
+            ResizePolicy::Throttled(interval) => {
+                self.resize_sent.is_some_and(|t| now - t < interval)
+            }
+        };
+        if hold {
+            self.held_resize = Some(size);
+        } else {
+            self.held_resize = None;
+            self.resize_sent = Some(now);
+        }
+        hold
+    }
+
+    // Sends the held Resized once the policy allows it. Called from the
+    // pump, since nothing else may arrive to trigger it.
+    pub(crate) fn flush_resize(&mut self, id: WindowId) {
+        let Some(size) = self.held_resize else {
+            return;
+        };
+        let due = match self.resize_policy {
+            ResizePolicy::Live => true,
+            // Without size-move boundaries from the platform, the drag is
+            // taken to be over once the sizes stop coming.
+            ResizePolicy::OnRelease => {
+                !self.in_size_move
+                    && (platform::platform_impl::REPORTS_SIZE_MOVE
+                        || self
+                            .resize_received
+                            .is_none_or(|t| t.elapsed() >= RESIZE_SETTLE))
+            }
+            ResizePolicy::Throttled(interval) => {
+                self.resize_sent.is_none_or(|t| t.elapsed() >= interval)
+            }
+        };
+        if due {
+            self.held_resize = None;
+            self.resize_sent = Some(Instant::now());
+            self.deliver(id, WindowEvent::Resized(size), false);
+        }
+    }
+
+    // The platform hooks for the user's move/resize loop.
+    pub(crate) fn begin_size_move(&mut self) {
+        self.in_size_move = true;
+    }
+
+    pub(crate) fn end_size_move(&mut self, id: WindowId) {
+        self.in_size_move = false;
+        self.flush_resize(id);
+    }
+
+    fn deliver(&mut self, id: WindowId, ev: WindowEvent, synthetic: bool) {
+        self.destroyed = ev == WindowEvent::Destroyed;
+        if ev.is_user_input() {
+            self.last_interaction = Some(Instant::now());
+        }
+        if self.unbound {
+            return;
+        }
         if let Some(r) = self.receiver.as_ref() {
-            while let Some(ev) = self.queued_evs.pop_front() {
-                r.write().unwrap().recv(id, ev);
+            let mut r = r.write().unwrap();
+            while let Some((ev, synthetic)) = self.queued_evs.pop_front() {
+                r.push(id, ev, synthetic);
             }
-            r.write().unwrap().recv(id, ev);
+            r.push(id, ev, synthetic);
         } else {
-            self.queued_evs.push_back(ev);
+            self.queued_evs.push_back((ev, synthetic));
         }
     }
 }
@@ -333,29 +1095,107 @@ impl EventSender {
 #[derive(Clone, Debug)]
 pub struct EventReceiver {
     events: VecDeque<(WindowId, WindowEvent)>, //_no_send: PhantomData<*mut ()>
+    // Number of events ever pushed, and the sequence numbers of the
+    // synthetic ones still in `events`.
+    pushed: u64,
+    synthetic: VecDeque<u64>,
+    last_interaction: Option<Instant>,
+    // Set by an EventStream with nothing to return, and woken by the next
+    // event, e.g. one sent from another thread.
+    waker: Option<Waker>,
+    // Set when the loop is dropped. A sender the loop couldn't unbind, e.g.
+    // one whose window it can no longer look up, then has nowhere to queue.
+    closed: bool,
 }
 
 impl EventReceiver {
     pub(crate) fn new() -> Self {
         Self {
             events: VecDeque::new(),
+            pushed: 0,
+            synthetic: VecDeque::new(),
+            last_interaction: None,
+            waker: None,
+            closed: false,
         }
     }
 
     pub(crate) fn recv(&mut self, id: WindowId, ev: WindowEvent) {
+        self.push(id, ev, false);
+    }
+
+    fn push(&mut self, id: WindowId, ev: WindowEvent, synthetic: bool) {
+        if self.closed {
+            return;
+        }
+        if synthetic {
+            self.synthetic.push_back(self.pushed);
+        }
+        if ev.is_user_input() {
+            self.last_interaction = Some(Instant::now());
+        }
+        self.pushed += 1;
         self.events.push_back((id, ev));
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    // Also returns whether the event was synthetic.
+    fn pop(&mut self) -> Option<(WindowId, WindowEvent, bool)> {
+        let (id, ev) = self.events.pop_front()?;
+        let seq = self.pushed - self.events.len() as u64 - 1;
+        let synthetic = self.synthetic.front() == Some(&seq);
+        if synthetic {
+            self.synthetic.pop_front();
+        }
+        Some((id, ev, synthetic))
+    }
+
+    fn take(&mut self) -> VecDeque<(WindowId, WindowEvent)> {
+        self.synthetic.clear();
+        std::mem::take(&mut self.events)
     }
 }
 
 unsafe impl Sync for EventReceiver {}
 
+// Chosen by the application, so it can tell its timers apart.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimerId(pub u32);
+
+#[derive(Copy, Clone, Debug)]
+struct Timer {
+    period: Duration,
+    deadline: Instant,
+}
+
 #[derive(Debug)]
 pub struct EventLoop {
     receiver: Arc<RwLock<EventReceiver>>,
     ids: HashSet<WindowId>,
+    minimized: HashSet<WindowId>,
+    auto_throttle: bool,
+    last_synthetic: bool,
+    tasks: Arc<Mutex<LoopTasks>>,
+    timers: HashMap<TimerId, Timer>,
+    hotkeys: hotkeys::OwnedHotkeys,
+    #[cfg(feature = "async")]
+    watcher: Option<Watcher>,
     _no_send_sync: PhantomData<*mut ()>,
 }
 
+// The thread an idle EventStream leaves waiting on the OS, which wakes the
+// stream's task when it's done. It waits for the windows that were bound
+// when it started, at most until `until`.
+#[cfg(feature = "async")]
+#[derive(Debug)]
+struct Watcher {
+    ids: HashSet<WindowId>,
+    until: Option<Instant>,
+    done: Arc<AtomicBool>,
+}
+
 impl Default for EventLoop {
     fn default() -> Self {
         Self::new()
@@ -367,6 +1207,14 @@ impl EventLoop {
         Self {
             receiver: Arc::new(RwLock::new(EventReceiver::new())),
             ids: HashSet::new(),
+            minimized: HashSet::new(),
+            auto_throttle: false,
+            last_synthetic: false,
+            tasks: Arc::new(Mutex::new(LoopTasks::default())),
+            timers: HashMap::new(),
+            hotkeys: hotkeys::OwnedHotkeys::default(),
+            #[cfg(feature = "async")]
+            watcher: None,
             _no_send_sync: Default::default(),
         }
     }
@@ -376,29 +1224,1367 @@ impl EventLoop {
         window.sender().write().unwrap().bind(self.receiver.clone());
     }
 
+    // Same as dropping the loop: every bound window is unbound and the
+    // events still queued are discarded.
+    pub fn shutdown(self) {}
+
+    // Like `hotkeys::register`, but the hotkey is released along with the
+    // loop.
+    pub fn register_hotkey(
+        &mut self,
+        combo: hotkeys::KeyCombo,
+    ) -> Result<hotkeys::HotkeyId, hotkeys::HotkeyError> {
+        self.hotkeys.register(combo)
+    }
+
+    pub fn unregister_hotkey(&mut self, id: hotkeys::HotkeyId) {
+        self.hotkeys.unregister(id)
+    }
+
+    pub fn set_auto_throttle(&mut self, auto_throttle: bool) {
+        self.auto_throttle = auto_throttle;
+    }
+
+    pub fn proxy(&self) -> EventLoopProxy {
+        EventLoopProxy {
+            tasks: self.tasks.clone(),
+            waker: platform::platform_impl::loop_waker(),
+        }
+    }
+
     pub fn next_event(&mut self) -> Option<(WindowId, WindowEvent)> {
-        let events = {
-            let receiver = self.receiver.read().unwrap();
-            receiver.events.clone()
+        self.run_tasks();
+        let empty = self.receiver.read().unwrap().events.is_empty();
+        if empty {
+            self.wait_if_throttled();
+            self.pump();
+        }
+        let (id, ev, synthetic) = self.receiver.write().unwrap().pop()?;
+        self.last_synthetic = synthetic;
+        self.observe(id, &ev);
+        Some((id, ev))
+    }
+
+    // Sends Timer(id) every `period`, replacing any timer with the same id.
+    // Timers fire when the loop pumps the OS, so one that fell behind, e.g.
+    // while the application was busy, fires once and starts its next period
+    // from then instead of catching up.
+    pub fn set_timer(&mut self, id: TimerId, period: Duration) {
+        let period = period.max(Duration::from_millis(1));
+        self.timers.insert(
+            id,
+            Timer {
+                period,
+                deadline: Instant::now() + period,
+            },
+        );
+    }
+
+    // Nothing more is sent for the timer once this returns, so it can be
+    // called while handling the timer's own event.
+    pub fn cancel_timer(&mut self, id: TimerId) {
+        self.timers.remove(&id);
+    }
+
+    // Queues a synthetic event behind everything already received, e.g. a
+    // CursorMoved after scrolling so hover state updates, or test input.
+    pub fn inject(&mut self, id: WindowId, event: WindowEvent) {
+        self.receiver.write().unwrap().push(id, event, true);
+    }
+
+    // Whether the last event returned by next_event was sent through inject
+    // or WindowTExt::send_event rather than by the OS.
+    pub fn is_synthetic(&self) -> bool {
+        self.last_synthetic
+    }
+
+    // When the last user input for any of the loop's windows was received,
+    // for idle detection. Input is counted when it's queued, so a consumer
+    // that falls behind doesn't make the user look idle.
+    pub fn last_interaction(&self) -> Option<Instant> {
+        self.receiver.read().unwrap().last_interaction
+    }
+
+    // What has been received but not yet returned, without pumping the OS.
+    // These are snapshots: windows on other threads can queue more as soon
+    // as they return. Resizes held back by a ResizePolicy aren't counted
+    // until they're released.
+    pub fn pending_len(&self) -> usize {
+        self.receiver.read().unwrap().events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.receiver.read().unwrap().events.is_empty()
+    }
+
+    pub fn has_events_for(&self, id: WindowId) -> bool {
+        self.receiver
+            .read()
+            .unwrap()
+            .events
+            .iter()
+            .any(|(ev_id, _)| *ev_id == id)
+    }
+
+    // The stream and `wait` pump the OS queues from `poll`, so they must be
+    // driven on the thread that owns the loop.
+    #[cfg(feature = "async")]
+    pub fn stream(&mut self) -> EventStream<'_> {
+        EventStream { event_loop: self }
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn wait(&mut self) -> (WindowId, WindowEvent) {
+        std::future::poll_fn(|cx| self.poll_event(cx)).await
+    }
+
+    #[cfg(feature = "async")]
+    fn poll_event(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<(WindowId, WindowEvent)> {
+        if let Some(ev) = self.next_event() {
+            return std::task::Poll::Ready(ev);
+        }
+        // Events and tasks queued from other threads wake the task through
+        // these; the watcher covers the OS and the timers.
+        self.receiver.write().unwrap().waker = Some(cx.waker().clone());
+        self.tasks.lock().unwrap().waker = Some(cx.waker().clone());
+        // Either may have been queued before the wakers were in place.
+        if !self.is_empty() || !self.tasks.lock().unwrap().queue.is_empty() {
+            cx.waker().wake_by_ref();
+        } else {
+            self.watch();
+        }
+        std::task::Poll::Pending
+    }
+
+    // Starts a watcher unless the one already running is waiting for the
+    // same windows and gives up no later than needed.
+    #[cfg(feature = "async")]
+    fn watch(&mut self) {
+        let timeout = self.next_timer();
+        let until = timeout.map(|t| Instant::now() + t);
+        if let Some(watcher) = &self.watcher {
+            if !watcher.done.load(Ordering::Acquire)
+                && watcher.ids == self.ids
+                && until.is_none_or(|until| watcher.until.is_some_and(|u| u <= until))
+            {
+                return;
+            }
+        }
+        self.watcher = None;
+        let Some(wait) = platform::platform_impl::event_waiter(&self.ids, timeout) else {
+            return;
         };
-        if events.is_empty() {
-            for id in self.ids.clone() {
-                id.next_event();
+        let done = Arc::new(AtomicBool::new(false));
+        let tasks = self.tasks.clone();
+        let finished = done.clone();
+        std::thread::spawn(move || {
+            wait();
+            finished.store(true, Ordering::Release);
+            let waker = tasks.lock().unwrap().waker.take();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        });
+        self.watcher = Some(Watcher {
+            ids: self.ids.clone(),
+            until,
+            done,
+        });
+    }
+
+    pub fn poll_batch(&mut self) -> EventBatch {
+        self.run_tasks();
+        if self.receiver.read().unwrap().events.is_empty() {
+            self.wait_if_throttled();
+        }
+        self.pump();
+        let events = self.events();
+        for (id, ev) in events.iter() {
+            self.observe(*id, ev);
+        }
+        EventBatch { events }
+    }
+
+    // Only runs what was queued before it started, so a closure that queues
+    // another doesn't keep the loop here.
+    fn run_tasks(&mut self) {
+        let count = self.tasks.lock().unwrap().queue.len();
+        for _ in 0..count {
+            let Some(task) = self.tasks.lock().unwrap().queue.pop_front() else {
+                break;
+            };
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(task)) {
+                self.receiver.write().unwrap().recv(
+                    WindowId::default(),
+                    WindowEvent::UnrecoverableError(panic_message(&*payload)),
+                );
+            }
+        }
+    }
+
+    // While every bound window is minimized there's nothing to draw, so block
+    // until the OS has input for one of them instead of spinning.
+    fn wait_if_throttled(&self) {
+        if self.throttled() {
+            platform::platform_impl::wait_for_events(&self.ids, self.next_timer());
+        }
+    }
+
+    fn throttled(&self) -> bool {
+        self.auto_throttle && !self.ids.is_empty() && self.ids.is_subset(&self.minimized)
+    }
+
+    // How long until the nearest timer is due, so a wait for the OS wakes up
+    // in time to fire it even if nothing else arrives.
+    fn next_timer(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.timers
+            .values()
+            .map(|t| t.deadline.saturating_duration_since(now))
+            .min()
+    }
+
+    fn fire_timers(&mut self) {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for (id, timer) in self.timers.iter_mut() {
+            if timer.deadline > now {
+                continue;
             }
+            timer.deadline += timer.period;
+            if timer.deadline <= now {
+                timer.deadline = now + timer.period;
+            }
+            due.push(*id);
         }
+        due.sort();
         let mut receiver = self.receiver.write().unwrap();
-        receiver.events.pop_front()
+        for id in due {
+            receiver.recv(WindowId::default(), WindowEvent::Timer(id));
+        }
+    }
+
+    fn observe(&mut self, id: WindowId, ev: &WindowEvent) {
+        match ev {
+            WindowEvent::SizeStateChanged(WindowSizeState::Minimized) => {
+                self.minimized.insert(id);
+            }
+            WindowEvent::SizeStateChanged(_) => {
+                self.minimized.remove(&id);
+            }
+            WindowEvent::Destroyed => {
+                self.minimized.remove(&id);
+                self.ids.remove(&id);
+            }
+            _ => {}
+        }
+    }
+
+    fn pump(&mut self) {
+        platform::platform_impl::pump_events();
+        for id in self.ids.iter() {
+            id.next_event();
+            if let Some(sender) = id.sender() {
+                sender.write().unwrap().flush_resize(*id);
+            }
+        }
+        // Hotkeys aren't tied to a window, so they're reported against the
+        // default id.
+        while let Some(hotkey) = hotkeys::next_hotkey() {
+            self.receiver
+                .write()
+                .unwrap()
+                .recv(WindowId::default(), WindowEvent::GlobalHotkey(hotkey));
+        }
+        self.fire_timers();
     }
 
     pub(crate) fn events(&mut self) -> VecDeque<(WindowId, WindowEvent)> {
-        let evs = self.receiver.write().unwrap().events.clone();
-        self.receiver.write().unwrap().events.clear();
-        evs
+        self.last_synthetic = false;
+        self.receiver.write().unwrap().take()
+    }
+}
+
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct EventStream<'a> {
+    event_loop: &'a mut EventLoop,
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for EventStream<'_> {
+    type Item = (WindowId, WindowEvent);
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.event_loop.poll_event(cx).map(Some)
+    }
+}
+
+impl Drop for EventLoop {
+    fn drop(&mut self) {
+        for id in self.ids.drain() {
+            if let Some(sender) = id.sender() {
+                sender.write().unwrap().unbind(&self.receiver);
+            }
+        }
+        let mut receiver = self.receiver.write().unwrap();
+        receiver.take();
+        receiver.closed = true;
+        drop(receiver);
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.closed = true;
+        tasks.queue.clear();
+    }
+}
+
+type LoopTask = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct LoopTasks {
+    queue: VecDeque<LoopTask>,
+    // Set when the loop is dropped.
+    closed: bool,
+    // Like EventReceiver's, for an EventStream waiting on a task.
+    waker: Option<Waker>,
+}
+
+impl fmt::Debug for LoopTasks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoopTasks")
+            .field("queued", &self.queue.len())
+            .field("closed", &self.closed)
+            .finish()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EventLoopClosed;
+
+// Runs code on the loop's thread from anywhere, e.g. to create a window
+// there. The closures run in the order they were queued, from next_event or
+// poll_batch before any event is returned, and never from inside the OS
+// pump. Queuing one wakes a loop blocked by auto-throttle.
+#[derive(Clone, Debug)]
+pub struct EventLoopProxy {
+    tasks: Arc<Mutex<LoopTasks>>,
+    waker: platform::platform_impl::LoopWaker,
+}
+
+impl EventLoopProxy {
+    pub fn run_on_loop(&self, f: impl FnOnce() + Send + 'static) -> Result<(), EventLoopClosed> {
+        let mut tasks = self.tasks.lock().unwrap();
+        if tasks.closed {
+            return Err(EventLoopClosed);
+        }
+        tasks.queue.push_back(Box::new(f));
+        let waker = tasks.waker.take();
+        drop(tasks);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        self.waker.wake();
+        Ok(())
+    }
+
+    pub fn call_on_loop<T: Send + 'static>(
+        &self,
+        f: impl FnOnce() -> T + Send + 'static,
+    ) -> Result<LoopCall<T>, EventLoopClosed> {
+        let (tx, rx) = mpsc::channel();
+        self.run_on_loop(move || {
+            let _ = tx.send(f());
+        })?;
+        Ok(LoopCall { rx })
+    }
+}
+
+// The result of EventLoopProxy::call_on_loop.
+#[derive(Debug)]
+pub struct LoopCall<T> {
+    rx: mpsc::Receiver<T>,
+}
+
+impl<T> LoopCall<T> {
+    // Blocks until the closure has run, so it must not be called on the
+    // loop's own thread. Fails if the loop is dropped first or the closure
+    // panics.
+    pub fn wait(self) -> Result<T, EventLoopClosed> {
+        self.rx.recv().map_err(|_| EventLoopClosed)
+    }
+
+    pub fn try_take(&self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventBatch {
+    events: VecDeque<(WindowId, WindowEvent)>,
+}
+
+impl EventBatch {
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(WindowId, WindowEvent)> {
+        self.events.iter()
+    }
+
+    pub fn for_window(&self, id: WindowId) -> impl DoubleEndedIterator<Item = &WindowEvent> {
+        self.events
+            .iter()
+            .filter(move |(i, _)| *i == id)
+            .map(|(_, ev)| ev)
+    }
+
+    pub fn keyboard(&self) -> impl Iterator<Item = &(WindowId, WindowEvent)> {
+        self.events.iter().filter(|(_, ev)| {
+            matches!(
+                ev,
+                WindowEvent::KeyDown { .. }
+                    | WindowEvent::KeyUp { .. }
+                    | WindowEvent::ModifiersChanged(_)
+            )
+        })
+    }
+
+    pub fn last_cursor_position(&self, id: WindowId) -> Option<PhysicalPosition<f64>> {
+        self.for_window(id).rev().find_map(|ev| match *ev {
+            WindowEvent::CursorMoved(position) => Some(position),
+            _ => None,
+        })
+    }
+}
+
+impl IntoIterator for EventBatch {
+    type Item = (WindowId, WindowEvent);
+    type IntoIter = std::collections::vec_deque::IntoIter<(WindowId, WindowEvent)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.events.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a EventBatch {
+    type Item = &'a (WindowId, WindowEvent);
+    type IntoIter = std::collections::vec_deque::Iter<'a, (WindowId, WindowEvent)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.events.iter()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum WindowKind {
+    #[default]
+    Normal,
+    Popup,
+    Tooltip,
+    Splash,
+}
+
+// Everything set here is applied before the window is first shown, so it
+// never appears at its default size or place.
+#[derive(Clone, Debug)]
+pub struct WindowBuilder {
+    pub(crate) kind: WindowKind,
+    pub(crate) background_color: Option<(u8, u8, u8)>,
+    pub(crate) title: Option<String>,
+    pub(crate) inner_size: Option<PhysicalSize<u32>>,
+    pub(crate) position: Option<PhysicalPosition<i32>>,
+    pub(crate) visible: bool,
+    pub(crate) resizeable: bool,
+    pub(crate) min_size: Option<(u32, u32)>,
+    pub(crate) max_size: Option<(u32, u32)>,
+    pub(crate) platform: platform::platform_impl::PlatformAttributes,
+}
+
+impl Default for WindowBuilder {
+    fn default() -> Self {
+        Self {
+            kind: WindowKind::default(),
+            background_color: None,
+            title: None,
+            inner_size: None,
+            position: None,
+            visible: false,
+            resizeable: true,
+            min_size: None,
+            max_size: None,
+            platform: Default::default(),
+        }
+    }
+}
+
+impl WindowBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    // Clamped to the size limits. The platform picks a size otherwise.
+    pub fn with_inner_size(mut self, size: PhysicalSize<u32>) -> Self {
+        self.inner_size = Some(size);
+        self
+    }
+
+    // Where the outer corner of the window goes, frame included. The window
+    // manager may still place it elsewhere.
+    pub fn with_position(mut self, position: PhysicalPosition<i32>) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    // Windows start hidden unless this is set.
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn with_resizeable(mut self, resizeable: bool) -> Self {
+        self.resizeable = resizeable;
+        self
+    }
+
+    pub fn with_min_size(mut self, width: u32, height: u32) -> Self {
+        self.min_size = Some((width, height));
+        self
+    }
+
+    pub fn with_max_size(mut self, width: u32, height: u32) -> Self {
+        self.max_size = Some((width, height));
+        self
+    }
+
+    pub fn with_kind(mut self, kind: WindowKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    // Filled in by the system before the application's first frame, so a
+    // dark window doesn't flash white while the renderer starts up.
+    pub fn with_background_color(mut self, color: Option<(u8, u8, u8)>) -> Self {
+        self.background_color = color;
+        self
+    }
+
+    pub fn build(
+        self,
+    ) -> Result<platform::platform_impl::Window, platform::platform_impl::OsError> {
+        platform::platform_impl::Window::from_builder(self)
     }
 }
 
 cfg_if::cfg_if! {
     if #[cfg(windows)] {
         pub use platform::win32::Window;
+    } else if #[cfg(all(unix, feature = "x11"))] {
+        pub use platform::xlib::Window;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_ids_display_as_tagged_hex() {
+        let kind = platform::platform_impl::WINDOW_ID_KIND;
+        assert_eq!(WindowId(0x3c00007).to_string(), format!("{kind} 0x3c00007"));
+        assert_eq!(WindowId(0).to_string(), format!("{kind} 0x0"));
+        assert_eq!(
+            WindowId(u64::MAX).to_string(),
+            format!("{kind} 0xffffffffffffffff")
+        );
+    }
+
+    #[test]
+    fn frame_interval_falls_back_to_60hz() {
+        assert_eq!(frame_interval(None), Duration::from_secs(1) / 60);
+        assert_eq!(frame_interval(Some(0)), Duration::from_secs(1) / 60);
+        assert_eq!(frame_interval(Some(144_000)), Duration::from_secs(1) / 144);
+    }
+
+    #[test]
+    fn redraw_requests_coalesce() {
+        let mut redraw = RedrawScheduler::default();
+        let now = Instant::now();
+        assert!(!redraw.poll(now));
+        redraw.request();
+        redraw.request();
+        assert!(redraw.poll(now));
+        assert!(!redraw.poll(now));
+    }
+
+    #[test]
+    fn continuous_redraws_skip_missed_frames() {
+        let interval = Duration::from_millis(10);
+        let mut redraw = RedrawScheduler::default();
+        redraw.set_interval(Some(interval));
+        assert!(redraw.continuous());
+        let start = redraw.next.unwrap();
+        assert!(redraw.poll(start));
+        assert!(!redraw.poll(start + interval / 2));
+        // Five frames late still makes one redraw, and the next is due on
+        // the following boundary.
+        assert!(redraw.poll(start + interval * 5 + interval / 2));
+        assert!(!redraw.poll(start + interval * 5 + interval * 3 / 4));
+        assert!(redraw.poll(start + interval * 6));
+
+        redraw.set_interval(None);
+        assert!(!redraw.continuous());
+        assert!(!redraw.poll(start + interval * 100));
+    }
+
+    #[test]
+    fn fullscreen_toggles_through_windowed() {
+        use FullscreenType::{Borderless, Exclusive, NotFullscreen};
+        let cases = [
+            (NotFullscreen, Borderless, Borderless),
+            (NotFullscreen, Exclusive, Exclusive),
+            (NotFullscreen, NotFullscreen, NotFullscreen),
+            (Borderless, Borderless, NotFullscreen),
+            (Borderless, Exclusive, NotFullscreen),
+            (Exclusive, Borderless, NotFullscreen),
+            (Exclusive, Exclusive, NotFullscreen),
+        ];
+        for (current, kind, expected) in cases {
+            assert_eq!(
+                toggled_fullscreen(current, kind),
+                expected,
+                "{current:?} {kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn size_limits_let_the_last_call_win() {
+        // (min, max, new bound) -> (min, max) after limit_min / limit_max.
+        let cases = [
+            ((100, 800), 400, (400, 800), (100, 400)),
+            // A new minimum above the maximum drags it up, and a new maximum
+            // below the minimum drags that down.
+            ((100, 400), 800, (800, 800), (100, 800)),
+            ((800, 1000), 400, (400, 1000), (400, 400)),
+            // Zero is raised to 1 either way.
+            ((100, 800), 0, (1, 800), (1, 1)),
+            ((0, 0), 0, (1, 1), (0, 1)),
+            ((1, u32::MAX), u32::MAX, (u32::MAX, u32::MAX), (1, u32::MAX)),
+        ];
+        for ((min, max), bound, after_min, after_max) in cases {
+            assert_eq!(
+                limit_min(bound, max),
+                after_min,
+                "min {bound} on {min}..{max}"
+            );
+            assert_eq!(
+                limit_max(min, bound),
+                after_max,
+                "max {bound} on {min}..{max}"
+            );
+        }
+    }
+
+    #[test]
+    fn size_limits_stay_ordered() {
+        let mut limits = (1, u32::MAX);
+        for (is_min, bound) in [(true, 800), (false, 400), (true, 0), (false, 0), (true, 50)] {
+            limits = if is_min {
+                limit_min(bound, limits.1)
+            } else {
+                limit_max(limits.0, bound)
+            };
+            assert!(0 < limits.0 && limits.0 <= limits.1, "{limits:?}");
+            // A requested width always fits once clamped into the range.
+            for width in [0, limits.0, limits.1, u32::MAX] {
+                let width = width.clamp(limits.0, limits.1);
+                assert!((limits.0..=limits.1).contains(&width));
+            }
+        }
+        assert_eq!(limits, (50, 50));
+    }
+
+    #[test]
+    fn scaled_size_limits_follow_the_same_rules() {
+        // Logical limits scaled for the monitor, e.g. 150%, and set in the
+        // wrong order: the maximum wins, and a tiny one still leaves a pixel.
+        for scale in [1.0, 1.25, 1.5, 1.75, 2.0, 3.0] {
+            let min = dpi::LogicalSize::new(300.0, 200.0).to_physical::<u32>(scale);
+            let max = dpi::LogicalSize::new(200.0, 0.2).to_physical::<u32>(scale);
+            assert_eq!(limit_max(min.width, max.width), (max.width, max.width));
+            let (min_h, max_h) = limit_max(min.height, max.height);
+            assert_eq!(
+                (min_h, max_h),
+                (max.height.max(1), max.height.max(1)),
+                "{scale}"
+            );
+        }
+    }
+
+    #[test]
+    fn maximize_is_hidden_for_fixed_size_windows() {
+        let all = WindowButtons::all();
+        let no_max = all - WindowButtons::MAXIMIZE;
+        // (buttons asked for, resizeable, min, max) -> buttons shown
+        let cases = [
+            (all, true, (1, 1), (800, 600), all),
+            (all, false, (1, 1), (800, 600), no_max),
+            (all, true, (400, 300), (400, 300), no_max),
+            (all, false, (400, 300), (400, 300), no_max),
+            // Only both dimensions fixed makes the size fixed.
+            (all, true, (400, 1), (400, 600), all),
+            (all, true, (1, 300), (800, 300), all),
+            // Buttons turned off explicitly stay off once resizing is
+            // possible again, and the others are left alone.
+            (no_max, true, (1, 1), (800, 600), no_max),
+            (
+                WindowButtons::CLOSE,
+                true,
+                (1, 1),
+                (800, 600),
+                WindowButtons::CLOSE,
+            ),
+            (
+                WindowButtons::CLOSE,
+                false,
+                (1, 1),
+                (800, 600),
+                WindowButtons::CLOSE,
+            ),
+            (
+                WindowButtons::MAXIMIZE,
+                false,
+                (1, 1),
+                (1, 1),
+                WindowButtons::empty(),
+            ),
+            (
+                WindowButtons::empty(),
+                true,
+                (1, 1),
+                (800, 600),
+                WindowButtons::empty(),
+            ),
+        ];
+        for (buttons, resizeable, min, max, shown) in cases {
+            assert_eq!(
+                effective_buttons(buttons, resizeable, min, max),
+                shown,
+                "{buttons:?} resizeable: {resizeable} {min:?}..{max:?}"
+            );
+        }
+    }
+
+    fn resizes(sender: &mut EventSender) -> Vec<u32> {
+        sender
+            .take_queued()
+            .into_iter()
+            .map(|ev| match ev {
+                WindowEvent::Resized(size) => size.width,
+                ev => panic!("{ev:?}"),
+            })
+            .collect()
+    }
+
+    fn drag(sender: &mut EventSender, widths: std::ops::Range<u32>) {
+        for width in widths {
+            sender.send(
+                WindowId::default(),
+                WindowEvent::Resized(PhysicalSize::new(width, 100)),
+            );
+        }
+    }
+
+    #[test]
+    fn live_resizes_are_all_sent() {
+        let mut sender = EventSender::new();
+        sender.begin_size_move();
+        drag(&mut sender, 0..5);
+        sender.end_size_move(WindowId::default());
+        assert_eq!(resizes(&mut sender), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn resizes_on_release_collapse_into_the_last() {
+        let mut sender = EventSender::new();
+        sender.resize_policy = ResizePolicy::OnRelease;
+        sender.begin_size_move();
+        drag(&mut sender, 0..5);
+        sender.flush_resize(WindowId::default());
+        assert_eq!(resizes(&mut sender), []);
+        // Where the platform doesn't report the end of the drag, the sizes
+        // having stopped stands in for it.
+        sender.resize_received = Some(Instant::now() - RESIZE_SETTLE);
+        sender.end_size_move(WindowId::default());
+        assert_eq!(resizes(&mut sender), [4]);
+        sender.flush_resize(WindowId::default());
+        assert_eq!(resizes(&mut sender), []);
+    }
+
+    #[test]
+    fn throttled_resizes_collapse_per_interval() {
+        let mut sender = EventSender::new();
+        sender.resize_policy = ResizePolicy::Throttled(Duration::from_secs(3600));
+        drag(&mut sender, 0..5);
+        sender.flush_resize(WindowId::default());
+        assert_eq!(resizes(&mut sender), [0]);
+        // Once the interval is up, as if nothing had been sent yet, only
+        // the latest size is.
+        sender.resize_sent = None;
+        sender.flush_resize(WindowId::default());
+        assert_eq!(resizes(&mut sender), [4]);
+        drag(&mut sender, 5..7);
+        assert_eq!(resizes(&mut sender), []);
+    }
+
+    #[test]
+    fn synthetic_resizes_are_never_held() {
+        let mut sender = EventSender::new();
+        sender.resize_policy = ResizePolicy::OnRelease;
+        sender.begin_size_move();
+        for width in 0..3 {
+            sender.send_synthetic(
+                WindowId::default(),
+                WindowEvent::Resized(PhysicalSize::new(width, 100)),
+            );
+        }
+        assert_eq!(resizes(&mut sender), [0, 1, 2]);
+    }
+
+    fn cursor(x: f64) -> WindowEvent {
+        WindowEvent::CursorMoved(PhysicalPosition::new(x, x))
+    }
+
+    #[test]
+    fn batches_keep_the_order_events_arrived_in() {
+        let (a, b) = (WindowId(1), WindowId(2));
+        let mut event_loop = EventLoop::new();
+        let mut sender = EventSender::with_receiver(event_loop.receiver.clone());
+        let key = WindowEvent::KeyUp {
+            logical_scancode: KeyboardScancode::S,
+            physical_scancode: None,
+        };
+        let modifiers = WindowEvent::ModifiersChanged(Modifiers::LCTRL);
+        sender.send(a, key.clone());
+        sender.send(b, cursor(5.0));
+        sender.send(a, cursor(1.0));
+        sender.send(a, modifiers.clone());
+        sender.send(a, cursor(2.0));
+
+        let batch = event_loop.poll_batch();
+        assert_eq!(batch.len(), 5);
+        assert_eq!(
+            batch.iter().cloned().collect::<Vec<_>>(),
+            [
+                (a, key.clone()),
+                (b, cursor(5.0)),
+                (a, cursor(1.0)),
+                (a, modifiers.clone()),
+                (a, cursor(2.0)),
+            ]
+        );
+        assert_eq!(
+            batch.for_window(a).cloned().collect::<Vec<_>>(),
+            [key.clone(), cursor(1.0), modifiers.clone(), cursor(2.0)]
+        );
+        assert_eq!(
+            batch.keyboard().cloned().collect::<Vec<_>>(),
+            [(a, key), (a, modifiers)]
+        );
+        assert_eq!(
+            batch.last_cursor_position(a),
+            Some(PhysicalPosition::new(2.0, 2.0))
+        );
+        assert_eq!(
+            batch.last_cursor_position(b),
+            Some(PhysicalPosition::new(5.0, 5.0))
+        );
+        assert_eq!(batch.last_cursor_position(WindowId(3)), None);
+    }
+
+    #[test]
+    fn events_sent_while_handling_a_batch_go_in_the_next() {
+        let id = WindowId(1);
+        let mut event_loop = EventLoop::new();
+        let sender = Arc::new(RwLock::new(EventSender::with_receiver(
+            event_loop.receiver.clone(),
+        )));
+        assert!(event_loop.poll_batch().is_empty());
+
+        sender.write().unwrap().send(id, cursor(1.0));
+        let proxy = event_loop.proxy();
+        let mut handled = 0;
+        for (id, ev) in event_loop.poll_batch() {
+            handled += 1;
+            assert_eq!(ev, cursor(1.0));
+            sender.write().unwrap().send(id, cursor(2.0));
+            let sender = sender.clone();
+            proxy
+                .run_on_loop(move || sender.write().unwrap().send(id, cursor(3.0)))
+                .unwrap();
+        }
+        assert_eq!(handled, 1);
+        // Tasks run before the queue is taken, so what they send is in the
+        // same batch, after what was already waiting.
+        assert_eq!(
+            event_loop.poll_batch().into_iter().collect::<Vec<_>>(),
+            [(id, cursor(2.0)), (id, cursor(3.0))]
+        );
+        assert!(event_loop.poll_batch().is_empty());
+    }
+
+    #[test]
+    fn nothing_is_delivered_after_destroyed() {
+        let (a, b) = (WindowId(1), WindowId(2));
+        let mut event_loop = EventLoop::new();
+        let mut sender_a = EventSender::with_receiver(event_loop.receiver.clone());
+        let mut sender_b = EventSender::with_receiver(event_loop.receiver.clone());
+        sender_a.send(a, WindowEvent::CloseRequested);
+        // Both the OS and the dropped handle report the destruction.
+        sender_a.send(a, WindowEvent::Destroyed);
+        sender_a.send(a, WindowEvent::Destroyed);
+        sender_a.send(a, cursor(1.0));
+        sender_a.send_synthetic(a, WindowEvent::RedrawRequested);
+        sender_b.send(b, cursor(2.0));
+
+        assert_eq!(
+            event_loop.poll_batch().into_iter().collect::<Vec<_>>(),
+            [
+                (a, WindowEvent::CloseRequested),
+                (a, WindowEvent::Destroyed),
+                (b, cursor(2.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn windows_are_left_open_by_default() {
+        assert_eq!(CloseAction::default(), CloseAction::Nothing);
+    }
+
+    #[test]
+    fn closing_a_window_that_is_gone_does_nothing() {
+        // The handle can be dropped between CloseRequested and the action,
+        // e.g. by another thread.
+        apply_close_action(WindowId(1));
+    }
+
+    #[test]
+    fn injected_events_interleave_in_arrival_order() {
+        let id = WindowId(1);
+        let mut event_loop = EventLoop::new();
+        let mut sender = EventSender::with_receiver(event_loop.receiver.clone());
+        sender.send(id, cursor(1.0));
+        event_loop.inject(id, cursor(2.0));
+        sender.send(id, cursor(3.0));
+        sender.send_synthetic(id, cursor(4.0));
+        event_loop.inject(WindowId(2), cursor(5.0));
+
+        let mut seen = Vec::new();
+        while let Some((id, ev)) = event_loop.next_event() {
+            seen.push((id, ev, event_loop.is_synthetic()));
+        }
+        assert_eq!(
+            seen,
+            [
+                (id, cursor(1.0), false),
+                (id, cursor(2.0), true),
+                (id, cursor(3.0), false),
+                (id, cursor(4.0), true),
+                (WindowId(2), cursor(5.0), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn synthetic_events_queued_before_binding_keep_their_marking() {
+        let id = WindowId(1);
+        let mut event_loop = EventLoop::new();
+        let mut sender = EventSender::new();
+        sender.send_synthetic(id, cursor(1.0));
+        sender.send(id, cursor(2.0));
+        sender.bind(event_loop.receiver.clone());
+        sender.send_synthetic(id, cursor(3.0));
+
+        let mut seen = Vec::new();
+        while let Some((_, ev)) = event_loop.next_event() {
+            seen.push((ev, event_loop.is_synthetic()));
+        }
+        assert_eq!(
+            seen,
+            [
+                (cursor(1.0), true),
+                (cursor(2.0), false),
+                (cursor(3.0), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn auto_throttle_follows_the_size_states() {
+        let (a, b) = (WindowId(1), WindowId(2));
+        let mut event_loop = EventLoop::new();
+        event_loop.set_auto_throttle(true);
+        // Nothing bound is never "all minimized".
+        assert!(!event_loop.throttled());
+        event_loop.ids.extend([a, b]);
+        let minimized = WindowEvent::SizeStateChanged(WindowSizeState::Minimized);
+        let restored = WindowEvent::SizeStateChanged(WindowSizeState::Other);
+
+        event_loop.observe(a, &minimized);
+        assert!(!event_loop.throttled());
+        event_loop.observe(b, &minimized);
+        assert!(event_loop.throttled());
+        event_loop.set_auto_throttle(false);
+        assert!(!event_loop.throttled());
+        event_loop.set_auto_throttle(true);
+        assert!(event_loop.throttled());
+
+        event_loop.observe(a, &restored);
+        assert!(!event_loop.throttled());
+        // Once the restored window is gone, the minimized one is all that's
+        // left.
+        event_loop.observe(a, &WindowEvent::Destroyed);
+        assert!(event_loop.throttled());
+        event_loop.observe(
+            b,
+            &WindowEvent::SizeStateChanged(WindowSizeState::Maximized),
+        );
+        assert!(!event_loop.throttled());
+    }
+
+    // The windows are made up, so only the timer can end the wait.
+    #[test]
+    fn a_throttled_loop_waits_instead_of_spinning() {
+        let id = WindowId(1);
+        let mut event_loop = EventLoop::new();
+        event_loop.set_auto_throttle(true);
+        event_loop.ids.insert(id);
+        event_loop.observe(
+            id,
+            &WindowEvent::SizeStateChanged(WindowSizeState::Minimized),
+        );
+        event_loop.set_timer(TimerId(0), Duration::from_millis(20));
+
+        let start = Instant::now();
+        let mut calls = 0;
+        while start.elapsed() < Duration::from_millis(100) {
+            calls += 1;
+            if let Some((_, ev)) = event_loop.next_event() {
+                assert_eq!(ev, WindowEvent::Timer(TimerId(0)));
+            }
+        }
+        // About one wait per timer; a spinning loop makes millions.
+        assert!(calls < 30, "{calls} calls");
+
+        event_loop.observe(id, &WindowEvent::SizeStateChanged(WindowSizeState::Other));
+        let start = Instant::now();
+        let mut calls = 0;
+        while start.elapsed() < Duration::from_millis(10) {
+            calls += 1;
+            event_loop.next_event();
+        }
+        assert!(calls > 10, "{calls} calls");
+    }
+
+    fn monitor(id: &str, refresh_rate_millihertz: Option<u32>) -> Monitor {
+        Monitor {
+            id: MonitorId(id.to_owned()),
+            name: id.to_owned(),
+            position: PhysicalPosition::new(0, 0),
+            size: PhysicalSize::new(1920, 1080),
+            refresh_rate_millihertz,
+            primary: false,
+        }
+    }
+
+    #[test]
+    fn monitor_changes_are_reported_once() {
+        let mut tracker = MonitorTracker::new(vec![monitor("a", Some(60_000))]);
+        assert_eq!(tracker.update(vec![monitor("a", Some(60_000))]), vec![]);
+
+        // A new mode on the same monitor isn't a reconnect.
+        assert_eq!(tracker.update(vec![monitor("a", Some(144_000))]), vec![]);
+
+        let b = monitor("b", None);
+        assert_eq!(
+            tracker.update(vec![monitor("a", Some(144_000)), b.clone()]),
+            vec![WindowEvent::MonitorConnected(b.clone())]
+        );
+        // Disconnects come first.
+        let c = monitor("c", None);
+        assert_eq!(
+            tracker.update(vec![c.clone(), b]),
+            vec![
+                WindowEvent::MonitorDisconnected(MonitorId("a".to_owned())),
+                WindowEvent::MonitorConnected(c),
+            ]
+        );
+        assert_eq!(
+            tracker.update(vec![]),
+            vec![
+                WindowEvent::MonitorDisconnected(MonitorId("c".to_owned())),
+                WindowEvent::MonitorDisconnected(MonitorId("b".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn refresh_rates_are_reported_on_change() {
+        let mut tracker = RefreshRateTracker::default();
+        assert_eq!(tracker.last(), None);
+        assert_eq!(tracker.update(None), None);
+        assert_eq!(tracker.update(Some(60_000)), Some(60_000));
+        assert_eq!(tracker.update(Some(60_000)), None);
+        // An unknown rate, e.g. while between monitors, keeps the last one.
+        assert_eq!(tracker.update(None), None);
+        assert_eq!(tracker.last(), Some(60_000));
+        assert_eq!(tracker.update(Some(144_000)), Some(144_000));
+        assert_eq!(tracker.last(), Some(144_000));
+    }
+
+    struct Flag(AtomicBool);
+
+    impl std::task::Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn flag_waker() -> (Arc<Flag>, Waker) {
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        (flag.clone(), Waker::from(flag))
+    }
+
+    #[test]
+    fn queued_events_wake_a_waiting_stream() {
+        let (flag, waker) = flag_waker();
+        let mut receiver = EventReceiver::new();
+        receiver.waker = Some(waker);
+        receiver.recv(WindowId::default(), WindowEvent::SystemSettingsChanged);
+        assert!(flag.0.load(Ordering::Relaxed));
+        assert!(receiver.waker.is_none());
+    }
+
+    #[test]
+    fn queued_tasks_wake_a_waiting_stream() {
+        let (flag, waker) = flag_waker();
+        let event_loop = EventLoop::new();
+        event_loop.tasks.lock().unwrap().waker = Some(waker);
+        event_loop.proxy().run_on_loop(|| {}).unwrap();
+        assert!(flag.0.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn tasks_run_in_the_order_they_were_queued() {
+        let mut event_loop = EventLoop::new();
+        let proxy = event_loop.proxy();
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..3 {
+            let ran = ran.clone();
+            proxy
+                .run_on_loop(move || ran.lock().unwrap().push(i))
+                .unwrap();
+        }
+        // Queued from a task, so it waits for the next pass.
+        let (again, proxy2) = (ran.clone(), proxy.clone());
+        proxy
+            .run_on_loop(move || {
+                proxy2
+                    .run_on_loop(move || again.lock().unwrap().push(4))
+                    .unwrap();
+            })
+            .unwrap();
+        event_loop.run_tasks();
+        assert_eq!(*ran.lock().unwrap(), [0, 1, 2]);
+        event_loop.run_tasks();
+        assert_eq!(*ran.lock().unwrap(), [0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn call_on_loop_hands_back_the_result() {
+        let mut event_loop = EventLoop::new();
+        let call = event_loop.proxy().call_on_loop(|| 6 * 7).unwrap();
+        assert_eq!(call.try_take(), None);
+        event_loop.run_tasks();
+        assert_eq!(call.try_take(), Some(42));
+    }
+
+    #[test]
+    fn a_panicking_task_is_reported_and_the_rest_still_run() {
+        let mut event_loop = EventLoop::new();
+        let proxy = event_loop.proxy();
+        let call = proxy.call_on_loop(|| -> u32 { panic!("boom") }).unwrap();
+        let after = proxy.call_on_loop(|| 1).unwrap();
+        event_loop.run_tasks();
+        assert_eq!(call.wait(), Err(EventLoopClosed));
+        assert_eq!(after.try_take(), Some(1));
+        assert_eq!(
+            event_loop.receiver.write().unwrap().take(),
+            [(
+                WindowId::default(),
+                WindowEvent::UnrecoverableError("boom".to_owned())
+            )]
+        );
+    }
+
+    #[test]
+    fn wheel_scrolls_are_scaled_by_the_line_setting() {
+        let scroll = |notches, lines| match wheel_scroll(notches, lines) {
+            WindowEvent::MouseWheelScroll { raw_delta, scaled } => (raw_delta, scaled),
+            ev => panic!("{ev:?}"),
+        };
+        assert_eq!(scroll(1.0, 3), (1.0, 3.0));
+        assert_eq!(scroll(-2.0, 3), (-2.0, -6.0));
+        // High-resolution wheels move by fractions of a notch.
+        assert_eq!(scroll(0.25, 4), (0.25, 1.0));
+        // Scrolling turned off still reports the raw movement.
+        assert_eq!(scroll(1.0, 0), (1.0, 0.0));
+    }
+
+    #[test]
+    fn only_user_input_counts_as_interaction() {
+        let (a, b) = (WindowId(1), WindowId(2));
+        let mut event_loop = EventLoop::new();
+        let mut sender_a = EventSender::with_receiver(event_loop.receiver.clone());
+        let mut sender_b = EventSender::with_receiver(event_loop.receiver.clone());
+        sender_a.send(a, WindowEvent::Resized(PhysicalSize::new(640, 480)));
+        sender_a.send(a, WindowEvent::Focused(true));
+        assert_eq!(sender_a.last_interaction, None);
+        assert_eq!(event_loop.last_interaction(), None);
+
+        let before = Instant::now();
+        sender_a.send(a, cursor(1.0));
+        let after = Instant::now();
+        let last = sender_a.last_interaction.unwrap();
+        assert!(before <= last && last <= after);
+        let last_loop = event_loop.last_interaction().unwrap();
+        assert!(before <= last_loop && last_loop <= after);
+        assert_eq!(sender_b.last_interaction, None);
+
+        sender_b.send(b, wheel_scroll(1.0, 3));
+        assert!(sender_b.last_interaction.unwrap() >= after);
+        assert_eq!(sender_a.last_interaction, Some(last));
+        let last_loop = event_loop.last_interaction().unwrap();
+        assert!(last_loop >= after);
+        // Counted when queued, so draining doesn't move it.
+        while event_loop.next_event().is_some() {}
+        assert_eq!(event_loop.last_interaction(), Some(last_loop));
+    }
+
+    #[test]
+    fn input_to_an_unbound_window_still_counts_for_it() {
+        let mut sender = EventSender::new();
+        sender.send(
+            WindowId(1),
+            WindowEvent::MouseButtonDown(MouseScancode::LClick),
+        );
+        assert!(sender.last_interaction.is_some());
+    }
+
+    #[test]
+    fn queue_queries_see_what_is_pending() {
+        let (a, b) = (WindowId(1), WindowId(2));
+        let mut event_loop = EventLoop::new();
+        assert!(event_loop.is_empty());
+        assert_eq!(event_loop.pending_len(), 0);
+        assert!(!event_loop.has_events_for(a));
+
+        let mut sender = EventSender::with_receiver(event_loop.receiver.clone());
+        sender.send(a, cursor(1.0));
+        sender.send(a, cursor(2.0));
+        event_loop.inject(b, cursor(3.0));
+        assert!(!event_loop.is_empty());
+        assert_eq!(event_loop.pending_len(), 3);
+        assert!(event_loop.has_events_for(a));
+        assert!(event_loop.has_events_for(b));
+        assert!(!event_loop.has_events_for(WindowId(3)));
+
+        // Asking doesn't take anything off the queue.
+        assert_eq!(event_loop.pending_len(), 3);
+        event_loop.next_event();
+        event_loop.next_event();
+        assert_eq!(event_loop.pending_len(), 1);
+        assert!(!event_loop.has_events_for(a));
+        event_loop.next_event();
+        assert!(event_loop.is_empty());
+    }
+
+    #[test]
+    fn held_resizes_are_not_pending() {
+        let id = WindowId(1);
+        let event_loop = EventLoop::new();
+        let mut sender = EventSender::with_receiver(event_loop.receiver.clone());
+        sender.resize_policy = ResizePolicy::Throttled(Duration::from_secs(60));
+        sender.send(id, WindowEvent::Resized(PhysicalSize::new(640, 480)));
+        sender.send(id, WindowEvent::Resized(PhysicalSize::new(800, 600)));
+        assert_eq!(event_loop.pending_len(), 1);
+        // Once the interval is up.
+        sender.resize_sent = None;
+        sender.flush_resize(id);
+        assert_eq!(event_loop.pending_len(), 2);
+    }
+
+    #[test]
+    fn panic_messages_come_from_str_and_string_payloads() {
+        assert_eq!(panic_message(&"boom"), "boom");
+        assert_eq!(panic_message(&format!("boom {}", 2)), "boom 2");
+        assert_eq!(panic_message(&2u32), "unknown panic");
+    }
+
+    #[test]
+    fn tasks_are_refused_once_the_loop_is_gone() {
+        let event_loop = EventLoop::new();
+        let proxy = event_loop.proxy();
+        let call = proxy.call_on_loop(|| 1).unwrap();
+        drop(event_loop);
+        assert_eq!(call.wait(), Err(EventLoopClosed));
+        assert_eq!(proxy.run_on_loop(|| {}), Err(EventLoopClosed));
+    }
+
+    // The headless backend has no windows to wait for, so nothing wakes it.
+    #[cfg(any(windows, all(unix, feature = "x11")))]
+    #[test]
+    fn a_queued_task_ends_a_wait_for_events() {
+        let event_loop = EventLoop::new();
+        event_loop.proxy().run_on_loop(|| {}).unwrap();
+        let start = Instant::now();
+        platform::platform_impl::wait_for_events(&HashSet::new(), Some(Duration::from_secs(5)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn dropping_the_loop_stops_the_queue_growing() {
+        let event_loop = EventLoop::new();
+        let receiver = event_loop.receiver.clone();
+        let mut sender = EventSender::with_receiver(receiver.clone());
+        let id = WindowId::default();
+        sender.send(id, WindowEvent::SystemSettingsChanged);
+        assert_eq!(receiver.read().unwrap().events.len(), 1);
+
+        // The sender was never in the loop's ids, so nothing unbinds it.
+        drop(event_loop);
+        assert!(receiver.read().unwrap().events.is_empty());
+        for _ in 0..100 {
+            sender.send(id, WindowEvent::SystemSettingsChanged);
+            sender.send_synthetic(id, WindowEvent::RedrawRequested);
+        }
+        assert!(receiver.read().unwrap().events.is_empty());
+        assert!(sender.queued_evs.is_empty());
     }
 }
@@ -1,9 +1,17 @@
 use cfg_if::cfg_if;
 
+#[cfg(test)]
+pub(crate) mod scancodes;
+
 cfg_if! {
     if #[cfg(windows)] {
         pub mod win32;
-    } else if #[cfg(unix)] {
+        pub(crate) use win32 as platform_impl;
+    } else if #[cfg(all(unix, feature = "x11"))] {
         pub mod xlib;
+        pub(crate) use xlib as platform_impl;
+    } else {
+        pub mod headless;
+        pub(crate) use headless as platform_impl;
     }
 }
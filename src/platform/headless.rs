@@ -0,0 +1,166 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use crate::{
+    clipboard::ClipboardError,
+    hotkeys::{HotkeyError, HotkeyId, KeyCombo},
+    keyboard::{LockError, LockKey},
+    single_instance::{Claim, InstanceError},
+    CloseAction, DpiError, DpiMode, EventSender, Monitor, RgbaImage, SystemSettings, Theme,
+    WindowId, WindowIdExt,
+};
+
+// Used when no windowing backend is compiled in. The event types and the
+// event loop still work, but no window can ever be created.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OsError;
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PlatformAttributes {}
+
+#[derive(Debug)]
+pub enum Window {}
+
+impl Window {
+    pub(crate) fn from_builder(_builder: crate::WindowBuilder) -> Result<Self, OsError> {
+        Err(OsError)
+    }
+
+    pub fn from_id(_id: WindowId) -> Option<Self> {
+        None
+    }
+
+    pub(crate) fn destroy_window(&self) {
+        match *self {}
+    }
+
+    pub(crate) fn close_action(&self) -> CloseAction {
+        match *self {}
+    }
+
+    pub(crate) fn hide(&mut self) {
+        match *self {}
+    }
+
+    pub(crate) fn sender(&self) -> Arc<RwLock<EventSender>> {
+        match *self {}
+    }
+}
+
+impl WindowIdExt for WindowId {
+    fn next_event(&self) {}
+
+    fn dispatch_pending(&self) {}
+
+    fn sender(&self) -> Option<Arc<RwLock<EventSender>>> {
+        None
+    }
+}
+
+pub(crate) struct InstanceLock {}
+
+impl InstanceLock {
+    pub(crate) fn attach(&mut self, _id: WindowId) {}
+}
+
+pub(crate) struct Notifier {}
+
+impl Notifier {
+    pub(crate) fn notify(&self, _args: &[String]) -> Result<(), InstanceError> {
+        Err(InstanceError::Unsupported)
+    }
+}
+
+pub(crate) fn claim_instance(_name: &str) -> Result<Claim, InstanceError> {
+    Err(InstanceError::Unsupported)
+}
+
+pub(crate) fn windows() -> Vec<WindowId> {
+    Vec::new()
+}
+
+pub(crate) fn system_settings() -> SystemSettings {
+    SystemSettings::default()
+}
+
+pub(crate) fn system_theme() -> Option<Theme> {
+    None
+}
+
+pub(crate) fn monitors() -> Vec<Monitor> {
+    Vec::new()
+}
+
+pub(crate) const REPORTS_SIZE_MOVE: bool = false;
+
+pub(crate) const WINDOW_ID_KIND: &str = "window";
+
+pub(crate) fn init_dpi(_mode: DpiMode) -> Result<(), DpiError> {
+    Ok(())
+}
+
+pub(crate) fn set_lock_state(_lock: LockKey, _enabled: bool) -> Result<(), LockError> {
+    Err(LockError::Unsupported)
+}
+
+pub(crate) fn lock_state(_lock: LockKey) -> Option<bool> {
+    None
+}
+
+// Stands in for the system clipboard, shared by the whole process.
+static CLIPBOARD: Mutex<Option<RgbaImage>> = Mutex::new(None);
+
+pub(crate) fn set_clipboard_image(image: RgbaImage) -> Result<(), ClipboardError> {
+    *CLIPBOARD.lock().unwrap() = Some(image);
+    Ok(())
+}
+
+pub(crate) fn clipboard_image() -> Option<RgbaImage> {
+    CLIPBOARD.lock().unwrap().clone()
+}
+
+// No OS input ever arrives here, so only a timer ends the wait.
+pub(crate) fn wait_for_events(_ids: &HashSet<WindowId>, timeout: Option<Duration>) {
+    if let Some(timeout) = timeout {
+        std::thread::sleep(timeout);
+    }
+}
+
+// As above, only a timer has anything to wait for.
+#[cfg(feature = "async")]
+pub(crate) fn event_waiter(
+    _ids: &HashSet<WindowId>,
+    timeout: Option<Duration>,
+) -> Option<Box<dyn FnOnce() + Send>> {
+    timeout.map(|t| Box::new(move || std::thread::sleep(t)) as Box<dyn FnOnce() + Send>)
+}
+
+pub(crate) fn pump_events() {}
+
+// wait_for_events never waits on anything another thread could signal.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct LoopWaker;
+
+impl LoopWaker {
+    pub(crate) fn wake(&self) {}
+}
+
+pub(crate) fn loop_waker() -> LoopWaker {
+    LoopWaker
+}
+
+pub(crate) fn register_hotkey(_combo: KeyCombo) -> Result<HotkeyId, HotkeyError> {
+    Err(HotkeyError::Os)
+}
+
+pub(crate) fn unregister_hotkey(_id: HotkeyId) {}
+
+pub(crate) fn unregister_all_hotkeys() {}
+
+pub(crate) fn next_hotkey() -> Option<HotkeyId> {
+    None
+}
@@ -0,0 +1,158 @@
+// The expected platform codes for every key, which each backend's tests check
+// its conversions against. None marks a key the platform has no code for.
+
+use crate::KeyboardScancode::{self, *};
+
+pub(crate) struct Key {
+    pub(crate) key: KeyboardScancode,
+    pub(crate) name: &'static str,
+    // win32 virtual-key code.
+    pub(crate) vk: Option<u16>,
+    // win32 scan code set 1, with 0xE0 in the high byte for extended keys.
+    pub(crate) oem: Option<u16>,
+    pub(crate) keysym: Option<u32>,
+}
+
+// In declaration order, one row per key.
+#[rustfmt::skip]
+pub(crate) const KEYS: &[Key] = &[
+    Key { key: Esc, name: "Esc", vk: Some(0x1B), oem: Some(0x0001), keysym: Some(0xFF1B) },
+    Key { key: F1, name: "F1", vk: Some(0x70), oem: Some(0x003B), keysym: Some(0xFFBE) },
+    Key { key: F2, name: "F2", vk: Some(0x71), oem: Some(0x003C), keysym: Some(0xFFBF) },
+    Key { key: F3, name: "F3", vk: Some(0x72), oem: Some(0x003D), keysym: Some(0xFFC0) },
+    Key { key: F4, name: "F4", vk: Some(0x73), oem: Some(0x003E), keysym: Some(0xFFC1) },
+    Key { key: F5, name: "F5", vk: Some(0x74), oem: Some(0x003F), keysym: Some(0xFFC2) },
+    Key { key: F6, name: "F6", vk: Some(0x75), oem: Some(0x0040), keysym: Some(0xFFC3) },
+    Key { key: F7, name: "F7", vk: Some(0x76), oem: Some(0x0041), keysym: Some(0xFFC4) },
+    Key { key: F8, name: "F8", vk: Some(0x77), oem: Some(0x0042), keysym: Some(0xFFC5) },
+    Key { key: F9, name: "F9", vk: Some(0x78), oem: Some(0x0043), keysym: Some(0xFFC6) },
+    Key { key: F10, name: "F10", vk: Some(0x79), oem: Some(0x0044), keysym: Some(0xFFC7) },
+    Key { key: F11, name: "F11", vk: Some(0x7A), oem: Some(0x0057), keysym: Some(0xFFC8) },
+    Key { key: F12, name: "F12", vk: Some(0x7B), oem: Some(0x0058), keysym: Some(0xFFC9) },
+    Key { key: PrtScSysRq, name: "PrtSc", vk: Some(0x2C), oem: Some(0xE037), keysym: Some(0xFF61) },
+    Key { key: ScrLk, name: "ScrLk", vk: Some(0x91), oem: Some(0x0046), keysym: Some(0xFF14) },
+    Key { key: PauseBreak, name: "Break", vk: Some(0x13), oem: Some(0x0045), keysym: Some(0xFF13) },
+    Key { key: Tilde, name: "Tilde", vk: Some(0xC0), oem: Some(0x0029), keysym: Some(0x0060) },
+    Key { key: Key1, name: "1", vk: Some(0x31), oem: Some(0x0002), keysym: Some(0x0031) },
+    Key { key: Key2, name: "2", vk: Some(0x32), oem: Some(0x0003), keysym: Some(0x0032) },
+    Key { key: Key3, name: "3", vk: Some(0x33), oem: Some(0x0004), keysym: Some(0x0033) },
+    Key { key: Key4, name: "4", vk: Some(0x34), oem: Some(0x0005), keysym: Some(0x0034) },
+    Key { key: Key5, name: "5", vk: Some(0x35), oem: Some(0x0006), keysym: Some(0x0035) },
+    Key { key: Key6, name: "6", vk: Some(0x36), oem: Some(0x0007), keysym: Some(0x0036) },
+    Key { key: Key7, name: "7", vk: Some(0x37), oem: Some(0x0008), keysym: Some(0x0037) },
+    Key { key: Key8, name: "8", vk: Some(0x38), oem: Some(0x0009), keysym: Some(0x0038) },
+    Key { key: Key9, name: "9", vk: Some(0x39), oem: Some(0x000A), keysym: Some(0x0039) },
+    Key { key: Key0, name: "0", vk: Some(0x30), oem: Some(0x000B), keysym: Some(0x0030) },
+    Key { key: Hyphen, name: "Hyphen", vk: Some(0xBD), oem: Some(0x000C), keysym: Some(0x002D) },
+    Key { key: Equals, name: "Equals", vk: Some(0xBB), oem: Some(0x000D), keysym: Some(0x003D) },
+    Key { key: Backspace, name: "Backspace", vk: Some(0x08), oem: Some(0x000E), keysym: Some(0xFF08) },
+    Key { key: Insert, name: "Insert", vk: Some(0x2D), oem: Some(0xE052), keysym: Some(0xFF63) },
+    Key { key: Home, name: "Home", vk: Some(0x24), oem: Some(0xE047), keysym: Some(0xFF50) },
+    Key { key: PgUp, name: "PgUp", vk: Some(0x21), oem: Some(0xE049), keysym: Some(0xFF55) },
+    Key { key: NumLk, name: "NumLk", vk: Some(0x90), oem: Some(0xE045), keysym: Some(0xFF7F) },
+    Key { key: NumSlash, name: "NumSlash", vk: Some(0x6F), oem: Some(0xE035), keysym: Some(0xFFAF) },
+    Key { key: NumAsterisk, name: "NumAsterisk", vk: Some(0x6A), oem: Some(0x0037), keysym: Some(0xFFAA) },
+    Key { key: NumHyphen, name: "NumHyphen", vk: Some(0x6D), oem: Some(0x004A), keysym: Some(0xFFAD) },
+    Key { key: Tab, name: "Tab", vk: Some(0x09), oem: Some(0x000F), keysym: Some(0xFF09) },
+    Key { key: Q, name: "Q", vk: Some(0x51), oem: Some(0x0010), keysym: Some(0x0071) },
+    Key { key: W, name: "W", vk: Some(0x57), oem: Some(0x0011), keysym: Some(0x0077) },
+    Key { key: E, name: "E", vk: Some(0x45), oem: Some(0x0012), keysym: Some(0x0065) },
+    Key { key: R, name: "R", vk: Some(0x52), oem: Some(0x0013), keysym: Some(0x0072) },
+    Key { key: T, name: "T", vk: Some(0x54), oem: Some(0x0014), keysym: Some(0x0074) },
+    Key { key: Y, name: "Y", vk: Some(0x59), oem: Some(0x0015), keysym: Some(0x0079) },
+    Key { key: U, name: "U", vk: Some(0x55), oem: Some(0x0016), keysym: Some(0x0075) },
+    Key { key: I, name: "I", vk: Some(0x49), oem: Some(0x0017), keysym: Some(0x0069) },
+    Key { key: O, name: "O", vk: Some(0x4F), oem: Some(0x0018), keysym: Some(0x006F) },
+    Key { key: P, name: "P", vk: Some(0x50), oem: Some(0x0019), keysym: Some(0x0070) },
+    Key { key: OpenBracket, name: "OpenBracket", vk: Some(0xDB), oem: Some(0x001A), keysym: Some(0x005B) },
+    Key { key: CloseBracket, name: "CloseBracket", vk: Some(0xDD), oem: Some(0x001B), keysym: Some(0x005D) },
+    Key { key: BackSlash, name: "BackSlash", vk: Some(0xDC), oem: Some(0x002B), keysym: Some(0x005C) },
+    Key { key: Del, name: "Del", vk: Some(0x2E), oem: Some(0xE053), keysym: Some(0xFFFF) },
+    Key { key: End, name: "End", vk: Some(0x23), oem: Some(0xE04F), keysym: Some(0xFF57) },
+    Key { key: PgDn, name: "PgDn", vk: Some(0x22), oem: Some(0xE051), keysym: Some(0xFF56) },
+    Key { key: Num7, name: "Num7", vk: Some(0x67), oem: Some(0x0047), keysym: Some(0xFFB7) },
+    Key { key: Num8, name: "Num8", vk: Some(0x68), oem: Some(0x0048), keysym: Some(0xFFB8) },
+    Key { key: Num9, name: "Num9", vk: Some(0x69), oem: Some(0x0049), keysym: Some(0xFFB9) },
+    Key { key: NumPlus, name: "NumPlus", vk: Some(0x6B), oem: Some(0x004E), keysym: Some(0xFFAB) },
+    Key { key: CapsLk, name: "CapsLk", vk: Some(0x14), oem: Some(0x003A), keysym: Some(0xFFE5) },
+    Key { key: A, name: "A", vk: Some(0x41), oem: Some(0x001E), keysym: Some(0x0061) },
+    Key { key: S, name: "S", vk: Some(0x53), oem: Some(0x001F), keysym: Some(0x0073) },
+    Key { key: D, name: "D", vk: Some(0x44), oem: Some(0x0020), keysym: Some(0x0064) },
+    Key { key: F, name: "F", vk: Some(0x46), oem: Some(0x0021), keysym: Some(0x0066) },
+    Key { key: G, name: "G", vk: Some(0x47), oem: Some(0x0022), keysym: Some(0x0067) },
+    Key { key: H, name: "H", vk: Some(0x48), oem: Some(0x0023), keysym: Some(0x0068) },
+    Key { key: J, name: "J", vk: Some(0x4A), oem: Some(0x0024), keysym: Some(0x006A) },
+    Key { key: K, name: "K", vk: Some(0x4B), oem: Some(0x0025), keysym: Some(0x006B) },
+    Key { key: L, name: "L", vk: Some(0x4C), oem: Some(0x0026), keysym: Some(0x006C) },
+    Key { key: Semicolon, name: "Semicolon", vk: Some(0xBA), oem: Some(0x0027), keysym: Some(0x003B) },
+    Key { key: Apostrophe, name: "Apostrophe", vk: Some(0xDE), oem: Some(0x0028), keysym: Some(0x0027) },
+    Key { key: Enter, name: "Enter", vk: Some(0x0D), oem: Some(0x001C), keysym: Some(0xFF0D) },
+    Key { key: Num4, name: "Num4", vk: Some(0x64), oem: Some(0x004B), keysym: Some(0xFFB4) },
+    Key { key: Num5, name: "Num5", vk: Some(0x65), oem: Some(0x004C), keysym: Some(0xFFB5) },
+    Key { key: Num6, name: "Num6", vk: Some(0x66), oem: Some(0x004D), keysym: Some(0xFFB6) },
+    Key { key: LShift, name: "LShift", vk: Some(0xA0), oem: Some(0x002A), keysym: Some(0xFFE1) },
+    Key { key: Z, name: "Z", vk: Some(0x5A), oem: Some(0x002C), keysym: Some(0x007A) },
+    Key { key: X, name: "X", vk: Some(0x58), oem: Some(0x002D), keysym: Some(0x0078) },
+    Key { key: C, name: "C", vk: Some(0x43), oem: Some(0x002E), keysym: Some(0x0063) },
+    Key { key: V, name: "V", vk: Some(0x56), oem: Some(0x002F), keysym: Some(0x0076) },
+    Key { key: B, name: "B", vk: Some(0x42), oem: Some(0x0030), keysym: Some(0x0062) },
+    Key { key: N, name: "N", vk: Some(0x4E), oem: Some(0x0031), keysym: Some(0x006E) },
+    Key { key: M, name: "M", vk: Some(0x4D), oem: Some(0x0032), keysym: Some(0x006D) },
+    Key { key: Comma, name: "Comma", vk: Some(0xBC), oem: Some(0x0033), keysym: Some(0x002C) },
+    Key { key: Period, name: "Period", vk: Some(0xBE), oem: Some(0x0034), keysym: Some(0x002E) },
+    Key { key: ForwardSlash, name: "ForwardSlash", vk: Some(0xBF), oem: Some(0x0035), keysym: Some(0x002F) },
+    Key { key: RShift, name: "RShift", vk: Some(0xA1), oem: Some(0x0036), keysym: Some(0xFFE2) },
+    Key { key: ArrowUp, name: "ArrowUp", vk: Some(0x26), oem: Some(0xE048), keysym: Some(0xFF52) },
+    Key { key: Num1, name: "Num1", vk: Some(0x61), oem: Some(0x004F), keysym: Some(0xFFB1) },
+    Key { key: Num2, name: "Num2", vk: Some(0x62), oem: Some(0x0050), keysym: Some(0xFFB2) },
+    Key { key: Num3, name: "Num3", vk: Some(0x63), oem: Some(0x0051), keysym: Some(0xFFB3) },
+    Key { key: NumEnter, name: "NumEnter", vk: None, oem: Some(0xE01C), keysym: Some(0xFF8D) },
+    Key { key: LCtrl, name: "LCtrl", vk: Some(0xA2), oem: Some(0x001D), keysym: Some(0xFFE3) },
+    Key { key: LSys, name: "LSys", vk: Some(0x5B), oem: Some(0xE05B), keysym: Some(0xFFEB) },
+    Key { key: LAlt, name: "LAlt", vk: Some(0xA4), oem: Some(0x0038), keysym: Some(0xFFE9) },
+    Key { key: Space, name: "Space", vk: Some(0x20), oem: Some(0x0039), keysym: Some(0x0020) },
+    Key { key: RAlt, name: "RAlt", vk: Some(0xA5), oem: Some(0xE038), keysym: Some(0xFFEA) },
+    Key { key: RSys, name: "RSys", vk: Some(0x5C), oem: Some(0xE05C), keysym: Some(0xFFEC) },
+    Key { key: Fn, name: "Fn", vk: None, oem: None, keysym: None },
+    Key { key: Menu, name: "Menu", vk: Some(0x5D), oem: Some(0xE05D), keysym: Some(0xFF67) },
+    Key { key: RCtrl, name: "RCtrl", vk: Some(0xA3), oem: Some(0xE01D), keysym: Some(0xFFE4) },
+    Key { key: ArrowLeft, name: "ArrowLeft", vk: Some(0x25), oem: Some(0xE04B), keysym: Some(0xFF51) },
+    Key { key: ArrowDown, name: "ArrowDown", vk: Some(0x28), oem: Some(0xE050), keysym: Some(0xFF54) },
+    Key { key: ArrowRight, name: "ArrowRight", vk: Some(0x27), oem: Some(0xE04D), keysym: Some(0xFF53) },
+    Key { key: Num0, name: "Num0", vk: Some(0x60), oem: Some(0x0052), keysym: Some(0xFFB0) },
+    Key { key: NumPeriod, name: "NumPeriod", vk: Some(0x6E), oem: Some(0x0053), keysym: Some(0xFFAE) },
+];
+
+// Stops compiling when a key is added, as a reminder to give it a row; the
+// new last variant then goes on the left.
+fn is_last(key: KeyboardScancode) -> bool {
+    match key {
+        NumPeriod => true,
+        Esc | F1 | F2 | F3 | F4 | F5 | F6 | F7 | F8 | F9 | F10 | F11 | F12 | PrtScSysRq | ScrLk
+        | PauseBreak | Tilde | Key1 | Key2 | Key3 | Key4 | Key5 | Key6 | Key7 | Key8 | Key9
+        | Key0 | Hyphen | Equals | Backspace | Insert | Home | PgUp | NumLk | NumSlash
+        | NumAsterisk | NumHyphen | Tab | Q | W | E | R | T | Y | U | I | O | P | OpenBracket
+        | CloseBracket | BackSlash | Del | End | PgDn | Num7 | Num8 | Num9 | NumPlus | CapsLk
+        | A | S | D | F | G | H | J | K | L | Semicolon | Apostrophe | Enter | Num4 | Num5
+        | Num6 | LShift | Z | X | C | V | B | N | M | Comma | Period | ForwardSlash | RShift
+        | ArrowUp | Num1 | Num2 | Num3 | NumEnter | LCtrl | LSys | LAlt | Space | RAlt | RSys
+        | Fn | Menu | RCtrl | ArrowLeft | ArrowDown | ArrowRight | Num0 => false,
+    }
+}
+
+#[test]
+fn every_key_has_one_row() {
+    for (i, row) in KEYS.iter().enumerate() {
+        assert_eq!(row.key as usize, i, "{:?} is out of order", row.key);
+    }
+    assert!(is_last(KEYS.last().unwrap().key));
+}
+
+#[test]
+fn names_match_and_parse_back() {
+    assert_eq!(crate::hotkeys::KEY_NAMES.len(), KEYS.len());
+    for row in KEYS {
+        assert_eq!(row.key.to_string(), row.name);
+        assert_eq!(row.name.parse(), Ok(row.key));
+    }
+}
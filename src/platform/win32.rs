@@ -2,69 +2,289 @@
 
 use core::slice;
 use std::{
-    collections::HashMap,
-    mem::{size_of, transmute},
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    ffi::{c_void, OsString},
+    mem::{size_of, ManuallyDrop},
+    os::windows::ffi::{OsStrExt, OsStringExt},
+    panic::{self, AssertUnwindSafe},
     ptr::{addr_of, addr_of_mut},
-    sync::{atomic::AtomicU16, Arc, RwLock},
+    sync::{
+        atomic::{AtomicU16, AtomicU32, Ordering},
+        Arc, PoisonError, RwLock, Weak,
+    },
     thread,
+    time::{Duration, Instant},
 };
 
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle, Win32WindowHandle};
 use windows::{
     core::PCWSTR,
     Win32::{
-        Foundation::{GetLastError, HINSTANCE, HWND, LPARAM, LRESULT, WIN32_ERROR, WPARAM},
-        Graphics::Gdi::{RedrawWindow, UpdateWindow, COLOR_WINDOW, HBRUSH, RDW_NOINTERNALPAINT},
-        System::LibraryLoader::GetModuleHandleW,
+        Foundation::{
+            CloseHandle, GetLastError, COLORREF, ERROR_ALREADY_EXISTS,
+            ERROR_HOTKEY_ALREADY_REGISTERED, ERROR_SUCCESS, HANDLE, HINSTANCE, HWND, LPARAM,
+            LRESULT, RECT, WIN32_ERROR, WPARAM,
+        },
+        Graphics::Gdi::{
+            BeginPaint, ChangeDisplaySettingsExW, ClientToScreen, CreateCompatibleDC,
+            CreateDIBSection, CreateSolidBrush, DeleteDC, DeleteObject, EndPaint,
+            EnumDisplayDevicesW, EnumDisplayMonitors, EnumDisplaySettingsW, FillRect, GetDC,
+            GetMonitorInfoW, InvalidateRect, MonitorFromWindow, RedrawWindow, ReleaseDC,
+            SelectObject, UpdateWindow, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, CDS_FULLSCREEN,
+            CDS_TYPE, COLOR_WINDOW, DEVMODEW, DIB_RGB_COLORS, DISPLAY_DEVICEW,
+            DISP_CHANGE_SUCCESSFUL, DM_DISPLAYFREQUENCY, DM_PELSHEIGHT, DM_PELSWIDTH,
+            ENUM_CURRENT_SETTINGS, HBRUSH, HDC, HMONITOR, MONITORINFO, MONITORINFOEXW,
+            MONITOR_DEFAULTTONEAREST, PAINTSTRUCT, RDW_INVALIDATE,
+        },
+        Storage::Xps::{PrintWindow, PRINT_WINDOW_FLAGS, PW_CLIENTONLY},
+        System::{
+            DataExchange::COPYDATASTRUCT,
+            LibraryLoader::GetModuleHandleW,
+            Threading::{CreateEventW, CreateMutexW, GetCurrentThreadId, ReleaseMutex, SetEvent},
+        },
         UI::{
             Input::KeyboardAndMouse::{
-                GetActiveWindow, MapVirtualKeyW, SetFocus, ToUnicode, MAPVK_VK_TO_CHAR,
-                MAPVK_VSC_TO_VK_EX, VIRTUAL_KEY, VK_ADD, VK_BACK, VK_CAPITAL, VK_CONTROL,
-                VK_DECIMAL, VK_DELETE, VK_DIVIDE, VK_DOWN, VK_END, VK_ESCAPE, VK_F1, VK_F10,
-                VK_F11, VK_F12, VK_F2, VK_F3, VK_F4, VK_F5, VK_F6, VK_F7, VK_F8, VK_F9, VK_HOME,
-                VK_INSERT, VK_LBUTTON, VK_LCONTROL, VK_LEFT, VK_LMENU, VK_LSHIFT, VK_LWIN,
-                VK_MBUTTON, VK_MENU, VK_MULTIPLY, VK_NEXT, VK_NUMLOCK, VK_NUMPAD0, VK_NUMPAD1,
-                VK_NUMPAD2, VK_NUMPAD3, VK_NUMPAD4, VK_NUMPAD5, VK_NUMPAD6, VK_NUMPAD7, VK_NUMPAD8,
-                VK_NUMPAD9, VK_OEM_1, VK_OEM_2, VK_OEM_3, VK_OEM_4, VK_OEM_5, VK_OEM_6, VK_OEM_7,
-                VK_OEM_COMMA, VK_OEM_MINUS, VK_OEM_PERIOD, VK_OEM_PLUS, VK_PAUSE, VK_PRIOR,
-                VK_RBUTTON, VK_RCONTROL, VK_RETURN, VK_RIGHT, VK_RMENU, VK_RSHIFT, VK_RWIN,
-                VK_SEPARATOR, VK_SHIFT, VK_SNAPSHOT, VK_SPACE, VK_SUBTRACT, VK_TAB, VK_UP,
-                VK_XBUTTON1, VK_XBUTTON2,
+                GetActiveWindow, GetDoubleClickTime, GetKeyState, MapVirtualKeyW, RegisterHotKey,
+                ReleaseCapture, SendInput, SetCapture, SetFocus, ToUnicode, UnregisterHotKey,
+                INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS,
+                KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, MAPVK_VK_TO_CHAR, MAPVK_VSC_TO_VK_EX,
+                MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, VIRTUAL_KEY, VK_ADD,
+                VK_APPS, VK_BACK, VK_CAPITAL, VK_CONTROL, VK_DECIMAL, VK_DELETE, VK_DIVIDE,
+                VK_DOWN, VK_END, VK_ESCAPE, VK_F1, VK_F10, VK_F11, VK_F12, VK_F2, VK_F3, VK_F4,
+                VK_F5, VK_F6, VK_F7, VK_F8, VK_F9, VK_HOME, VK_INSERT, VK_LBUTTON, VK_LCONTROL,
+                VK_LEFT, VK_LMENU, VK_LSHIFT, VK_LWIN, VK_MBUTTON, VK_MENU, VK_MULTIPLY, VK_NEXT,
+                VK_NUMLOCK, VK_NUMPAD0, VK_NUMPAD1, VK_NUMPAD2, VK_NUMPAD3, VK_NUMPAD4, VK_NUMPAD5,
+                VK_NUMPAD6, VK_NUMPAD7, VK_NUMPAD8, VK_NUMPAD9, VK_OEM_1, VK_OEM_2, VK_OEM_3,
+                VK_OEM_4, VK_OEM_5, VK_OEM_6, VK_OEM_7, VK_OEM_COMMA, VK_OEM_MINUS, VK_OEM_PERIOD,
+                VK_OEM_PLUS, VK_PAUSE, VK_PRIOR, VK_RBUTTON, VK_RCONTROL, VK_RETURN, VK_RIGHT,
+                VK_RMENU, VK_RSHIFT, VK_RWIN, VK_SCROLL, VK_SEPARATOR, VK_SHIFT, VK_SNAPSHOT,
+                VK_SPACE, VK_SUBTRACT, VK_TAB, VK_UP, VK_XBUTTON1, VK_XBUTTON2,
+            },
+            Input::{
+                GetRawInputData, RegisterRawInputDevices, HRAWINPUT, RAWINPUT, RAWINPUTDEVICE,
+                RAWINPUTDEVICE_FLAGS, RAWINPUTHEADER, RIDEV_REMOVE, RID_INPUT, RIM_TYPEMOUSE,
             },
             WindowsAndMessaging::{
-                CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, FlashWindowEx,
-                GetSystemMetrics, GetWindowLongPtrW, LoadCursorW, LoadIconW, PeekMessageW,
-                PostMessageW, RegisterClassExW, SendMessageW, SetWindowLongPtrW, SetWindowPos,
-                SetWindowTextW, ShowWindow, CS_DBLCLKS, CS_NOCLOSE, CW_USEDEFAULT, FLASHWINFO,
-                FLASHW_ALL, FLASHW_TIMERNOFG, FLASHW_TRAY, GWL_EXSTYLE, GWL_STYLE, HCURSOR, HICON,
-                HMENU, HWND_TOP, IDC_ARROW, IDI_APPLICATION, MINMAXINFO, MSG, PM_REMOVE,
-                SC_MAXIMIZE, SC_NEXTWINDOW, SC_RESTORE, SIZE_MAXHIDE, SIZE_MAXIMIZED, SIZE_MAXSHOW,
-                SIZE_MINIMIZED, SIZE_RESTORED, SM_CXSCREEN, SM_CYSCREEN, SWP_ASYNCWINDOWPOS,
-                SWP_DRAWFRAME, SWP_FRAMECHANGED, SWP_HIDEWINDOW, SWP_NOACTIVATE, SWP_NOCOPYBITS,
-                SWP_SHOWWINDOW, SW_HIDE, SW_MAXIMIZE, SW_MINIMIZE, SW_NORMAL, WA_ACTIVE,
-                WA_CLICKACTIVE, WA_INACTIVE, WINDOW_EX_STYLE, WINDOW_STYLE, WM_ACTIVATE, WM_CLOSE,
-                WM_CREATE, WM_DESTROY, WM_DISPLAYCHANGE, WM_GETMINMAXINFO, WM_KEYDOWN, WM_KEYUP,
-                WM_MOUSEWHEEL, WM_MOVE, WM_SETTEXT, WM_SIZE, WM_SYSCOMMAND, WM_SYSKEYDOWN,
-                WM_SYSKEYUP, WNDCLASSEXW, WNDCLASS_STYLES, WS_CLIPSIBLINGS, WS_EX_APPWINDOW,
-                WS_MAXIMIZEBOX, WS_MINIMIZEBOX, WS_OVERLAPPEDWINDOW, WS_POPUP, WS_SIZEBOX,
-                WS_VISIBLE,
+                AdjustWindowRectEx, AllowSetForegroundWindow, CreateWindowExW, DefWindowProcW,
+                DestroyWindow, DispatchMessageW, EnableMenuItem, FindWindowExW, FlashWindowEx,
+                GetCaretBlinkTime, GetClientRect, GetMessageExtraInfo, GetSystemMenu,
+                GetSystemMetrics, GetWindow, GetWindowLongPtrW, GetWindowRect,
+                GetWindowThreadProcessId, LoadCursorW, LoadIconW, MsgWaitForMultipleObjects,
+                PeekMessageW, RegisterClassExW, SendMessageW, SetForegroundWindow,
+                SetWindowLongPtrW, SetWindowPos, SetWindowTextW, ShowWindow, SystemParametersInfoW,
+                WaitMessage, ANIMATIONINFO, CREATESTRUCTW, CS_DBLCLKS, CS_NOCLOSE, CW_USEDEFAULT,
+                EDD_GET_DEVICE_INTERFACE_NAME, FLASHWINFO, FLASHW_ALL, FLASHW_TIMERNOFG,
+                FLASHW_TRAY, GWLP_USERDATA, GWL_EXSTYLE, GWL_STYLE, GW_HWNDNEXT, HCURSOR, HICON,
+                HMENU, HWND_BOTTOM, HWND_MESSAGE, HWND_TOP, IDC_ARROW, IDI_APPLICATION,
+                MA_NOACTIVATE, MF_BYCOMMAND, MF_ENABLED, MF_GRAYED, MINMAXINFO,
+                MONITORINFOF_PRIMARY, MSG, PM_REMOVE, PT_PEN, PT_TOUCH, PW_RENDERFULLCONTENT,
+                QS_ALLINPUT, SC_CLOSE, SC_MAXIMIZE, SC_NEXTWINDOW, SC_RESTORE, SIZE_MAXHIDE,
+                SIZE_MAXIMIZED, SIZE_MAXSHOW, SIZE_MINIMIZED, SIZE_RESTORED, SM_CXDOUBLECLK,
+                SM_CXDRAG, SM_CXSCREEN, SM_CYDOUBLECLK, SM_CYDRAG, SM_CYSCREEN, SPI_GETANIMATION,
+                SPI_GETCLIENTAREAANIMATION, SPI_GETHIGHCONTRAST, SPI_GETWHEELSCROLLLINES,
+                SWP_ASYNCWINDOWPOS, SWP_DRAWFRAME, SWP_FRAMECHANGED, SWP_HIDEWINDOW,
+                SWP_NOACTIVATE, SWP_NOCOPYBITS, SWP_NOMOVE, SWP_NOSIZE, SWP_NOZORDER,
+                SWP_SHOWWINDOW, SW_HIDE, SW_MAXIMIZE, SW_MINIMIZE, SW_NORMAL, SW_RESTORE,
+                SW_SHOWMAXIMIZED, SW_SHOWMINIMIZED, SW_SHOWNOACTIVATE,
+                SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS, USER_DEFAULT_SCREEN_DPI, WA_ACTIVE,
+                WA_CLICKACTIVE, WA_INACTIVE, WHEEL_DELTA, WINDOW_EX_STYLE, WINDOW_STYLE,
+                WM_ACTIVATE, WM_CLOSE, WM_COPYDATA, WM_CREATE, WM_DESTROY, WM_DISPLAYCHANGE,
+                WM_DPICHANGED, WM_ENTERSIZEMOVE, WM_ERASEBKGND, WM_EXITSIZEMOVE, WM_GETMINMAXINFO,
+                WM_HOTKEY, WM_INPUT, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP,
+                WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEACTIVATE, WM_MOUSEMOVE, WM_MOUSEWHEEL,
+                WM_MOVE, WM_NCCREATE, WM_NCDESTROY, WM_PAINT, WM_POINTERUPDATE, WM_RBUTTONDOWN,
+                WM_RBUTTONUP, WM_SETTEXT, WM_SETTINGCHANGE, WM_SIZE, WM_SYSCOMMAND, WM_SYSKEYDOWN,
+                WM_SYSKEYUP, WM_XBUTTONDOWN, WM_XBUTTONUP, WNDCLASSEXW, WNDCLASS_STYLES,
+                WS_CLIPSIBLINGS, WS_EX_APPWINDOW, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW,
+                WS_EX_TOPMOST, WS_MAXIMIZEBOX, WS_MINIMIZEBOX, WS_OVERLAPPEDWINDOW, WS_POPUP,
+                WS_SIZEBOX, WS_VISIBLE, XBUTTON1, XBUTTON2,
+            },
+        },
+    },
+};
+
+use windows::{
+    core::{ComInterface, IUnknown, IUnknown_Vtbl, Interface, GUID, HRESULT},
+    Win32::{
+        Foundation::{
+            BOOL, DRAGDROP_S_CANCEL, DRAGDROP_S_DROP, DRAGDROP_S_USEDEFAULTCURSORS, E_NOINTERFACE,
+            HGLOBAL, POINT, POINTL, S_OK,
+        },
+        Graphics::Dwm::{
+            DwmExtendFrameIntoClientArea, DwmSetWindowAttribute, DWMNCRENDERINGPOLICY,
+            DWMNCRP_ENABLED, DWMNCRP_USEWINDOWSTYLE, DWMWA_NCRENDERING_POLICY,
+            DWMWA_USE_IMMERSIVE_DARK_MODE, DWMWINDOWATTRIBUTE,
+        },
+        System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD},
+        System::{
+            Com::{
+                IDataObject, DVASPECT_CONTENT, FORMATETC, STGMEDIUM, STGMEDIUM_0, TYMED_HGLOBAL,
+            },
+            DataExchange::{
+                CloseClipboard, EmptyClipboard, GetClipboardData, IsClipboardFormatAvailable,
+                OpenClipboard, RegisterClipboardFormatW, SetClipboardData,
+            },
+            Memory::{
+                GlobalAlloc, GlobalFree, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE,
             },
+            Ole::{
+                DoDragDrop, IDropSource, IDropSource_Vtbl, IDropTarget, IDropTarget_Vtbl,
+                OleInitialize, RegisterDragDrop, ReleaseStgMedium, RevokeDragDrop, CF_DIB,
+                CF_DIBV5, CF_HDROP, CF_UNICODETEXT, DROPEFFECT, DROPEFFECT_COPY, DROPEFFECT_LINK,
+                DROPEFFECT_MOVE, DROPEFFECT_NONE,
+            },
+            SystemServices::{MK_LBUTTON, MK_MBUTTON, MK_RBUTTON, MODIFIERKEYS_FLAGS},
+        },
+        UI::Accessibility::{HCF_HIGHCONTRASTON, HIGHCONTRASTW},
+        UI::Controls::MARGINS,
+        UI::HiDpi::{
+            GetDpiForWindow, SetProcessDpiAwarenessContext,
+            DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, DPI_AWARENESS_CONTEXT_SYSTEM_AWARE,
+            DPI_AWARENESS_CONTEXT_UNAWARE,
         },
+        UI::Input::Pointer::{GetPointerDeviceRects, GetPointerInfo, POINTER_INFO},
+        UI::Shell::{DragQueryFileW, SHCreateDataObject, DROPFILES, HDROP},
+        UI::WindowsAndMessaging::{PostThreadMessageW, WM_NULL},
     },
 };
 
+#[cfg(feature = "accesskit")]
+use windows::Win32::UI::WindowsAndMessaging::{WM_GETOBJECT, WM_KILLFOCUS, WM_SETFOCUS};
+
 use crate::{
-    EventSender, FullscreenType, KeyboardScancode, Modifiers, MouseScancode, Theme,
-    UserAttentionType, WindowButtons, WindowEvent, WindowId, WindowIdExt, WindowSizeState,
-    WindowTExt,
+    clipboard::{self, ClipboardError},
+    dpi::{PhysicalPosition, PhysicalSize},
+    hotkeys::{HotkeyError, HotkeyId, KeyCombo},
+    input_state::KeyStateTracker,
+    keyboard::{LockError, LockKey},
+    single_instance::{Claim, InstanceError},
+    CloseAction, DpiError, DpiMode, DragOutcome, DragPayload, EventSender, FullscreenType,
+    KeyboardScancode, Modifiers, Monitor, MonitorId, MonitorTracker, MouseButtons, MouseScancode,
+    PointerKind, RedrawScheduler, RefreshRateTracker, RgbaImage, SystemSettings, Theme,
+    UserAttentionType, WindowButtons, WindowEvent, WindowId, WindowIdExt, WindowKind,
+    WindowSizeState, WindowTExt,
 };
 
 #[derive(Clone, Debug, Default)]
 pub struct Window {
     hwnd: Arc<HWND>,
+    // Fixed for the window's lifetime, so kept out of WINDOW_INFO.
+    hinstance: HINSTANCE,
+}
+
+pub type OsError = WIN32_ERROR;
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PlatformAttributes {}
+
+// What an exclusive fullscreen window does when it loses focus.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ExclusiveFocusPolicy {
+    // Gives the desktop back its own display mode and minimizes, switching
+    // back when the window is activated again.
+    #[default]
+    AutoMinimize,
+    // Keeps the mode and the window in place, e.g. for a game on one
+    // monitor while the user works on another.
+    StayActive,
+}
+
+// The display mode an exclusive fullscreen window switched a monitor to.
+#[derive(Clone)]
+struct ExclusiveMode {
+    device: [u16; 32],
+    mode: DEVMODEW,
+}
+
+impl ExclusiveMode {
+    fn apply(&self) -> bool {
+        let result = unsafe {
+            ChangeDisplaySettingsExW(
+                PCWSTR(self.device.as_ptr()),
+                Some(&self.mode),
+                None,
+                CDS_FULLSCREEN,
+                None,
+            )
+        };
+        result == DISP_CHANGE_SUCCESSFUL
+    }
+
+    // Goes back to the mode stored in the registry.
+    fn restore(&self) {
+        unsafe {
+            ChangeDisplaySettingsExW(PCWSTR(self.device.as_ptr()), None, None, CDS_TYPE(0), None)
+        };
+    }
+}
+
+impl std::fmt::Debug for ExclusiveMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExclusiveMode")
+            .field("device", &String::from_utf16_lossy(&self.device))
+            .field("width", &self.mode.dmPelsWidth)
+            .field("height", &self.mode.dmPelsHeight)
+            .finish()
+    }
+}
+
+// Everything that contributes to GWL_STYLE. The features that touch the style
+// only change their own field here and recompute the whole thing, so they
+// can't undo each other.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct StyleState {
+    base: WINDOW_STYLE,
+    resizeable: bool,
+    buttons: WindowButtons,
+    // Minimum and maximum size, for turning off the maximize box when they
+    // match.
+    size_limits: ((u32, u32), (u32, u32)),
+    borderless_fullscreen: bool,
+    // Set by set_style_raw; nwin leaves GWL_STYLE alone from then on.
+    external: bool,
+}
+
+impl Default for StyleState {
+    fn default() -> Self {
+        Self {
+            base: WS_OVERLAPPEDWINDOW,
+            resizeable: true,
+            buttons: WindowButtons::all(),
+            size_limits: ((0, 0), (u32::MAX, u32::MAX)),
+            borderless_fullscreen: false,
+            external: false,
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+impl StyleState {
+    fn style(&self, visible: bool) -> WINDOW_STYLE {
+        let mut style = if self.borderless_fullscreen {
+            WS_POPUP
+        } else {
+            self.base
+        };
+        style |= WS_CLIPSIBLINGS;
+        if !self.resizeable {
+            style &= !WS_SIZEBOX;
+        }
+        let (min, max) = self.size_limits;
+        let buttons = crate::effective_buttons(self.buttons, self.resizeable, min, max);
+        if !buttons.contains(WindowButtons::MAXIMIZE) {
+            style &= !WS_MAXIMIZEBOX;
+        }
+        if !buttons.contains(WindowButtons::MINIMIZE) {
+            style &= !WS_MINIMIZEBOX;
+        }
+        if visible {
+            style |= WS_VISIBLE;
+        }
+        style
+    }
+}
+
+#[derive(Clone)]
 pub(crate) struct WindowInfo {
     hinstance: HINSTANCE,
     visible: bool,
@@ -76,37 +296,88 @@ pub(crate) struct WindowInfo {
     min_height: i32,
     max_width: i32,
     max_height: i32,
+    // Until the user sets a maximum, it tracks the screen size.
+    max_follows_screen: bool,
     parent: Option<HWND>,
     icon: HICON,
     icon_small: HICON,
     menu: Option<HMENU>,
     menu_name: String,
     style: WINDOW_STYLE,
+    style_state: StyleState,
     style_ex: WINDOW_EX_STYLE,
     class_name: String,
     class_id: WndClassId,
     title: String,
     cursor: HCURSOR,
     background: HBRUSH,
+    // Painted on WM_ERASEBKGND instead of the class brush, which is shared
+    // by every window.
+    background_color: Option<(u8, u8, u8)>,
     no_close: bool,
     focused: bool,
-    resizeable: bool,
+    // The focus last sent in Focused; see report_focus.
+    focus_reported: bool,
     theme: Theme,
+    theme_preference: Option<Theme>,
     has_frame: bool,
     fullscreen: FullscreenType,
-    non_fullscreen_style: WINDOW_STYLE,
+    // The outer rect to go back to when leaving fullscreen.
+    windowed_rect: Option<RECT>,
+    exclusive_mode: Option<ExclusiveMode>,
+    exclusive_focus_policy: ExclusiveFocusPolicy,
+    // The size and refresh rate asked for with
+    // set_exclusive_fullscreen_mode; the monitor's current mode otherwise.
+    exclusive_request: Option<((u32, u32), Option<u32>)>,
     size_state: WindowSizeState,
-    enabled_buttons: WindowButtons,
-    modifiers: Modifiers,
+    keys: KeyStateTracker,
+    mouse_buttons: MouseButtons,
+    pointer_kind: PointerKind,
+    // The last position sent in CursorMoved.
+    cursor_position: Option<PhysicalPosition<f64>>,
+    raw_mouse: bool,
+    raw_buttons: u32,
+    close_action: CloseAction,
+    // None until set_shadow is called, so DWM is left alone by default.
+    shadow: Option<bool>,
+    // Set once a handler panics; see main_wnd_proc.
+    poisoned: bool,
+    kind: WindowKind,
+    redraw: RedrawScheduler,
+    refresh_rate: RefreshRateTracker,
+    monitors: MonitorTracker,
+    // Lets Window::from_id hand out handles sharing the original Arc.
+    handle: Weak<HWND>,
     sender: Arc<RwLock<EventSender>>,
 }
 
+// A summary for logs; the handles, trackers and sender are only noise there.
+impl std::fmt::Debug for WindowInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = self
+            .handle
+            .upgrade()
+            .map_or_else(|| "none".to_owned(), |h| WindowId(h.0 as _).to_string());
+        f.debug_struct("WindowInfo")
+            .field("id", &format_args!("{}", id))
+            .field("title", &self.title)
+            .field("position", &(self.x, self.y))
+            .field("size", &(self.width, self.height))
+            .field("visible", &self.visible)
+            .field("focused", &self.focused)
+            .field("size_state", &self.size_state)
+            .field("fullscreen", &self.fullscreen)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Default for WindowInfo {
     fn default() -> Self {
         Self {
             width: CW_USEDEFAULT,
             height: CW_USEDEFAULT,
             style: WS_OVERLAPPEDWINDOW | WS_CLIPSIBLINGS,
+            style_state: StyleState::default(),
             style_ex: WS_EX_APPWINDOW,
             class_name: "nwin default".to_owned(),
             hinstance: get_instance().unwrap(),
@@ -118,6 +389,7 @@ impl Default for WindowInfo {
             max_width: unsafe { GetSystemMetrics(SM_CXSCREEN) } as _,
             min_height: 20,
             max_height: unsafe { GetSystemMetrics(SM_CYSCREEN) } as _,
+            max_follows_screen: true,
             parent: None,
             icon: unsafe { LoadIconW(None, IDI_APPLICATION).unwrap() },
             icon_small: unsafe { LoadIconW(None, IDI_APPLICATION).unwrap() },
@@ -126,16 +398,33 @@ impl Default for WindowInfo {
             class_id: WndClassId(0),
             cursor: unsafe { LoadCursorW(None, IDC_ARROW).unwrap() },
             background: HBRUSH(COLOR_WINDOW.0 as isize + 1),
+            background_color: None,
             no_close: false,
             focused: false,
-            resizeable: true,
-            theme: Theme::Light,
+            focus_reported: false,
+            theme: system_theme().unwrap_or_default(),
+            theme_preference: None,
             has_frame: false,
             fullscreen: FullscreenType::NotFullscreen,
-            non_fullscreen_style: WS_OVERLAPPEDWINDOW | WS_CLIPSIBLINGS,
+            windowed_rect: None,
+            exclusive_mode: None,
+            exclusive_focus_policy: ExclusiveFocusPolicy::default(),
+            exclusive_request: None,
             size_state: WindowSizeState::Other,
-            enabled_buttons: WindowButtons::all(),
-            modifiers: Modifiers::empty(),
+            keys: KeyStateTracker::default(),
+            mouse_buttons: MouseButtons::empty(),
+            pointer_kind: PointerKind::default(),
+            cursor_position: None,
+            raw_mouse: false,
+            raw_buttons: 0,
+            close_action: CloseAction::default(),
+            shadow: None,
+            poisoned: false,
+            kind: WindowKind::Normal,
+            redraw: RedrawScheduler::default(),
+            refresh_rate: RefreshRateTracker::default(),
+            monitors: MonitorTracker::default(),
+            handle: Weak::new(),
             sender: Arc::new(RwLock::new(EventSender::new())),
         }
     }
@@ -148,6 +437,61 @@ impl WindowInfo {
         Self::default()
     }
 
+    // Recomputes GWL_STYLE after one of its contributing properties changed.
+    fn apply_style(&mut self, hwnd: HWND) {
+        if self.style_state.external {
+            return;
+        }
+        let style = self.style_state.style(self.visible);
+        if style == self.style {
+            return;
+        }
+        self.style = style;
+        unsafe {
+            SetWindowLongPtrW(hwnd, GWL_STYLE, style.0 as _);
+            SetWindowPos(
+                hwnd,
+                None,
+                0,
+                0,
+                0,
+                0,
+                SWP_FRAMECHANGED | SWP_NOMOVE | SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+        }
+        self.apply_shadow(hwnd);
+    }
+
+    // A WS_POPUP window has no shadow of its own. With non-client rendering
+    // forced on and the frame extended 1px into the client area, DWM draws
+    // one; zero margins and the style's own policy take it away again.
+    fn apply_shadow(&self, hwnd: HWND) {
+        let Some(shadow) = self.shadow else {
+            return;
+        };
+        let (policy, inset) = if shadow {
+            (DWMNCRP_ENABLED, 1)
+        } else {
+            (DWMNCRP_USEWINDOWSTYLE, 0)
+        };
+        let margins = MARGINS {
+            cxLeftWidth: inset,
+            cxRightWidth: inset,
+            cyTopHeight: inset,
+            cyBottomHeight: inset,
+        };
+        // Fails without composition, where there are no shadows to have.
+        unsafe {
+            let _ = DwmSetWindowAttribute(
+                hwnd,
+                DWMWA_NCRENDERING_POLICY,
+                addr_of!(policy).cast(),
+                size_of::<DWMNCRENDERINGPOLICY>() as _,
+            );
+            let _ = DwmExtendFrameIntoClientArea(hwnd, addr_of!(margins));
+        }
+    }
+
     pub(crate) fn register(&mut self) -> Result<WndClassId, WIN32_ERROR> {
         let res = register_class(
             &self.menu_name,
@@ -166,63 +510,105 @@ impl WindowInfo {
         res
     }
 
-    pub(crate) fn create(&mut self) -> Result<HWND, WIN32_ERROR> {
+    // The info is handed to WM_NCCREATE, so the window procedure has it,
+    // sender included, from the first message on.
+    pub(crate) fn create(info: &Arc<RwLock<Self>>) -> Result<HWND, WIN32_ERROR> {
+        // Not held across CreateWindowExW, whose messages modify the info.
+        let this = info.read().unwrap().clone();
         create_window(
-            &self.class_name,
-            &self.title,
-            self.visible,
-            Some(self.style_ex),
-            Some(self.style),
-            self.x,
-            self.y,
-            self.width,
-            self.height,
-            self.parent,
-            self.menu,
-            self.hinstance,
+            &this.class_name,
+            &this.title,
+            this.visible,
+            Some(this.style_ex),
+            Some(this.style),
+            this.x,
+            this.y,
+            this.width,
+            this.height,
+            this.parent,
+            this.menu,
+            this.hinstance,
+            Some(Arc::as_ptr(info).cast()),
         )
     }
 }
 
+// Each window's info lives behind a pointer in its GWLP_USERDATA, set in
+// WM_NCCREATE and released in WM_NCDESTROY. This registry only serves
+// enumeration and lookups from other threads.
 lazy_static::lazy_static! {
-    static ref WINDOW_INFO: Arc<RwLock<HashMap<isize, WindowInfo>>> = Arc::new(RwLock::new(HashMap::new()));
+    static ref WINDOW_INFO: RwLock<HashMap<isize, Arc<RwLock<WindowInfo>>>> =
+        RwLock::new(HashMap::new());
+}
+
+// On the window's own thread the pointer can't be released underneath us,
+// since WM_NCDESTROY is handled there too, so messages never touch the
+// registry.
+fn try_window_info(hwnd: isize) -> Option<Arc<RwLock<WindowInfo>>> {
+    let hwnd = HWND(hwnd);
+    if unsafe { GetWindowThreadProcessId(hwnd, None) != GetCurrentThreadId() } {
+        return WINDOW_INFO.read().unwrap().get(&hwnd.0).cloned();
+    }
+    let ptr = unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *const RwLock<WindowInfo>;
+    if ptr.is_null() {
+        return None;
+    }
+    unsafe {
+        Arc::increment_strong_count(ptr);
+        Some(Arc::from_raw(ptr))
+    }
+}
+
+// Windows that are gone get a throwaway default, so late messages and calls
+// on stale handles go nowhere.
+fn window_info(hwnd: isize) -> Arc<RwLock<WindowInfo>> {
+    try_window_info(hwnd).unwrap_or_default()
+}
+
+// The dark title bar is DWMWA_USE_IMMERSIVE_DARK_MODE, which builds
+// before 20H1 knew as attribute 19. Before 1809 neither exists and the
+// frame just stays light.
+fn apply_theme(hwnd: HWND, theme: Theme) {
+    let dark = BOOL::from(theme == Theme::Dark);
+    let set = |attr| unsafe {
+        DwmSetWindowAttribute(hwnd, attr, addr_of!(dark).cast(), size_of::<BOOL>() as _).is_ok()
+    };
+    if !set(DWMWA_USE_IMMERSIVE_DARK_MODE) && !set(DWMWINDOWATTRIBUTE(19)) {
+        return;
+    }
+    // DWM only repaints the caption on the next frame change.
+    unsafe {
+        SetWindowPos(
+            hwnd,
+            None,
+            0,
+            0,
+            0,
+            0,
+            SWP_FRAMECHANGED | SWP_NOMOVE | SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+        );
+    }
+}
+
+fn modify_info<R>(hwnd: isize, f: impl FnOnce(&mut WindowInfo) -> R) -> R {
+    f(&mut window_info(hwnd).write().unwrap())
 }
 
 macro_rules! info_modify {
     ($hwnd:expr, $b:expr) => {
-        WINDOW_INFO
-            .clone()
-            .write()
-            .unwrap()
-            .entry($hwnd)
-            .and_modify($b)
-            .or_insert(WindowInfo::default())
-    };
-    ($hwnd:expr, $b:expr, $def:expr) => {
-        WINDOW_INFO
-            .clone()
-            .write()
-            .unwrap()
-            .entry($hwnd)
-            .and_modify($b)
-            .or_insert($def)
+        modify_info($hwnd, $b)
     };
 }
 
 macro_rules! info_get {
     ($hwnd:expr) => {
-        WINDOW_INFO
-            .clone()
-            .write()
-            .unwrap()
-            .entry($hwnd)
-            .or_default()
+        window_info($hwnd).read().unwrap()
     };
 }
 
 macro_rules! info_remove {
     ($hwnd:expr) => {
-        WINDOW_INFO.clone().write().unwrap().remove($hwnd)
+        WINDOW_INFO.write().unwrap().remove($hwnd)
     };
 }
 
@@ -236,28 +622,171 @@ macro_rules! send_ev {
 
 impl Window {
     pub fn try_new() -> Result<Self, WIN32_ERROR> {
+        Self::from_builder(crate::WindowBuilder::new())
+    }
+
+    pub(crate) fn from_builder(builder: crate::WindowBuilder) -> Result<Self, OsError> {
         let mut info = WindowInfo::new();
-        assert_eq!(info.style, WS_OVERLAPPEDWINDOW | WS_CLIPSIBLINGS);
+        info.kind = builder.kind;
+        info.background_color = builder.background_color;
+        if let Some(title) = builder.title {
+            info.title = title;
+        }
+        if let Some((width, height)) = builder.min_size {
+            let (min, max) = crate::limit_min(width, info.max_width as _);
+            info.min_width = min.min(i32::MAX as _) as _;
+            info.max_width = max.min(i32::MAX as _) as _;
+            let (min, max) = crate::limit_min(height, info.max_height as _);
+            info.min_height = min.min(i32::MAX as _) as _;
+            info.max_height = max.min(i32::MAX as _) as _;
+        }
+        if let Some((width, height)) = builder.max_size {
+            let (min, max) = crate::limit_max(info.min_width as _, width);
+            info.min_width = min.min(i32::MAX as _) as _;
+            info.max_width = max.min(i32::MAX as _) as _;
+            let (min, max) = crate::limit_max(info.min_height as _, height);
+            info.min_height = min.min(i32::MAX as _) as _;
+            info.max_height = max.min(i32::MAX as _) as _;
+            info.max_follows_screen = false;
+        }
+        info.style_state.resizeable = builder.resizeable;
+        info.style_state.size_limits = (
+            (info.min_width as _, info.min_height as _),
+            (info.max_width as _, info.max_height as _),
+        );
+        match builder.kind {
+            WindowKind::Normal => {}
+            WindowKind::Popup => {
+                info.style_state.base = WS_POPUP;
+                info.style_ex = WS_EX_TOOLWINDOW | WS_EX_TOPMOST;
+            }
+            WindowKind::Tooltip => {
+                info.style_state.base = WS_POPUP;
+                info.style_ex = WS_EX_TOOLWINDOW | WS_EX_TOPMOST | WS_EX_NOACTIVATE;
+            }
+            WindowKind::Splash => {
+                info.style_state.base = WS_POPUP;
+                info.style_ex = WS_EX_TOOLWINDOW;
+            }
+        }
+        info.style = info.style_state.style(info.visible);
+        // Owning popups by the active window keeps them above it and hides
+        // them along with it.
+        if builder.kind != WindowKind::Normal {
+            let owner = unsafe { GetActiveWindow() };
+            if owner.0 != 0 {
+                info.parent = Some(owner);
+            }
+        }
+        // CreateWindowExW takes the outer size.
+        if let Some(size) = builder.inner_size {
+            let mut rect = RECT {
+                left: 0,
+                top: 0,
+                right: (size.width.min(i32::MAX as _) as i32).clamp(info.min_width, info.max_width),
+                bottom: (size.height.min(i32::MAX as _) as i32)
+                    .clamp(info.min_height, info.max_height),
+            };
+            unsafe {
+                AdjustWindowRectEx(
+                    addr_of_mut!(rect),
+                    info.style,
+                    info.menu.is_some(),
+                    info.style_ex,
+                )
+            };
+            info.width = rect.right - rect.left;
+            info.height = rect.bottom - rect.top;
+        }
+        if let Some(position) = builder.position {
+            info.x = position.x;
+            info.y = position.y;
+        }
+        let mut window = Self::with_info(info)?;
+        apply_theme(*window.hwnd, crate::WindowT::theme(&window));
+        if builder.inner_size.is_some() || builder.position.is_some() {
+            window.sync_geometry();
+        }
+        if builder.visible {
+            crate::WindowT::show(&mut window);
+        }
+        Ok(window)
+    }
+
+    // The info starts out with the outer geometry CreateWindowExW was given,
+    // but tracks the client area's.
+    fn sync_geometry(&self) {
+        let mut client = RECT::default();
+        let mut origin = POINT::default();
+        unsafe {
+            GetClientRect(*self.hwnd, addr_of_mut!(client));
+            ClientToScreen(*self.hwnd, addr_of_mut!(origin));
+        }
+        info_modify!(self.hwnd.0, |info| {
+            info.x = origin.x;
+            info.y = origin.y;
+            info.width = client.right - client.left;
+            info.height = client.bottom - client.top;
+        });
+    }
+
+    fn with_info(mut info: WindowInfo) -> Result<Self, WIN32_ERROR> {
         let class_id = if CLASS_ID.load(std::sync::atomic::Ordering::Relaxed) == 0 {
             info.register()?
         } else {
             WndClassId(CLASS_ID.load(std::sync::atomic::Ordering::Relaxed))
         };
         info.class_id = class_id;
-        let hwnd = info.create()?;
+        let (style, hinstance) = (info.style, info.hinstance);
+        let hwnd = WindowInfo::create(&Arc::new(RwLock::new(info)))?;
+        crate::WINDOW_CREATED.store(true, Ordering::Relaxed);
         assert_eq!(
-            info.style,
+            style,
             WINDOW_STYLE(unsafe { GetWindowLongPtrW(hwnd, GWL_STYLE) } as _)
         );
 
-        info_modify!(hwnd.0, |v| *v = info.clone(), info);
+        let hwnd = Arc::new(hwnd);
+        info_modify!(hwnd.0, |info| info.handle = Arc::downgrade(&hwnd));
 
-        assert_eq!(
-            info_get!(hwnd.0).style,
-            WS_OVERLAPPEDWINDOW | WS_CLIPSIBLINGS
-        );
-        Ok(Self {
-            hwnd: Arc::new(hwnd),
+        Ok(Self { hwnd, hinstance })
+    }
+
+    // MINMAXINFO only constrains later resizes, so bring the current size
+    // into range right away. Runs outside the info lock, since resizing sends
+    // WM_SIZE synchronously.
+    fn fit_size_limits(&mut self) {
+        info_modify!(self.hwnd.0, |info| {
+            info.style_state.size_limits = (
+                (info.min_width as _, info.min_height as _),
+                (info.max_width as _, info.max_height as _),
+            );
+            info.apply_style(*self.hwnd);
+        });
+        let info = info_get!(self.hwnd.0).clone();
+        if info.width == CW_USEDEFAULT || info.height == CW_USEDEFAULT {
+            return;
+        }
+        let width = info.width.clamp(info.min_width, info.max_width);
+        let height = info.height.clamp(info.min_height, info.max_height);
+        if (width, height) != (info.width, info.height) {
+            crate::WindowT::request_inner_size(self, PhysicalSize::new(width as _, height as _));
+        }
+    }
+
+    // Destroys the window without waiting for the handles to be dropped.
+    // Only works on the thread that created it.
+    pub(crate) fn destroy_window(&self) {
+        unsafe { DestroyWindow(*self.hwnd) };
+    }
+
+    // Handles can be used from any thread, but calls that send messages to
+    // the window block until the thread that created it pumps its queue.
+    pub fn from_id(id: WindowId) -> Option<Self> {
+        let info = WINDOW_INFO.read().unwrap().get(&(id.0 as isize))?.clone();
+        let w = info.read().unwrap();
+        Some(Self {
+            hwnd: w.handle.upgrade()?,
+            hinstance: w.hinstance,
         })
     }
 }
@@ -265,107 +794,741 @@ impl Window {
 impl Drop for Window {
     fn drop(&mut self) {
         if Arc::strong_count(&self.hwnd) <= 1 {
-            info_remove!(&self.hwnd.0);
+            // HWNDs are recycled, so one destroyed through destroy_window may
+            // already belong to another window by now.
+            let owned = WINDOW_INFO
+                .read()
+                .unwrap()
+                .get(&self.hwnd.0)
+                .is_some_and(|info| {
+                    info.read()
+                        .unwrap()
+                        .handle
+                        .ptr_eq(&Arc::downgrade(&self.hwnd))
+                });
+            if owned {
+                // Sends WM_DESTROY, which reports Destroyed. Only works on
+                // the thread that created the window.
+                unsafe { DestroyWindow(*self.hwnd) };
+                info_remove!(&self.hwnd.0);
+            }
         }
     }
 }
 
-impl WindowIdExt for WindowId {
-    fn next_event(&self) {
-        let mut msg = MSG::default();
-        if unsafe { PeekMessageW(addr_of_mut!(msg), HWND(self.0 as _), 0, 0, PM_REMOVE) }.as_bool()
-        {
+// All windows on a thread share one message queue, so it's drained once per
+// pump rather than filtered per window; filtering left messages for one window
+// queued while another's were dispatched. WM_HOTKEY is skipped, since
+// next_hotkey collects those itself.
+pub(crate) fn pump_events() {
+    let mut msg = MSG::default();
+    for (min, max) in [(0, WM_HOTKEY - 1), (WM_HOTKEY + 1, u32::MAX)] {
+        while unsafe { PeekMessageW(addr_of_mut!(msg), HWND(0), min, max, PM_REMOVE) }.as_bool() {
             unsafe { DispatchMessageW(addr_of_mut!(msg)) };
         }
     }
 }
 
-fn get_instance() -> Option<HINSTANCE> {
-    unsafe { GetModuleHandleW(None).ok() }
+impl WindowIdExt for WindowId {
+    fn next_event(&self) {
+        info_modify!(self.0 as _, |info| {
+            if info.visible
+                && info.size_state != WindowSizeState::Minimized
+                && info.redraw.poll(Instant::now())
+            {
+                info.sender
+                    .write()
+                    .unwrap()
+                    .send(*self, WindowEvent::RedrawRequested);
+            }
+        });
+    }
+
+    // Messages reach wnd_proc through whoever dispatches them; only the
+    // redraw schedule is left to run.
+    fn dispatch_pending(&self) {
+        self.next_event();
+    }
+
+    fn sender(&self) -> Option<Arc<RwLock<EventSender>>> {
+        WINDOW_INFO
+            .read()
+            .unwrap()
+            .get(&(self.0 as isize))
+            .map(|info| info.read().unwrap().sender.clone())
+    }
 }
 
-#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
-pub(crate) struct WndClassId(u16);
+fn refresh_rate(hwnd: HWND) -> Option<u32> {
+    let monitor = unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST) };
+    let mut mi = MONITORINFOEXW::default();
+    mi.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as _;
+    if unsafe { GetMonitorInfoW(monitor, addr_of_mut!(mi) as *mut MONITORINFO) }.as_bool() == false
+    {
+        return None;
+    }
+    device_refresh_rate(&mi.szDevice)
+}
 
-type WndProc = unsafe extern "system" fn(HWND, u32, WPARAM, LPARAM) -> LRESULT;
+// The mode exclusive fullscreen switches the window's monitor to.
+fn exclusive_mode(hwnd: HWND, request: Option<((u32, u32), Option<u32>)>) -> Option<ExclusiveMode> {
+    let monitor = unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST) };
+    let mut mi = MONITORINFOEXW::default();
+    mi.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as _;
+    if unsafe { GetMonitorInfoW(monitor, addr_of_mut!(mi) as *mut MONITORINFO) }.as_bool() == false
+    {
+        return None;
+    }
+    let mut mode = DEVMODEW {
+        dmSize: size_of::<DEVMODEW>() as _,
+        ..Default::default()
+    };
+    if unsafe {
+        EnumDisplaySettingsW(
+            PCWSTR(mi.szDevice.as_ptr()),
+            ENUM_CURRENT_SETTINGS,
+            addr_of_mut!(mode),
+        )
+    }
+    .as_bool()
+        == false
+    {
+        return None;
+    }
+    if let Some(((width, height), rate)) = request {
+        mode.dmPelsWidth = width;
+        mode.dmPelsHeight = height;
+        mode.dmFields |= DM_PELSWIDTH | DM_PELSHEIGHT;
+        if let Some(rate) = rate {
+            mode.dmDisplayFrequency = rate;
+            mode.dmFields |= DM_DISPLAYFREQUENCY;
+        }
+    }
+    Some(ExclusiveMode {
+        device: mi.szDevice,
+        mode,
+    })
+}
 
-fn register_class(
-    menu_name: &str,
-    class_name: &str,
-    icon: Option<HICON>,
-    icon_small: Option<HICON>,
-    cursor: Option<HCURSOR>,
-    background: Option<HBRUSH>,
-    no_close: bool,
-) -> Result<WndClassId, WIN32_ERROR> {
-    let close = if no_close {
-        CS_NOCLOSE
+// The outer rect is saved on the way into fullscreen, kept while switching
+// between fullscreen kinds, and handed back on the way out.
+fn track_windowed_rect(
+    saved: &mut Option<RECT>,
+    from: FullscreenType,
+    to: FullscreenType,
+    current: impl FnOnce() -> RECT,
+) -> Option<RECT> {
+    if from == FullscreenType::NotFullscreen {
+        *saved = Some(current());
+    }
+    if to == FullscreenType::NotFullscreen {
+        saved.take()
     } else {
-        WNDCLASS_STYLES(0)
+        None
+    }
+}
+
+// The monitor's bounds, which move and change size with its mode.
+fn monitor_rect(hwnd: HWND) -> Option<RECT> {
+    let monitor = unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST) };
+    let mut mi = MONITORINFO {
+        cbSize: size_of::<MONITORINFO>() as _,
+        ..Default::default()
     };
-    let mut menu_name_w = menu_name.encode_utf16().collect::<Vec<_>>();
-    menu_name_w.push(0x0000);
-    let mut class_name_w = class_name.encode_utf16().collect::<Vec<_>>();
-    class_name_w.push(0x0000);
+    if unsafe { GetMonitorInfoW(monitor, addr_of_mut!(mi)) }.as_bool() == false {
+        return None;
+    }
+    Some(mi.rcMonitor)
+}
 
-    let wndclass = WNDCLASSEXW {
-        cbSize: size_of::<WNDCLASSEXW>() as u32,
-        style: CS_DBLCLKS | close,
-        lpfnWndProc: Some(main_wnd_proc),
-        cbClsExtra: 0,
-        cbWndExtra: 0,
-        hInstance: get_instance().unwrap(),
-        hIcon: icon.unwrap_or_else(|| unsafe { LoadIconW(None, IDI_APPLICATION) }.unwrap()),
-        hCursor: cursor.unwrap_or_else(|| unsafe { LoadCursorW(None, IDI_APPLICATION) }.unwrap()),
-        hbrBackground: background.unwrap_or(HBRUSH((COLOR_WINDOW.0 + 1) as _)),
-        lpszMenuName: windows::core::PCWSTR(menu_name_w.as_ptr()),
-        lpszClassName: windows::core::PCWSTR(class_name_w.as_ptr()),
-        hIconSm: icon_small.unwrap_or_else(|| unsafe { LoadIconW(None, IDI_APPLICATION) }.unwrap()),
+// EnumDisplaySettingsW only reports whole hertz.
+fn device_refresh_rate(device: &[u16]) -> Option<u32> {
+    let mut dm = DEVMODEW {
+        dmSize: size_of::<DEVMODEW>() as _,
+        ..Default::default()
     };
+    if unsafe {
+        EnumDisplaySettingsW(
+            PCWSTR(device.as_ptr()),
+            ENUM_CURRENT_SETTINGS,
+            addr_of_mut!(dm),
+        )
+    }
+    .as_bool()
+        == false
+    {
+        return None;
+    }
 
-    let res = unsafe { RegisterClassExW(addr_of!(wndclass)) };
-    if res == 0 {
-        Err(unsafe { GetLastError() })
+    // 0 and 1 both mean "the hardware's default rate".
+    if dm.dmDisplayFrequency > 1 {
+        Some(dm.dmDisplayFrequency * 1000)
     } else {
-        Ok(WndClassId(res))
+        None
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn create_window(
-    class_name: &str,
-    window_name: &str,
-    visible: bool,
-    style_ex: Option<WINDOW_EX_STYLE>,
-    style: Option<WINDOW_STYLE>,
-    x: i32,
-    y: i32,
-    width: i32,
-    height: i32,
-    parent: Option<HWND>,
-    menu: Option<HMENU>,
-    hinstance: HINSTANCE,
-) -> Result<HWND, WIN32_ERROR> {
-    let mut class_name_w = class_name.encode_utf16().collect::<Vec<_>>();
-    class_name_w.push(0x0000);
+// Tags the key presses sent by set_lock_state, so the window procedure can
+// tell them from the user's.
+const LOCK_KEY_SIGNATURE: usize = 0x6E77_696E;
 
-    let mut window_name_w = window_name.encode_utf16().collect::<Vec<_>>();
-    window_name_w.push(0x0000);
+fn lock_vk(lock: LockKey) -> VIRTUAL_KEY {
+    match lock {
+        LockKey::CapsLock => VK_CAPITAL,
+        LockKey::NumLock => VK_NUMLOCK,
+        LockKey::ScrollLock => VK_SCROLL,
+    }
+}
 
-    let hwnd = unsafe {
-        CreateWindowExW(
-            style_ex.unwrap_or(WINDOW_EX_STYLE(0)),
-            PCWSTR(class_name_w.as_ptr()),
-            PCWSTR(window_name_w.as_ptr()),
-            style.unwrap_or(WINDOW_STYLE(0)) | WS_CLIPSIBLINGS,
-            x,
-            y,
-            width,
-            height,
+pub(crate) fn lock_state(lock: LockKey) -> Option<bool> {
+    Some(unsafe { GetKeyState(lock_vk(lock).0 as _) } & 1 != 0)
+}
+
+// The lock state can only be changed by pressing the key, so a press and
+// release are sent when it differs.
+pub(crate) fn set_lock_state(lock: LockKey, enabled: bool) -> Result<(), LockError> {
+    if lock_state(lock) == Some(enabled) {
+        return Ok(());
+    }
+    let extended = if lock == LockKey::NumLock {
+        KEYEVENTF_EXTENDEDKEY
+    } else {
+        KEYBD_EVENT_FLAGS(0)
+    };
+    let input = |flags| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: lock_vk(lock),
+                wScan: 0,
+                dwFlags: extended | flags,
+                time: 0,
+                dwExtraInfo: LOCK_KEY_SIGNATURE,
+            },
+        },
+    };
+    let inputs = [input(KEYBD_EVENT_FLAGS(0)), input(KEYEVENTF_KEYUP)];
+    if unsafe { SendInput(&inputs, size_of::<INPUT>() as _) } != inputs.len() as u32 {
+        return Err(LockError::Os);
+    }
+    for (hwnd, info) in WINDOW_INFO.read().unwrap().iter() {
+        let mut info = info.write().unwrap();
+        if let Some(m) = info.keys.set_lock(lock.modifier(), enabled) {
+            info.sender
+                .write()
+                .unwrap()
+                .send(WindowId(*hwnd as _), WindowEvent::ModifiersChanged(m));
+        }
+    }
+    Ok(())
+}
+
+// WM_ENTERSIZEMOVE and WM_EXITSIZEMOVE bracket the user's drags.
+pub(crate) const REPORTS_SIZE_MOVE: bool = true;
+
+pub(crate) const WINDOW_ID_KIND: &str = "HWND";
+
+pub(crate) fn init_dpi(mode: DpiMode) -> Result<(), DpiError> {
+    let context = match mode {
+        DpiMode::PerMonitorV2 => DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        DpiMode::SystemAware => DPI_AWARENESS_CONTEXT_SYSTEM_AWARE,
+        DpiMode::Unaware => DPI_AWARENESS_CONTEXT_UNAWARE,
+    };
+    if unsafe { SetProcessDpiAwarenessContext(context) }.as_bool() {
+        Ok(())
+    } else {
+        Err(DpiError::Os)
+    }
+}
+
+pub(crate) fn monitors() -> Vec<Monitor> {
+    unsafe extern "system" fn push(
+        monitor: HMONITOR,
+        _hdc: HDC,
+        _rect: *mut RECT,
+        data: LPARAM,
+    ) -> BOOL {
+        (*(data.0 as *mut Vec<HMONITOR>)).push(monitor);
+        true.into()
+    }
+    let mut handles = Vec::<HMONITOR>::new();
+    unsafe { EnumDisplayMonitors(None, None, Some(push), LPARAM(addr_of_mut!(handles) as _)) };
+    handles.into_iter().filter_map(monitor_info).collect()
+}
+
+fn monitor_info(monitor: HMONITOR) -> Option<Monitor> {
+    let mut mi = MONITORINFOEXW::default();
+    mi.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as _;
+    if unsafe { GetMonitorInfoW(monitor, addr_of_mut!(mi) as *mut MONITORINFO) }.as_bool() == false
+    {
+        return None;
+    }
+    let wide = |s: &[u16]| {
+        String::from_utf16_lossy(&s[..s.iter().position(|c| *c == 0).unwrap_or(s.len())])
+    };
+    // \\.\DISPLAYn can be renumbered when adapters change; the interface
+    // path is derived from the monitor's EDID instead.
+    let mut dd = DISPLAY_DEVICEW {
+        cb: size_of::<DISPLAY_DEVICEW>() as _,
+        ..Default::default()
+    };
+    let (id, name) = if unsafe {
+        EnumDisplayDevicesW(
+            PCWSTR(mi.szDevice.as_ptr()),
+            0,
+            addr_of_mut!(dd),
+            EDD_GET_DEVICE_INTERFACE_NAME,
+        )
+    }
+    .as_bool()
+    {
+        (wide(&dd.DeviceID), wide(&dd.DeviceString))
+    } else {
+        (wide(&mi.szDevice), wide(&mi.szDevice))
+    };
+    let rect = mi.monitorInfo.rcMonitor;
+    Some(Monitor {
+        id: MonitorId(id),
+        name,
+        position: PhysicalPosition::new(rect.left, rect.top),
+        size: PhysicalSize::new((rect.right - rect.left) as _, (rect.bottom - rect.top) as _),
+        refresh_rate_millihertz: device_refresh_rate(&mi.szDevice),
+        primary: mi.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0,
+    })
+}
+
+// Monitors coming and going change the desktop, which is announced with
+// WM_DISPLAYCHANGE.
+fn update_monitors(hwnd: HWND, info: &mut WindowInfo) {
+    for ev in info.monitors.update(monitors()) {
+        info.sender.write().unwrap().send(WindowId(hwnd.0 as _), ev);
+    }
+}
+
+fn update_refresh_rate(hwnd: HWND, info: &mut WindowInfo) {
+    if let Some(rate) = info.refresh_rate.update(refresh_rate(hwnd)) {
+        info.sender
+            .write()
+            .unwrap()
+            .send(WindowId(hwnd.0 as _), WindowEvent::RefreshRateChanged(rate));
+    }
+}
+
+// A minimal IDropTarget taking files, URLs and text. It's only ever called on
+// the window's thread; RegisterDragDrop holds the reference keeping it alive
+// until RevokeDragDrop.
+#[repr(C)]
+struct DropTarget {
+    vtbl: &'static IDropTarget_Vtbl,
+    refs: AtomicU32,
+    hwnd: HWND,
+    accepted: Cell<bool>,
+}
+
+static DROP_TARGET_VTBL: IDropTarget_Vtbl = IDropTarget_Vtbl {
+    base__: IUnknown_Vtbl {
+        QueryInterface: drop_target_query_interface,
+        AddRef: drop_target_add_ref,
+        Release: drop_target_release,
+    },
+    DragEnter: drop_target_drag_enter,
+    DragOver: drop_target_drag_over,
+    DragLeave: drop_target_drag_leave,
+    Drop: drop_target_drop,
+};
+
+impl DropTarget {
+    // The interface owns the only reference.
+    unsafe fn into_interface(hwnd: HWND) -> IDropTarget {
+        IDropTarget::from_raw(
+            Box::into_raw(Box::new(Self {
+                vtbl: &DROP_TARGET_VTBL,
+                refs: AtomicU32::new(1),
+                hwnd,
+                accepted: Cell::new(false),
+            }))
+            .cast(),
+        )
+    }
+
+    // Copying is preferred; browsers only offer linking for some URLs.
+    fn effect(&self, allowed: DROPEFFECT) -> DROPEFFECT {
+        if !self.accepted.get() {
+            DROPEFFECT_NONE
+        } else if allowed.0 & DROPEFFECT_COPY.0 != 0 {
+            DROPEFFECT_COPY
+        } else if allowed.0 & DROPEFFECT_LINK.0 != 0 {
+            DROPEFFECT_LINK
+        } else {
+            DROPEFFECT_NONE
+        }
+    }
+}
+
+unsafe extern "system" fn drop_target_query_interface(
+    this: *mut c_void,
+    iid: &GUID,
+    interface: *mut *const c_void,
+) -> HRESULT {
+    if *iid == IUnknown::IID || *iid == IDropTarget::IID {
+        drop_target_add_ref(this);
+        *interface = this;
+        S_OK
+    } else {
+        *interface = core::ptr::null();
+        E_NOINTERFACE
+    }
+}
+
+unsafe extern "system" fn drop_target_add_ref(this: *mut c_void) -> u32 {
+    (*this.cast::<DropTarget>())
+        .refs
+        .fetch_add(1, Ordering::Relaxed)
+        + 1
+}
+
+unsafe extern "system" fn drop_target_release(this: *mut c_void) -> u32 {
+    let refs = (*this.cast::<DropTarget>())
+        .refs
+        .fetch_sub(1, Ordering::AcqRel)
+        - 1;
+    if refs == 0 {
+        drop(Box::from_raw(this.cast::<DropTarget>()));
+    }
+    refs
+}
+
+unsafe extern "system" fn drop_target_drag_enter(
+    this: *mut c_void,
+    data: *mut c_void,
+    _keys: MODIFIERKEYS_FLAGS,
+    _pt: POINTL,
+    effect: *mut DROPEFFECT,
+) -> HRESULT {
+    let target = &*this.cast::<DropTarget>();
+    let accepted = IDataObject::from_raw_borrowed(&data).is_some_and(|d| drop_format(d).is_some());
+    target.accepted.set(accepted);
+    *effect = target.effect(*effect);
+    S_OK
+}
+
+unsafe extern "system" fn drop_target_drag_over(
+    this: *mut c_void,
+    _keys: MODIFIERKEYS_FLAGS,
+    _pt: POINTL,
+    effect: *mut DROPEFFECT,
+) -> HRESULT {
+    *effect = (*this.cast::<DropTarget>()).effect(*effect);
+    S_OK
+}
+
+unsafe extern "system" fn drop_target_drag_leave(this: *mut c_void) -> HRESULT {
+    (*this.cast::<DropTarget>()).accepted.set(false);
+    S_OK
+}
+
+unsafe extern "system" fn drop_target_drop(
+    this: *mut c_void,
+    data: *mut c_void,
+    _keys: MODIFIERKEYS_FLAGS,
+    _pt: POINTL,
+    effect: *mut DROPEFFECT,
+) -> HRESULT {
+    let target = &*this.cast::<DropTarget>();
+    let allowed = *effect;
+    *effect = DROPEFFECT_NONE;
+    let Some(data) = IDataObject::from_raw_borrowed(&data) else {
+        return S_OK;
+    };
+    let Some(format) = drop_format(data) else {
+        return S_OK;
+    };
+    let Ok(mut medium) = data.GetData(&formatetc(format)) else {
+        return S_OK;
+    };
+
+    let hglobal = medium.Anonymous.hGlobal;
+    let events = if format == CF_HDROP.0 {
+        let hdrop = HDROP(hglobal.0);
+        (0..DragQueryFileW(hdrop, u32::MAX, None))
+            .map(|i| {
+                let len = DragQueryFileW(hdrop, i, None) as usize;
+                let mut buf = vec![0u16; len + 1];
+                DragQueryFileW(hdrop, i, Some(&mut buf));
+                WindowEvent::DroppedFile(OsString::from_wide(&buf[..len]).into())
+            })
+            .collect()
+    } else {
+        let text = GlobalLock(hglobal) as *const u16;
+        let text = slice::from_raw_parts(text, GlobalSize(hglobal) / 2);
+        let len = text.iter().position(|&c| c == 0).unwrap_or(text.len());
+        let text = String::from_utf16_lossy(&text[..len]);
+        GlobalUnlock(hglobal);
+        if format == CF_UNICODETEXT.0 {
+            vec![WindowEvent::DroppedText(text)]
+        } else {
+            vec![WindowEvent::DroppedUri(text)]
+        }
+    };
+    ReleaseStgMedium(addr_of_mut!(medium));
+
+    for ev in events {
+        send_ev!(target.hwnd.0, ev);
+    }
+    *effect = target.effect(allowed);
+    target.accepted.set(false);
+    S_OK
+}
+
+// Has no state, and DoDragDrop releases it before returning, so a single
+// static instance without reference counting does.
+#[repr(C)]
+struct DropSource {
+    vtbl: &'static IDropSource_Vtbl,
+}
+
+static DROP_SOURCE: DropSource = DropSource {
+    vtbl: &IDropSource_Vtbl {
+        base__: IUnknown_Vtbl {
+            QueryInterface: drop_source_query_interface,
+            AddRef: drop_source_add_ref,
+            Release: drop_source_add_ref,
+        },
+        QueryContinueDrag: drop_source_query_continue_drag,
+        GiveFeedback: drop_source_give_feedback,
+    },
+};
+
+unsafe extern "system" fn drop_source_query_interface(
+    this: *mut c_void,
+    iid: &GUID,
+    interface: *mut *const c_void,
+) -> HRESULT {
+    if *iid == IUnknown::IID || *iid == IDropSource::IID {
+        *interface = this;
+        S_OK
+    } else {
+        *interface = core::ptr::null();
+        E_NOINTERFACE
+    }
+}
+
+unsafe extern "system" fn drop_source_add_ref(_this: *mut c_void) -> u32 {
+    1
+}
+
+unsafe extern "system" fn drop_source_query_continue_drag(
+    _this: *mut c_void,
+    escape: BOOL,
+    keys: MODIFIERKEYS_FLAGS,
+) -> HRESULT {
+    if escape.as_bool() {
+        DRAGDROP_S_CANCEL
+    } else if keys.0 & (MK_LBUTTON.0 | MK_RBUTTON.0 | MK_MBUTTON.0) == 0 {
+        DRAGDROP_S_DROP
+    } else {
+        S_OK
+    }
+}
+
+unsafe extern "system" fn drop_source_give_feedback(
+    _this: *mut c_void,
+    _effect: DROPEFFECT,
+) -> HRESULT {
+    DRAGDROP_S_USEDEFAULTCURSORS
+}
+
+// There's no general mapping from HRESULTs to Win32 error codes, so they're
+// passed through as is.
+fn hresult_error(hr: HRESULT) -> OsError {
+    WIN32_ERROR(hr.0 as u32)
+}
+
+fn formatetc(format: u16) -> FORMATETC {
+    FORMATETC {
+        cfFormat: format,
+        ptd: core::ptr::null_mut(),
+        dwAspect: DVASPECT_CONTENT.0,
+        lindex: -1,
+        tymed: TYMED_HGLOBAL.0 as _,
+    }
+}
+
+// Files win over a URL, which wins over plain text; browsers offer all of
+// them for a dragged link.
+fn drop_format(data: &IDataObject) -> Option<u16> {
+    let url: Vec<u16> = "UniformResourceLocatorW\0".encode_utf16().collect();
+    let url = unsafe { RegisterClipboardFormatW(PCWSTR(url.as_ptr())) } as u16;
+    [CF_HDROP.0, url, CF_UNICODETEXT.0]
+        .into_iter()
+        .find(|&f| unsafe { data.QueryGetData(&formatetc(f)) } == S_OK)
+}
+
+// Other applications hold the clipboard open for a moment while they read it.
+fn open_clipboard() -> Result<(), ClipboardError> {
+    for _ in 0..10 {
+        if unsafe { OpenClipboard(None) }.as_bool() {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Err(ClipboardError::Os)
+}
+
+// Registered by browsers and image editors for lossless copies.
+fn png_format() -> u32 {
+    let name: Vec<u16> = "PNG\0".encode_utf16().collect();
+    unsafe { RegisterClipboardFormatW(PCWSTR(name.as_ptr())) }
+}
+
+// The clipboard owns the memory once SetClipboardData succeeds.
+unsafe fn set_clipboard_bytes(format: u32, data: &[u8]) -> Result<(), ClipboardError> {
+    let hglobal = GlobalAlloc(GMEM_MOVEABLE, data.len()).map_err(|_| ClipboardError::Os)?;
+    let ptr = GlobalLock(hglobal).cast::<u8>();
+    core::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+    GlobalUnlock(hglobal);
+    if SetClipboardData(format, HANDLE(hglobal.0)).is_err() {
+        let _ = GlobalFree(hglobal);
+        return Err(ClipboardError::Os);
+    }
+    Ok(())
+}
+
+unsafe fn clipboard_bytes(format: u32) -> Option<Vec<u8>> {
+    if !IsClipboardFormatAvailable(format).as_bool() {
+        return None;
+    }
+    let hglobal = HGLOBAL(GetClipboardData(format).ok()?.0);
+    let ptr = GlobalLock(hglobal).cast::<u8>();
+    if ptr.is_null() {
+        return None;
+    }
+    let data = slice::from_raw_parts(ptr, GlobalSize(hglobal)).to_vec();
+    GlobalUnlock(hglobal);
+    Some(data)
+}
+
+// CF_DIBV5 for everything that reads bitmaps, which Windows also converts
+// to CF_DIB and CF_BITMAP on request, and PNG for the alpha channel.
+pub(crate) fn set_clipboard_image(image: RgbaImage) -> Result<(), ClipboardError> {
+    open_clipboard()?;
+    let result = unsafe {
+        if EmptyClipboard().as_bool() {
+            set_clipboard_bytes(CF_DIBV5.0 as _, &clipboard::encode_dib(&image))
+                .and_then(|_| set_clipboard_bytes(png_format(), &clipboard::encode_png(&image)))
+        } else {
+            Err(ClipboardError::Os)
+        }
+    };
+    unsafe { CloseClipboard() };
+    result
+}
+
+// PNG first, since it survives the round trip exactly.
+pub(crate) fn clipboard_image() -> Option<RgbaImage> {
+    open_clipboard().ok()?;
+    let image = unsafe {
+        clipboard_bytes(png_format())
+            .and_then(|png| clipboard::decode_png(&png))
+            .or_else(|| clipboard_bytes(CF_DIBV5.0 as _).and_then(|d| clipboard::decode_dib(&d)))
+            .or_else(|| clipboard_bytes(CF_DIB.0 as _).and_then(|d| clipboard::decode_dib(&d)))
+    };
+    unsafe { CloseClipboard() };
+    image
+}
+
+fn is_topmost(hwnd: HWND) -> bool {
+    let style_ex = unsafe { GetWindowLongPtrW(hwnd, GWL_EXSTYLE) } as u32;
+    style_ex & WS_EX_TOPMOST.0 != 0
+}
+
+fn get_instance() -> Option<HINSTANCE> {
+    unsafe { GetModuleHandleW(None).ok() }
+}
+
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub(crate) struct WndClassId(u16);
+
+type WndProc = unsafe extern "system" fn(HWND, u32, WPARAM, LPARAM) -> LRESULT;
+
+fn register_class(
+    menu_name: &str,
+    class_name: &str,
+    icon: Option<HICON>,
+    icon_small: Option<HICON>,
+    cursor: Option<HCURSOR>,
+    background: Option<HBRUSH>,
+    no_close: bool,
+) -> Result<WndClassId, WIN32_ERROR> {
+    let close = if no_close {
+        CS_NOCLOSE
+    } else {
+        WNDCLASS_STYLES(0)
+    };
+    let mut menu_name_w = menu_name.encode_utf16().collect::<Vec<_>>();
+    menu_name_w.push(0x0000);
+    let mut class_name_w = class_name.encode_utf16().collect::<Vec<_>>();
+    class_name_w.push(0x0000);
+
+    let wndclass = WNDCLASSEXW {
+        cbSize: size_of::<WNDCLASSEXW>() as u32,
+        style: CS_DBLCLKS | close,
+        lpfnWndProc: Some(main_wnd_proc),
+        cbClsExtra: 0,
+        cbWndExtra: 0,
+        hInstance: get_instance().unwrap(),
+        hIcon: icon.unwrap_or_else(|| unsafe { LoadIconW(None, IDI_APPLICATION) }.unwrap()),
+        hCursor: cursor.unwrap_or_else(|| unsafe { LoadCursorW(None, IDI_APPLICATION) }.unwrap()),
+        hbrBackground: background.unwrap_or(HBRUSH((COLOR_WINDOW.0 + 1) as _)),
+        lpszMenuName: windows::core::PCWSTR(menu_name_w.as_ptr()),
+        lpszClassName: windows::core::PCWSTR(class_name_w.as_ptr()),
+        hIconSm: icon_small.unwrap_or_else(|| unsafe { LoadIconW(None, IDI_APPLICATION) }.unwrap()),
+    };
+
+    let res = unsafe { RegisterClassExW(addr_of!(wndclass)) };
+    if res == 0 {
+        Err(unsafe { GetLastError() })
+    } else {
+        Ok(WndClassId(res))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_window(
+    class_name: &str,
+    window_name: &str,
+    visible: bool,
+    style_ex: Option<WINDOW_EX_STYLE>,
+    style: Option<WINDOW_STYLE>,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    parent: Option<HWND>,
+    menu: Option<HMENU>,
+    hinstance: HINSTANCE,
+    create_params: Option<*const c_void>,
+) -> Result<HWND, WIN32_ERROR> {
+    let mut class_name_w = class_name.encode_utf16().collect::<Vec<_>>();
+    class_name_w.push(0x0000);
+
+    let mut window_name_w = window_name.encode_utf16().collect::<Vec<_>>();
+    window_name_w.push(0x0000);
+
+    let hwnd = unsafe {
+        CreateWindowExW(
+            style_ex.unwrap_or(WINDOW_EX_STYLE(0)),
+            PCWSTR(class_name_w.as_ptr()),
+            PCWSTR(window_name_w.as_ptr()),
+            style.unwrap_or(WINDOW_STYLE(0)) | WS_CLIPSIBLINGS,
+            x,
+            y,
+            width,
+            height,
             parent.unwrap_or(HWND(0)),
             menu.unwrap_or(HMENU(0)),
             hinstance,
-            None,
+            create_params,
         )
     };
     if hwnd.0 == 0 {
@@ -477,6 +1640,19 @@ impl TryFrom<VIRTUAL_KEY> for KeyboardScancode {
             VK_OEM_6 => Ok(Self::CloseBracket),
             VK_OEM_7 => Ok(Self::Apostrophe),
 
+            VK_CAPITAL => Ok(Self::CapsLk),
+            VK_NUMLOCK => Ok(Self::NumLk),
+            VK_SCROLL => Ok(Self::ScrLk),
+            VK_LSHIFT => Ok(Self::LShift),
+            VK_RSHIFT => Ok(Self::RShift),
+            VK_LCONTROL => Ok(Self::LCtrl),
+            VK_RCONTROL => Ok(Self::RCtrl),
+            VK_LMENU => Ok(Self::LAlt),
+            VK_RMENU => Ok(Self::RAlt),
+            VK_LWIN => Ok(Self::LSys),
+            VK_RWIN => Ok(Self::RSys),
+            VK_APPS => Ok(Self::Menu),
+
             _ => Err(()),
         }
     }
@@ -496,33 +1672,6 @@ impl TryFrom<VIRTUAL_KEY> for MouseScancode {
     }
 }
 
-trait ModifiersExt {
-    fn try_from_vk(vk: VIRTUAL_KEY, scancode: u16) -> Option<Modifiers>;
-}
-
-impl ModifiersExt for Modifiers {
-    fn try_from_vk(vk: VIRTUAL_KEY, scancode: u16) -> Option<Self> {
-        let vk = if vk == VK_SHIFT || vk == VK_MENU || vk == VK_CONTROL {
-            VIRTUAL_KEY(unsafe { MapVirtualKeyW(scancode as _, MAPVK_VSC_TO_VK_EX) } as _)
-        } else {
-            vk
-        };
-
-        match vk {
-            VK_LSHIFT => Some(Modifiers::LSHIFT),
-            VK_RSHIFT => Some(Modifiers::RSHIFT),
-            VK_LMENU => Some(Modifiers::LALT),
-            VK_RMENU => Some(Modifiers::RALT),
-            VK_LCONTROL => Some(Modifiers::LCTRL),
-            VK_RCONTROL => Some(Modifiers::RCTRL),
-            VK_LWIN => Some(Modifiers::LSYS),
-            VK_RWIN => Some(Modifiers::RSYS),
-            VK_CAPITAL => Some(Modifiers::CAPSLOCK),
-            VK_NUMLOCK => Some(Modifiers::NUMLOCK),
-            _ => None,
-        }
-    }
-}
 enum KeyState {
     Up,
     Down,
@@ -571,6 +1720,22 @@ impl KeyPressInfo {
     }
 }
 
+// Key messages carry VK_SHIFT, VK_CONTROL and VK_MENU for either side; the
+// scancode tells which.
+fn sided_vk(vk: VIRTUAL_KEY, scancode: u16) -> VIRTUAL_KEY {
+    let extended = scancode & 0xE000 == 0xE000;
+    match vk {
+        VK_SHIFT => {
+            VIRTUAL_KEY(unsafe { MapVirtualKeyW((scancode & 0xFF) as _, MAPVK_VSC_TO_VK_EX) } as _)
+        }
+        VK_CONTROL if extended => VK_RCONTROL,
+        VK_CONTROL => VK_LCONTROL,
+        VK_MENU if extended => VK_RMENU,
+        VK_MENU => VK_LMENU,
+        _ => vk,
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 struct OemScancode(u16);
 
@@ -649,6 +1814,8 @@ impl TryFrom<OemScancode> for KeyboardScancode {
             0x0058 => Ok(Self::F12),
 
             0x0046 => Ok(Self::ScrLk),
+            0xE045 => Ok(Self::NumLk),
+            0x0045 => Ok(Self::PauseBreak),
             0xE052 => Ok(Self::Insert),
             0xE047 => Ok(Self::Home),
             0xE049 => Ok(Self::PgUp),
@@ -686,64 +1853,200 @@ impl TryFrom<OemScancode> for KeyboardScancode {
             0x0036 => Ok(Self::RShift),
             0xE038 => Ok(Self::RAlt),
             0xE05C => Ok(Self::RSys),
+            0xE05D => Ok(Self::Menu),
+            0xE037 => Ok(Self::PrtScSysRq),
 
             _ => Err(()),
         }
     }
 }
 
+// The scancode a key sends on a standard layout, e.g. for SendInput.
+fn oem_scancode(key: KeyboardScancode) -> Option<OemScancode> {
+    (0..=0xFF)
+        .chain(0xE000..=0xE0FF)
+        .map(OemScancode)
+        .find(|sc| KeyboardScancode::try_from(*sc).ok() == Some(key))
+}
+
+// Unwinding across the extern "system" boundary is undefined behavior, so a
+// panic in a handler or hook stops here. Only the window it happened in is
+// poisoned: it reports UnrecoverableError and is left to DefWindowProcW from
+// then on, apart from WM_DESTROY so it's still cleaned up.
 unsafe extern "system" fn main_wnd_proc(
     hwnd: HWND,
     msg: u32,
     wparam: WPARAM,
     lparam: LPARAM,
 ) -> LRESULT {
+    let poisoned = try_window_info(hwnd.0)
+        .is_some_and(|info| info.read().unwrap_or_else(PoisonError::into_inner).poisoned);
+    if poisoned && msg != WM_DESTROY {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+    match panic::catch_unwind(AssertUnwindSafe(|| wnd_proc(hwnd, msg, wparam, lparam))) {
+        Ok(res) => res,
+        Err(payload) => {
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| poison_window(hwnd, &*payload)));
+            DefWindowProcW(hwnd, msg, wparam, lparam)
+        }
+    }
+}
+
+fn poison_window(hwnd: HWND, payload: &(dyn std::any::Any + Send)) {
+    let Some(info) = try_window_info(hwnd.0) else {
+        return;
+    };
+    // The panic may have happened with the lock held.
+    info.clear_poison();
+    let sender = {
+        let mut info = info.write().unwrap();
+        info.poisoned = true;
+        info.sender.clone()
+    };
+    sender.clear_poison();
+    sender.write().unwrap().send(
+        WindowId(hwnd.0 as _),
+        WindowEvent::UnrecoverableError(crate::panic_message(payload)),
+    );
+}
+
+unsafe fn wnd_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
     match msg {
+        // The first message a window gets. Windows created by Window::new
+        // pass their info as the creation parameter. A registry entry left
+        // under a recycled HWND is replaced, so the new window doesn't
+        // inherit its sender.
+        WM_NCCREATE => {
+            let params =
+                (*(lparam.0 as *const CREATESTRUCTW)).lpCreateParams as *const RwLock<WindowInfo>;
+            let info = if params.is_null() {
+                Arc::default()
+            } else {
+                Arc::increment_strong_count(params);
+                Arc::from_raw(params)
+            };
+            WINDOW_INFO.write().unwrap().insert(hwnd.0, info.clone());
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, Arc::into_raw(info) as _);
+            return DefWindowProcW(hwnd, msg, wparam, lparam);
+        }
         WM_CREATE => {
-            WINDOW_INFO
-                .clone()
-                .write()
-                .unwrap()
-                .entry(hwnd.0)
-                .or_insert(WindowInfo::default())
-                .sender
-                .write()
-                .unwrap()
-                .send(WindowId(hwnd.0 as _), WindowEvent::Created);
+            info_modify!(hwnd.0, |info| {
+                info.monitors = MonitorTracker::new(monitors());
+                info.sender
+                    .write()
+                    .unwrap()
+                    .send(WindowId(hwnd.0 as _), WindowEvent::Created);
+            });
         }
+        // Left to the window's CloseAction; by default the application
+        // destroys the window by dropping it.
         WM_CLOSE => {
             send_ev!(hwnd.0, WindowEvent::CloseRequested);
-            DestroyWindow(hwnd);
+            crate::apply_close_action(WindowId(hwnd.0 as _));
+        }
+        // The low four bits are used internally by the system.
+        WM_SYSCOMMAND => {
+            let command = (wparam.0 & 0xFFF0) as u32;
+            if !run_syscommand_hook(hwnd, command) {
+                return LRESULT(0);
+            }
+            // Handled here rather than through the WM_CLOSE that
+            // DefWindowProcW would send, so the taskbar preview's close
+            // button takes the same path. Windows without a close button
+            // keep the default handling. Size commands are reported by the
+            // WM_SIZE that follows.
+            if command == SC_CLOSE && !info_get!(hwnd.0).no_close {
+                // The taskbar preview offers it even when it's disabled.
+                if !info_get!(hwnd.0)
+                    .style_state
+                    .buttons
+                    .contains(WindowButtons::CLOSE)
+                {
+                    return LRESULT(0);
+                }
+                send_ev!(hwnd.0, WindowEvent::CloseRequested);
+                crate::apply_close_action(WindowId(hwnd.0 as _));
+                return LRESULT(0);
+            }
+            return DefWindowProcW(hwnd, msg, wparam, lparam);
         }
         WM_DESTROY => {
-            PostMessageW(hwnd, msg, wparam, lparam);
+            // Don't leave the desktop at the game's resolution. Restoring
+            // sends WM_DISPLAYCHANGE, which needs the info lock.
+            let exclusive = info_get!(hwnd.0).exclusive_mode.clone();
+            if let Some(exclusive) = exclusive {
+                exclusive.restore();
+            }
             send_ev!(hwnd.0, WindowEvent::Destroyed);
             info_remove!(&hwnd.0);
+            SYSCOMMAND_HOOKS.with(|hooks| hooks.borrow_mut().remove(&hwnd.0));
+            // Releases the drop target, if one was registered.
+            let _ = RevokeDragDrop(hwnd);
+            #[cfg(feature = "accesskit")]
+            ACCESSKIT_ADAPTERS.with(|adapters| adapters.borrow_mut().remove(&hwnd.0));
             return LRESULT(0);
         }
+        // The last message a window gets. Messages after WM_DESTROY still
+        // reach the info through GWLP_USERDATA, so it's only released here.
+        WM_NCDESTROY => {
+            let ptr = SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0) as *const RwLock<WindowInfo>;
+            if !ptr.is_null() {
+                let info = Arc::from_raw(ptr);
+                let mut registry = WINDOW_INFO.write().unwrap();
+                if registry.get(&hwnd.0).is_some_and(|i| Arc::ptr_eq(i, &info)) {
+                    registry.remove(&hwnd.0);
+                }
+            }
+            return DefWindowProcW(hwnd, msg, wparam, lparam);
+        }
+        // Screen readers query the tree through WM_GETOBJECT, which has to be
+        // answered before DefWindowProcW supplies the default MSAA proxy.
+        #[cfg(feature = "accesskit")]
+        WM_GETOBJECT => {
+            if let Some(Some(res)) =
+                with_accesskit_adapter(hwnd, |a| a.handle_wm_getobject(wparam, lparam))
+            {
+                return res;
+            }
+            return DefWindowProcW(hwnd, msg, wparam, lparam);
+        }
+        // Sent after WM_ACTIVATE, so the adapter sees focus once the window
+        // has reported `Focused`.
+        #[cfg(feature = "accesskit")]
+        WM_SETFOCUS | WM_KILLFOCUS => {
+            with_accesskit_adapter(hwnd, |a| a.window_focused(msg == WM_SETFOCUS));
+            return DefWindowProcW(hwnd, msg, wparam, lparam);
+        }
         WM_GETMINMAXINFO => {
             let mmi = lparam.0 as *mut MINMAXINFO;
             let info = info_get!(hwnd.0).clone();
-            (*mmi).ptMinTrackSize.x = info.min_height;
-            (*mmi).ptMinTrackSize.y = info.min_height;
-            (*mmi).ptMaxTrackSize.x = info.max_width;
-            (*mmi).ptMaxTrackSize.y = info.max_height;
+            let Some((min, max)) = track_sizes(
+                info.fullscreen,
+                (info.min_width, info.min_height),
+                (info.max_width, info.max_height),
+            ) else {
+                return DefWindowProcW(hwnd, msg, wparam, lparam);
+            };
+            (*mmi).ptMinTrackSize = min;
+            (*mmi).ptMaxTrackSize = max;
             return LRESULT(0);
         }
         WM_MOVE => {
-            let x = lparam.0 & 0xFFFF;
-            let y = (lparam.0 >> 16) & 0xFFFF;
+            // Signed, since windows can sit left of or above the primary
+            // monitor.
+            let x = (lparam.0 & 0xFFFF) as i16;
+            let y = ((lparam.0 >> 16) & 0xFFFF) as i16;
 
             info_modify!(hwnd.0, |info| {
-                info.x = x as _;
-                info.y = y as _;
-                info.sender.write().unwrap().send(
-                    WindowId(hwnd.0 as _),
-                    WindowEvent::Moved {
-                        x: x as _,
-                        y: y as _,
-                    },
-                );
+                let Some(event) = move_event(info, x as _, y as _) else {
+                    return;
+                };
+                info.sender
+                    .write()
+                    .unwrap()
+                    .send(WindowId(hwnd.0 as _), event);
+                update_refresh_rate(hwnd, info);
             });
             return LRESULT(0);
         }
@@ -752,35 +2055,22 @@ unsafe extern "system" fn main_wnd_proc(
             let height = (lparam.0 >> 16) & 0xFFFF;
             match wparam.0 as u32 {
                 SIZE_RESTORED => {
-                    info_modify!(hwnd.0, |info| {
-                        info.width = width as _;
-                        info.height = height as _;
-                        info.size_state = WindowSizeState::Other;
-                        info.sender.write().unwrap().send(
-                            WindowId(hwnd.0 as _),
-                            WindowEvent::Resized {
-                                width: width as _,
-                                height: height as _,
-                            },
-                        );
-                    });
-
+                    set_size_state(hwnd, WindowSizeState::Other);
+                    set_client_size(hwnd, width as _, height as _);
                     return LRESULT(0);
                 }
                 SIZE_MINIMIZED => {
-                    info_modify!(hwnd.0, |info| {
-                        info.size_state = WindowSizeState::Minimized;
-                    });
+                    set_size_state(hwnd, WindowSizeState::Minimized);
                     return LRESULT(0);
                 }
                 SIZE_MAXIMIZED => {
-                    info_modify!(hwnd.0, |info| {
-                        info.size_state = WindowSizeState::Maximized;
-                    });
-
+                    set_size_state(hwnd, WindowSizeState::Maximized);
+                    set_client_size(hwnd, width as _, height as _);
                     return LRESULT(0);
                 }
-                SIZE_MAXSHOW | SIZE_MAXHIDE => todo!(),
+                // Another window was maximized or restored; this one's size
+                // is unaffected.
+                SIZE_MAXSHOW | SIZE_MAXHIDE => return LRESULT(0),
                 _ => return LRESULT(0),
             }
         }
@@ -794,13 +2084,39 @@ unsafe extern "system" fn main_wnd_proc(
             info_modify!(hwnd.0, |info| {
                 info.focused = focused;
             });
-            send_ev!(hwnd.0, WindowEvent::Focused(focused));
+            report_focus(hwnd);
+            // After Focused, so the SizeStateChanged from minimizing or
+            // restoring always follows it.
+            let policy = info_get!(hwnd.0).exclusive_focus_policy;
+            let exclusive = info_get!(hwnd.0).exclusive_mode.clone();
+            if let (ExclusiveFocusPolicy::AutoMinimize, Some(exclusive)) = (policy, exclusive) {
+                if focused {
+                    exclusive.apply();
+                    ShowWindow(hwnd, SW_RESTORE);
+                } else {
+                    exclusive.restore();
+                    ShowWindow(hwnd, SW_MINIMIZE);
+                }
+            }
+            if !focused {
+                release_mouse_buttons(hwnd);
+                let mut modifiers_changed = None;
+                info_modify!(hwnd.0, |info| modifiers_changed = info.keys.focus_lost());
+                if let Some(modifiers) = modifiers_changed {
+                    send_ev!(hwnd.0, WindowEvent::ModifiersChanged(modifiers));
+                }
+            }
+            // Hiding a popup deactivates it too, but it's already gone.
+            if !focused && info_get!(hwnd.0).kind == WindowKind::Popup && info_get!(hwnd.0).visible
+            {
+                send_ev!(hwnd.0, WindowEvent::DismissRequested);
+            }
 
             return LRESULT(0);
         }
         WM_SETTEXT => {
             let text = lparam.0 as *mut u16;
-            let mut len = 1;
+            let mut len = 0;
             while unsafe { *text.add(len) } != 0x0000 {
                 len += 1;
             }
@@ -812,8 +2128,68 @@ unsafe extern "system" fn main_wnd_proc(
             };
             return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) };
         }
-        WM_DISPLAYCHANGE => todo!(),
+        WM_DPICHANGED if crate::scale_factor_override().is_none() => {
+            let dpi = wparam.0 & 0xFFFF;
+            send_ev!(
+                hwnd.0,
+                WindowEvent::ScaleFactorChanged(dpi as f64 / USER_DEFAULT_SCREEN_DPI as f64)
+            );
+            // The suggested rect keeps the window the same size on screen at
+            // the new scale.
+            let r = *(lparam.0 as *const RECT);
+            SetWindowPos(
+                hwnd,
+                None,
+                r.left,
+                r.top,
+                r.right - r.left,
+                r.bottom - r.top,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+            info_modify!(hwnd.0, |info| update_refresh_rate(hwnd, info));
+            return LRESULT(0);
+        }
+        WM_DISPLAYCHANGE | WM_DPICHANGED => {
+            info_modify!(hwnd.0, |info| {
+                if info.max_follows_screen {
+                    let screen =
+                        unsafe { (GetSystemMetrics(SM_CXSCREEN), GetSystemMetrics(SM_CYSCREEN)) };
+                    let (min, max) = screen_limits((info.min_width, info.min_height), screen);
+                    (info.min_width, info.min_height) = min;
+                    (info.max_width, info.max_height) = max;
+                }
+                update_monitors(hwnd, info);
+                update_refresh_rate(hwnd, info);
+            });
+            return DefWindowProcW(hwnd, msg, wparam, lparam);
+        }
+        WM_SETTINGCHANGE => {
+            WHEEL_SCROLL_LINES.store(u32::MAX, Ordering::Relaxed);
+            send_ev!(hwnd.0, WindowEvent::SystemSettingsChanged);
+            // Sent to every top-level window when the light/dark setting is
+            // toggled. Windows with a preference keep it.
+            let area = PCWSTR(lparam.0 as _);
+            if !area.is_null() && area.to_string().is_ok_and(|s| s == "ImmersiveColorSet") {
+                let theme = system_theme().unwrap_or_default();
+                let changed = info_modify!(hwnd.0, |info| {
+                    let changed = info.theme_preference.is_none() && info.theme != theme;
+                    if changed {
+                        info.theme = theme;
+                    }
+                    changed
+                });
+                if changed {
+                    apply_theme(hwnd, theme);
+                    send_ev!(hwnd.0, WindowEvent::ThemeChanged(theme));
+                }
+            }
+            return DefWindowProcW(hwnd, msg, wparam, lparam);
+        }
         WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP => {
+            // set_lock_state has already updated every window's modifiers.
+            if GetMessageExtraInfo().0 as usize == LOCK_KEY_SIGNATURE {
+                return DefWindowProcW(hwnd, msg, wparam, lparam);
+            }
             let sys = msg == WM_SYSKEYDOWN || msg == WM_SYSKEYUP;
             let down = msg == WM_KEYDOWN || msg == WM_SYSKEYDOWN;
             let kpi = KeyPressInfo::from_lparam(lparam);
@@ -836,7 +2212,19 @@ unsafe extern "system" fn main_wnd_proc(
                 return LRESULT(0);
             }
 
-            if let Ok(k) = TryInto::<KeyboardScancode>::try_into(vk) {
+            let logical_scancode =
+                TryInto::<KeyboardScancode>::try_into(sided_vk(vk, kpi.scancode)).ok();
+            let mut modifiers_changed = None;
+            let mut repeat = matches!(kpi.previous_state, KeyState::Down);
+            if let Some(key) = physical_scancode.or(logical_scancode) {
+                info_modify!(hwnd.0, |info| {
+                    let transition = info.keys.key(key, down, repeat);
+                    repeat = transition.repeat;
+                    modifiers_changed = transition.modifiers_changed;
+                });
+            }
+
+            if let Some(k) = logical_scancode {
                 info_modify!(hwnd.0, |info| {
                     if !down {
                         info.sender.clone().write().unwrap().send(
@@ -858,9 +2246,10 @@ unsafe extern "system" fn main_wnd_proc(
                         .nth(0);
 
                     let mut keystate = [0u8; 256];
-                    let b = info.modifiers.contains(Modifiers::LSHIFT)
-                        || info.modifiers.contains(Modifiers::RSHIFT);
-                    let b = if info.modifiers.contains(Modifiers::CAPSLOCK) {
+                    let modifiers = info.keys.modifiers();
+                    let b = modifiers.contains(Modifiers::LSHIFT)
+                        || modifiers.contains(Modifiers::RSHIFT);
+                    let b = if modifiers.contains(Modifiers::CAPSLOCK) {
                         !b
                     } else {
                         b
@@ -868,7 +2257,7 @@ unsafe extern "system" fn main_wnd_proc(
                     if b {
                         keystate[0x10] = 0x80;
                     }
-                    let mut buf = [0u16; 1];
+                    let mut buf = [0u16; 8];
                     let res = unsafe {
                         ToUnicode(
                             (vk.0 & 0xFF) as _,
@@ -878,16 +2267,8 @@ unsafe extern "system" fn main_wnd_proc(
                             0,
                         )
                     };
-                    let character = if res != 1 {
-                        None
-                    } else {
-                        std::char::decode_utf16(buf)
-                            .flatten()
-                            .collect::<Vec<_>>()
-                            .iter()
-                            .copied()
-                            .nth(0)
-                    };
+                    let text = typed_text(&buf, res);
+                    let character = text.as_deref().and_then(|t| t.chars().next());
 
                     info.sender.clone().write().unwrap().send(
                         WindowId(hwnd.0 as _),
@@ -895,76 +2276,642 @@ unsafe extern "system" fn main_wnd_proc(
                             logical_scancode: k,
                             character,
                             unshifted_char,
+                            text,
                             physical_scancode,
+                            repeat,
                         },
                     );
                 });
             }
 
-            if let Ok(k) = TryInto::<MouseScancode>::try_into(vk) {
-                send_ev!(
-                    hwnd.0,
-                    if down {
-                        WindowEvent::MouseButtonDown(k)
-                    } else {
-                        WindowEvent::MouseButtonUp(k)
-                    }
-                );
+            if let Some(modifiers) = modifiers_changed {
+                send_ev!(hwnd.0, WindowEvent::ModifiersChanged(modifiers));
+            }
+            return LRESULT(0);
+        }
+        WM_MOUSEACTIVATE if info_get!(hwnd.0).kind == WindowKind::Tooltip => {
+            return LRESULT(MA_NOACTIVATE as _);
+        }
+        WM_LBUTTONDOWN | WM_LBUTTONUP | WM_RBUTTONDOWN | WM_RBUTTONUP | WM_MBUTTONDOWN
+        | WM_MBUTTONUP | WM_XBUTTONDOWN | WM_XBUTTONUP => {
+            let k = match msg {
+                WM_LBUTTONDOWN | WM_LBUTTONUP => MouseScancode::LClick,
+                WM_RBUTTONDOWN | WM_RBUTTONUP => MouseScancode::RClick,
+                WM_MBUTTONDOWN | WM_MBUTTONUP => MouseScancode::MClick,
+                _ => match ((wparam.0 >> 16) & 0xFFFF) as u16 {
+                    XBUTTON1 => MouseScancode::Button4,
+                    XBUTTON2 => MouseScancode::Button5,
+                    _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
+                },
+            };
+            let down = matches!(
+                msg,
+                WM_LBUTTONDOWN | WM_RBUTTONDOWN | WM_MBUTTONDOWN | WM_XBUTTONDOWN
+            );
+
+            // While a popup holds the mouse capture, clicks anywhere on the
+            // desktop are delivered to it.
+            if down && info_get!(hwnd.0).kind == WindowKind::Popup {
+                let x = (lparam.0 & 0xFFFF) as i16 as i32;
+                let y = ((lparam.0 >> 16) & 0xFFFF) as i16 as i32;
+                let mut rect = RECT::default();
+                GetClientRect(hwnd, addr_of_mut!(rect));
+                if x < rect.left || y < rect.top || x >= rect.right || y >= rect.bottom {
+                    send_ev!(hwnd.0, WindowEvent::DismissRequested);
+                    return LRESULT(0);
+                }
             }
 
-            if let Some(k) = Modifiers::try_from_vk(vk, kpi.scancode) {
+            // Taps and pen presses arrive as these same messages, so the
+            // kind comes from their signature rather than the message.
+            set_pointer_kind(hwnd, promoted_pointer_kind().unwrap_or(PointerKind::Mouse));
+            set_mouse_button(hwnd, k, down);
+            // The mouse is captured while any button is held, so a release
+            // outside the window still arrives here. Popups keep the capture
+            // they took when shown.
+            if info_get!(hwnd.0).kind != WindowKind::Popup {
+                if down {
+                    SetCapture(hwnd);
+                } else if info_get!(hwnd.0).mouse_buttons.is_empty() {
+                    ReleaseCapture();
+                }
+            }
+            // The XBUTTON messages expect TRUE when handled.
+            return LRESULT((msg == WM_XBUTTONDOWN || msg == WM_XBUTTONUP) as _);
+        }
+        // usButtonFlags stops at button 5, so anything above that is only
+        // visible in the device-specific ulRawButtons state.
+        WM_INPUT if info_get!(hwnd.0).raw_mouse => {
+            let mut raw = RAWINPUT::default();
+            let mut size = size_of::<RAWINPUT>() as u32;
+            let res = GetRawInputData(
+                HRAWINPUT(lparam.0),
+                RID_INPUT,
+                Some(addr_of_mut!(raw) as _),
+                addr_of_mut!(size),
+                size_of::<RAWINPUTHEADER>() as _,
+            );
+            if res != u32::MAX && raw.header.dwType == RIM_TYPEMOUSE.0 {
+                let buttons = raw.data.mouse.ulRawButtons;
+                let mut changed = 0;
                 info_modify!(hwnd.0, |info| {
-                    if k == Modifiers::CAPSLOCK || k == Modifiers::NUMLOCK {
-                        if down {
-                            info.modifiers ^= k;
-                        } else {
-                        }
-                    } else if down {
-                        info.modifiers |= k;
-                    } else if !down {
-                        info.modifiers &= !k;
-                    }
-
-                    info.sender.clone().write().unwrap().send(
-                        WindowId(hwnd.0 as _),
-                        WindowEvent::ModifiersChanged(info.modifiers),
-                    );
+                    changed = info.raw_buttons ^ buttons;
+                    info.raw_buttons = buttons;
                 });
+                for n in 6..=20u8 {
+                    let bit = 1 << (n - 1);
+                    if changed & bit != 0 {
+                        set_mouse_button(hwnd, MouseScancode::ButtonN(n), buttons & bit != 0);
+                    }
+                }
+            }
+            return DefWindowProcW(hwnd, msg, wparam, lparam);
+        }
+        // Pen and touch input, and the mouse if the host opted into
+        // EnableMouseInPointer. Passed on to DefWindowProcW so the usual
+        // mouse messages are still synthesized for it.
+        WM_POINTERUPDATE => {
+            if let Some((kind, position)) = pointer_position(hwnd, (wparam.0 & 0xFFFF) as u32) {
+                set_pointer_kind(hwnd, kind);
+                set_cursor_position(hwnd, position);
             }
+            return DefWindowProcW(hwnd, msg, wparam, lparam);
+        }
+        // Skips the moves synthesized from pen and touch, which
+        // WM_POINTERUPDATE has already reported more precisely.
+        WM_MOUSEMOVE if promoted_pointer_kind().is_none() => {
+            set_pointer_kind(hwnd, PointerKind::Mouse);
+            // Signed, since the position goes negative past the top left
+            // while the mouse is captured.
+            let x = (lparam.0 & 0xFFFF) as i16;
+            let y = ((lparam.0 >> 16) & 0xFFFF) as i16;
+            set_cursor_position(hwnd, PhysicalPosition::new(x as f64, y as f64));
+        }
+        WM_ENTERSIZEMOVE => {
+            info_modify!(hwnd.0, |info| info
+                .sender
+                .write()
+                .unwrap()
+                .begin_size_move());
+        }
+        WM_EXITSIZEMOVE => {
+            info_modify!(hwnd.0, |info| {
+                info.sender
+                    .write()
+                    .unwrap()
+                    .end_size_move(WindowId(hwnd.0 as _));
+            });
+        }
+        // Validated right away so it isn't sent again; the application draws
+        // when it gets RedrawRequested from the next pump. BeginPaint still
+        // sends WM_ERASEBKGND for the background color.
+        WM_PAINT => {
+            let mut ps = PAINTSTRUCT::default();
+            BeginPaint(hwnd, addr_of_mut!(ps));
+            EndPaint(hwnd, addr_of!(ps));
+            info_modify!(hwnd.0, |info| info.redraw.request());
             return LRESULT(0);
         }
+        WM_ERASEBKGND => {
+            let Some((r, g, b)) = info_get!(hwnd.0).background_color else {
+                return DefWindowProcW(hwnd, msg, wparam, lparam);
+            };
+            let brush = CreateSolidBrush(COLORREF(r as u32 | (g as u32) << 8 | (b as u32) << 16));
+            let mut rect = RECT::default();
+            GetClientRect(hwnd, addr_of_mut!(rect));
+            FillRect(HDC(wparam.0 as _), addr_of!(rect), brush);
+            DeleteObject(brush);
+            return LRESULT(1);
+        }
         WM_MOUSEWHEEL => {
-            let delta = ((wparam.0 & 0xFFFF0000) >> 16) as i16;
-            send_ev!(hwnd.0, WindowEvent::MouseWheelScroll(delta as _));
+            send_ev!(
+                hwnd.0,
+                crate::wheel_scroll(wheel_notches(wparam), wheel_scroll_lines())
+            );
         }
         _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
     };
     LRESULT(0)
 }
 
+// In fractional client pixels. ptPixelLocation is rounded, so the HIMETRIC
+// location is mapped from the device's rect onto its display instead where
+// the device reports one. Precision touchpads count as a mouse.
+unsafe fn pointer_position(
+    hwnd: HWND,
+    pointer_id: u32,
+) -> Option<(PointerKind, PhysicalPosition<f64>)> {
+    let mut info = POINTER_INFO::default();
+    if !GetPointerInfo(pointer_id, addr_of_mut!(info)).as_bool() {
+        return None;
+    }
+    let mut device = RECT::default();
+    let mut display = RECT::default();
+    let (x, y) = if GetPointerDeviceRects(
+        info.sourceDevice,
+        addr_of_mut!(device),
+        addr_of_mut!(display),
+    )
+    .as_bool()
+        && device.right > device.left
+        && device.bottom > device.top
+    {
+        let map = |p: i32, d0: i32, d1: i32, s0: i32, s1: i32| {
+            s0 as f64 + (p - d0) as f64 * (s1 - s0) as f64 / (d1 - d0) as f64
+        };
+        let himetric = info.ptHimetricLocation;
+        (
+            map(
+                himetric.x,
+                device.left,
+                device.right,
+                display.left,
+                display.right,
+            ),
+            map(
+                himetric.y,
+                device.top,
+                device.bottom,
+                display.top,
+                display.bottom,
+            ),
+        )
+    } else {
+        (info.ptPixelLocation.x as f64, info.ptPixelLocation.y as f64)
+    };
+    let mut origin = POINT::default();
+    ClientToScreen(hwnd, addr_of_mut!(origin));
+    let kind = match info.pointerType {
+        PT_TOUCH => PointerKind::Touch,
+        PT_PEN => PointerKind::Pen,
+        _ => PointerKind::Mouse,
+    };
+    Some((
+        kind,
+        PhysicalPosition::new(x - origin.x as f64, y - origin.y as f64),
+    ))
+}
+
+// Mouse messages synthesized from pen or touch input carry this signature in
+// their extra info, with a bit telling touch from pen.
+fn promoted_pointer_kind() -> Option<PointerKind> {
+    const MI_WP_SIGNATURE: u32 = 0xFF515700;
+    const SIGNATURE_MASK: u32 = 0xFFFFFF00;
+    const TOUCH_BIT: u32 = 0x80;
+    let extra = unsafe { GetMessageExtraInfo() }.0 as u32;
+    if extra & SIGNATURE_MASK != MI_WP_SIGNATURE {
+        None
+    } else if extra & TOUCH_BIT != 0 {
+        Some(PointerKind::Touch)
+    } else {
+        Some(PointerKind::Pen)
+    }
+}
+
+fn set_pointer_kind(hwnd: HWND, kind: PointerKind) {
+    let mut changed = false;
+    info_modify!(hwnd.0, |info| {
+        changed = info.pointer_kind != kind;
+        info.pointer_kind = kind;
+    });
+    if changed {
+        send_ev!(hwnd.0, WindowEvent::PointerKindChanged(kind));
+    }
+}
+
+// Windows also sends WM_MOUSEMOVE when nothing moved, e.g. when a window
+// appears under the cursor or the cursor is set, so repeats are dropped.
+fn set_cursor_position(hwnd: HWND, position: PhysicalPosition<f64>) {
+    let mut changed = false;
+    info_modify!(hwnd.0, |info| {
+        changed = info.cursor_position != Some(position);
+        info.cursor_position = Some(position);
+    });
+    if changed {
+        send_ev!(hwnd.0, WindowEvent::CursorMoved(position));
+    }
+}
+
+// Ignores presses of buttons already held and releases of ones that aren't, so
+// the releases synthesized on focus loss aren't reported twice.
+fn set_mouse_button(hwnd: HWND, k: MouseScancode, down: bool) {
+    let flag = MouseButtons::from_scancode(k);
+    let mut changed = flag.is_empty();
+    info_modify!(hwnd.0, |info| {
+        changed |= info.mouse_buttons.contains(flag) != down;
+        info.mouse_buttons.set(flag, down);
+    });
+    if changed {
+        send_ev!(
+            hwnd.0,
+            if down {
+                WindowEvent::MouseButtonDown(k)
+            } else {
+                WindowEvent::MouseButtonUp(k)
+            }
+        );
+    }
+}
+
+// Button releases that happen after focus moves elsewhere are never
+// delivered, so report everything still held as released.
+fn release_mouse_buttons(hwnd: HWND) {
+    let held = info_get!(hwnd.0).mouse_buttons;
+    for k in held.scancodes() {
+        set_mouse_button(hwnd, k, false);
+    }
+    info_modify!(hwnd.0, |info| info.raw_buttons = 0);
+}
+
+// What ToUnicode typed into `buf`. `res` is negative for a dead key, which
+// types nothing until the next key. Characters outside the BMP arrive as
+// surrogate pairs; unpaired halves become U+FFFD.
+fn typed_text(buf: &[u16], res: i32) -> Option<String> {
+    let len = usize::try_from(res).ok().filter(|&len| len > 0)?;
+    Some(String::from_utf16_lossy(&buf[..len.min(buf.len())]))
+}
+
+// Missing from the bindings.
+const WHEEL_PAGESCROLL: u32 = u32::MAX;
+
+// u32::MAX until read, and again after WM_SETTINGCHANGE.
+static WHEEL_SCROLL_LINES: AtomicU32 = AtomicU32::new(u32::MAX);
+
+fn wheel_scroll_lines() -> u32 {
+    let cached = WHEEL_SCROLL_LINES.load(Ordering::Relaxed);
+    if cached != u32::MAX {
+        return cached;
+    }
+    let mut lines = 0u32;
+    let ok = unsafe {
+        SystemParametersInfoW(
+            SPI_GETWHEELSCROLLLINES,
+            0,
+            Some(&mut lines as *mut u32 as _),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+    };
+    let lines = scroll_lines(ok.as_bool().then_some(lines));
+    WHEEL_SCROLL_LINES.store(lines, Ordering::Relaxed);
+    lines
+}
+
+// WHEEL_PAGESCROLL asks for a page per notch, which only the application
+// knows the size of; it falls back to the default, as does a failed read.
+fn scroll_lines(setting: Option<u32>) -> u32 {
+    match setting {
+        Some(lines) if lines != WHEEL_PAGESCROLL => lines,
+        _ => SystemSettings::default().wheel_scroll_lines,
+    }
+}
+
+// The high word of WM_MOUSEWHEEL's wParam is the signed distance, in
+// multiples of WHEEL_DELTA per notch.
+fn wheel_notches(wparam: WPARAM) -> f32 {
+    let delta = ((wparam.0 & 0xFFFF0000) >> 16) as i16;
+    delta as f32 / WHEEL_DELTA as f32
+}
+
+pub(crate) fn system_settings() -> SystemSettings {
+    let blink = unsafe { GetCaretBlinkTime() };
+    SystemSettings {
+        double_click_time: Duration::from_millis(unsafe { GetDoubleClickTime() } as _),
+        double_click_area: unsafe {
+            (
+                GetSystemMetrics(SM_CXDOUBLECLK) as _,
+                GetSystemMetrics(SM_CYDOUBLECLK) as _,
+            )
+        },
+        // INFINITE when blinking is turned off.
+        caret_blink_interval: (blink != 0 && blink != u32::MAX)
+            .then(|| Duration::from_millis(blink as _)),
+        drag_threshold: unsafe {
+            (
+                GetSystemMetrics(SM_CXDRAG) as _,
+                GetSystemMetrics(SM_CYDRAG) as _,
+            )
+        },
+        wheel_scroll_lines: wheel_scroll_lines(),
+        reduced_motion: reduced_motion(),
+        high_contrast: high_contrast(),
+    }
+}
+
+// Either "Animation effects" or the minimize and restore animations being
+// turned off.
+fn reduced_motion() -> bool {
+    let mut client_area = BOOL(1);
+    let ok = unsafe {
+        SystemParametersInfoW(
+            SPI_GETCLIENTAREAANIMATION,
+            0,
+            Some(addr_of_mut!(client_area).cast()),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+    };
+    let client_area = ok.as_bool().then_some(client_area.as_bool());
+    let mut animation = ANIMATIONINFO {
+        cbSize: size_of::<ANIMATIONINFO>() as _,
+        iMinAnimate: 1,
+    };
+    let ok = unsafe {
+        SystemParametersInfoW(
+            SPI_GETANIMATION,
+            animation.cbSize,
+            Some(addr_of_mut!(animation).cast()),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+    };
+    motion_reduced(client_area, ok.as_bool().then_some(animation.iMinAnimate))
+}
+
+// Settings that couldn't be read are None, and don't count.
+fn motion_reduced(client_area_animation: Option<bool>, min_animate: Option<i32>) -> bool {
+    client_area_animation == Some(false) || min_animate == Some(0)
+}
+
+fn high_contrast() -> bool {
+    let mut hc = HIGHCONTRASTW {
+        cbSize: size_of::<HIGHCONTRASTW>() as _,
+        ..Default::default()
+    };
+    let ok = unsafe {
+        SystemParametersInfoW(
+            SPI_GETHIGHCONTRAST,
+            hc.cbSize,
+            Some(addr_of_mut!(hc).cast()),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+    };
+    ok.as_bool() && high_contrast_on(&hc)
+}
+
+// The other flags only say which parts of the feature are available.
+fn high_contrast_on(hc: &HIGHCONTRASTW) -> bool {
+    hc.dwFlags.0 & HCF_HIGHCONTRASTON.0 != 0
+}
+
+// AppsUseLightTheme is missing before Windows 10 1809.
+pub(crate) fn system_theme() -> Option<Theme> {
+    let mut light = 0u32;
+    let mut size = size_of::<u32>() as u32;
+    let res = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            windows::w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+            windows::w!("AppsUseLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(addr_of_mut!(light).cast()),
+            Some(addr_of_mut!(size)),
+        )
+    };
+    (res == ERROR_SUCCESS).then_some(if light == 0 {
+        Theme::Dark
+    } else {
+        Theme::Light
+    })
+}
+
+// Focus changes aren't reported while the window is hidden, and hide() resets
+// the reported focus, so showing the window reports it afresh.
+fn report_focus(hwnd: HWND) {
+    info_modify!(hwnd.0, |info| {
+        if info.visible && info.focused != info.focus_reported {
+            info.focus_reported = info.focused;
+            info.sender
+                .write()
+                .unwrap()
+                .send(WindowId(hwnd.0 as _), WindowEvent::Focused(info.focused));
+        }
+    });
+}
+
+fn set_size_state(hwnd: HWND, size_state: WindowSizeState) {
+    info_modify!(hwnd.0, |info| {
+        if info.size_state != size_state {
+            info.size_state = size_state;
+            info.sender.write().unwrap().send(
+                WindowId(hwnd.0 as _),
+                WindowEvent::SizeStateChanged(size_state),
+            );
+        }
+    });
+}
+
+// The tracking sizes WM_GETMINMAXINFO reports, or None to leave them to
+// DefWindowProc. The limits are for the windowed state; clamping a fullscreen
+// window to them can leave part of a larger monitor uncovered.
+fn track_sizes(
+    fullscreen: FullscreenType,
+    min: (i32, i32),
+    max: (i32, i32),
+) -> Option<(POINT, POINT)> {
+    if fullscreen != FullscreenType::NotFullscreen {
+        return None;
+    }
+    Some((POINT { x: min.0, y: min.1 }, POINT { x: max.0, y: max.1 }))
+}
+
+// The limits of a window whose maximum follows the screen, once the screen
+// is `screen`. A minimum that no longer fits comes down with it.
+fn screen_limits(min: (i32, i32), screen: (i32, i32)) -> ((i32, i32), (i32, i32)) {
+    ((min.0.min(screen.0), min.1.min(screen.1)), screen)
+}
+
+fn set_client_size(hwnd: HWND, width: i32, height: i32) {
+    info_modify!(hwnd.0, |info| {
+        if let Some(event) = resize_event(info, width, height) {
+            info.sender
+                .write()
+                .unwrap()
+                .send(WindowId(hwnd.0 as _), event);
+        }
+    });
+}
+
+// DWM repeats WM_MOVE and WM_SIZE with the same geometry, e.g. when
+// composition changes, so these only report an actual change.
+fn move_event(info: &mut WindowInfo, x: i32, y: i32) -> Option<WindowEvent> {
+    if (info.x, info.y) == (x, y) {
+        return None;
+    }
+    (info.x, info.y) = (x, y);
+    Some(WindowEvent::Moved(PhysicalPosition::new(x, y)))
+}
+
+fn resize_event(info: &mut WindowInfo, width: i32, height: i32) -> Option<WindowEvent> {
+    if (info.width, info.height) == (width, height) {
+        return None;
+    }
+    (info.width, info.height) = (width, height);
+    Some(WindowEvent::Resized(PhysicalSize::new(
+        width as _,
+        height as _,
+    )))
+}
+
+// Windows still being created have no Window yet, so only entries with a live
+// one count.
+pub(crate) fn windows() -> Vec<WindowId> {
+    WINDOW_INFO
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, info)| info.read().unwrap().handle.strong_count() > 0)
+        .map(|(hwnd, _)| WindowId(*hwnd as _))
+        .collect()
+}
+
+// WaitMessage also covers the hotkey window, which lives on this thread.
+pub(crate) fn wait_for_events(_ids: &HashSet<WindowId>, timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => unsafe {
+            MsgWaitForMultipleObjects(None, false, wait_millis(Some(timeout)), QS_ALLINPUT);
+        },
+        None => unsafe {
+            WaitMessage();
+        },
+    }
+}
+
+// Rounded up so a timer isn't polled just before it's due.
+fn wait_millis(timeout: Option<Duration>) -> u32 {
+    // u32::MAX is INFINITE.
+    timeout.map_or(u32::MAX, |t| {
+        t.as_nanos().div_ceil(1_000_000).min(u32::MAX as u128 - 1) as u32
+    })
+}
+
+// Set by LoopWaker::wake, so a thread other than the loop's can wait for it.
+fn loop_event() -> HANDLE {
+    thread_local! {
+        static EVENT: HANDLE =
+            unsafe { CreateEventW(None, false, false, None) }.unwrap_or_default();
+    }
+    EVENT.with(|event| *event)
+}
+
+// Another thread can't wait on this thread's message queue, so an idle
+// EventStream waits for the loop's event, which tasks and proxies set. With
+// windows bound it still checks back once a frame, or sooner for a timer, as
+// nothing tells it about their messages.
+#[cfg(feature = "async")]
+pub(crate) fn event_waiter(
+    ids: &HashSet<WindowId>,
+    timeout: Option<Duration>,
+) -> Option<Box<dyn FnOnce() + Send>> {
+    let event = loop_event();
+    let wait = if ids.is_empty() {
+        timeout
+    } else {
+        let interval = crate::frame_interval(None);
+        Some(timeout.map_or(interval, |t| t.min(interval)))
+    };
+    Some(Box::new(move || unsafe {
+        windows::Win32::System::Threading::WaitForSingleObject(event, wait_millis(wait));
+    }))
+}
+
+// Lets another thread end wait_for_events on the loop's thread, or the wait
+// of an idle EventStream. The message has no window, so pump_events just
+// drops it.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct LoopWaker {
+    thread: u32,
+    event: HANDLE,
+}
+
+impl LoopWaker {
+    pub(crate) fn wake(&self) {
+        unsafe { SetEvent(self.event) };
+        unsafe { PostThreadMessageW(self.thread, WM_NULL, WPARAM(0), LPARAM(0)) };
+    }
+}
+
+pub(crate) fn loop_waker() -> LoopWaker {
+    LoopWaker {
+        thread: unsafe { GetCurrentThreadId() },
+        event: loop_event(),
+    }
+}
+
+// WM_SIZE usually arrives synchronously from ShowWindow, but not always, so
+// the state is also set here; set_size_state only reports actual changes, so
+// whichever comes second is a no-op.
+// ShowWindow would show a hidden window, so it only gets the new state, which
+// show() applies.
 fn minimize_window(hwnd: HWND) {
     if info_get!(hwnd.0).size_state != WindowSizeState::Minimized {
-        unsafe {
-            ShowWindow(hwnd, SW_MINIMIZE);
+        if info_get!(hwnd.0).visible {
+            unsafe {
+                ShowWindow(hwnd, SW_MINIMIZE);
+            }
         }
+        set_size_state(hwnd, WindowSizeState::Minimized);
     }
 }
 
 fn maximize_window(hwnd: HWND) {
     if info_get!(hwnd.0).size_state != WindowSizeState::Maximized {
-        unsafe {
-            ShowWindow(hwnd, SW_MAXIMIZE);
+        if info_get!(hwnd.0).visible {
+            unsafe {
+                ShowWindow(hwnd, SW_MAXIMIZE);
+            }
         }
+        set_size_state(hwnd, WindowSizeState::Maximized);
     }
 }
 
 impl super::super::WindowT for Window {
     fn id(&self) -> WindowId {
-        WindowId(unsafe { transmute(self.hwnd.0 as i64) })
+        WindowId(self.hwnd.0 as _)
     }
 
     fn focus(&mut self) {
-        if unsafe { GetActiveWindow() } == HWND(self.hwnd.0) {
+        if unsafe { GetActiveWindow() } == HWND(self.hwnd.0)
+            || info_get!(self.hwnd.0).kind == WindowKind::Tooltip
+        {
             return;
         }
 
@@ -977,6 +2924,146 @@ impl super::super::WindowT for Window {
         });
     }
 
+    // Windows only lets the foreground process hand out the foreground; when
+    // it refuses, it flashes the taskbar button itself.
+    fn request_activation(&mut self) {
+        if info_get!(self.hwnd.0).kind == WindowKind::Tooltip {
+            return;
+        }
+        unsafe { SetForegroundWindow(*self.hwnd) };
+    }
+
+    fn raise(&mut self) {
+        unsafe {
+            SetWindowPos(
+                *self.hwnd,
+                HWND_TOP,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOACTIVATE | SWP_NOMOVE | SWP_NOSIZE,
+            )
+        };
+    }
+
+    // HWND_BOTTOM would also strip WS_EX_TOPMOST, so a topmost window only
+    // goes to the bottom of the topmost windows.
+    fn lower(&mut self) {
+        let hwnd = *self.hwnd;
+        let mut after = HWND_BOTTOM;
+        if self.is_topmost() {
+            after = hwnd;
+            let mut next = unsafe { GetWindow(hwnd, GW_HWNDNEXT) };
+            while next.0 != 0 && is_topmost(next) {
+                after = next;
+                next = unsafe { GetWindow(next, GW_HWNDNEXT) };
+            }
+            if after == hwnd {
+                return;
+            }
+        }
+        unsafe {
+            SetWindowPos(
+                hwnd,
+                after,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOACTIVATE | SWP_NOMOVE | SWP_NOSIZE,
+            )
+        };
+    }
+
+    fn is_topmost(&self) -> bool {
+        is_topmost(*self.hwnd)
+    }
+
+    // Uses OLE drag and drop rather than DragAcceptFiles, which only takes
+    // files and can't show the copy/link cursor.
+    fn set_accepts_drops(&mut self, accept: bool) {
+        let hwnd = *self.hwnd;
+        unsafe {
+            if accept {
+                // Reference counted, so calling it for every window is fine.
+                let _ = OleInitialize(core::ptr::null());
+                let target = DropTarget::into_interface(hwnd);
+                let _ = RegisterDragDrop(hwnd, &target);
+            } else {
+                let _ = RevokeDragDrop(hwnd);
+            }
+        }
+    }
+
+    // Blocks in OLE's modal loop until the drop or cancel. Messages are still
+    // dispatched to nwin windows meanwhile, so their events queue up and are
+    // delivered once this returns.
+    fn start_drag(&mut self, payload: DragPayload) -> Result<DragOutcome, OsError> {
+        let (format, data, allowed) = match &payload {
+            DragPayload::Files(paths) => {
+                let mut wide: Vec<u16> = Vec::new();
+                for path in paths {
+                    wide.extend(path.as_os_str().encode_wide());
+                    wide.push(0);
+                }
+                wide.push(0);
+                let header = DROPFILES {
+                    pFiles: size_of::<DROPFILES>() as _,
+                    pt: POINT::default(),
+                    fNC: false.into(),
+                    fWide: true.into(),
+                };
+                let header = unsafe {
+                    slice::from_raw_parts(addr_of!(header).cast::<u8>(), size_of::<DROPFILES>())
+                };
+                let mut data = header.to_vec();
+                data.extend(wide.iter().flat_map(|c| c.to_ne_bytes()));
+                let allowed = DROPEFFECT(DROPEFFECT_COPY.0 | DROPEFFECT_MOVE.0 | DROPEFFECT_LINK.0);
+                (CF_HDROP.0, data, allowed)
+            }
+            DragPayload::Text(text) => {
+                let data = text
+                    .encode_utf16()
+                    .chain([0])
+                    .flat_map(|c| c.to_ne_bytes())
+                    .collect();
+                (CF_UNICODETEXT.0, data, DROPEFFECT_COPY)
+            }
+        };
+
+        unsafe {
+            let _ = OleInitialize(core::ptr::null());
+            // The shell's generic data object saves implementing IDataObject.
+            let object: IDataObject =
+                SHCreateDataObject(None, None, None).map_err(|e| hresult_error(e.code()))?;
+            let hglobal =
+                GlobalAlloc(GMEM_MOVEABLE, data.len()).map_err(|e| hresult_error(e.code()))?;
+            let ptr = GlobalLock(hglobal).cast::<u8>();
+            core::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+            GlobalUnlock(hglobal);
+            let medium = STGMEDIUM {
+                tymed: TYMED_HGLOBAL,
+                Anonymous: STGMEDIUM_0 { hGlobal: hglobal },
+                pUnkForRelease: ManuallyDrop::new(None),
+            };
+            if let Err(e) = object.SetData(&formatetc(format), &medium, true) {
+                let _ = GlobalFree(hglobal);
+                return Err(hresult_error(e.code()));
+            }
+
+            let source = IDropSource::from_raw(addr_of!(DROP_SOURCE) as *mut c_void);
+            let mut effect = DROPEFFECT_NONE;
+            match DoDragDrop(&object, &source, allowed, addr_of_mut!(effect)) {
+                DRAGDROP_S_DROP if effect.0 & DROPEFFECT_MOVE.0 != 0 => Ok(DragOutcome::Moved),
+                DRAGDROP_S_DROP if effect.0 & DROPEFFECT_COPY.0 != 0 => Ok(DragOutcome::Copied),
+                DRAGDROP_S_DROP if effect.0 & DROPEFFECT_LINK.0 != 0 => Ok(DragOutcome::Linked),
+                DRAGDROP_S_DROP | DRAGDROP_S_CANCEL => Ok(DragOutcome::Cancelled),
+                hr => Err(hresult_error(hr)),
+            }
+        }
+    }
+
     fn focused(&self) -> bool {
         info_get!(self.hwnd.0).focused
     }
@@ -994,33 +3081,26 @@ impl super::super::WindowT for Window {
     }
 
     fn set_width(&mut self, width: u32) {
-        info_modify!(self.hwnd.0, |v| {
-            v.width = width as _;
-            let mut flags = SWP_NOACTIVATE;
-            if v.has_frame {
-                flags |= SWP_DRAWFRAME;
-            }
-            flags |= if v.visible {
-                SWP_SHOWWINDOW
-            } else {
-                SWP_HIDEWINDOW
-            };
-            unsafe {
-                SetWindowPos(*self.hwnd, HWND_TOP, v.x, v.y, v.width, v.height, flags);
-            }
-        });
+        self.request_inner_size(PhysicalSize::new(width, self.height()));
     }
 
     fn set_min_width(&mut self, width: u32) {
         info_modify!(self.hwnd.0, |info| {
-            info.min_width = width as _;
+            let (min, max) = crate::limit_min(width, info.max_width as _);
+            info.min_width = min.min(i32::MAX as _) as _;
+            info.max_width = max.min(i32::MAX as _) as _;
         });
+        self.fit_size_limits();
     }
 
     fn set_max_width(&mut self, width: u32) {
         info_modify!(self.hwnd.0, |info| {
-            info.max_width = width as _;
+            let (min, max) = crate::limit_max(info.min_width as _, width);
+            info.min_width = min.min(i32::MAX as _) as _;
+            info.max_width = max.min(i32::MAX as _) as _;
+            info.max_follows_screen = false;
         });
+        self.fit_size_limits();
     }
 
     fn height(&self) -> u32 {
@@ -1036,33 +3116,74 @@ impl super::super::WindowT for Window {
     }
 
     fn set_height(&mut self, height: u32) {
-        info_modify!(self.hwnd.0, |v| {
-            v.height = height as _;
-            let mut flags = SWP_NOACTIVATE;
-            if v.has_frame {
-                flags |= SWP_DRAWFRAME;
-            }
-            flags |= if v.visible {
-                SWP_SHOWWINDOW
-            } else {
-                SWP_HIDEWINDOW
-            };
-            unsafe {
-                SetWindowPos(*self.hwnd, HWND_TOP, v.x, v.y, v.width, v.height, flags);
-            }
+        self.request_inner_size(PhysicalSize::new(self.width(), height));
+    }
+
+    // A pure resize: leaves the position, z-order, activation and visibility
+    // alone, so neither a stale cached position nor a raise sneaks in. The
+    // size actually applied is read back from the client rect.
+    fn request_inner_size(&mut self, size: PhysicalSize<u32>) -> Option<PhysicalSize<u32>> {
+        let info = info_get!(self.hwnd.0).clone();
+        let PhysicalSize { width, height } = size;
+        let width = (width.min(i32::MAX as _) as i32).clamp(info.min_width, info.max_width);
+        let height = (height.min(i32::MAX as _) as i32).clamp(info.min_height, info.max_height);
+        let mut rect = RECT {
+            left: 0,
+            top: 0,
+            right: width,
+            bottom: height,
+        };
+        let mut flags = SWP_NOMOVE | SWP_NOZORDER | SWP_NOACTIVATE;
+        if info.has_frame {
+            flags |= SWP_DRAWFRAME;
+        }
+        unsafe {
+            AdjustWindowRectEx(
+                addr_of_mut!(rect),
+                info.style,
+                info.menu.is_some(),
+                info.style_ex,
+            );
+            SetWindowPos(
+                *self.hwnd,
+                None,
+                0,
+                0,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+                flags,
+            );
+        }
+
+        let mut client = RECT::default();
+        if unsafe { GetClientRect(*self.hwnd, addr_of_mut!(client)) }.as_bool() == false {
+            return None;
+        }
+        let (width, height) = (client.right - client.left, client.bottom - client.top);
+        info_modify!(self.hwnd.0, |info| {
+            info.width = width;
+            info.height = height;
         });
+        Some(PhysicalSize::new(width as _, height as _))
     }
 
     fn set_min_height(&mut self, height: u32) {
         info_modify!(self.hwnd.0, |info| {
-            info.min_height = height as _;
+            let (min, max) = crate::limit_min(height, info.max_height as _);
+            info.min_height = min.min(i32::MAX as _) as _;
+            info.max_height = max.min(i32::MAX as _) as _;
         });
+        self.fit_size_limits();
     }
 
     fn set_max_height(&mut self, height: u32) {
         info_modify!(self.hwnd.0, |info| {
-            info.max_height = height as _;
+            let (min, max) = crate::limit_max(info.min_height as _, height);
+            info.min_height = min.min(i32::MAX as _) as _;
+            info.max_height = max.min(i32::MAX as _) as _;
+            info.max_follows_screen = false;
         });
+        self.fit_size_limits();
     }
 
     fn visible(&self) -> bool {
@@ -1075,50 +3196,245 @@ impl super::super::WindowT for Window {
             info.style |= WS_VISIBLE;
         });
 
-        unsafe {
-            ShowWindow(*self.hwnd, SW_NORMAL);
+        // Read first: ShowWindow sends messages that need the info.
+        let (kind, size_state) = {
+            let info = window_info(self.hwnd.0);
+            let info = info.read().unwrap();
+            (info.kind, info.size_state)
+        };
+        match kind {
+            WindowKind::Tooltip => unsafe {
+                ShowWindow(*self.hwnd, SW_SHOWNOACTIVATE);
+            },
+            WindowKind::Popup => unsafe {
+                ShowWindow(*self.hwnd, SW_NORMAL);
+                SetCapture(*self.hwnd);
+            },
+            _ => unsafe {
+                ShowWindow(
+                    *self.hwnd,
+                    match size_state {
+                        WindowSizeState::Minimized => SW_SHOWMINIMIZED,
+                        WindowSizeState::Maximized => SW_SHOWMAXIMIZED,
+                        WindowSizeState::Other => SW_NORMAL,
+                    },
+                );
+            },
         }
+        // Covers a window that was already active, which gets no
+        // WM_ACTIVATE.
+        report_focus(*self.hwnd);
     }
 
     fn hide(&mut self) {
         info_modify!(self.hwnd.0, |info| {
             info.visible = false;
+            info.focus_reported = false;
             info.style &= !WS_VISIBLE;
         });
         unsafe {
+            if info_get!(self.hwnd.0).kind == WindowKind::Popup {
+                ReleaseCapture();
+            }
             ShowWindow(*self.hwnd, SW_HIDE);
         }
     }
 
     fn resizeable(&self) -> bool {
-        info_get!(self.hwnd.0).resizeable
+        info_get!(self.hwnd.0).style_state.resizeable
     }
 
     fn set_resizeable(&mut self, resizeable: bool) {
         info_modify!(self.hwnd.0, |info| {
-            info.resizeable = resizeable;
+            info.style_state.resizeable = resizeable;
+            info.apply_style(*self.hwnd);
         });
-        unsafe {
-            SetWindowLongPtrW(
-                *self.hwnd,
-                GWL_STYLE,
-                GetWindowLongPtrW(*self.hwnd, GWL_STYLE) & !WS_SIZEBOX.0 as isize,
-            )
-        };
     }
 
     fn theme(&self) -> Theme {
         info_get!(self.hwnd.0).theme
     }
 
-    fn set_theme(&mut self, _theme: Theme) {
-        todo!()
+    fn theme_preference(&self) -> Option<Theme> {
+        info_get!(self.hwnd.0).theme_preference
+    }
+
+    fn set_theme_preference(&mut self, theme: Option<Theme>) {
+        info_modify!(self.hwnd.0, |info| {
+            info.theme_preference = theme;
+            info.theme = theme.or_else(system_theme).unwrap_or_default();
+        });
+        // Outside the lock: the frame change is handled by the window
+        // procedure before SetWindowPos returns.
+        apply_theme(*self.hwnd, self.theme());
+    }
+
+    fn close_action(&self) -> CloseAction {
+        info_get!(self.hwnd.0).close_action
+    }
+
+    fn set_close_action(&mut self, action: CloseAction) {
+        info_modify!(self.hwnd.0, |info| info.close_action = action);
+    }
+
+    fn set_background_color(&mut self, color: Option<(u8, u8, u8)>) {
+        info_modify!(self.hwnd.0, |info| info.background_color = color);
+        unsafe { InvalidateRect(*self.hwnd, None, true) };
+    }
+
+    fn set_shadow(&mut self, shadow: bool) {
+        info_modify!(self.hwnd.0, |info| {
+            info.shadow = Some(shadow);
+            info.apply_shadow(*self.hwnd);
+        });
+    }
+
+    fn current_refresh_rate_millihertz(&self) -> Option<u32> {
+        refresh_rate(*self.hwnd)
+    }
+
+    fn scale_factor(&self) -> f64 {
+        crate::scale_factor_override().unwrap_or_else(|| {
+            // 0 before Windows 10 1607.
+            match unsafe { GetDpiForWindow(*self.hwnd) } {
+                0 => 1.0,
+                dpi => dpi as f64 / USER_DEFAULT_SCREEN_DPI as f64,
+            }
+        })
+    }
+
+    fn capture(&self) -> Result<RgbaImage, OsError> {
+        let hwnd = *self.hwnd;
+        let mut rect = RECT::default();
+        unsafe { GetClientRect(hwnd, addr_of_mut!(rect)) };
+        let width = (rect.right - rect.left).max(0) as u32;
+        let height = (rect.bottom - rect.top).max(0) as u32;
+        if width == 0 || height == 0 {
+            return Ok(RgbaImage {
+                width,
+                height,
+                data: Vec::new(),
+            });
+        }
+
+        let bmi = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: size_of::<BITMAPINFOHEADER>() as _,
+                biWidth: width as _,
+                // Negative for a top-down DIB.
+                biHeight: -(height as i32),
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let hdc = unsafe { GetDC(hwnd) };
+        let mem_dc = unsafe { CreateCompatibleDC(hdc) };
+        let mut bits = std::ptr::null_mut();
+        let res = match unsafe {
+            CreateDIBSection(hdc, addr_of!(bmi), DIB_RGB_COLORS, &mut bits, None, 0)
+        } {
+            Ok(bitmap) => {
+                let old = unsafe { SelectObject(mem_dc, bitmap) };
+                // PW_RENDERFULLCONTENT also picks up DirectComposition and
+                // other GPU-rendered content, which a plain BitBlt misses.
+                let printed = unsafe {
+                    PrintWindow(
+                        hwnd,
+                        mem_dc,
+                        PRINT_WINDOW_FLAGS(PW_CLIENTONLY.0 | PW_RENDERFULLCONTENT),
+                    )
+                }
+                .as_bool();
+                let res = if printed {
+                    let len = width as usize * height as usize * 4;
+                    let mut data =
+                        unsafe { slice::from_raw_parts(bits as *const u8, len) }.to_vec();
+                    for px in data.chunks_exact_mut(4) {
+                        px.swap(0, 2);
+                        px[3] = 0xFF;
+                    }
+                    Ok(RgbaImage {
+                        width,
+                        height,
+                        data,
+                    })
+                } else {
+                    Err(unsafe { GetLastError() })
+                };
+                unsafe {
+                    SelectObject(mem_dc, old);
+                    DeleteObject(bitmap);
+                }
+                res
+            }
+            Err(_) => Err(unsafe { GetLastError() }),
+        };
+        unsafe {
+            DeleteDC(mem_dc);
+            ReleaseDC(hwnd, hdc);
+        }
+        res
     }
 
     fn title(&self) -> String {
         info_get!(self.hwnd.0).title.clone()
     }
 
+    fn position(&self) -> PhysicalPosition<i32> {
+        let info = window_info(self.hwnd.0);
+        let info = info.read().unwrap();
+        PhysicalPosition::new(info.x, info.y)
+    }
+
+    fn outer_size(&self) -> PhysicalSize<u32> {
+        let mut rect = RECT::default();
+        unsafe { GetWindowRect(*self.hwnd, addr_of_mut!(rect)) };
+        PhysicalSize::new((rect.right - rect.left) as _, (rect.bottom - rect.top) as _)
+    }
+
+    fn outer_position(&self) -> PhysicalPosition<i32> {
+        let mut rect = RECT::default();
+        unsafe { GetWindowRect(*self.hwnd, addr_of_mut!(rect)) };
+        PhysicalPosition::new(rect.left, rect.top)
+    }
+
+    // Moved reports the client area's origin, so the frame is offset by
+    // however far that sits inside it.
+    fn set_position(&mut self, position: PhysicalPosition<i32>) {
+        let mut frame = RECT::default();
+        let mut origin = POINT::default();
+        unsafe {
+            GetWindowRect(*self.hwnd, addr_of_mut!(frame));
+            ClientToScreen(*self.hwnd, addr_of_mut!(origin));
+            SetWindowPos(
+                *self.hwnd,
+                None,
+                position.x - (origin.x - frame.left),
+                position.y - (origin.y - frame.top),
+                0,
+                0,
+                SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+        }
+    }
+
+    fn set_title(&mut self, title: &str) {
+        let mut title_w = title.encode_utf16().collect::<Vec<_>>();
+        title_w.push(0x0000);
+
+        // WM_SETTEXT records it too, but only once the owning thread gets to
+        // it.
+        info_modify!(self.hwnd.0, |info| {
+            info.title = title.to_owned();
+        });
+        unsafe {
+            SetWindowTextW(*self.hwnd, PCWSTR(title_w.as_ptr())).unwrap();
+        }
+    }
+
     fn fullscreen(&self) -> bool {
         let fullscreen = info_get!(self.hwnd.0).fullscreen;
         fullscreen == FullscreenType::Exclusive || fullscreen == FullscreenType::Borderless
@@ -1129,56 +3445,68 @@ impl super::super::WindowT for Window {
     }
 
     fn set_fullscreen(&mut self, fullscreen: FullscreenType) {
-        if info_get!(self.hwnd.0).fullscreen == fullscreen {
-            return;
-        }
-
-        info_modify!(self.hwnd.0, |v| {
-            let mut flags = SWP_NOACTIVATE | SWP_FRAMECHANGED;
-            if v.has_frame {
-                flags |= SWP_DRAWFRAME;
+        let hwnd = *self.hwnd;
+        let (visible, has_frame, windowed) = {
+            let info = window_info(hwnd.0);
+            let mut v = info.write().unwrap();
+            if v.fullscreen == fullscreen {
+                return;
             }
-            flags |= if v.visible {
-                SWP_SHOWWINDOW
-            } else {
-                SWP_HIDEWINDOW
-            };
-
-            if fullscreen == FullscreenType::Borderless {
-                v.non_fullscreen_style =
-                    WINDOW_STYLE(unsafe { GetWindowLongPtrW(*self.hwnd, GWL_STYLE) } as _);
-                if v.non_fullscreen_style.contains(WS_POPUP) {
-                    let style = WS_VISIBLE | WS_OVERLAPPEDWINDOW | WS_CLIPSIBLINGS;
-                    unsafe {
-                        SetWindowLongPtrW(*self.hwnd, GWL_STYLE, style.0 as _);
-                    }
-                    v.style = style;
-                    unsafe {
-                        SetWindowPos(*self.hwnd, None, 0, 0, 600, 400, flags);
-                    }
-                } else {
-                    let w = unsafe { GetSystemMetrics(SM_CXSCREEN) };
-                    let h = unsafe { GetSystemMetrics(SM_CYSCREEN) };
-                    let style = WS_VISIBLE | WS_POPUP;
-                    unsafe {
-                        SetWindowLongPtrW(*self.hwnd, GWL_STYLE, style.0 as isize);
-                    }
-                    v.style = style;
-                    unsafe {
-                        SetWindowPos(*self.hwnd, HWND_TOP, 0, 0, w, h, flags);
-                    }
-                }
-            } else if fullscreen == FullscreenType::Exclusive {
-                todo!()
-            } else {
-                unsafe {
-                    SetWindowLongPtrW(*self.hwnd, GWL_STYLE, v.non_fullscreen_style.0 as _);
-                }
-                unsafe {
-                    SetWindowPos(*self.hwnd, HWND_TOP, v.x, v.y, v.width, v.height, flags);
+            if fullscreen == FullscreenType::Exclusive {
+                // Stays in the current state if the mode can't be set.
+                match exclusive_mode(hwnd, v.exclusive_request) {
+                    Some(exclusive) if exclusive.apply() => v.exclusive_mode = Some(exclusive),
+                    _ => return,
                 }
+            } else if let Some(exclusive) = v.exclusive_mode.take() {
+                exclusive.restore();
             }
-        });
+            let from = v.fullscreen;
+            let windowed = track_windowed_rect(&mut v.windowed_rect, from, fullscreen, || {
+                let mut rect = RECT::default();
+                unsafe { GetWindowRect(hwnd, addr_of_mut!(rect)) };
+                rect
+            });
+            // Set first so WM_GETMINMAXINFO already sees the new state.
+            v.fullscreen = fullscreen;
+            v.style_state.borderless_fullscreen = fullscreen != FullscreenType::NotFullscreen;
+            v.apply_style(hwnd);
+            (v.visible, v.has_frame, windowed)
+        };
+
+        let mut flags = SWP_NOACTIVATE | SWP_FRAMECHANGED;
+        if has_frame {
+            flags |= SWP_DRAWFRAME;
+        }
+        flags |= if visible {
+            SWP_SHOWWINDOW
+        } else {
+            SWP_HIDEWINDOW
+        };
+        let (x, y, w, h) = match windowed {
+            Some(r) => (r.left, r.top, r.right - r.left, r.bottom - r.top),
+            None if fullscreen == FullscreenType::Borderless => unsafe {
+                (
+                    0,
+                    0,
+                    GetSystemMetrics(SM_CXSCREEN),
+                    GetSystemMetrics(SM_CYSCREEN),
+                )
+            },
+            None => match monitor_rect(hwnd) {
+                Some(r) => (r.left, r.top, r.right - r.left, r.bottom - r.top),
+                None => {
+                    let v = info_get!(hwnd.0).clone();
+                    (v.x, v.y, v.width, v.height)
+                }
+            },
+        };
+        // Called without the lock held: SetWindowPos sends WM_GETMINMAXINFO,
+        // WM_SIZE and WM_MOVE before returning, so Resized has been queued
+        // by the time set_fullscreen returns.
+        unsafe {
+            SetWindowPos(hwnd, HWND_TOP, x, y, w, h, flags);
+        }
     }
 
     fn maximized(&self) -> bool {
@@ -1203,7 +3531,14 @@ impl super::super::WindowT for Window {
 
     fn normalize(&mut self) {
         let info = info_get!(self.hwnd.0).clone();
-        if info.size_state != WindowSizeState::Minimized {
+        if info.size_state != WindowSizeState::Other {
+            if info.visible {
+                unsafe {
+                    ShowWindow(*self.hwnd, SW_RESTORE);
+                }
+            }
+            set_size_state(*self.hwnd, WindowSizeState::Other);
+        } else {
             let mut flags = SWP_FRAMECHANGED | SWP_ASYNCWINDOWPOS | SWP_NOCOPYBITS;
             if info.has_frame {
                 flags |= SWP_DRAWFRAME;
@@ -1262,36 +3597,40 @@ impl super::super::WindowT for Window {
 
     fn request_redraw(&mut self) {
         unsafe {
-            RedrawWindow(*self.hwnd, None, None, RDW_NOINTERNALPAINT);
+            RedrawWindow(*self.hwnd, None, None, RDW_INVALIDATE);
         }
     }
 
+    fn request_redraw_continuous(&mut self, continuous: bool) {
+        let interval = continuous.then(|| crate::frame_interval(refresh_rate(*self.hwnd)));
+        info_modify!(self.hwnd.0, |info| {
+            info.redraw.set_interval(interval);
+        });
+    }
+
     fn enabled_buttons(&self) -> WindowButtons {
-        info_get!(self.hwnd.0).enabled_buttons
+        info_get!(self.hwnd.0).style_state.buttons
     }
 
     fn set_enabled_buttons(&mut self, buttons: WindowButtons) {
         info_modify!(self.hwnd.0, |info| {
-            info.enabled_buttons = buttons;
-            let mut style = WINDOW_STYLE(0);
-            if buttons.contains(WindowButtons::MAXIMIZE) {
-                style |= WS_MAXIMIZEBOX
-            };
-            if buttons.contains(WindowButtons::MINIMIZE) {
-                style |= WS_MINIMIZEBOX
-            };
-            info.style &= !style;
-
-            unsafe {
-                SetWindowLongPtrW(*self.hwnd, GWL_STYLE, info.style.0 as _);
-            }
-
-            if info.no_close == false && buttons.contains(WindowButtons::CLOSE) {
-                return;
-            }
-
-            todo!()
+            info.style_state.buttons = buttons;
+            info.apply_style(*self.hwnd);
         });
+        // There's no style for the close button; it follows the system
+        // menu's Close item, which also gates Alt+F4.
+        let enable = if buttons.contains(WindowButtons::CLOSE) {
+            MF_ENABLED
+        } else {
+            MF_GRAYED
+        };
+        unsafe {
+            EnableMenuItem(
+                GetSystemMenu(*self.hwnd, false),
+                SC_CLOSE,
+                MF_BYCOMMAND | enable,
+            )
+        };
     }
 }
 
@@ -1301,11 +3640,28 @@ impl WindowTExt for Window {
     }
 }
 
+// Gets a WM_SYSCOMMAND's command and returns whether to carry it out.
+pub type SystemCommandHook = Box<dyn FnMut(u32) -> bool>;
+
 pub trait WindowExtWindows {
     fn style(&self) -> WINDOW_STYLE;
     fn set_style(&mut self, style: WINDOW_STYLE);
+    fn set_style_raw(&mut self, style: WINDOW_STYLE);
     fn set_style_ex(&mut self, style_ex: WINDOW_EX_STYLE);
     fn set_title(&mut self, title: &str);
+    fn set_raw_mouse_input(&mut self, enabled: bool) -> Result<(), WIN32_ERROR>;
+    // Called with each WM_SYSCOMMAND's command (SC_CLOSE, SC_SCREENSAVE,
+    // SC_MONITORPOWER, ...) before it's carried out; returning false blocks
+    // it. Only runs on the thread that created the window.
+    fn set_system_command_hook(&mut self, hook: Option<SystemCommandHook>);
+    fn exclusive_fullscreen_focus_policy(&self) -> ExclusiveFocusPolicy;
+    fn set_exclusive_fullscreen_focus_policy(&mut self, policy: ExclusiveFocusPolicy);
+    // The size and refresh rate exclusive fullscreen switches to; None keeps
+    // the monitor's current mode. Takes effect the next time the window
+    // enters exclusive fullscreen.
+    fn set_exclusive_fullscreen_mode(&mut self, mode: Option<((u32, u32), Option<u32>)>);
+    #[cfg(feature = "accesskit")]
+    fn set_accesskit_adapter(&mut self, adapter: Box<dyn AccessKitAdapter>);
 }
 
 impl WindowExtWindows for Window {
@@ -1313,10 +3669,24 @@ impl WindowExtWindows for Window {
         info_get!(self.hwnd.0).style
     }
 
+    // Replaces the base style; resizeability, enabled buttons, fullscreen and
+    // visibility are still layered on top of it.
     fn set_style(&mut self, style: WINDOW_STYLE) {
         info_modify!(self.hwnd.0, |info| {
-            info.style = style | WS_CLIPSIBLINGS;
-            info.non_fullscreen_style = style | WS_CLIPSIBLINGS;
+            info.style_state.base = style & !WS_VISIBLE;
+            info.style_state.external = false;
+            info.apply_style(*self.hwnd);
+            unsafe { UpdateWindow(*self.hwnd) };
+        });
+    }
+
+    // Writes GWL_STYLE as given and stops nwin from managing it, so the
+    // resizeable, enabled-button and fullscreen setters no longer change it.
+    // Call set_style to hand control back.
+    fn set_style_raw(&mut self, style: WINDOW_STYLE) {
+        info_modify!(self.hwnd.0, |info| {
+            info.style_state.external = true;
+            info.style = style;
             unsafe { SetWindowLongPtrW(*self.hwnd, GWL_STYLE, style.0 as _) };
             unsafe { UpdateWindow(*self.hwnd) };
         });
@@ -1331,26 +3701,647 @@ impl WindowExtWindows for Window {
     }
 
     fn set_title(&mut self, title: &str) {
-        let mut title_w = title.encode_utf16().collect::<Vec<_>>();
-        title_w.push(0x0000);
+        crate::WindowT::set_title(self, title)
+    }
 
-        unsafe {
-            SetWindowTextW(*self.hwnd, PCWSTR(title_w.as_ptr())).unwrap();
+    // Raw input is registered per process, so only one window receives it at a
+    // time. Needed for mouse buttons above 5.
+    fn set_raw_mouse_input(&mut self, enabled: bool) -> Result<(), WIN32_ERROR> {
+        let device = RAWINPUTDEVICE {
+            usUsagePage: 0x01,
+            usUsage: 0x02,
+            dwFlags: if enabled {
+                RAWINPUTDEVICE_FLAGS(0)
+            } else {
+                RIDEV_REMOVE
+            },
+            hwndTarget: if enabled { *self.hwnd } else { HWND(0) },
+        };
+        if unsafe { RegisterRawInputDevices(&[device], size_of::<RAWINPUTDEVICE>() as _) }.as_bool()
+            == false
+        {
+            return Err(unsafe { GetLastError() });
         }
+        info_modify!(self.hwnd.0, |info| {
+            info.raw_mouse = enabled;
+            info.raw_buttons = 0;
+        });
+        Ok(())
+    }
+
+    fn set_system_command_hook(&mut self, hook: Option<SystemCommandHook>) {
+        SYSCOMMAND_HOOKS.with(|hooks| match hook {
+            Some(hook) => hooks.borrow_mut().insert(self.hwnd.0, hook),
+            None => hooks.borrow_mut().remove(&self.hwnd.0),
+        });
+    }
+
+    fn exclusive_fullscreen_focus_policy(&self) -> ExclusiveFocusPolicy {
+        info_get!(self.hwnd.0).exclusive_focus_policy
+    }
+
+    fn set_exclusive_fullscreen_focus_policy(&mut self, policy: ExclusiveFocusPolicy) {
+        info_modify!(self.hwnd.0, |info| {
+            info.exclusive_focus_policy = policy;
+        });
     }
+
+    fn set_exclusive_fullscreen_mode(&mut self, mode: Option<((u32, u32), Option<u32>)>) {
+        info_modify!(self.hwnd.0, |info| {
+            info.exclusive_request = mode;
+        });
+    }
+
+    #[cfg(feature = "accesskit")]
+    fn set_accesskit_adapter(&mut self, adapter: Box<dyn AccessKitAdapter>) {
+        ACCESSKIT_ADAPTERS.with(|adapters| adapters.borrow_mut().insert(self.hwnd.0, adapter));
+    }
+}
+
+thread_local! {
+    static SYSCOMMAND_HOOKS: RefCell<HashMap<isize, SystemCommandHook>> =
+        RefCell::new(HashMap::new());
+}
+
+// Like the accesskit adapters, the hook is taken out of the map while it runs.
+// Commands pass when there's no hook.
+fn run_syscommand_hook(hwnd: HWND, command: u32) -> bool {
+    let Some(mut hook) = SYSCOMMAND_HOOKS.with(|hooks| hooks.borrow_mut().remove(&hwnd.0)) else {
+        return true;
+    };
+    let res = hook(command);
+    SYSCOMMAND_HOOKS.with(|hooks| {
+        hooks.borrow_mut().entry(hwnd.0).or_insert(hook);
+    });
+    res
+}
+
+#[cfg(feature = "accesskit")]
+pub trait AccessKitAdapter {
+    fn handle_wm_getobject(&mut self, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT>;
+    fn window_focused(&mut self, focused: bool);
+}
+
+#[cfg(feature = "accesskit")]
+thread_local! {
+    static ACCESSKIT_ADAPTERS: RefCell<HashMap<isize, Box<dyn AccessKitAdapter>>> =
+        RefCell::new(HashMap::new());
+}
+
+// The adapter is taken out of the map while it runs so it can call back into
+// the window.
+#[cfg(feature = "accesskit")]
+fn with_accesskit_adapter<T>(
+    hwnd: HWND,
+    f: impl FnOnce(&mut dyn AccessKitAdapter) -> T,
+) -> Option<T> {
+    let mut adapter = ACCESSKIT_ADAPTERS.with(|adapters| adapters.borrow_mut().remove(&hwnd.0))?;
+    let res = f(adapter.as_mut());
+    ACCESSKIT_ADAPTERS.with(|adapters| {
+        adapters.borrow_mut().entry(hwnd.0).or_insert(adapter);
+    });
+    Some(res)
+}
+
+pub(crate) struct InstanceLock {
+    mutex: HANDLE,
+    hwnd: HWND,
+}
+
+pub(crate) struct Notifier {
+    name: String,
+}
+
+const INSTANCE_CLASS_NAME: &str = "nwin instance";
+const ACTIVATION_MAGIC: usize = 0x6E77696E;
+
+static INSTANCE_CLASS: std::sync::Once = std::sync::Once::new();
+
+// The first instance holds a named mutex and a message-only window titled
+// after the instance; later instances find that window and deliver their
+// arguments with WM_COPYDATA.
+pub(crate) fn claim_instance(name: &str) -> Result<Claim, InstanceError> {
+    let mut mutex_name_w = format!("Local\\nwin-instance-{name}")
+        .encode_utf16()
+        .collect::<Vec<_>>();
+    mutex_name_w.push(0x0000);
+    let mut class_name_w = INSTANCE_CLASS_NAME.encode_utf16().collect::<Vec<_>>();
+    class_name_w.push(0x0000);
+    let mut name_w = name.encode_utf16().collect::<Vec<_>>();
+    name_w.push(0x0000);
+
+    let mutex = unsafe { CreateMutexW(None, true, PCWSTR(mutex_name_w.as_ptr())) }
+        .map_err(|_| InstanceError::Os)?;
+    if unsafe { GetLastError() } == ERROR_ALREADY_EXISTS {
+        unsafe { CloseHandle(mutex) };
+        return Ok(Claim::AlreadyRunning(crate::single_instance::Notifier {
+            inner: Notifier {
+                name: name.to_owned(),
+            },
+        }));
+    }
+
+    INSTANCE_CLASS.call_once(|| {
+        let wndclass = WNDCLASSEXW {
+            cbSize: size_of::<WNDCLASSEXW>() as u32,
+            lpfnWndProc: Some(instance_wnd_proc),
+            hInstance: get_instance().unwrap(),
+            lpszClassName: PCWSTR(class_name_w.as_ptr()),
+            ..Default::default()
+        };
+        unsafe { RegisterClassExW(addr_of!(wndclass)) };
+    });
+
+    let hwnd = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE(0),
+            PCWSTR(class_name_w.as_ptr()),
+            PCWSTR(name_w.as_ptr()),
+            WINDOW_STYLE(0),
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            HMENU(0),
+            get_instance().unwrap(),
+            None,
+        )
+    };
+    if hwnd.0 == 0 {
+        unsafe { ReleaseMutex(mutex) };
+        unsafe { CloseHandle(mutex) };
+        return Err(InstanceError::Os);
+    }
+
+    Ok(Claim::Acquired(crate::single_instance::InstanceLock {
+        inner: InstanceLock { mutex, hwnd },
+    }))
+}
+
+unsafe extern "system" fn instance_wnd_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_COPYDATA {
+        let cds = &*(lparam.0 as *const COPYDATASTRUCT);
+        let target = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+        if cds.dwData == ACTIVATION_MAGIC && target != 0 {
+            let args = if cds.lpData.is_null() {
+                Vec::new()
+            } else {
+                crate::single_instance::decode_args(slice::from_raw_parts(
+                    cds.lpData as *const u8,
+                    cds.cbData as _,
+                ))
+            };
+            send_ev!(target, WindowEvent::ActivationRequested { args });
+            return LRESULT(1);
+        }
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
+
+impl InstanceLock {
+    pub(crate) fn attach(&mut self, id: WindowId) {
+        unsafe { SetWindowLongPtrW(self.hwnd, GWLP_USERDATA, id.0 as _) };
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        unsafe { DestroyWindow(self.hwnd) };
+        unsafe { ReleaseMutex(self.mutex) };
+        unsafe { CloseHandle(self.mutex) };
+    }
+}
+
+impl Notifier {
+    pub(crate) fn notify(&self, args: &[String]) -> Result<(), InstanceError> {
+        let mut class_name_w = INSTANCE_CLASS_NAME.encode_utf16().collect::<Vec<_>>();
+        class_name_w.push(0x0000);
+        let mut name_w = self.name.encode_utf16().collect::<Vec<_>>();
+        name_w.push(0x0000);
+
+        let hwnd = unsafe {
+            FindWindowExW(
+                HWND_MESSAGE,
+                None,
+                PCWSTR(class_name_w.as_ptr()),
+                PCWSTR(name_w.as_ptr()),
+            )
+        };
+        if hwnd.0 == 0 {
+            return Err(InstanceError::NotRunning);
+        }
+
+        // Let the running instance take the foreground when it presents itself.
+        let mut pid = 0u32;
+        unsafe { GetWindowThreadProcessId(hwnd, Some(addr_of_mut!(pid))) };
+        unsafe { AllowSetForegroundWindow(pid) };
+
+        let mut data = crate::single_instance::encode_args(args);
+        let cds = COPYDATASTRUCT {
+            dwData: ACTIVATION_MAGIC,
+            cbData: data.len() as _,
+            lpData: data.as_mut_ptr().cast(),
+        };
+        let res = unsafe { SendMessageW(hwnd, WM_COPYDATA, WPARAM(0), LPARAM(addr_of!(cds) as _)) };
+        if res.0 == 0 {
+            Err(InstanceError::Os)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+struct Hotkeys {
+    hwnd: HWND,
+    next_id: i32,
+    registered: Vec<i32>,
+}
+
+thread_local! {
+    static HOTKEYS: RefCell<Option<Hotkeys>> = const { RefCell::new(None) };
+}
+
+impl Hotkeys {
+    // WM_HOTKEY is posted to a message-only window owned by the registering
+    // thread and picked up when its event loop is pumped.
+    fn new() -> Result<Self, HotkeyError> {
+        let hwnd = create_window(
+            "STATIC",
+            "nwin hotkeys",
+            false,
+            None,
+            None,
+            0,
+            0,
+            0,
+            0,
+            Some(HWND_MESSAGE),
+            None,
+            get_instance().ok_or(HotkeyError::Os)?,
+            None,
+        )
+        .map_err(|_| HotkeyError::Os)?;
+        Ok(Self {
+            hwnd,
+            next_id: 1,
+            registered: Vec::new(),
+        })
+    }
+}
+
+impl Drop for Hotkeys {
+    fn drop(&mut self) {
+        for id in self.registered.iter() {
+            unsafe { UnregisterHotKey(self.hwnd, *id) };
+        }
+        unsafe { DestroyWindow(self.hwnd) };
+    }
+}
+
+fn virtual_key(key: KeyboardScancode) -> Option<VIRTUAL_KEY> {
+    // VK_SEPARATOR comes first and also reads as NumPeriod.
+    if key == KeyboardScancode::NumPeriod {
+        return Some(VK_DECIMAL);
+    }
+    (0..=0xFF)
+        .map(VIRTUAL_KEY)
+        .find(|vk| KeyboardScancode::try_from(*vk).ok() == Some(key))
+}
+
+pub(crate) fn register_hotkey(combo: KeyCombo) -> Result<HotkeyId, HotkeyError> {
+    HOTKEYS.with(|hotkeys| {
+        let mut hotkeys = hotkeys.borrow_mut();
+        if hotkeys.is_none() {
+            *hotkeys = Some(Hotkeys::new()?);
+        }
+        let h = hotkeys.as_mut().unwrap();
+
+        let vk = virtual_key(combo.key).ok_or(HotkeyError::UnsupportedKey)?;
+        let mut modifiers = MOD_NOREPEAT;
+        if combo
+            .modifiers
+            .intersects(Modifiers::LSHIFT | Modifiers::RSHIFT)
+        {
+            modifiers |= MOD_SHIFT;
+        }
+        if combo
+            .modifiers
+            .intersects(Modifiers::LCTRL | Modifiers::RCTRL)
+        {
+            modifiers |= MOD_CONTROL;
+        }
+        if combo
+            .modifiers
+            .intersects(Modifiers::LALT | Modifiers::RALT)
+        {
+            modifiers |= MOD_ALT;
+        }
+        if combo
+            .modifiers
+            .intersects(Modifiers::LSYS | Modifiers::RSYS)
+        {
+            modifiers |= MOD_WIN;
+        }
+
+        let id = h.next_id;
+        if unsafe { RegisterHotKey(h.hwnd, id, modifiers, vk.0 as _) }.as_bool() == false {
+            return if unsafe { GetLastError() } == ERROR_HOTKEY_ALREADY_REGISTERED {
+                Err(HotkeyError::AlreadyRegistered)
+            } else {
+                Err(HotkeyError::Os)
+            };
+        }
+        h.next_id += 1;
+        h.registered.push(id);
+        Ok(HotkeyId(id as _))
+    })
+}
+
+pub(crate) fn unregister_hotkey(id: HotkeyId) {
+    HOTKEYS.with(|hotkeys| {
+        if let Some(h) = hotkeys.borrow_mut().as_mut() {
+            if let Some(i) = h.registered.iter().position(|k| *k == id.0 as i32) {
+                unsafe { UnregisterHotKey(h.hwnd, h.registered.remove(i)) };
+            }
+        }
+    })
+}
+
+pub(crate) fn unregister_all_hotkeys() {
+    HOTKEYS.with(|hotkeys| hotkeys.borrow_mut().take());
+}
+
+pub(crate) fn next_hotkey() -> Option<HotkeyId> {
+    HOTKEYS.with(|hotkeys| {
+        let hotkeys = hotkeys.borrow();
+        let h = hotkeys.as_ref()?;
+        let mut msg = MSG::default();
+        if unsafe { PeekMessageW(addr_of_mut!(msg), h.hwnd, WM_HOTKEY, WM_HOTKEY, PM_REMOVE) }
+            .as_bool()
+        {
+            Some(HotkeyId(msg.wParam.0 as _))
+        } else {
+            None
+        }
+    })
 }
 
 unsafe impl HasRawWindowHandle for Window {
     fn raw_window_handle(&self) -> RawWindowHandle {
         let mut handle = Win32WindowHandle::empty();
-        let hinstance = info_get!(self.hwnd.0).hinstance;
-        handle.hinstance = hinstance.0 as _;
+        handle.hinstance = self.hinstance.0 as _;
         handle.hwnd = self.hwnd.0 as _;
         RawWindowHandle::Win32(handle)
     }
 }
 
+#[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::platform::scancodes::KEYS;
+
+    #[test]
+    fn virtual_keys_match_the_golden_table() {
+        for row in KEYS {
+            if let Some(vk) = row.vk {
+                assert_eq!(
+                    KeyboardScancode::try_from(VIRTUAL_KEY(vk)),
+                    Ok(row.key),
+                    "{}",
+                    row.name
+                );
+            }
+            assert_eq!(virtual_key(row.key).map(|vk| vk.0), row.vk, "{}", row.name);
+        }
+        // Nothing outside the table maps to a key, apart from the keypad
+        // separator, which some layouts have instead of the decimal point.
+        for vk in 0..=0xFF {
+            if vk != VK_SEPARATOR.0 && !KEYS.iter().any(|row| row.vk == Some(vk)) {
+                assert!(
+                    KeyboardScancode::try_from(VIRTUAL_KEY(vk)).is_err(),
+                    "{vk:#x}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scancodes_match_the_golden_table() {
+        for row in KEYS {
+            if let Some(sc) = row.oem {
+                assert_eq!(
+                    KeyboardScancode::try_from(OemScancode(sc)),
+                    Ok(row.key),
+                    "{}",
+                    row.name
+                );
+            }
+            assert_eq!(
+                oem_scancode(row.key).map(|sc| sc.0),
+                row.oem,
+                "{}",
+                row.name
+            );
+        }
+        for sc in (0..=0xFF).chain(0xE000..=0xE0FF) {
+            if !KEYS.iter().any(|row| row.oem == Some(sc)) {
+                assert!(
+                    KeyboardScancode::try_from(OemScancode(sc)).is_err(),
+                    "{sc:#x}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn repeated_geometry_is_not_reported() {
+        let mut info = WindowInfo {
+            x: 0,
+            y: 0,
+            width: 640,
+            height: 480,
+            ..WindowInfo::default()
+        };
+        let mut sender = EventSender::new();
+        let steps: [(bool, i32, i32); 6] = [
+            (true, 0, 0),
+            (false, 640, 480),
+            (true, 10, 20),
+            (true, 10, 20),
+            (false, 800, 600),
+            (false, 800, 600),
+        ];
+        for (is_move, a, b) in steps {
+            let event = if is_move {
+                move_event(&mut info, a, b)
+            } else {
+                resize_event(&mut info, a, b)
+            };
+            if let Some(event) = event {
+                sender.send(WindowId(1), event);
+            }
+        }
+        assert_eq!(
+            sender.take_queued(),
+            [
+                WindowEvent::Moved(PhysicalPosition::new(10, 20)),
+                WindowEvent::Resized(PhysicalSize::new(800, 600)),
+            ]
+        );
+    }
+
+    #[test]
+    fn window_info_debug_is_a_summary() {
+        let handle = Arc::new(HWND(0x1a2b));
+        let info = WindowInfo {
+            title: "hello".to_owned(),
+            x: -10,
+            y: 20,
+            width: 640,
+            height: 480,
+            handle: Arc::downgrade(&handle),
+            ..WindowInfo::default()
+        };
+        let shown = format!("{info:?}");
+        assert!(
+            shown.starts_with(r#"WindowInfo { id: HWND 0x1a2b, title: "hello", position: (-10, 20), size: (640, 480), "#),
+            "{shown}"
+        );
+        assert!(shown.ends_with(", .. }"), "{shown}");
+        drop(handle);
+        assert!(format!("{info:?}").starts_with("WindowInfo { id: none,"));
+    }
+
+    #[test]
+    fn high_contrast_only_follows_its_own_flag() {
+        use windows::Win32::UI::Accessibility::{
+            HCF_AVAILABLE, HCF_CONFIRMHOTKEY, HCF_HOTKEYACTIVE, HCF_HOTKEYAVAILABLE,
+            HIGHCONTRASTW_FLAGS,
+        };
+        let hc = |flags: u32| HIGHCONTRASTW {
+            cbSize: size_of::<HIGHCONTRASTW>() as _,
+            dwFlags: HIGHCONTRASTW_FLAGS(flags),
+            ..Default::default()
+        };
+        let others =
+            HCF_AVAILABLE.0 | HCF_HOTKEYACTIVE.0 | HCF_CONFIRMHOTKEY.0 | HCF_HOTKEYAVAILABLE.0;
+        assert!(!high_contrast_on(&hc(0)));
+        assert!(!high_contrast_on(&hc(others)));
+        assert!(high_contrast_on(&hc(HCF_HIGHCONTRASTON.0)));
+        assert!(high_contrast_on(&hc(HCF_HIGHCONTRASTON.0 | others)));
+    }
+
+    #[test]
+    fn either_animation_setting_reduces_motion() {
+        assert!(!motion_reduced(None, None));
+        assert!(!motion_reduced(Some(true), Some(1)));
+        assert!(motion_reduced(Some(false), Some(1)));
+        assert!(motion_reduced(Some(true), Some(0)));
+        assert!(motion_reduced(None, Some(0)));
+        assert!(motion_reduced(Some(false), None));
+    }
+
+    #[test]
+    fn fullscreen_windows_skip_the_size_limits() {
+        let (min, max) = track_sizes(FullscreenType::NotFullscreen, (20, 30), (800, 600)).unwrap();
+        assert_eq!((min.x, min.y, max.x, max.y), (20, 30, 800, 600));
+        for fullscreen in [FullscreenType::Borderless, FullscreenType::Exclusive] {
+            assert!(track_sizes(fullscreen, (20, 30), (800, 600)).is_none());
+        }
+    }
+
+    #[test]
+    fn screen_limits_follow_the_new_screen() {
+        // A bigger monitor raises the maximum and leaves the minimum alone.
+        assert_eq!(
+            screen_limits((20, 30), (2560, 1440)),
+            ((20, 30), (2560, 1440))
+        );
+        // A smaller one drags a minimum that no longer fits down with it.
+        assert_eq!(
+            screen_limits((1600, 300), (1280, 720)),
+            ((1280, 300), (1280, 720))
+        );
+        assert_eq!(
+            screen_limits((1280, 720), (1280, 720)),
+            ((1280, 720), (1280, 720))
+        );
+    }
+
+    #[test]
+    fn leaving_fullscreen_restores_the_windowed_rect() {
+        use FullscreenType::{Borderless, Exclusive, NotFullscreen};
+        let rect = |left| RECT {
+            left,
+            top: 20,
+            right: left + 640,
+            bottom: 500,
+        };
+        let mut saved = None;
+        assert_eq!(
+            track_windowed_rect(&mut saved, NotFullscreen, Borderless, || rect(10)),
+            None
+        );
+        // Switching kinds keeps the rect from before the first one.
+        assert_eq!(
+            track_windowed_rect(&mut saved, Borderless, Exclusive, || rect(0)),
+            None
+        );
+        assert_eq!(
+            track_windowed_rect(&mut saved, Exclusive, NotFullscreen, || rect(0)),
+            Some(rect(10))
+        );
+        assert_eq!(saved, None);
+        // A window moved while windowed comes back to where it was last.
+        track_windowed_rect(&mut saved, NotFullscreen, Exclusive, || rect(30));
+        assert_eq!(
+            track_windowed_rect(&mut saved, Exclusive, NotFullscreen, || rect(0)),
+            Some(rect(30))
+        );
+    }
+
+    #[test]
+    fn wheel_deltas_are_in_notches() {
+        let wparam = |delta: i16| WPARAM((delta as u16 as usize) << 16 | 0x0008);
+        assert_eq!(wheel_notches(wparam(120)), 1.0);
+        assert_eq!(wheel_notches(wparam(-240)), -2.0);
+        assert_eq!(wheel_notches(wparam(30)), 0.25);
+        assert_eq!(wheel_notches(wparam(0)), 0.0);
+    }
+
+    #[test]
+    fn page_scrolling_falls_back_to_the_default_lines() {
+        assert_eq!(scroll_lines(Some(5)), 5);
+        assert_eq!(scroll_lines(Some(0)), 0);
+        assert_eq!(scroll_lines(Some(WHEEL_PAGESCROLL)), 3);
+        assert_eq!(scroll_lines(None), 3);
+    }
+
+    #[test]
+    fn typed_text_pairs_surrogates() {
+        let emoji: Vec<u16> = "😀".encode_utf16().collect();
+        assert_eq!(typed_text(&emoji, 2).as_deref(), Some("😀"));
+        assert_eq!(typed_text(&[0x61, 0x301], 2).as_deref(), Some("a\u{301}"));
+        // A lone half, at either end, never panics.
+        assert_eq!(typed_text(&emoji, 1).as_deref(), Some("\u{fffd}"));
+        assert_eq!(typed_text(&emoji[1..], 1).as_deref(), Some("\u{fffd}"));
+        assert_eq!(
+            typed_text(&[0x61, emoji[0]], 2).as_deref(),
+            Some("a\u{fffd}")
+        );
+        // Dead keys and keys that type nothing.
+        assert_eq!(typed_text(&[0x60], -1), None);
+        assert_eq!(typed_text(&[0; 8], 0), None);
+        // A count past the buffer is cut to it.
+        assert_eq!(typed_text(&[0x61], 4).as_deref(), Some("a"));
+    }
+
     //#[test]
     fn cw_test() {
         use crate::platform::win32::{create_window, get_instance, register_class};
@@ -1378,6 +4369,7 @@ mod tests {
             None,
             None,
             get_instance().unwrap(),
+            None,
         )
         .unwrap();
 
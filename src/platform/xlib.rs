@@ -2,42 +2,96 @@
 
 use core::slice;
 use std::{
-    collections::HashMap,
-    ffi::CString,
-    mem::MaybeUninit,
-    ptr::addr_of_mut,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ffi::{c_char, c_int, c_long, c_short, c_uint, c_ulong, CStr, CString, OsString},
+    mem::{size_of, MaybeUninit},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::{OsStrExt, OsStringExt},
+    },
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    ptr::{addr_of, addr_of_mut},
+    rc::Rc,
     sync::{
-        atomic::{AtomicU32, AtomicU64},
-        Arc, RwLock,
+        atomic::{AtomicBool, AtomicU64, AtomicU8},
+        Arc, PoisonError, RwLock, Weak,
     },
+    time::{Duration, Instant},
 };
 
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle, XlibWindowHandle};
+use x11::keysym::{
+    XK_Alt_L, XK_Alt_R, XK_BackSpace, XK_Caps_Lock, XK_Control_L, XK_Control_R, XK_Delete, XK_Down,
+    XK_End, XK_Escape, XK_Home, XK_Insert, XK_KP_Add, XK_KP_Decimal, XK_KP_Divide, XK_KP_Enter,
+    XK_KP_Multiply, XK_KP_Subtract, XK_Left, XK_Menu, XK_Next, XK_Num_Lock, XK_Pause, XK_Print,
+    XK_Prior, XK_Return, XK_Right, XK_Scroll_Lock, XK_Shift_L, XK_Shift_R, XK_Super_L, XK_Super_R,
+    XK_Tab, XK_Up, XK_a, XK_apostrophe, XK_b, XK_backslash, XK_bracketleft, XK_bracketright, XK_c,
+    XK_comma, XK_d, XK_e, XK_equal, XK_f, XK_g, XK_grave, XK_h, XK_i, XK_j, XK_k, XK_l, XK_m,
+    XK_minus, XK_n, XK_o, XK_p, XK_period, XK_q, XK_r, XK_s, XK_semicolon, XK_slash, XK_space,
+    XK_t, XK_u, XK_v, XK_w, XK_x, XK_y, XK_z, XK_0, XK_1, XK_2, XK_3, XK_4, XK_5, XK_6, XK_7, XK_8,
+    XK_9, XK_F1, XK_F10, XK_F11, XK_F12, XK_F2, XK_F3, XK_F4, XK_F5, XK_F6, XK_F7, XK_F8, XK_F9,
+    XK_KP_0, XK_KP_1, XK_KP_2, XK_KP_3, XK_KP_4, XK_KP_5, XK_KP_6, XK_KP_7, XK_KP_8, XK_KP_9,
+};
 use x11::xlib::{
-    Always, Button1, Button1MotionMask, Button2, Button2MotionMask, Button3, Button3MotionMask,
-    Button4, Button4MotionMask, Button5, Button5MotionMask, ButtonMotionMask, ButtonPress,
-    ButtonPressMask, ButtonRelease, ButtonReleaseMask, CWBackPixel, CWBackPixmap, CWBackingPixel,
-    CWBackingPlanes, CWBackingStore, CWBitGravity, CWBorderPixel, CWBorderPixmap, CWColormap,
-    CWCursor, CWDontPropagate, CWEventMask, CWOverrideRedirect, CWSaveUnder, CWWinGravity,
-    CenterGravity, ClientMessage, ClientMessageData, Colormap, ColormapChangeMask, ConfigureNotify,
-    ControlMask, CopyFromParent, CurrentTime, Cursor, DestroyNotify, EastGravity, EnterWindowMask,
-    ExposureMask, FocusChangeMask, FocusIn, FocusOut, ForgetGravity, InputOnly, InputOutput,
-    KeyPress, KeyPressMask, KeyRelease, KeyReleaseMask, KeymapStateMask, LeaveWindowMask, LockMask,
-    Mod1Mask, Mod4Mask, NorthEastGravity, NorthGravity, NorthWestGravity, NotUseful,
+    Above, AllocNone, AlreadyGrabbed, Always, AnyPropertyType, BadAccess, BadWindow, Below,
+    Button1, Button1MotionMask, Button2, Button2MotionMask, Button3, Button3MotionMask, Button4,
+    Button4MotionMask, Button5, Button5MotionMask, ButtonMotionMask, ButtonPress, ButtonPressMask,
+    ButtonRelease, ButtonReleaseMask, CWBackPixel, CWBackPixmap, CWBackingPixel, CWBackingPlanes,
+    CWBackingStore, CWBitGravity, CWBorderPixel, CWBorderPixmap, CWColormap, CWCursor,
+    CWDontPropagate, CWEventMask, CWOverrideRedirect, CWSaveUnder, CWWinGravity, CenterGravity,
+    ClientMessage, ClientMessageData, Colormap, ColormapChangeMask, ConfigureNotify, ControlMask,
+    CopyFromParent, CurrentTime, Cursor, DestroyNotify, DirectColor, DoBlue, DoGreen, DoRed,
+    EastGravity, EnterWindowMask, Expose, ExposureMask, FocusChangeMask, FocusIn, FocusOut,
+    ForgetGravity, GrabFrozen, GrabInvalidTime, GrabModeAsync, GrabSuccess, GrayScale, InputOnly,
+    InputOutput, KeyPress, KeyPressMask, KeyRelease, KeyReleaseMask, KeySym, KeymapStateMask,
+    LeaveWindowMask, LockMask, MapNotify, Mod1Mask, Mod2Mask, Mod4Mask, MotionNotify, NoEventMask,
+    NorthEastGravity, NorthGravity, NorthWestGravity, NotUseful, NotifyInferior, NotifyNormal,
     OwnerGrabButtonMask, PMaxSize, PMinSize, Pixmap, PointerMotionHintMask, PointerMotionMask,
-    PropertyChangeMask, ResizeRedirectMask, RevertToParent, ShiftMask, SouthEastGravity,
-    SouthGravity, SouthWestGravity, StaticGravity, StructureNotifyMask, SubstructureNotifyMask,
-    SubstructureRedirectMask, VisibilityChangeMask, Visual, VisualAllMask, WestGravity, WhenMapped,
-    XAllocSizeHints, XCheckWindowEvent, XClientMessageEvent, XCloseDisplay, XCreateWindow,
-    XDefaultRootWindow, XDefaultScreen, XDestroyWindow, XEvent, XFree, XGetVisualInfo,
-    XIconifyWindow, XInternAtom, XMapWindow, XMatchVisualInfo, XOpenDisplay, XRaiseWindow,
-    XResizeWindow, XRootWindow, XSelectInput, XSendEvent, XSetInputFocus, XSetWMNormalHints,
-    XSetWindowAttributes, XStoreName, XUnmapWindow, XVisualInfo,
+    PropModeReplace, PropertyChangeMask, PropertyDelete, PropertyNewValue, PropertyNotify,
+    PseudoColor, ReparentNotify, ResizeRedirectMask, RevertToParent, SelectionClear,
+    SelectionNotify, SelectionRequest, ShiftMask, SouthEastGravity, SouthGravity, SouthWestGravity,
+    StateHint, StaticColor, StaticGravity, StaticGray, StructureNotifyMask, SubstructureNotifyMask,
+    SubstructureRedirectMask, Success, TrueColor, USPosition, UnmapNotify, VisibilityChangeMask,
+    Visual, VisualScreenMask, WestGravity, WhenMapped, XAllocColor, XAllocSizeHints,
+    XChangeProperty, XCheckTypedWindowEvent, XCheckWindowEvent, XClearArea, XClientMessageEvent,
+    XCloseDisplay, XColor, XConfigureEvent, XConnectionNumber, XConvertSelection, XCreateColormap,
+    XCreateSimpleWindow, XCreateWindow, XDefaultColormap, XDefaultRootWindow, XDefaultScreen,
+    XDefaultVisual, XDeleteProperty, XDestroyImage, XDestroyWindow, XErrorEvent, XEvent, XFlush,
+    XFree, XGetGeometry, XGetImage, XGetPixel, XGetSelectionOwner, XGetVisualInfo, XGetWMHints,
+    XGetWindowAttributes, XGetWindowProperty, XGrabKey, XGrabKeyboard, XGrabPointer,
+    XIconifyWindow, XInternAtom, XKeysymToKeycode, XLookupKeysym, XLookupString, XLowerWindow,
+    XMapWindow, XMaxRequestSize, XMoveResizeWindow, XMoveWindow, XNextEvent, XOpenDisplay,
+    XPeekEvent, XPending, XPutBackEvent, XQLength, XQueryPointer, XQueryTree, XRaiseWindow,
+    XResizeWindow, XResourceManagerString, XRootWindow, XSelectInput, XSelectionEvent,
+    XSelectionRequestEvent, XSendEvent, XSetErrorHandler, XSetInputFocus, XSetSelectionOwner,
+    XSetWMHints, XSetWMNormalHints, XSetWMProtocols, XSetWindowAttributes, XSetWindowBackground,
+    XSetWindowBackgroundPixmap, XSetWindowBorderWidth, XSizeHints, XStoreName, XSync,
+    XTranslateCoordinates, XUngrabKey, XUngrabKeyboard, XUngrabPointer, XUnmapWindow, XUrgencyHint,
+    XVisualIDFromVisual, XVisualInfo, XWMHints, XWindowAttributes, XkbKeysymToModifiers,
+    XkbLockModifiers, XkbSetDetectableAutoRepeat, ZPixmap, XA_ATOM,
+};
+#[cfg(feature = "xrandr")]
+use x11::xrandr::{
+    RRCrtcChangeNotifyMask, RRNotify, RROutputChangeNotifyMask, RRScreenChangeNotify,
+    RRScreenChangeNotifyMask, RR_Connected, RR_DoubleScan, RR_Interlace, XRRFreeCrtcInfo,
+    XRRFreeOutputInfo, XRRFreeScreenResources, XRRGetCrtcInfo, XRRGetOutputInfo,
+    XRRGetOutputPrimary, XRRGetScreenResourcesCurrent, XRRModeInfo, XRRQueryExtension,
+    XRRSelectInput, XRRUpdateConfiguration,
 };
 
 use crate::{
-    EventSender, FullscreenType, Modifiers, MouseButtons, Theme, WindowButtons, WindowId,
-    WindowIdExt, WindowSizeState, WindowTExt,
+    clipboard::{self, ClipboardError},
+    dpi::{PhysicalPosition, PhysicalSize},
+    hotkeys::{HotkeyError, HotkeyId, KeyCombo},
+    input_state::KeyStateTracker,
+    keyboard::{LockError, LockKey},
+    single_instance::{Claim, InstanceError},
+    CloseAction, DpiError, DpiMode, DragOutcome, DragPayload, EventSender, FullscreenType,
+    KeyboardScancode, Modifiers, Monitor, MonitorId, MonitorTracker, MouseScancode,
+    RedrawScheduler, RefreshRateTracker, RgbaImage, SystemSettings, Theme, WindowButtons, WindowId,
+    WindowIdExt, WindowKind, WindowSizeState, WindowTExt,
 };
 
 #[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
@@ -55,6 +109,42 @@ impl WindowClass {
     }
 }
 
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum VisualClass {
+    StaticGray,
+    GrayScale,
+    StaticColor,
+    PseudoColor,
+    TrueColor,
+    DirectColor,
+}
+
+impl VisualClass {
+    pub fn from_i32(class: i32) -> Option<Self> {
+        match class {
+            StaticGray => Some(Self::StaticGray),
+            GrayScale => Some(Self::GrayScale),
+            StaticColor => Some(Self::StaticColor),
+            PseudoColor => Some(Self::PseudoColor),
+            TrueColor => Some(Self::TrueColor),
+            DirectColor => Some(Self::DirectColor),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VisualInfo {
+    pub id: x11::xlib::VisualID,
+    pub depth: i32,
+    pub class: VisualClass,
+    pub red_mask: u64,
+    pub green_mask: u64,
+    pub blue_mask: u64,
+    pub colormap_size: i32,
+    pub bits_per_rgb: i32,
+}
+
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 #[repr(i32)]
 pub enum Gravity {
@@ -124,6 +214,25 @@ bitflags::bitflags! {
     }
 }
 
+impl EventMask {
+    // What a normal top-level window needs. SUBSTRUCTURE_REDIRECT and
+    // RESIZE_REDIRECT are meant for window managers: only one client can
+    // select the former on a window, and the latter hands the WM's resizes
+    // to us instead of applying them.
+    pub const CLIENT_DEFAULT: Self = Self::KEY_PRESS
+        .union(Self::KEY_RELEASE)
+        .union(Self::BUTTON_PRESS)
+        .union(Self::BUTTON_RELEASE)
+        .union(Self::POINTER_MOTION)
+        .union(Self::ENTER_WINDOW)
+        .union(Self::LEAVE_WINDOW)
+        .union(Self::FOCUS_CHANGE)
+        .union(Self::STRUCTURE_NOTIFY)
+        .union(Self::PROPERTY_CHANGE)
+        .union(Self::EXPOSURE)
+        .union(Self::VISIBILITY_CHANGE);
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct WindowAttributes {
     inner: XSetWindowAttributes,
@@ -159,13 +268,16 @@ pub struct WindowAttributesBuilder {
     inner: WindowAttributes,
 }
 
+impl Default for WindowAttributesBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WindowAttributesBuilder {
     pub fn new() -> Self {
         Self {
-            inner: WindowAttributes {
-                inner: unsafe { MaybeUninit::zeroed().assume_init() },
-                mask: 0,
-            },
+            inner: WindowAttributes::default(),
         }
     }
 
@@ -264,6 +376,54 @@ impl WindowAttributesBuilder {
     }
 }
 
+struct SizeHints(*mut XSizeHints);
+
+impl SizeHints {
+    fn new() -> Self {
+        let hints = unsafe { XAllocSizeHints() };
+        assert!(!hints.is_null());
+        Self(hints)
+    }
+
+    fn with_min_size(self, width: u32, height: u32) -> Self {
+        unsafe {
+            (*self.0).min_width = width.min(i32::MAX as _) as _;
+            (*self.0).min_height = height.min(i32::MAX as _) as _;
+            (*self.0).flags |= PMinSize;
+        }
+        self
+    }
+
+    fn with_max_size(self, width: u32, height: u32) -> Self {
+        unsafe {
+            (*self.0).max_width = width.min(i32::MAX as _) as _;
+            (*self.0).max_height = height.min(i32::MAX as _) as _;
+            (*self.0).flags |= PMaxSize;
+        }
+        self
+    }
+
+    // Without USPosition window managers place new windows themselves.
+    fn with_position(self, x: i32, y: i32) -> Self {
+        unsafe {
+            (*self.0).x = x;
+            (*self.0).y = y;
+            (*self.0).flags |= USPosition;
+        }
+        self
+    }
+
+    fn apply(&self, display: *mut x11::xlib::Display, window: x11::xlib::Window) {
+        unsafe { XSetWMNormalHints(display, window, self.0) };
+    }
+}
+
+impl Drop for SizeHints {
+    fn drop(&mut self) {
+        unsafe { XFree(self.0.cast()) };
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn create_window(
     window_name: &str,
@@ -275,9 +435,11 @@ fn create_window(
     visible: bool,
     border_width: u32,
     depth: Option<i32>,
+    visual_id: Option<x11::xlib::VisualID>,
     class: WindowClass,
     attributes: Option<WindowAttributes>,
     event_mask: EventMask,
+    background: Option<(u8, u8, u8)>,
 ) -> Result<
     (
         x11::xlib::Window,
@@ -285,48 +447,55 @@ fn create_window(
         i32,
         x11::xlib::VisualID,
     ),
-    (),
+    OsError,
 > {
     let display = unsafe { XOpenDisplay(core::ptr::null()) };
     if display.is_null() {
-        return Err(());
+        return Err(OsError);
     }
 
     let screen = unsafe { XDefaultScreen(display) };
 
-    let mut vinfo: XVisualInfo = unsafe { MaybeUninit::zeroed().assume_init() };
-    vinfo.class = class.as_u32() as _;
-    vinfo.screen = screen;
-    vinfo.depth = depth.unwrap_or(0);
-    let (visual, visual_id) = if unsafe {
-        XMatchVisualInfo(
-            display,
-            screen,
-            depth.unwrap_or(0),
-            class.as_u32() as _,
-            addr_of_mut!(vinfo),
-        )
-    } == 0
-    {
-        let mut nitems = 0i32;
-        let p = unsafe {
-            XGetVisualInfo(
-                display,
-                VisualAllMask,
-                addr_of_mut!(vinfo),
-                addr_of_mut!(nitems),
-            )
-        };
-        let ret = if nitems == 0 {
-            (core::ptr::null_mut(), 0)
-        } else {
-            let vi = unsafe { slice::from_raw_parts(p, nitems as _) };
-            (vi[0].visual, vi[0].visualid)
-        };
-        unsafe { XFree(p.cast()) };
-        ret
+    let depth = depth.filter(|d| *d != CopyFromParent);
+    let mut attributes = attributes;
+    let (visual, visual_id, depth) = if class == WindowClass::InputOnly {
+        (core::ptr::null_mut(), 0, CopyFromParent)
     } else {
-        (vinfo.visual, vinfo.visualid)
+        let available = screen_visuals(display, screen);
+        let default_id = unsafe { XVisualIDFromVisual(XDefaultVisual(display, screen)) };
+        let infos = available.iter().map(|(v, _)| *v).collect::<Vec<_>>();
+        let Some(index) = select_visual(&infos, visual_id, depth, default_id) else {
+            unsafe { XCloseDisplay(display) };
+            return Err(OsError);
+        };
+        let (info, visual) = available[index];
+
+        // A window on a non-default visual needs a colormap and border of its
+        // own, otherwise XCreateWindow fails with BadMatch.
+        if info.id != default_id {
+            let a = attributes.get_or_insert_with(WindowAttributes::default);
+            if a.mask & CWColormap == 0 {
+                a.inner.colormap = unsafe {
+                    XCreateColormap(display, XRootWindow(display, screen), visual, AllocNone)
+                };
+                a.mask |= CWColormap;
+            }
+            if a.mask & (CWBorderPixel | CWBorderPixmap) == 0 {
+                a.mask |= CWBorderPixel;
+            }
+        }
+        if let Some(color) = background {
+            let colormap = match attributes {
+                Some(ref a) if a.mask & CWColormap != 0 => a.inner.colormap,
+                _ => unsafe { XDefaultColormap(display, screen) },
+            };
+            if let Some(pixel) = rgb_pixel(display, &info, colormap, color) {
+                let a = attributes.get_or_insert_with(WindowAttributes::default);
+                a.inner.background_pixel = pixel;
+                a.mask |= CWBackPixel;
+            }
+        }
+        (visual, info.id, info.depth)
     };
 
     let mask = if let Some(ref a) = attributes {
@@ -334,7 +503,7 @@ fn create_window(
     } else {
         0
     };
-    let attributes = if let Some(mut a) = attributes {
+    let attributes = if let Some(ref mut a) = attributes {
         addr_of_mut!(a.inner)
     } else {
         core::ptr::null_mut()
@@ -349,7 +518,7 @@ fn create_window(
             width,
             height,
             border_width,
-            depth.unwrap_or(CopyFromParent as _),
+            depth,
             class.as_u32(),
             visual,
             mask,
@@ -357,9 +526,10 @@ fn create_window(
         )
     };
     assert_ne!(window, 0);
+    crate::WINDOW_CREATED.store(true, std::sync::atomic::Ordering::Relaxed);
 
     if window < 16 {
-        return Err(());
+        return Err(OsError);
     }
 
     unsafe { XSelectInput(display, window, event_mask.bits()) };
@@ -368,12 +538,457 @@ fn create_window(
             XMapWindow(display, window);
         }
     };
-    let window_name_c = CString::new(window_name).unwrap();
-    unsafe { XStoreName(display, window, window_name_c.as_ptr()) };
+    store_title(display, window, window_name);
     Ok((window, display, screen, visual_id))
 }
 
+// WM_NAME is Latin-1, so the title also goes in _NET_WM_NAME, which window
+// managers prefer when it's there.
+fn store_title(display: *mut x11::xlib::Display, window: x11::xlib::Window, title: &str) {
+    let title = title.split('\0').next().unwrap_or_default();
+    let title_c = CString::new(title).unwrap();
+    unsafe {
+        XStoreName(display, window, title_c.as_ptr());
+        XChangeProperty(
+            display,
+            window,
+            intern_atom(display, "_NET_WM_NAME"),
+            intern_atom(display, "UTF8_STRING"),
+            8,
+            PropModeReplace,
+            title.as_ptr(),
+            title.len() as _,
+        );
+    }
+}
+
+#[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn fields(a: &XSetWindowAttributes) -> [i64; 15] {
+        [
+            a.background_pixmap as _,
+            a.background_pixel as _,
+            a.border_pixmap as _,
+            a.border_pixel as _,
+            a.bit_gravity as _,
+            a.win_gravity as _,
+            a.backing_store as _,
+            a.backing_planes as _,
+            a.backing_pixel as _,
+            a.save_under as _,
+            a.event_mask,
+            a.do_not_propagate_mask,
+            a.override_redirect as _,
+            a.colormap as _,
+            a.cursor as _,
+        ]
+    }
+
+    #[test]
+    fn attribute_builder_sets_one_field_each() {
+        let b = WindowAttributesBuilder::new;
+        // In the order of `fields`.
+        let cases = [
+            (b().with_background_pixmap(3).build(), CWBackPixmap, 3),
+            (b().with_background_pixel(4).build(), CWBackPixel, 4),
+            (b().with_border_pixmap(5).build(), CWBorderPixmap, 5),
+            (b().with_border_pixel(6).build(), CWBorderPixel, 6),
+            (
+                b().with_bit_gravity(Gravity::Center).build(),
+                CWBitGravity,
+                CenterGravity as _,
+            ),
+            (
+                b().with_win_gravity(Gravity::SouthEast).build(),
+                CWWinGravity,
+                SouthEastGravity as _,
+            ),
+            (
+                b().with_backing_store(BackingStore::Always).build(),
+                CWBackingStore,
+                Always as _,
+            ),
+            (
+                b().with_backing_planes(BackingPlanes(0xff)).build(),
+                CWBackingPlanes,
+                0xff,
+            ),
+            (b().with_backing_pixel(7).build(), CWBackingPixel, 7),
+            (b().with_save_under(true).build(), CWSaveUnder, 1),
+            (
+                b().with_event_mask(EventMask::EXPOSURE).build(),
+                CWEventMask,
+                ExposureMask,
+            ),
+            (
+                b().with_do_not_propagate_mask(EventMask::KEY_PRESS).build(),
+                CWDontPropagate,
+                KeyPressMask,
+            ),
+            (
+                b().with_override_redirect(true).build(),
+                CWOverrideRedirect,
+                1,
+            ),
+            (b().with_colormap(8).build(), CWColormap, 8),
+            (b().with_cursor(9).build(), CWCursor, 9),
+        ];
+        let default = fields(&WindowAttributes::default().inner);
+        for (i, (attributes, bit, value)) in cases.into_iter().enumerate() {
+            assert_eq!(attributes.mask, bit, "field {i}");
+            let mut expected = default;
+            expected[i] = value;
+            assert_eq!(fields(&attributes.inner), expected, "field {i}");
+        }
+    }
+
+    #[test]
+    fn keysyms_match_the_golden_table() {
+        for row in crate::platform::scancodes::KEYS {
+            assert_eq!(
+                keysym(row.key),
+                row.keysym.map(KeySym::from),
+                "{}",
+                row.name
+            );
+        }
+    }
+
+    // scancode() takes the first key whose keysym is on the keycode, so a
+    // keysym shared by two keys would hide one of them.
+    #[test]
+    fn keysyms_are_unique() {
+        let mut seen = HashSet::new();
+        for row in crate::platform::scancodes::KEYS {
+            if let Some(sym) = keysym(row.key) {
+                assert!(seen.insert(sym), "{} repeats {sym:#x}", row.name);
+            }
+        }
+    }
+
+    enum Value<'a> {
+        Int(i32),
+        String(&'a str),
+        Color,
+    }
+
+    // Serializes settings the way an XSETTINGS manager does.
+    fn xsettings(msb_first: bool, entries: &[(&str, Value)]) -> Vec<u8> {
+        let card16 = |v: u16| {
+            if msb_first {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let card32 = |v: u32| {
+            if msb_first {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let pad = |data: &mut Vec<u8>| data.resize((data.len() + 3) & !3, 0);
+        let mut data = vec![msb_first as u8, 0, 0, 0];
+        data.extend(card32(7));
+        data.extend(card32(entries.len() as _));
+        for (name, value) in entries {
+            let kind = match value {
+                Value::Int(_) => 0,
+                Value::String(_) => 1,
+                Value::Color => 2,
+            };
+            data.extend([kind, 0]);
+            data.extend(card16(name.len() as _));
+            data.extend(name.as_bytes());
+            pad(&mut data);
+            data.extend(card32(1));
+            match value {
+                Value::Int(v) => data.extend(card32(*v as _)),
+                Value::String(v) => {
+                    data.extend(card32(v.len() as _));
+                    data.extend(v.as_bytes());
+                    pad(&mut data);
+                }
+                Value::Color => data.extend([0xFF; 8]),
+            }
+        }
+        data
+    }
+
+    fn sample(msb_first: bool) -> Vec<u8> {
+        xsettings(
+            msb_first,
+            &[
+                ("Net/ThemeName", Value::String("Adwaita-dark")),
+                // Names and values of every length mod 4, to cover the
+                // padding.
+                ("A", Value::String("x")),
+                ("Ab", Value::String("xy")),
+                ("Abc", Value::String("xyz")),
+                ("Abcd", Value::String("")),
+                ("Gtk/CursorThemeColor", Value::Color),
+                ("Net/DoubleClickTime", Value::Int(400)),
+                ("Net/CursorBlink", Value::Int(-1)),
+            ],
+        )
+    }
+
+    #[test]
+    fn xsettings_parse_in_either_byte_order() {
+        for msb_first in [false, true] {
+            let settings = parse_xsettings(&sample(msb_first));
+            let expected = HashMap::from([
+                ("Net/ThemeName", XSetting::String("Adwaita-dark".to_owned())),
+                ("A", XSetting::String("x".to_owned())),
+                ("Ab", XSetting::String("xy".to_owned())),
+                ("Abc", XSetting::String("xyz".to_owned())),
+                ("Abcd", XSetting::String(String::new())),
+                ("Net/DoubleClickTime", XSetting::Int(400)),
+                ("Net/CursorBlink", XSetting::Int(-1)),
+            ]);
+            assert_eq!(
+                settings,
+                expected
+                    .into_iter()
+                    .map(|(k, v)| (k.to_owned(), v))
+                    .collect(),
+                "msb_first: {msb_first}"
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_xsettings_keep_what_was_complete() {
+        let data = sample(false);
+        let full = parse_xsettings(&data);
+        for len in 0..data.len() {
+            let settings = parse_xsettings(&data[..len]);
+            assert!(settings.len() <= full.len());
+            for (name, value) in settings {
+                assert_eq!(full.get(&name), Some(&value), "{len}: {name}");
+            }
+        }
+        // An unknown type ends the parse rather than misreading the rest.
+        let mut data = xsettings(false, &[("A", Value::Int(1)), ("B", Value::Int(2))]);
+        data[28] = 9;
+        assert_eq!(parse_xsettings(&data).len(), 1);
+        // As does a count larger than what's there.
+        let mut data = xsettings(false, &[("A", Value::Int(1))]);
+        data[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse_xsettings(&data).len(), 1);
+    }
+
+    #[test]
+    fn settings_come_from_xsettings() {
+        let values = parse_xsettings(&xsettings(
+            true,
+            &[
+                ("Net/ThemeName", Value::String("HighContrastInverse")),
+                ("Net/DoubleClickTime", Value::Int(400)),
+                ("Net/DoubleClickDistance", Value::Int(5)),
+                ("Net/DndDragThreshold", Value::Int(0)),
+                ("Net/CursorBlinkTime", Value::Int(1000)),
+                ("Gtk/EnableAnimations", Value::Int(0)),
+            ],
+        ));
+        let settings = settings_from_xsettings(&values);
+        assert_eq!(
+            settings,
+            SystemSettings {
+                double_click_time: Duration::from_millis(400),
+                double_click_area: (10, 10),
+                caret_blink_interval: Some(Duration::from_millis(500)),
+                reduced_motion: true,
+                high_contrast: true,
+                ..SystemSettings::default()
+            }
+        );
+        assert_eq!(theme_from_settings(&values), Some(Theme::Light));
+
+        let values = parse_xsettings(&xsettings(
+            false,
+            &[
+                ("Net/ThemeName", Value::Int(1)),
+                ("Net/CursorBlink", Value::Int(0)),
+                ("Net/CursorBlinkTime", Value::Int(1000)),
+            ],
+        ));
+        assert_eq!(settings_from_xsettings(&values).caret_blink_interval, None);
+        assert_eq!(theme_from_settings(&values), None);
+        assert_eq!(
+            settings_from_xsettings(&HashMap::new()),
+            SystemSettings::default()
+        );
+    }
+
+    #[test]
+    fn dark_themes_are_named_that_way() {
+        for (name, theme) in [
+            ("Adwaita-dark", Theme::Dark),
+            ("Yaru-Dark", Theme::Dark),
+            ("Adwaita", Theme::Light),
+            ("", Theme::Light),
+        ] {
+            let values = HashMap::from([(
+                "Net/ThemeName".to_owned(),
+                XSetting::String(name.to_owned()),
+            )]);
+            assert_eq!(theme_from_settings(&values), Some(theme), "{name}");
+        }
+    }
+
+    #[test]
+    fn window_info_debug_is_a_summary() {
+        let handle = Arc::new(0x3c00007);
+        let info = WindowInfo {
+            name: "hello".to_owned(),
+            width: 640,
+            height: 480,
+            handle: Arc::downgrade(&handle),
+            ..WindowInfo::default()
+        };
+        let shown = format!("{info:?}");
+        assert!(
+            shown.starts_with(r#"WindowInfo { id: XID 0x3c00007, title: "hello", position: (0, 0), size: (640, 480), "#),
+            "{shown}"
+        );
+        assert!(shown.ends_with(", .. }"), "{shown}");
+        assert!(!shown.contains("display"), "{shown}");
+        drop(handle);
+        assert!(format!("{info:?}").starts_with("WindowInfo { id: none,"));
+    }
+
+    #[test]
+    fn configures_report_only_what_changed() {
+        use crate::WindowEvent::{Moved, Resized};
+        let configure = |x, y, width, height| XConfigureEvent {
+            x,
+            y,
+            width,
+            height,
+            ..unsafe { MaybeUninit::zeroed().assume_init() }
+        };
+        let mut w = WindowInfo {
+            width: 640,
+            height: 480,
+            ..WindowInfo::default()
+        };
+        let mut sender = EventSender::new();
+        for cfg in [
+            configure(0, 0, 640, 480),
+            configure(10, 20, 640, 480),
+            configure(10, 20, 800, 600),
+            // Moved and resized at once reports both, move first.
+            configure(-5, 0, 1024, 768),
+            configure(-5, 0, 1024, 768),
+            configure(-5, 0, 1024, 767),
+        ] {
+            for event in configure_events(&mut w, &cfg) {
+                sender.send(WindowId(1), event);
+            }
+        }
+        assert_eq!(
+            sender.take_queued(),
+            [
+                Moved(PhysicalPosition::new(10, 20)),
+                Resized(PhysicalSize::new(800, 600)),
+                Moved(PhysicalPosition::new(-5, 0)),
+                Resized(PhysicalSize::new(1024, 768)),
+                Resized(PhysicalSize::new(1024, 767)),
+            ]
+        );
+        assert_eq!((w.x, w.y, w.width, w.height), (-5, 0, 1024, 767));
+    }
+
+    #[test]
+    fn a_panic_poisons_only_its_window() {
+        use std::panic;
+        let (poisoned, healthy) = (0x7fff_0001, 0x7fff_0002);
+        let event_loop = crate::EventLoop::new();
+        let sender = || {
+            Arc::new(RwLock::new(EventSender::with_receiver(
+                event_loop.receiver.clone(),
+            )))
+        };
+        let (sender_a, sender_b) = (sender(), sender());
+        for (id, sender) in [(poisoned, &sender_a), (healthy, &sender_b)] {
+            let w = WindowInfo {
+                sender: sender.clone(),
+                ..WindowInfo::default()
+            };
+            WINDOW_INFO.write().unwrap().insert(id, w);
+        }
+        // The handler panicked while sending.
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _sender = sender_a.write().unwrap();
+            panic!("boom");
+        }));
+        assert!(sender_a.is_poisoned());
+
+        poison_window(poisoned, &"boom");
+        assert!(!sender_a.is_poisoned());
+        let is_poisoned = |id| WINDOW_INFO.read().unwrap()[&id].poisoned;
+        assert!(is_poisoned(poisoned));
+        assert!(!is_poisoned(healthy));
+        // A poisoned window isn't pumped any more, so this doesn't touch its
+        // (null) display.
+        WindowId(poisoned).next_event();
+        sender_b
+            .write()
+            .unwrap()
+            .send(WindowId(healthy), crate::WindowEvent::RedrawRequested);
+        assert_eq!(
+            event_loop.receiver.write().unwrap().take(),
+            [
+                (
+                    WindowId(poisoned),
+                    crate::WindowEvent::UnrecoverableError("boom".to_owned())
+                ),
+                (WindowId(healthy), crate::WindowEvent::RedrawRequested),
+            ]
+        );
+
+        let mut info = WINDOW_INFO.write().unwrap();
+        info.remove(&poisoned);
+        info.remove(&healthy);
+    }
+
+    #[test]
+    fn mouse_buttons_skip_the_wheel() {
+        assert_eq!(mouse_scancode(Button1), Some(MouseScancode::LClick));
+        assert_eq!(mouse_scancode(Button2), Some(MouseScancode::MClick));
+        assert_eq!(mouse_scancode(Button3), Some(MouseScancode::RClick));
+        for wheel in 4..=7 {
+            assert_eq!(mouse_scancode(wheel), None);
+        }
+        // The side buttons come after the wheel.
+        for n in 8..=20 {
+            assert_eq!(
+                mouse_scancode(n),
+                Some(MouseScancode::from_number(n as u8 - 4))
+            );
+        }
+        assert_eq!(mouse_scancode(8), Some(MouseScancode::Button4));
+        assert_eq!(mouse_scancode(9), Some(MouseScancode::Button5));
+        assert_eq!(
+            mouse_scancode(c_uint::MAX),
+            Some(MouseScancode::ButtonN(u8::MAX))
+        );
+    }
+
+    #[test]
+    fn attribute_builder_starts_from_the_defaults() {
+        let attributes = WindowAttributesBuilder::default().build();
+        assert_eq!(attributes.mask, 0);
+        assert_eq!(
+            fields(&attributes.inner),
+            fields(&WindowAttributes::default().inner)
+        );
+    }
+
     /*
     use crate::WindowT;
 
@@ -439,7 +1054,7 @@ mod tests {
         use std::{mem::MaybeUninit, ptr::addr_of_mut};
         use x11::xlib::{KeyPress, XEvent, XNextEvent};
         use x11::xlib::XClearWindow;
-        use crate::platform::xlib::{WindowExtXlib, EventMask};
+        use crate::platform::xlib::{WindowExtX11, EventMask};
         use x11::xlib::{FocusIn, FocusOut, MapNotify, UnmapNotify, ReparentNotify, ConfigureNotify, ResizeRequest};
 
         let mut window = super::Window::try_new(None, None).unwrap();
@@ -490,12 +1105,185 @@ mod tests {
     */
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct Window {
     id: Arc<x11::xlib::Window>,
+    // Fixed for the window's lifetime, so kept out of WINDOW_INFO.
+    display: *mut x11::xlib::Display,
+    visual_id: x11::xlib::VisualID,
 }
 
-#[derive(Clone, Debug)]
+impl Default for Window {
+    fn default() -> Self {
+        Self {
+            id: Arc::default(),
+            display: std::ptr::null_mut(),
+            visual_id: 0,
+        }
+    }
+}
+
+// Xlib serializes calls on a display with its own lock.
+unsafe impl Send for Window {}
+unsafe impl Sync for Window {}
+
+// X11 reports the details of failed requests asynchronously, through the error
+// handler, so there's nothing more specific to return.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OsError;
+
+// Why XGrabKeyboard refused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GrabError {
+    // Another client holds an active grab.
+    AlreadyGrabbed,
+    // The keyboard is frozen by another client's synchronous grab.
+    Frozen,
+    InvalidTime,
+    // The window isn't mapped, or is hidden by an unmapped ancestor.
+    NotViewable,
+}
+
+fn grab_keyboard(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+) -> Result<(), GrabError> {
+    let status = unsafe {
+        XGrabKeyboard(
+            display,
+            window,
+            x11::xlib::True,
+            GrabModeAsync,
+            GrabModeAsync,
+            CurrentTime,
+        )
+    };
+    match status {
+        GrabSuccess => Ok(()),
+        AlreadyGrabbed => Err(GrabError::AlreadyGrabbed),
+        GrabFrozen => Err(GrabError::Frozen),
+        GrabInvalidTime => Err(GrabError::InvalidTime),
+        _ => Err(GrabError::NotViewable),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PlatformAttributes {
+    parent: Option<x11::xlib::Window>,
+    attributes: WindowAttributes,
+    event_mask: Option<EventMask>,
+    border_width: Option<u32>,
+    depth: Option<i32>,
+    visual_id: Option<x11::xlib::VisualID>,
+    startup_id: Option<Option<String>>,
+}
+
+impl PlatformAttributes {
+    fn modify_attributes(
+        &mut self,
+        f: impl FnOnce(WindowAttributesBuilder) -> WindowAttributesBuilder,
+    ) {
+        self.attributes = f(WindowAttributesBuilder {
+            inner: self.attributes,
+        })
+        .build();
+    }
+}
+
+pub trait WindowBuilderExtX11 {
+    fn with_override_redirect(self, override_redirect: bool) -> Self;
+    fn with_save_under(self, save_under: bool) -> Self;
+    fn with_backing_store(self, backing_store: BackingStore) -> Self;
+    fn with_x11_colormap(self, colormap: Colormap) -> Self;
+    fn with_x11_cursor(self, cursor: Cursor) -> Self;
+    fn with_x11_event_mask(self, event_mask: EventMask) -> Self;
+    fn with_x11_parent(self, parent: x11::xlib::Window) -> Self;
+    fn with_x11_attributes(self, attributes: WindowAttributes) -> Self;
+    fn with_border_width(self, border_width: u32) -> Self;
+    fn with_border_pixel(self, pixel: u64) -> Self;
+    fn with_depth(self, depth: i32) -> Self;
+    fn with_visual(self, visual_id: x11::xlib::VisualID) -> Self;
+    fn with_startup_id(self, startup_id: Option<String>) -> Self;
+}
+
+impl WindowBuilderExtX11 for crate::WindowBuilder {
+    fn with_override_redirect(mut self, override_redirect: bool) -> Self {
+        self.platform
+            .modify_attributes(|a| a.with_override_redirect(override_redirect));
+        self
+    }
+
+    fn with_save_under(mut self, save_under: bool) -> Self {
+        self.platform
+            .modify_attributes(|a| a.with_save_under(save_under));
+        self
+    }
+
+    fn with_backing_store(mut self, backing_store: BackingStore) -> Self {
+        self.platform
+            .modify_attributes(|a| a.with_backing_store(backing_store));
+        self
+    }
+
+    fn with_x11_colormap(mut self, colormap: Colormap) -> Self {
+        self.platform
+            .modify_attributes(|a| a.with_colormap(colormap));
+        self
+    }
+
+    fn with_x11_cursor(mut self, cursor: Cursor) -> Self {
+        self.platform.modify_attributes(|a| a.with_cursor(cursor));
+        self
+    }
+
+    fn with_x11_event_mask(mut self, event_mask: EventMask) -> Self {
+        self.platform.event_mask = Some(event_mask);
+        self
+    }
+
+    fn with_x11_parent(mut self, parent: x11::xlib::Window) -> Self {
+        self.platform.parent = Some(parent);
+        self
+    }
+
+    fn with_x11_attributes(mut self, attributes: WindowAttributes) -> Self {
+        self.platform.attributes = attributes;
+        self
+    }
+
+    fn with_border_width(mut self, border_width: u32) -> Self {
+        self.platform.border_width = Some(border_width);
+        self
+    }
+
+    fn with_border_pixel(mut self, pixel: u64) -> Self {
+        self.platform
+            .modify_attributes(|a| a.with_border_pixel(pixel));
+        self
+    }
+
+    // Checked against the screen's visuals when the window is built.
+    fn with_depth(mut self, depth: i32) -> Self {
+        self.platform.depth = Some(depth);
+        self
+    }
+
+    // Takes precedence over `with_depth`; the window gets a colormap for the
+    // visual unless one was supplied.
+    fn with_visual(mut self, visual_id: x11::xlib::VisualID) -> Self {
+        self.platform.visual_id = Some(visual_id);
+        self
+    }
+
+    // Overrides DESKTOP_STARTUP_ID, which is otherwise claimed by the first
+    // window built. None opts the window out of startup notification.
+    fn with_startup_id(mut self, startup_id: Option<String>) -> Self {
+        self.platform.startup_id = Some(startup_id);
+        self
+    }
+}
+
+#[derive(Clone)]
 pub(crate) struct WindowInfo {
     display: *mut x11::xlib::Display,
     visual_id: x11::xlib::VisualID,
@@ -516,21 +1304,171 @@ pub(crate) struct WindowInfo {
     class: WindowClass,
     visual: Option<Visual>,
     event_mask: EventMask,
+    background_color: Option<(u8, u8, u8)>,
     enabled_buttons: WindowButtons,
     focused: bool,
+    // The focus last sent in Focused; see report_focus.
+    focus_reported: bool,
     fullscreen: FullscreenType,
+    // The frame's position and the client size from before fullscreen.
+    windowed_geometry: Option<(i32, i32, u32, u32)>,
+    // See frame_extents.
+    frame_extents: [u32; 4],
+    // The outstanding request_user_attention, until the window is activated.
+    attention: Option<crate::UserAttentionType>,
     size_state: WindowSizeState,
     resizeable: bool,
     theme: Theme,
-    modifiers: Modifiers,
+    theme_preference: Option<Theme>,
+    keys: KeyStateTracker,
+    kind: WindowKind,
+    xsettings_owner: x11::xlib::Window,
+    // XRandR's first event code, if the extension is present.
+    #[cfg(feature = "xrandr")]
+    rr_event_base: Option<i32>,
+    redraw: RedrawScheduler,
+    refresh_rate: RefreshRateTracker,
+    monitors: MonitorTracker,
+    // Lets Window::from_id hand out handles sharing the original Arc.
+    handle: Weak<x11::xlib::Window>,
+    requested_size: Option<(u32, u32)>,
+    close_action: CloseAction,
+    // Set once a handler panics; see next_event.
+    poisoned: bool,
+    // Time of the last key or button event, passed along with activation
+    // requests.
+    user_time: x11::xlib::Time,
+    // Whether the server reports held keys as repeated presses with a single
+    // release at the end, rather than release/press pairs.
+    detectable_repeat: bool,
+    accepts_drops: bool,
+    keyboard_grabbed: bool,
+    // Where the pointer was last seen over the window, in client coordinates.
+    cursor_position: Option<PhysicalPosition<f64>>,
+    // The XDND drag over the window, and the source of a drop whose data
+    // has been requested.
+    dnd: Option<DndOffer>,
+    dnd_drop: Option<x11::xlib::Window>,
+    // Completed, and cleared, once the window is first mapped.
+    startup_id: Option<String>,
     sender: Arc<RwLock<EventSender>>,
 }
 
+impl WindowInfo {
+    // The size most recently asked for, which later setters build on until
+    // the WM has answered with a ConfigureNotify.
+    fn target_size(&self) -> (u32, u32) {
+        self.requested_size.unwrap_or((self.width, self.height))
+    }
+
+    // The root's _NET_ACTIVE_WINDOW is only watched while a request is
+    // outstanding, since its other properties change all the time.
+    fn update_attention(
+        &mut self,
+        window: x11::xlib::Window,
+        attention: Option<crate::UserAttentionType>,
+    ) {
+        if self.attention == attention {
+            return;
+        }
+        self.attention = attention;
+        set_attention(self.display, window, self.visible, attention);
+        let mask = if attention.is_some() {
+            PropertyChangeMask
+        } else {
+            NoEventMask
+        };
+        unsafe { XSelectInput(self.display, XDefaultRootWindow(self.display), mask) };
+    }
+
+    // Focus changes aren't reported while the window is unmapped, and hide()
+    // resets the reported focus, so mapping the window reports it afresh.
+    fn report_focus(&mut self, window: x11::xlib::Window) {
+        if self.visible && self.focused != self.focus_reported {
+            self.focus_reported = self.focused;
+            self.sender
+                .write()
+                .unwrap()
+                .send(WindowId(window), crate::WindowEvent::Focused(self.focused));
+        }
+    }
+
+    // Pushes the size hints and resizes the window if it no longer fits in
+    // them, so the window matches what the window manager will enforce.
+    fn apply_size_limits(&mut self, window: x11::xlib::Window) {
+        let (cur_width, cur_height) = self.target_size();
+        let width = cur_width.clamp(self.min_width, self.max_width);
+        let height = cur_height.clamp(self.min_height, self.max_height);
+        if (width, height) != (cur_width, cur_height) {
+            self.resize(window, width, height);
+        } else {
+            self.size_hints(width, height).apply(self.display, window);
+        }
+    }
+
+    // Non-resizeable windows are pinned with min == max, which the window
+    // manager enforces against our own requests too.
+    fn size_hints(&self, width: u32, height: u32) -> SizeHints {
+        if self.resizeable {
+            SizeHints::new()
+                .with_min_size(self.min_width, self.min_height)
+                .with_max_size(self.max_width, self.max_height)
+        } else {
+            SizeHints::new()
+                .with_min_size(width, height)
+                .with_max_size(width, height)
+        }
+    }
+
+    // Moves the pin before resizing so the WM doesn't snap the window back.
+    // The cached size is only updated by the ConfigureNotify carrying
+    // whatever the WM actually allowed.
+    fn resize(&mut self, window: x11::xlib::Window, width: u32, height: u32) {
+        self.requested_size = Some((width, height));
+        self.size_hints(width, height).apply(self.display, window);
+        unsafe {
+            XResizeWindow(self.display, window, width, height);
+            XFlush(self.display);
+        }
+    }
+
+    fn set_size_state(&mut self, id: x11::xlib::Window, size_state: WindowSizeState) {
+        if self.size_state != size_state {
+            self.size_state = size_state;
+            self.sender.write().unwrap().send(
+                WindowId(id),
+                crate::WindowEvent::SizeStateChanged(size_state),
+            );
+        }
+    }
+}
+
 unsafe impl Send for WindowInfo {}
 unsafe impl Sync for WindowInfo {}
 
+// Only what helps when logging a window, not the display pointer or the
+// event plumbing.
+impl std::fmt::Debug for WindowInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = self
+            .handle
+            .upgrade()
+            .map_or_else(|| "none".to_owned(), |h| WindowId(*h).to_string());
+        f.debug_struct("WindowInfo")
+            .field("id", &format_args!("{}", id))
+            .field("title", &self.name)
+            .field("position", &(self.x, self.y))
+            .field("size", &(self.width, self.height))
+            .field("visible", &self.visible)
+            .field("focused", &self.focused)
+            .field("size_state", &self.size_state)
+            .field("fullscreen", &self.fullscreen)
+            .finish_non_exhaustive()
+    }
+}
+
 lazy_static::lazy_static! {
-    static ref WINDOW_INFO: Arc<RwLock<HashMap<x11::xlib::XID, WindowInfo>>> = Arc::new(RwLock::new(HashMap::new()));
+    static ref WINDOW_INFO: RwLock<HashMap<x11::xlib::XID, WindowInfo>> = RwLock::new(HashMap::new());
 }
 
 impl Default for WindowInfo {
@@ -550,18 +1488,43 @@ impl Default for WindowInfo {
             max_width: u32::MAX,
             max_height: u32::MAX,
             visible: false,
-            border_width: 10,
+            border_width: 0,
             depth: CopyFromParent as _,
             class: WindowClass::InputOutput,
             visual: None,
-            event_mask: EventMask::all(),
+            event_mask: EventMask::CLIENT_DEFAULT,
+            background_color: None,
             enabled_buttons: WindowButtons::all(),
             focused: false,
+            focus_reported: false,
             fullscreen: FullscreenType::NotFullscreen,
+            windowed_geometry: None,
+            frame_extents: [0; 4],
+            attention: None,
             size_state: WindowSizeState::Other,
-            resizeable: false,
+            resizeable: true,
             theme: Theme::Light,
-            modifiers: Modifiers::empty(),
+            theme_preference: None,
+            keys: KeyStateTracker::default(),
+            kind: WindowKind::Normal,
+            xsettings_owner: 0,
+            #[cfg(feature = "xrandr")]
+            rr_event_base: None,
+            redraw: RedrawScheduler::default(),
+            refresh_rate: RefreshRateTracker::default(),
+            monitors: MonitorTracker::default(),
+            handle: Weak::new(),
+            requested_size: None,
+            close_action: CloseAction::default(),
+            poisoned: false,
+            user_time: CurrentTime,
+            detectable_repeat: false,
+            accepts_drops: false,
+            keyboard_grabbed: false,
+            cursor_position: None,
+            dnd: None,
+            dnd_drop: None,
+            startup_id: None,
             sender: Arc::new(RwLock::new(EventSender::new())),
         }
     }
@@ -570,8 +1533,28 @@ impl Default for WindowInfo {
 impl Drop for Window {
     fn drop(&mut self) {
         if Arc::strong_count(&self.id) <= 1 {
-            WINDOW_INFO.clone().write().unwrap().remove(&*self.id);
-            //unsafe { XDestroyWindow(w.display, *self.id) };
+            // Only the entry this handle created; one found under a reused
+            // XID belongs to another window.
+            let w = {
+                let mut info = WINDOW_INFO.write().unwrap();
+                match info.get(&*self.id) {
+                    Some(w) if w.handle.ptr_eq(&Arc::downgrade(&self.id)) => info.remove(&*self.id),
+                    _ => None,
+                }
+            };
+            let Some(w) = w else {
+                return;
+            };
+            // The DestroyNotify would arrive after the info is gone, so
+            // report it here.
+            w.sender
+                .write()
+                .unwrap()
+                .send(WindowId(*self.id), crate::WindowEvent::Destroyed);
+            unsafe {
+                XDestroyWindow(w.display, *self.id);
+                XCloseDisplay(w.display);
+            }
         }
     }
 }
@@ -580,20 +1563,182 @@ impl Window {
     pub fn try_new(
         parent: Option<x11::xlib::Window>,
         attributes: Option<WindowAttributes>,
-    ) -> Result<Self, ()> {
-        let mut w = Self::default();
-        let mut info = WindowInfo::default();
-        let (id, display, screen, visual_id) = w.create(parent, attributes, &info)?;
+    ) -> Result<Self, OsError> {
+        let mut builder = crate::WindowBuilder::new();
+        builder.platform.parent = parent;
+        if let Some(attributes) = attributes {
+            builder.platform.attributes = attributes;
+        }
+        Self::from_builder(builder)
+    }
+
+    pub(crate) fn from_builder(builder: crate::WindowBuilder) -> Result<Self, OsError> {
+        let mut p = builder.platform;
+        let mut info = WindowInfo {
+            kind: builder.kind,
+            background_color: builder.background_color,
+            resizeable: builder.resizeable,
+            ..Default::default()
+        };
+        if let Some(title) = builder.title {
+            info.name = title;
+        }
+        if let Some((width, height)) = builder.min_size {
+            (info.min_width, info.max_width) = crate::limit_min(width, info.max_width);
+            (info.min_height, info.max_height) = crate::limit_min(height, info.max_height);
+        }
+        if let Some((width, height)) = builder.max_size {
+            (info.min_width, info.max_width) = crate::limit_max(info.min_width, width);
+            (info.min_height, info.max_height) = crate::limit_max(info.min_height, height);
+        }
+        if let Some(size) = builder.inner_size {
+            info.width = size.width;
+            info.height = size.height;
+        }
+        info.width = info.width.clamp(info.min_width, info.max_width);
+        info.height = info.height.clamp(info.min_height, info.max_height);
+        if let Some(position) = builder.position {
+            info.x = position.x;
+            info.y = position.y;
+        }
+        // Popups and tooltips are placed by the application and must not be
+        // decorated or listed by the window manager.
+        if matches!(builder.kind, WindowKind::Popup | WindowKind::Tooltip) {
+            p.modify_attributes(|a| a.with_override_redirect(true));
+        }
+        if let Some(event_mask) = p.event_mask {
+            info.event_mask = event_mask;
+        }
+        if let Some(border_width) = p.border_width {
+            info.border_width = border_width;
+        }
+        if let Some(depth) = p.depth {
+            info.depth = depth;
+        }
+        if let Some(visual_id) = p.visual_id {
+            info.visual_id = visual_id;
+        }
+        info.startup_id = p.startup_id.unwrap_or_else(take_startup_id);
+        let attributes = if p.attributes.mask != 0 {
+            Some(p.attributes)
+        } else {
+            None
+        };
+        let mut window = Self::with_info(p.parent, attributes, info)?;
+        // The window manager reads the hints when the window is mapped, so
+        // they go in while it's still unmapped.
+        if let Some(w) = WINDOW_INFO.read().unwrap().get(&*window.id) {
+            let mut hints = w.size_hints(w.width, w.height);
+            if builder.position.is_some() {
+                hints = hints.with_position(w.x, w.y);
+            }
+            hints.apply(w.display, *window.id);
+        }
+        if builder.visible {
+            crate::WindowT::show(&mut window);
+        }
+        Ok(window)
+    }
+
+    // Destroys the window without waiting for the handles to be dropped; the
+    // DestroyNotify reports Destroyed.
+    pub(crate) fn destroy_window(&self) {
+        unsafe {
+            XDestroyWindow(self.display, *self.id);
+            XFlush(self.display);
+        }
+    }
+
+    // Handles can be used from any thread; every call goes through the
+    // shared display connection, which Xlib serializes with its own lock.
+    pub fn from_id(id: WindowId) -> Option<Self> {
+        let info = WINDOW_INFO.read().unwrap();
+        let w = info.get(&(id.0 as x11::xlib::Window))?;
+        Some(Self {
+            id: w.handle.upgrade()?,
+            display: w.display,
+            visual_id: w.visual_id,
+        })
+    }
+
+    fn with_info(
+        parent: Option<x11::xlib::Window>,
+        attributes: Option<WindowAttributes>,
+        mut info: WindowInfo,
+    ) -> Result<Self, OsError> {
+        let mut w = Self::default();
+        let (id, display, screen, visual_id) = w.create(parent, attributes, &info)?;
+        set_window_type(display, id, info.kind);
         w.id = Arc::new(id);
+        w.display = display;
+        w.visual_id = visual_id;
+        info.handle = Arc::downgrade(&w.id);
         info.display = display;
         info.screen = screen;
         info.visual_id = visual_id;
         info.parent = parent.unwrap_or(unsafe { XRootWindow(display, info.screen) });
-        WINDOW_INFO.clone().write().unwrap().insert(id, info);
-        let wm_delete_window_s = CString::new("WM_DELETE_WINDOW").unwrap();
-        let wm_delete_window =
-            unsafe { XInternAtom(display, wm_delete_window_s.as_ptr(), x11::xlib::True) };
+        info.xsettings_owner = xsettings_owner(display, screen);
+        info.theme = theme_from_xsettings(display, info.xsettings_owner).unwrap_or_default();
+        let mut supported = x11::xlib::False;
+        info.detectable_repeat = unsafe {
+            XkbSetDetectableAutoRepeat(display, x11::xlib::True, addr_of_mut!(supported))
+        } != 0
+            && supported != 0;
+        if let Some(startup_id) = info.startup_id.as_ref() {
+            let utf8_string = intern_atom(display, "UTF8_STRING");
+            unsafe {
+                XChangeProperty(
+                    display,
+                    id,
+                    intern_atom(display, "_NET_STARTUP_ID"),
+                    utf8_string,
+                    8,
+                    PropModeReplace,
+                    startup_id.as_ptr(),
+                    startup_id.len() as _,
+                )
+            };
+        }
+        if info.xsettings_owner != 0 {
+            unsafe { XSelectInput(display, info.xsettings_owner, PropertyChangeMask) };
+        }
+        #[cfg(feature = "xrandr")]
+        let (mut rr_event_base, mut rr_error_base) = (0, 0);
+        #[cfg(feature = "xrandr")]
+        if unsafe {
+            XRRQueryExtension(
+                display,
+                addr_of_mut!(rr_event_base),
+                addr_of_mut!(rr_error_base),
+            )
+        } == x11::xlib::True
+        {
+            info.rr_event_base = Some(rr_event_base);
+            unsafe {
+                XRRSelectInput(
+                    display,
+                    XDefaultRootWindow(display),
+                    RRScreenChangeNotifyMask | RRCrtcChangeNotifyMask | RROutputChangeNotifyMask,
+                )
+            };
+            info.monitors = MonitorTracker::new(monitors_on(display));
+        }
+        #[cfg(not(feature = "xrandr"))]
+        {
+            info.monitors = MonitorTracker::new(monitors_on(display));
+        }
+        WINDOW_INFO.write().unwrap().insert(id, info);
+        let wm_protocols = intern_atom(display, "WM_PROTOCOLS");
+        let wm_delete_window = intern_atom(display, "WM_DELETE_WINDOW");
+        let net_wm_ping = intern_atom(display, "_NET_WM_PING");
+        WM_PROTOCOLS.store(wm_protocols, std::sync::atomic::Ordering::Relaxed);
         WM_DELETE_WINDOW.store(wm_delete_window, std::sync::atomic::Ordering::Relaxed);
+        NET_WM_PING.store(net_wm_ping, std::sync::atomic::Ordering::Relaxed);
+        // _NET_WM_SYNC_REQUEST is deliberately not advertised: the counter can
+        // only be updated once the application has redrawn, which nwin can't
+        // observe, and WMs only wait on clients that list the protocol.
+        let mut protocols = [wm_delete_window, net_wm_ping];
+        unsafe { XSetWMProtocols(display, id, protocols.as_mut_ptr(), protocols.len() as _) };
         Ok(w)
     }
 
@@ -609,7 +1754,7 @@ impl Window {
             i32,
             x11::xlib::VisualID,
         ),
-        (),
+        OsError,
     > {
         create_window(
             &w.name,
@@ -621,9 +1766,11 @@ impl Window {
             w.visible,
             w.border_width,
             Some(w.depth),
+            (w.visual_id != 0).then_some(w.visual_id),
             w.class,
             attributes,
             w.event_mask,
+            w.background_color,
         )
     }
 }
@@ -631,7 +1778,6 @@ impl Window {
 impl crate::WindowT for Window {
     fn enabled_buttons(&self) -> crate::WindowButtons {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -639,50 +1785,127 @@ impl crate::WindowT for Window {
             .enabled_buttons
     }
 
+    // _NET_WM_ALLOWED_ACTIONS belongs to the WM, which derives it from
+    // these hints, so only the Motif hints are written.
     fn set_enabled_buttons(&mut self, buttons: WindowButtons) {
-        /*
-        let allowed_actions_s = CString::new("_NET_WM_ALLOWED_ACTIONS").unwrap();
-        let maximize_horz_s = CString::new("_NET_WM_ACTION_MAXIMIZE_HORZ").unwrap();
-        let maximize_vert_s = CString::new("_NET_WM_ACTION_MAXIMIZE_VERT").unwrap();
-
-        let allowed_actions = unsafe { XInternAtom(w.display, allowed_actions_s.as_ptr(), x11::xlib::False) };
-        let maximize_horz = unsafe { XInternAtom(w.display, maximize_horz_s.as_ptr(), x11::xlib::False) };
-        let maximize_vert = unsafe { XInternAtom(w.display, maximize_vert_s.as_ptr(), x11::xlib::False) };
-
-        unsafe { XChangeProperty(w.display, *self.id, allowed_actions, XA_ATOM, 32, PropModeAppend, addr_of_mut!(maximize_horz) as _, 1) }
-        */
-        if buttons != WindowButtons::all() {
-            todo!()
+        if let Some(w) = WINDOW_INFO.write().unwrap().get_mut(&*self.id) {
+            w.enabled_buttons = buttons;
+            set_motif_buttons(w.display, *self.id, buttons);
         }
     }
 
+    // Goes through the window manager when it supports _NET_ACTIVE_WINDOW,
+    // so focus-stealing prevention sees the timestamp of the last user input.
+    // Override-redirect windows and WMs without EWMH get the focus directly.
     fn focus(&mut self) {
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
             .and_modify(|w| {
+                if w.kind == WindowKind::Tooltip {
+                    return;
+                }
                 w.focused = true;
-                unsafe { XSetInputFocus(w.display, *self.id, RevertToParent, CurrentTime) };
-                unsafe { XRaiseWindow(w.display, *self.id) };
+                if w.kind == WindowKind::Popup || !request_active(w, *self.id) {
+                    unsafe { XSetInputFocus(w.display, *self.id, RevertToParent, CurrentTime) };
+                    unsafe { XRaiseWindow(w.display, *self.id) };
+                }
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
-    fn focused(&self) -> bool {
-        WINDOW_INFO
-            .clone()
+    // Also flags the window as demanding attention, which the WM clears when
+    // it does activate the window and keeps as a flash when it doesn't.
+    fn request_activation(&mut self) {
+        if let Some(w) = WINDOW_INFO.read().unwrap().get(&*self.id) {
+            if w.kind != WindowKind::Normal || !request_active(w, *self.id) {
+                return;
+            }
+            send_root_message(
+                w.display,
+                *self.id,
+                intern_atom(w.display, "_NET_WM_STATE"),
+                [
+                    NET_WM_STATE_ADD,
+                    intern_atom(w.display, "_NET_WM_STATE_DEMANDS_ATTENTION") as _,
+                    0,
+                    1,
+                    0,
+                ],
+            );
+        }
+    }
+
+    fn raise(&mut self) {
+        restack(self.display, *self.id, true);
+    }
+
+    fn lower(&mut self) {
+        restack(self.display, *self.id, false);
+    }
+
+    fn is_topmost(&self) -> bool {
+        get_property32(
+            self.display,
+            *self.id,
+            intern_atom(self.display, "_NET_WM_STATE"),
+        )
+        .contains(&(intern_atom(self.display, "_NET_WM_STATE_ABOVE") as c_long))
+    }
+
+    fn start_drag(&mut self, payload: DragPayload) -> Result<DragOutcome, OsError> {
+        let time = WINDOW_INFO
             .read()
             .unwrap()
             .get(&*self.id)
+            .map_or(CurrentTime, |w| w.user_time);
+        let outcome = run_drag(self.display, *self.id, &payload, time);
+        // The drag takes the keyboard for Escape and lets go of it at the
+        // end, dropping any grab of our own.
+        let grabbed = WINDOW_INFO
+            .read()
             .unwrap()
-            .focused
+            .get(&*self.id)
+            .is_some_and(|w| w.keyboard_grabbed);
+        if grabbed {
+            let _ = grab_keyboard(self.display, *self.id);
+        }
+        outcome
+    }
+
+    fn set_accepts_drops(&mut self, accept: bool) {
+        let xdnd_aware = intern_atom(self.display, "XdndAware");
+        if accept {
+            let mut version = XDND_VERSION;
+            unsafe {
+                XChangeProperty(
+                    self.display,
+                    *self.id,
+                    xdnd_aware,
+                    XA_ATOM,
+                    32,
+                    PropModeReplace,
+                    addr_of_mut!(version) as _,
+                    1,
+                )
+            };
+        } else {
+            unsafe { XDeleteProperty(self.display, *self.id, xdnd_aware) };
+        }
+        unsafe { XFlush(self.display) };
+        if let Some(w) = WINDOW_INFO.write().unwrap().get_mut(&*self.id) {
+            w.accepts_drops = accept;
+            w.dnd = None;
+        }
+    }
+
+    fn focused(&self) -> bool {
+        WINDOW_INFO.read().unwrap().get(&*self.id).unwrap().focused
     }
 
     fn fullscreen_type(&self) -> FullscreenType {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -691,49 +1914,54 @@ impl crate::WindowT for Window {
     }
 
     fn width(&self) -> u32 {
-        WINDOW_INFO
-            .clone()
-            .read()
-            .unwrap()
-            .get(&*self.id)
-            .unwrap()
-            .width
+        WINDOW_INFO.read().unwrap().get(&*self.id).unwrap().width
     }
 
     fn set_width(&mut self, width: u32) {
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
             .and_modify(|w| {
-                w.width = width;
-                unsafe { XResizeWindow(w.display, *self.id, w.width, w.height) };
+                let (_, height) = w.target_size();
+                w.resize(*self.id, width.clamp(w.min_width, w.max_width), height);
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
     fn height(&self) -> u32 {
+        WINDOW_INFO.read().unwrap().get(&*self.id).unwrap().height
+    }
+
+    fn set_height(&mut self, height: u32) {
         WINDOW_INFO
-            .clone()
-            .read()
-            .unwrap()
-            .get(&*self.id)
+            .write()
             .unwrap()
-            .height
+            .entry(*self.id)
+            .and_modify(|w| {
+                let (width, _) = w.target_size();
+                w.resize(*self.id, width, height.clamp(w.min_height, w.max_height));
+            })
+            .or_default();
     }
 
-    fn set_height(&mut self, height: u32) {
+    // The WM answers asynchronously, so the applied size only ever arrives
+    // as a Resized event.
+    fn request_inner_size(&mut self, size: PhysicalSize<u32>) -> Option<PhysicalSize<u32>> {
+        let PhysicalSize { width, height } = size;
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
             .and_modify(|w| {
-                w.height = height;
-                unsafe { XResizeWindow(w.display, *self.id, w.width, w.height) };
+                w.resize(
+                    *self.id,
+                    width.clamp(w.min_width, w.max_width),
+                    height.clamp(w.min_height, w.max_height),
+                );
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
+        None
     }
 
     fn id(&self) -> WindowId {
@@ -742,7 +1970,6 @@ impl crate::WindowT for Window {
 
     fn min_width(&self) -> u32 {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -752,25 +1979,18 @@ impl crate::WindowT for Window {
 
     fn set_min_width(&mut self, width: u32) {
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
             .and_modify(|w| {
-                w.min_width = width;
-                let size_hints = &mut unsafe { *XAllocSizeHints() };
-                size_hints.min_width = w.min_width as _;
-                size_hints.min_height = w.min_height as _;
-                size_hints.flags = PMinSize;
-                unsafe { XSetWMNormalHints(w.display, *self.id, addr_of_mut!(*size_hints)) };
-                unsafe { XFree(addr_of_mut!(*size_hints) as _) };
+                (w.min_width, w.max_width) = crate::limit_min(width, w.max_width);
+                w.apply_size_limits(*self.id);
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
     fn min_height(&self) -> u32 {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -780,25 +2000,18 @@ impl crate::WindowT for Window {
 
     fn set_min_height(&mut self, height: u32) {
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
             .and_modify(|w| {
-                w.min_height = height;
-                let size_hints = &mut unsafe { *XAllocSizeHints() };
-                size_hints.min_width = w.min_width as _;
-                size_hints.min_height = w.min_height as _;
-                size_hints.flags = PMinSize;
-                unsafe { XSetWMNormalHints(w.display, *self.id, addr_of_mut!(*size_hints)) };
-                unsafe { XFree(addr_of_mut!(*size_hints) as _) };
+                (w.min_height, w.max_height) = crate::limit_min(height, w.max_height);
+                w.apply_size_limits(*self.id);
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
     fn max_width(&self) -> u32 {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -808,25 +2021,18 @@ impl crate::WindowT for Window {
 
     fn set_max_width(&mut self, width: u32) {
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
             .and_modify(|w| {
-                w.max_width = width;
-                let size_hints = &mut unsafe { *XAllocSizeHints() };
-                size_hints.min_width = w.min_width as _;
-                size_hints.min_height = w.min_height as _;
-                size_hints.flags = PMinSize;
-                unsafe { XSetWMNormalHints(w.display, *self.id, addr_of_mut!(*size_hints)) };
-                unsafe { XFree(addr_of_mut!(*size_hints) as _) };
+                (w.min_width, w.max_width) = crate::limit_max(w.min_width, width);
+                w.apply_size_limits(*self.id);
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
     fn max_height(&self) -> u32 {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -836,25 +2042,18 @@ impl crate::WindowT for Window {
 
     fn set_max_height(&mut self, height: u32) {
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
             .and_modify(|w| {
-                w.max_height = height;
-                let size_hints = &mut unsafe { *XAllocSizeHints() };
-                size_hints.min_width = w.min_width as _;
-                size_hints.min_height = w.min_height as _;
-                size_hints.flags = PMinSize;
-                unsafe { XSetWMNormalHints(w.display, *self.id, addr_of_mut!(*size_hints)) };
-                unsafe { XFree(addr_of_mut!(*size_hints) as _) };
+                (w.min_height, w.max_height) = crate::limit_max(w.min_height, height);
+                w.apply_size_limits(*self.id);
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
     fn maximized(&self) -> bool {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -871,7 +2070,6 @@ impl crate::WindowT for Window {
         let max_height_s = CString::new("_NET_WM_STATE_MAXIMIZED_VERT").unwrap();
 
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
@@ -909,14 +2107,13 @@ impl crate::WindowT for Window {
                         addr_of_mut!(ev) as _,
                     )
                 };
-                w.size_state = WindowSizeState::Maximized;
+                w.set_size_state(*self.id, WindowSizeState::Maximized);
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
     fn minimized(&self) -> bool {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -927,20 +2124,18 @@ impl crate::WindowT for Window {
 
     fn minimize(&mut self) {
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
             .and_modify(|w| {
                 unsafe { XIconifyWindow(w.display, *self.id, w.screen) };
-                w.size_state = WindowSizeState::Minimized;
+                w.set_size_state(*self.id, WindowSizeState::Minimized);
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
     fn normalized(&self) -> bool {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -959,19 +2154,17 @@ impl crate::WindowT for Window {
         }
 
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
             .and_modify(|w| {
-                w.size_state = WindowSizeState::Other;
+                w.set_size_state(*self.id, WindowSizeState::Other);
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
     fn resizeable(&self) -> bool {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -981,54 +2174,163 @@ impl crate::WindowT for Window {
 
     fn set_resizeable(&mut self, resizeable: bool) {
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
             .and_modify(|w| {
                 w.resizeable = resizeable;
-                let size_hints = &mut unsafe { *XAllocSizeHints() };
-                if resizeable == false {
-                    size_hints.min_width = w.width as _;
-                    size_hints.max_width = w.width as _;
-                    size_hints.min_height = w.height as _;
-                    size_hints.max_height = w.height as _;
-                } else {
-                    size_hints.min_width = w.min_width as _;
-                    size_hints.max_width = w.max_width as _;
-                    size_hints.min_height = w.min_height as _;
-                    size_hints.max_height = w.min_height as _;
-                }
-                size_hints.flags = PMinSize | PMaxSize;
-                unsafe { XSetWMNormalHints(w.display, *self.id, addr_of_mut!(*size_hints)) };
+                let (width, height) = w.target_size();
+                w.size_hints(width, height).apply(w.display, *self.id);
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
     fn theme(&self) -> Theme {
+        WINDOW_INFO.read().unwrap().get(&*self.id).unwrap().theme
+    }
+
+    fn close_action(&self) -> CloseAction {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
             .unwrap()
-            .theme
+            .close_action
     }
 
-    fn set_theme(&mut self, theme: Theme) {
+    fn set_background_color(&mut self, color: Option<(u8, u8, u8)>) {
+        let mut info = WINDOW_INFO.write().unwrap();
+        let Some(w) = info.get_mut(&*self.id) else {
+            return;
+        };
+        w.background_color = color;
+        let visual = screen_visuals(self.display, w.screen)
+            .into_iter()
+            .find(|(v, _)| v.id == self.visual_id);
+        let mut attributes: XWindowAttributes = unsafe { MaybeUninit::zeroed().assume_init() };
+        unsafe { XGetWindowAttributes(self.display, *self.id, addr_of_mut!(attributes)) };
+        let pixel = color
+            .zip(visual)
+            .and_then(|(color, (v, _))| rgb_pixel(self.display, &v, attributes.colormap, color));
+        unsafe {
+            match pixel {
+                Some(pixel) => XSetWindowBackground(self.display, *self.id, pixel),
+                None => XSetWindowBackgroundPixmap(self.display, *self.id, 0),
+            };
+            // Exposes the whole window, so the application redraws on top.
+            XClearArea(self.display, *self.id, 0, 0, 0, 0, x11::xlib::True);
+            XFlush(self.display);
+        }
+    }
+
+    // Shadows are drawn by the compositor, if there is one.
+    fn set_shadow(&mut self, _shadow: bool) {}
+
+    fn set_close_action(&mut self, action: CloseAction) {
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .get_mut(&*self.id)
             .unwrap()
-            .theme = theme;
-        todo!()
+            .close_action = action;
+    }
+
+    fn theme_preference(&self) -> Option<Theme> {
+        WINDOW_INFO
+            .read()
+            .unwrap()
+            .get(&*self.id)
+            .unwrap()
+            .theme_preference
+    }
+
+    // Sets _GTK_THEME_VARIANT, which decorations that follow GTK pick up.
+    fn set_theme_preference(&mut self, theme: Option<Theme>) {
+        let mut info = WINDOW_INFO.write().unwrap();
+        let w = info.get_mut(&*self.id).unwrap();
+        w.theme_preference = theme;
+        w.theme = theme
+            .or_else(|| theme_from_xsettings(w.display, w.xsettings_owner))
+            .unwrap_or_default();
+        let variant = intern_atom(self.display, "_GTK_THEME_VARIANT");
+        unsafe {
+            match theme {
+                Some(theme) => {
+                    let value: &[u8] = match theme {
+                        Theme::Light => b"light",
+                        Theme::Dark => b"dark",
+                    };
+                    XChangeProperty(
+                        self.display,
+                        *self.id,
+                        variant,
+                        intern_atom(self.display, "UTF8_STRING"),
+                        8,
+                        PropModeReplace,
+                        value.as_ptr(),
+                        value.len() as _,
+                    );
+                }
+                None => {
+                    XDeleteProperty(self.display, *self.id, variant);
+                }
+            }
+            XFlush(self.display);
+        }
+    }
+
+    fn scale_factor(&self) -> f64 {
+        crate::scale_factor_override()
+            .or_else(|| xft_scale_factor(self.display))
+            .unwrap_or(1.0)
+    }
+
+    fn current_refresh_rate_millihertz(&self) -> Option<u32> {
+        let (width, height) = {
+            let info = WINDOW_INFO.read().unwrap();
+            let w = info.get(&*self.id).unwrap();
+            (w.width, w.height)
+        };
+        refresh_rate(self.display, *self.id, width, height)
+    }
+
+    fn capture(&self) -> Result<RgbaImage, OsError> {
+        let display = self.display;
+        let (width, height) = {
+            let info = WINDOW_INFO.read().unwrap();
+            let w = info.get(&*self.id).ok_or(OsError)?;
+            (w.width, w.height)
+        };
+        let image = unsafe { XGetImage(display, *self.id, 0, 0, width, height, !0, ZPixmap) };
+        if image.is_null() {
+            return Err(OsError);
+        }
+        // XGetPixel deals with depth and byte order; the masks say where each
+        // channel lives in the pixel it returns.
+        let (red_mask, green_mask, blue_mask) =
+            unsafe { ((*image).red_mask, (*image).green_mask, (*image).blue_mask) };
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let px = unsafe { XGetPixel(image, x as _, y as _) };
+                data.extend([
+                    channel(px, red_mask),
+                    channel(px, green_mask),
+                    channel(px, blue_mask),
+                    0xFF,
+                ]);
+            }
+        }
+        unsafe { XDestroyImage(image) };
+        Ok(RgbaImage {
+            width,
+            height,
+            data,
+        })
     }
 
     fn title(&self) -> String {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -1037,69 +2339,192 @@ impl crate::WindowT for Window {
             .clone()
     }
 
-    fn visible(&self) -> bool {
+    fn set_title(&mut self, title: &str) {
         WINDOW_INFO
-            .clone()
+            .write()
+            .unwrap()
+            .entry(*self.id)
+            .and_modify(|w| w.name = title.to_owned())
+            .or_default();
+        store_title(self.display, *self.id, title);
+        unsafe { XFlush(self.display) };
+    }
+
+    fn position(&self) -> PhysicalPosition<i32> {
+        let info = WINDOW_INFO.read().unwrap();
+        let w = info.get(&*self.id).unwrap();
+        PhysicalPosition::new(w.x, w.y)
+    }
+
+    fn set_position(&mut self, position: PhysicalPosition<i32>) {
+        unsafe {
+            XMoveWindow(self.display, *self.id, position.x, position.y);
+            XFlush(self.display);
+        }
+    }
+
+    fn outer_size(&self) -> PhysicalSize<u32> {
+        let info = WINDOW_INFO.read().unwrap();
+        let w = info.get(&*self.id).unwrap();
+        let [left, right, top, bottom] = w.frame_extents;
+        PhysicalSize::new(
+            w.width + 2 * w.border_width + left + right,
+            w.height + 2 * w.border_width + top + bottom,
+        )
+    }
+
+    fn outer_position(&self) -> PhysicalPosition<i32> {
+        let extents = WINDOW_INFO
             .read()
             .unwrap()
             .get(&*self.id)
             .unwrap()
-            .visible
+            .frame_extents;
+        let (x, y) = frame_origin(self.display, *self.id, extents);
+        PhysicalPosition::new(x, y)
+    }
+
+    fn visible(&self) -> bool {
+        WINDOW_INFO.read().unwrap().get(&*self.id).unwrap().visible
     }
 
     fn hide(&mut self) {
-        unsafe {
-            XUnmapWindow(
-                WINDOW_INFO
-                    .clone()
-                    .read()
-                    .unwrap()
-                    .get(&*self.id)
-                    .unwrap()
-                    .display,
-                *self.id,
-            )
-        };
+        WINDOW_INFO
+            .write()
+            .unwrap()
+            .entry(*self.id)
+            .and_modify(|w| {
+                w.visible = false;
+                w.focus_reported = false;
+                if w.kind == WindowKind::Popup {
+                    unsafe { XUngrabPointer(w.display, CurrentTime) };
+                }
+                unsafe { XUnmapWindow(w.display, *self.id) };
+            })
+            .or_default();
     }
 
     fn show(&mut self) {
-        unsafe {
-            XMapWindow(
-                WINDOW_INFO
-                    .clone()
-                    .read()
-                    .unwrap()
-                    .get(&*self.id)
-                    .unwrap()
-                    .display,
-                *self.id,
-            )
-        };
+        WINDOW_INFO
+            .write()
+            .unwrap()
+            .entry(*self.id)
+            .and_modify(|w| {
+                w.visible = true;
+                // The WM forgets the state of unmapped windows, so a state
+                // set while hidden is handed over before mapping.
+                set_initial_state(
+                    w.display,
+                    *self.id,
+                    w.size_state == WindowSizeState::Minimized,
+                );
+                let maximized = w.size_state == WindowSizeState::Maximized;
+                for state in [
+                    "_NET_WM_STATE_MAXIMIZED_HORZ",
+                    "_NET_WM_STATE_MAXIMIZED_VERT",
+                ] {
+                    let state = intern_atom(w.display, state);
+                    set_net_wm_state(w.display, *self.id, false, state, maximized);
+                }
+                let fullscreen = intern_atom(w.display, "_NET_WM_STATE_FULLSCREEN");
+                let enable = w.fullscreen != FullscreenType::NotFullscreen;
+                set_net_wm_state(w.display, *self.id, false, fullscreen, enable);
+                unsafe { XMapWindow(w.display, *self.id) };
+            })
+            .or_default();
     }
 
+    // Reported from the next pump once the window is mapped and not
+    // minimized, so it also works before the window is first shown.
     fn request_redraw(&mut self) {
-        todo!()
+        if let Some(w) = WINDOW_INFO.write().unwrap().get_mut(&*self.id) {
+            w.redraw.request();
+        }
+    }
+
+    // Paced by a timer at the refresh rate of the window's monitor, since
+    // there's no vblank to wait on without XPresent or GLX_OML_sync_control.
+    fn request_redraw_continuous(&mut self, continuous: bool) {
+        WINDOW_INFO
+            .write()
+            .unwrap()
+            .entry(*self.id)
+            .and_modify(|w| {
+                let rate = w
+                    .refresh_rate
+                    .last()
+                    .or_else(|| refresh_rate(w.display, *self.id, w.width, w.height));
+                w.redraw
+                    .set_interval(continuous.then(|| crate::frame_interval(rate)));
+            })
+            .or_default();
     }
 
-    fn request_user_attention(&mut self, _attention: crate::UserAttentionType) {
-        todo!()
+    // Cleared again once the window is focused or activated.
+    fn request_user_attention(&mut self, attention: crate::UserAttentionType) {
+        if let Some(w) = WINDOW_INFO.write().unwrap().get_mut(&*self.id) {
+            if !w.focused {
+                w.update_attention(*self.id, Some(attention));
+            }
+        }
     }
 
-    fn set_fullscreen(&mut self, _fullscreen: FullscreenType) {
-        todo!()
+    // The WM applies the change asynchronously. fullscreen_type reports the
+    // request right away and follows the _NET_WM_STATE the WM sets after.
+    fn set_fullscreen(&mut self, fullscreen: FullscreenType) {
+        let mut info = WINDOW_INFO.write().unwrap();
+        let Some(w) = info.get_mut(&*self.id) else {
+            return;
+        };
+        if w.fullscreen == fullscreen {
+            return;
+        }
+        // Mode switching isn't supported here, so exclusive fullscreen is
+        // shown as borderless at the desktop's resolution. It's still
+        // reported as Exclusive, so toggling it off works as asked.
+        if w.fullscreen == FullscreenType::NotFullscreen {
+            let (x, y) = frame_origin(w.display, *self.id, w.frame_extents);
+            w.windowed_geometry = Some((x, y, w.width, w.height));
+        }
+        w.fullscreen = fullscreen;
+        let enable = fullscreen != FullscreenType::NotFullscreen;
+        set_net_wm_state(
+            w.display,
+            *self.id,
+            w.visible,
+            intern_atom(w.display, "_NET_WM_STATE_FULLSCREEN"),
+            enable,
+        );
+        // WMs usually restore the geometry themselves, but not all do.
+        if !enable {
+            if let Some((x, y, width, height)) = w.windowed_geometry.take() {
+                unsafe { XMoveResizeWindow(w.display, *self.id, x, y, width, height) };
+            }
+        }
+        unsafe { XFlush(w.display) };
     }
 }
 
-trait WindowExtXlib {
+pub trait WindowExtX11 {
     fn event_mask(&self) -> EventMask;
     fn set_event_mask(&mut self, event_mask: EventMask);
     fn set_title(&mut self, title: &str);
+    fn border_width(&self) -> u32;
+    fn set_border_width(&mut self, border_width: u32);
+    // Sends all keyboard input to the window whatever has focus, e.g. for
+    // kiosks and screen lockers. The server ends the grab when the window is
+    // hidden or destroyed.
+    fn grab_keyboard(&mut self, grab: bool) -> Result<(), GrabError>;
+    // Whether a request_user_attention is still waiting for the window to
+    // be activated.
+    fn attention_requested(&self) -> bool;
+    #[cfg(feature = "accesskit")]
+    fn set_accesskit_adapter(&mut self, adapter: Box<dyn AccessKitAdapter>);
 }
 
-impl WindowExtXlib for Window {
+impl WindowExtX11 for Window {
     fn event_mask(&self) -> EventMask {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -1109,7 +2534,6 @@ impl WindowExtXlib for Window {
 
     fn set_event_mask(&mut self, event_mask: EventMask) {
         WINDOW_INFO
-            .clone()
             .write()
             .unwrap()
             .entry(*self.id)
@@ -1117,31 +2541,92 @@ impl WindowExtXlib for Window {
                 w.event_mask = event_mask;
                 unsafe { XSelectInput(w.display, *self.id, event_mask.bits()) };
             })
-            .or_insert(WindowInfo::default());
+            .or_default();
     }
 
     fn set_title(&mut self, title: &str) {
-        let title_c = CString::new(title).unwrap();
+        crate::WindowT::set_title(self, title)
+    }
+
+    fn border_width(&self) -> u32 {
+        WINDOW_INFO
+            .read()
+            .unwrap()
+            .get(&*self.id)
+            .unwrap()
+            .border_width
+    }
+
+    fn set_border_width(&mut self, border_width: u32) {
+        WINDOW_INFO
+            .write()
+            .unwrap()
+            .entry(*self.id)
+            .and_modify(|w| {
+                w.border_width = border_width;
+                unsafe { XSetWindowBorderWidth(w.display, *self.id, border_width) };
+            })
+            .or_default();
+    }
+
+    fn grab_keyboard(&mut self, grab: bool) -> Result<(), GrabError> {
+        let mut info = WINDOW_INFO.write().unwrap();
+        let Some(w) = info.get_mut(&*self.id) else {
+            return Err(GrabError::NotViewable);
+        };
+        if grab {
+            grab_keyboard(w.display, *self.id)?;
+        } else if w.keyboard_grabbed {
+            unsafe { XUngrabKeyboard(w.display, CurrentTime) };
+        }
+        unsafe { XFlush(w.display) };
+        w.keyboard_grabbed = grab;
+        Ok(())
+    }
+
+    fn attention_requested(&self) -> bool {
+        WINDOW_INFO
+            .read()
+            .unwrap()
+            .get(&*self.id)
+            .is_some_and(|w| w.attention.is_some())
+    }
+
+    // AT-SPI matches accessible applications to their windows by PID.
+    #[cfg(feature = "accesskit")]
+    fn set_accesskit_adapter(&mut self, adapter: Box<dyn AccessKitAdapter>) {
+        let display = self.display;
+        let mut pid = std::process::id() as std::ffi::c_ulong;
         unsafe {
-            XStoreName(
-                WINDOW_INFO
-                    .clone()
-                    .read()
-                    .unwrap()
-                    .get(&*self.id)
-                    .unwrap()
-                    .display,
+            XChangeProperty(
+                display,
                 *self.id,
-                title_c.as_ptr(),
+                intern_atom(display, "_NET_WM_PID"),
+                x11::xlib::XA_CARDINAL,
+                32,
+                PropModeReplace,
+                addr_of_mut!(pid) as _,
+                1,
             )
         };
+        ACCESSKIT_ADAPTERS.with(|adapters| adapters.borrow_mut().insert(*self.id, adapter));
     }
 }
 
+#[cfg(feature = "accesskit")]
+pub trait AccessKitAdapter {
+    fn window_focused(&mut self, focused: bool);
+}
+
+#[cfg(feature = "accesskit")]
+thread_local! {
+    static ACCESSKIT_ADAPTERS: RefCell<HashMap<x11::xlib::Window, Box<dyn AccessKitAdapter>>> =
+        RefCell::new(HashMap::new());
+}
+
 impl WindowTExt for Window {
     fn sender(&self) -> Arc<RwLock<EventSender>> {
         WINDOW_INFO
-            .clone()
             .read()
             .unwrap()
             .get(&*self.id)
@@ -1155,193 +2640,2973 @@ unsafe impl HasRawWindowHandle for Window {
     fn raw_window_handle(&self) -> RawWindowHandle {
         let mut handle = XlibWindowHandle::empty();
         handle.window = *self.id;
-        handle.visual_id = WINDOW_INFO
-            .clone()
-            .read()
-            .unwrap()
-            .get(&*self.id)
-            .unwrap()
-            .visual_id;
+        handle.visual_id = self.visual_id;
         RawWindowHandle::Xlib(handle)
     }
 }
 
+static WM_PROTOCOLS: AtomicU64 = AtomicU64::new(0);
 static WM_DELETE_WINDOW: AtomicU64 = AtomicU64::new(0);
+static NET_WM_PING: AtomicU64 = AtomicU64::new(0);
+static NWIN_ACTIVATE: AtomicU64 = AtomicU64::new(0);
 
-impl WindowIdExt for WindowId {
-    fn next_event(&self) {
-        let mut ev: XEvent = unsafe { MaybeUninit::zeroed().assume_init() };
-        WINDOW_INFO
-            .clone()
-            .write()
-            .unwrap()
-            .entry(self.0)
-            .and_modify(|w| {
-                if unsafe {
-                    XCheckWindowEvent(
-                        w.display,
-                        self.0 as _,
-                        w.event_mask.bits(),
-                        addr_of_mut!(ev),
+fn set_window_type(display: *mut x11::xlib::Display, window: x11::xlib::Window, kind: WindowKind) {
+    let name = match kind {
+        WindowKind::Normal => return,
+        WindowKind::Popup => "_NET_WM_WINDOW_TYPE_MENU",
+        WindowKind::Tooltip => "_NET_WM_WINDOW_TYPE_TOOLTIP",
+        WindowKind::Splash => "_NET_WM_WINDOW_TYPE_SPLASH",
+    };
+    let window_type = intern_atom(display, "_NET_WM_WINDOW_TYPE");
+    let mut value = intern_atom(display, name);
+    unsafe {
+        XChangeProperty(
+            display,
+            window,
+            window_type,
+            XA_ATOM,
+            32,
+            PropModeReplace,
+            addr_of_mut!(value) as _,
+            1,
+        )
+    };
+}
+
+const NET_WM_STATE_REMOVE: c_long = 0;
+const NET_WM_STATE_ADD: c_long = 1;
+// EWMH source indication for requests from applications.
+const NET_SOURCE_APPLICATION: c_long = 1;
+
+fn wm_supports(display: *mut x11::xlib::Display, atom: x11::xlib::Atom) -> bool {
+    let root = unsafe { XDefaultRootWindow(display) };
+    get_property32(display, root, intern_atom(display, "_NET_SUPPORTED"))
+        .contains(&(atom as c_long))
+}
+
+// Mapped windows ask the WM; unmapped ones set the property it reads when
+// they're mapped.
+fn set_net_wm_state(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    mapped: bool,
+    state: x11::xlib::Atom,
+    enable: bool,
+) {
+    let net_wm_state = intern_atom(display, "_NET_WM_STATE");
+    if mapped {
+        let action = if enable {
+            NET_WM_STATE_ADD
+        } else {
+            NET_WM_STATE_REMOVE
+        };
+        send_root_message(
+            display,
+            window,
+            net_wm_state,
+            [action, state as _, 0, NET_SOURCE_APPLICATION, 0],
+        );
+        return;
+    }
+    let mut states = get_property32(display, window, net_wm_state);
+    states.retain(|s| *s != state as c_long);
+    if enable {
+        states.push(state as _);
+    }
+    unsafe {
+        XChangeProperty(
+            display,
+            window,
+            net_wm_state,
+            XA_ATOM,
+            32,
+            PropModeReplace,
+            states.as_ptr().cast(),
+            states.len() as _,
+        )
+    };
+}
+
+fn root_origin(display: *mut x11::xlib::Display, window: x11::xlib::Window) -> (i32, i32) {
+    let root = unsafe { XDefaultRootWindow(display) };
+    let (mut x, mut y, mut child) = (0, 0, 0);
+    unsafe {
+        XTranslateCoordinates(
+            display,
+            window,
+            root,
+            0,
+            0,
+            addr_of_mut!(x),
+            addr_of_mut!(y),
+            addr_of_mut!(child),
+        )
+    };
+    (x, y)
+}
+
+// Where the WM's frame starts, which is what a move under the default
+// NorthWest gravity positions.
+fn frame_origin(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    extents: [u32; 4],
+) -> (i32, i32) {
+    let (x, y) = root_origin(display, window);
+    (x - extents[0] as i32, y - extents[2] as i32)
+}
+
+// Left, right, top and bottom: how far the WM's frame reaches past the
+// window's border. WMs that don't set _NET_FRAME_EXTENTS are measured from
+// the frame they reparented the window into. Without a WM there is none.
+fn frame_extents(display: *mut x11::xlib::Display, window: x11::xlib::Window) -> [u32; 4] {
+    let extents = get_property32(display, window, intern_atom(display, "_NET_FRAME_EXTENTS"));
+    if let [left, right, top, bottom] = extents[..] {
+        return [left as _, right as _, top as _, bottom as _];
+    }
+    let root = unsafe { XDefaultRootWindow(display) };
+    // The frame is the ancestor that's a child of the root.
+    let mut frame = window;
+    loop {
+        let (mut root_ret, mut parent, mut children, mut count) = (0, 0, core::ptr::null_mut(), 0);
+        let ok = unsafe {
+            XQueryTree(
+                display,
+                frame,
+                addr_of_mut!(root_ret),
+                addr_of_mut!(parent),
+                addr_of_mut!(children),
+                addr_of_mut!(count),
+            )
+        };
+        if !children.is_null() {
+            unsafe { XFree(children.cast()) };
+        }
+        if ok == 0 || parent == 0 {
+            return [0; 4];
+        }
+        if parent == root {
+            break;
+        }
+        frame = parent;
+    }
+    if frame == window {
+        return [0; 4];
+    }
+    let geometry = |drawable| {
+        let (mut root_ret, mut x, mut y) = (0, 0, 0);
+        let (mut width, mut height, mut border, mut depth) = (0, 0, 0, 0);
+        unsafe {
+            XGetGeometry(
+                display,
+                drawable,
+                addr_of_mut!(root_ret),
+                addr_of_mut!(x),
+                addr_of_mut!(y),
+                addr_of_mut!(width),
+                addr_of_mut!(height),
+                addr_of_mut!(border),
+                addr_of_mut!(depth),
+            )
+        };
+        (
+            x,
+            y,
+            width as i32 + 2 * border as i32,
+            height as i32 + 2 * border as i32,
+            border as i32,
+        )
+    };
+    // Both boxes include their borders, in root coordinates.
+    let (fx, fy, fw, fh, _) = geometry(frame);
+    let (_, _, w, h, border) = geometry(window);
+    let (x, y) = root_origin(display, window);
+    let (x, y) = (x - border, y - border);
+    [
+        (x - fx).max(0) as _,
+        (fx + fw - x - w).max(0) as _,
+        (y - fy).max(0) as _,
+        (fy + fh - y - h).max(0) as _,
+    ]
+}
+
+// Mutter, KWin, Xfwm and most others drop the buttons for functions the
+// hints leave out. Without the property the WM decorates the window as it
+// normally would.
+fn set_motif_buttons(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    buttons: WindowButtons,
+) {
+    let motif_wm_hints = intern_atom(display, "_MOTIF_WM_HINTS");
+    if buttons == WindowButtons::all() {
+        unsafe {
+            XDeleteProperty(display, window, motif_wm_hints);
+            XFlush(display);
+        }
+        return;
+    }
+    const MWM_HINTS_FUNCTIONS: c_long = 1 << 0;
+    const MWM_HINTS_DECORATIONS: c_long = 1 << 1;
+    const MWM_FUNC_RESIZE: c_long = 1 << 1;
+    const MWM_FUNC_MOVE: c_long = 1 << 2;
+    const MWM_FUNC_MINIMIZE: c_long = 1 << 3;
+    const MWM_FUNC_MAXIMIZE: c_long = 1 << 4;
+    const MWM_FUNC_CLOSE: c_long = 1 << 5;
+    const MWM_DECOR_BORDER: c_long = 1 << 1;
+    const MWM_DECOR_RESIZEH: c_long = 1 << 2;
+    const MWM_DECOR_TITLE: c_long = 1 << 3;
+    const MWM_DECOR_MENU: c_long = 1 << 4;
+    const MWM_DECOR_MINIMIZE: c_long = 1 << 5;
+    const MWM_DECOR_MAXIMIZE: c_long = 1 << 6;
+    let mut functions = MWM_FUNC_RESIZE | MWM_FUNC_MOVE;
+    let mut decorations = MWM_DECOR_BORDER | MWM_DECOR_RESIZEH | MWM_DECOR_TITLE | MWM_DECOR_MENU;
+    if buttons.contains(WindowButtons::MINIMIZE) {
+        functions |= MWM_FUNC_MINIMIZE;
+        decorations |= MWM_DECOR_MINIMIZE;
+    }
+    if buttons.contains(WindowButtons::MAXIMIZE) {
+        functions |= MWM_FUNC_MAXIMIZE;
+        decorations |= MWM_DECOR_MAXIMIZE;
+    }
+    if buttons.contains(WindowButtons::CLOSE) {
+        functions |= MWM_FUNC_CLOSE;
+    }
+    // Flags, functions, decorations, input mode, status.
+    let hints: [c_long; 5] = [
+        MWM_HINTS_FUNCTIONS | MWM_HINTS_DECORATIONS,
+        functions,
+        decorations,
+        0,
+        0,
+    ];
+    unsafe {
+        XChangeProperty(
+            display,
+            window,
+            motif_wm_hints,
+            motif_wm_hints,
+            32,
+            PropModeReplace,
+            hints.as_ptr().cast(),
+            hints.len() as _,
+        );
+        XFlush(display);
+    }
+}
+
+// Sends an EWMH client message about `window` to the window manager.
+fn send_root_message(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    message_type: x11::xlib::Atom,
+    data: [c_long; 5],
+) {
+    let mut ev = XEvent {
+        client_message: XClientMessageEvent {
+            type_: ClientMessage,
+            serial: 0,
+            send_event: 0,
+            display,
+            window,
+            message_type,
+            format: 32,
+            data: ClientMessageData::from(data),
+        },
+    };
+    unsafe {
+        XSendEvent(
+            display,
+            XDefaultRootWindow(display),
+            x11::xlib::False,
+            SubstructureNotifyMask | SubstructureRedirectMask,
+            addr_of_mut!(ev),
+        );
+        XFlush(display);
+    }
+}
+
+// Returns false when the WM doesn't support _NET_ACTIVE_WINDOW.
+fn request_active(w: &WindowInfo, window: x11::xlib::Window) -> bool {
+    let net_active_window = intern_atom(w.display, "_NET_ACTIVE_WINDOW");
+    if !wm_supports(w.display, net_active_window) {
+        return false;
+    }
+    send_root_message(
+        w.display,
+        window,
+        net_active_window,
+        [NET_SOURCE_APPLICATION, w.user_time as _, 0, 0, 0],
+    );
+    true
+}
+
+// Most WMs ignore stacking requests from clients unless they come through
+// _NET_RESTACK_WINDOW.
+fn restack(display: *mut x11::xlib::Display, window: x11::xlib::Window, above: bool) {
+    let net_restack_window = intern_atom(display, "_NET_RESTACK_WINDOW");
+    if !wm_supports(display, net_restack_window) {
+        if above {
+            unsafe { XRaiseWindow(display, window) };
+        } else {
+            unsafe { XLowerWindow(display, window) };
+        }
+        unsafe { XFlush(display) };
+        return;
+    }
+    let detail = if above { Above } else { Below };
+    send_root_message(
+        display,
+        window,
+        net_restack_window,
+        [NET_SOURCE_APPLICATION, 0, detail as _, 0, 0],
+    );
+}
+
+const XDND_VERSION: c_long = 5;
+
+// Payloads taken from XDND drags, most preferred first. File managers and
+// browsers offer URI lists; text/plain without a charset is assumed UTF-8.
+const DROP_TYPES: [&str; 4] = [
+    "text/uri-list",
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "text/plain",
+];
+
+#[derive(Copy, Clone, Debug)]
+struct DndOffer {
+    source: x11::xlib::Window,
+    // The first of DROP_TYPES the source offers, if any.
+    target: Option<x11::xlib::Atom>,
+}
+
+fn send_client_message(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    message_type: x11::xlib::Atom,
+    data: [c_long; 5],
+) {
+    let mut ev = XEvent {
+        client_message: XClientMessageEvent {
+            type_: ClientMessage,
+            serial: 0,
+            send_event: 0,
+            display,
+            window,
+            message_type,
+            format: 32,
+            data: ClientMessageData::from(data),
+        },
+    };
+    unsafe {
+        XSendEvent(
+            display,
+            window,
+            x11::xlib::False,
+            NoEventMask,
+            addr_of_mut!(ev),
+        );
+        XFlush(display);
+    }
+}
+
+// The data is requested on XdndDrop and reported when the SelectionNotify
+// arrives.
+fn handle_xdnd(w: &mut WindowInfo, window: x11::xlib::Window, cm: &XClientMessageEvent) {
+    let display = w.display;
+    let source = cm.data.get_long(0) as x11::xlib::Window;
+    if cm.message_type == intern_atom(display, "XdndEnter") {
+        // More than three types are listed on the source instead.
+        let offered: Vec<x11::xlib::Atom> = if cm.data.get_long(1) & 1 != 0 {
+            get_property32(display, source, intern_atom(display, "XdndTypeList"))
+                .into_iter()
+                .map(|a| a as _)
+                .collect()
+        } else {
+            (2..5).map(|i| cm.data.get_long(i) as _).collect()
+        };
+        let target = DROP_TYPES
+            .iter()
+            .map(|t| intern_atom(display, t))
+            .find(|t| offered.contains(t));
+        w.dnd = Some(DndOffer { source, target });
+    } else if cm.message_type == intern_atom(display, "XdndPosition") {
+        let accept = w
+            .dnd
+            .is_some_and(|o| o.source == source && o.target.is_some());
+        let action = if accept {
+            intern_atom(display, "XdndActionCopy") as c_long
+        } else {
+            0
+        };
+        send_client_message(
+            display,
+            source,
+            intern_atom(display, "XdndStatus"),
+            [window as _, accept as _, 0, 0, action],
+        );
+    } else if cm.message_type == intern_atom(display, "XdndLeave") {
+        w.dnd = None;
+    } else if cm.message_type == intern_atom(display, "XdndDrop") {
+        match w.dnd.take() {
+            Some(DndOffer {
+                source: s,
+                target: Some(target),
+            }) if s == source => {
+                unsafe {
+                    XConvertSelection(
+                        display,
+                        intern_atom(display, "XdndSelection"),
+                        target,
+                        intern_atom(display, "NWIN_XDND"),
+                        window,
+                        cm.data.get_long(2) as _,
                     )
-                } == x11::xlib::False
+                };
+                w.dnd_drop = Some(source);
+            }
+            _ => send_client_message(
+                display,
+                source,
+                intern_atom(display, "XdndFinished"),
+                [window as _, 0, 0, 0, 0],
+            ),
+        }
+    }
+}
+
+// How long to wait for XdndFinished after dropping.
+const XDND_FINISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Drives the sending side of XDND with its own loop over the window's
+// connection until the drop finishes or the drag is cancelled. Anything
+// unrelated is put back afterwards so next_event still sees it.
+fn run_drag(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    payload: &DragPayload,
+    time: x11::xlib::Time,
+) -> Result<DragOutcome, OsError> {
+    let atom = |name: &str| intern_atom(display, name);
+    // At most three types, so XdndEnter can carry them without XdndTypeList.
+    let types: Vec<x11::xlib::Atom> = match payload {
+        DragPayload::Files(_) => vec![atom("text/uri-list")],
+        DragPayload::Text(_) => ["UTF8_STRING", "text/plain;charset=utf-8", "text/plain"]
+            .iter()
+            .map(|t| atom(t))
+            .collect(),
+    };
+    let selection = atom("XdndSelection");
+    let root = unsafe { XDefaultRootWindow(display) };
+    unsafe { XSetSelectionOwner(display, selection, window, time) };
+    if unsafe { XGetSelectionOwner(display, selection) } != window {
+        return Err(OsError);
+    }
+    if unsafe {
+        XGrabPointer(
+            display,
+            window,
+            x11::xlib::False,
+            (ButtonReleaseMask | PointerMotionMask) as _,
+            GrabModeAsync,
+            GrabModeAsync,
+            0,
+            0,
+            time,
+        )
+    } != GrabSuccess
+    {
+        return Err(OsError);
+    }
+    // Only for Escape; the drag goes ahead without it.
+    unsafe {
+        XGrabKeyboard(
+            display,
+            window,
+            x11::xlib::False,
+            GrabModeAsync,
+            GrabModeAsync,
+            time,
+        )
+    };
+
+    // The XdndAware window under the pointer and its protocol version, and
+    // the action it accepted the last position with.
+    let mut target: Option<(x11::xlib::Window, c_long)> = None;
+    let mut accepted: Option<x11::xlib::Atom> = None;
+    let mut dropped_at: Option<Instant> = None;
+    let mut outcome = DragOutcome::Cancelled;
+    let mut deferred = Vec::new();
+    let mut ev: XEvent = unsafe { MaybeUninit::zeroed().assume_init() };
+    loop {
+        if let Some(dropped_at) = dropped_at {
+            if dropped_at.elapsed() > XDND_FINISH_TIMEOUT {
+                break;
+            }
+            if unsafe { XPending(display) } == 0 {
+                std::thread::sleep(Duration::from_millis(1));
+                continue;
+            }
+        }
+        unsafe { XNextEvent(display, addr_of_mut!(ev)) };
+        match unsafe { ev.type_ } {
+            MotionNotify if dropped_at.is_none() => {
+                let m = unsafe { ev.motion };
+                let under = xdnd_target(display, root, m.x_root, m.y_root);
+                if under.map(|t| t.0) != target.map(|t| t.0) {
+                    if let Some((old, _)) = target {
+                        send_client_message(
+                            display,
+                            old,
+                            atom("XdndLeave"),
+                            [window as _, 0, 0, 0, 0],
+                        );
+                    }
+                    accepted = None;
+                    if let Some((new, version)) = under {
+                        let mut data = [window as c_long, version.min(XDND_VERSION) << 24, 0, 0, 0];
+                        for (slot, ty) in data[2..].iter_mut().zip(&types) {
+                            *slot = *ty as _;
+                        }
+                        send_client_message(display, new, atom("XdndEnter"), data);
+                    }
+                    target = under;
+                }
+                if let Some((t, _)) = target {
+                    send_client_message(
+                        display,
+                        t,
+                        atom("XdndPosition"),
+                        [
+                            window as _,
+                            0,
+                            ((m.x_root as c_long) << 16) | (m.y_root as c_long & 0xFFFF),
+                            m.time as _,
+                            atom("XdndActionCopy") as _,
+                        ],
+                    );
+                }
+            }
+            ButtonRelease if dropped_at.is_none() => {
+                let Some((t, _)) = target else {
+                    break;
+                };
+                if accepted.is_none() {
+                    send_client_message(display, t, atom("XdndLeave"), [window as _, 0, 0, 0, 0]);
+                    break;
+                }
+                send_client_message(
+                    display,
+                    t,
+                    atom("XdndDrop"),
+                    [window as _, 0, unsafe { ev.button.time } as _, 0, 0],
+                );
+                dropped_at = Some(Instant::now());
+            }
+            KeyPress
+                if dropped_at.is_none()
+                    && unsafe { XLookupKeysym(addr_of_mut!(ev.key), 0) } == XK_Escape as _ =>
+            {
+                if let Some((t, _)) = target {
+                    send_client_message(display, t, atom("XdndLeave"), [window as _, 0, 0, 0, 0]);
+                }
+                break;
+            }
+            SelectionRequest => {
+                answer_drag_request(display, unsafe { &ev.selection_request }, payload, &types);
+            }
+            ClientMessage => {
+                let cm = unsafe { ev.client_message };
+                let from = cm.data.get_long(0) as x11::xlib::Window;
+                if cm.message_type == atom("XdndStatus") && target.map(|t| t.0) == Some(from) {
+                    accepted = (cm.data.get_long(1) & 1 != 0).then(|| cm.data.get_long(4) as _);
+                } else if cm.message_type == atom("XdndFinished") && dropped_at.is_some() {
+                    // Before version 5 the status is all there is to go on.
+                    let action = if target.map_or(0, |t| t.1) >= 5 {
+                        (cm.data.get_long(1) & 1 != 0).then(|| cm.data.get_long(2) as _)
+                    } else {
+                        accepted
+                    };
+                    outcome = match action {
+                        None => DragOutcome::Cancelled,
+                        Some(a) if a == atom("XdndActionMove") => DragOutcome::Moved,
+                        Some(a) if a == atom("XdndActionLink") => DragOutcome::Linked,
+                        Some(_) => DragOutcome::Copied,
+                    };
+                    break;
+                } else {
+                    deferred.push(ev);
+                }
+            }
+            _ => deferred.push(ev),
+        }
+    }
+
+    unsafe {
+        XUngrabPointer(display, CurrentTime);
+        XUngrabKeyboard(display, CurrentTime);
+        for ev in deferred.iter_mut().rev() {
+            XPutBackEvent(display, ev);
+        }
+        XFlush(display);
+    }
+    Ok(outcome)
+}
+
+// Descends from the root through WM frames to the XdndAware window under the
+// pointer, returning it with its protocol version.
+fn xdnd_target(
+    display: *mut x11::xlib::Display,
+    root: x11::xlib::Window,
+    x: i32,
+    y: i32,
+) -> Option<(x11::xlib::Window, c_long)> {
+    let xdnd_aware = intern_atom(display, "XdndAware");
+    let mut window = root;
+    loop {
+        let (mut wx, mut wy, mut child) = (0, 0, 0);
+        unsafe {
+            XTranslateCoordinates(
+                display,
+                root,
+                window,
+                x,
+                y,
+                addr_of_mut!(wx),
+                addr_of_mut!(wy),
+                addr_of_mut!(child),
+            )
+        };
+        if child == 0 {
+            return None;
+        }
+        window = child;
+        if let Some(&version) = get_property32(display, window, xdnd_aware).first() {
+            return Some((window, version));
+        }
+    }
+}
+
+fn answer_drag_request(
+    display: *mut x11::xlib::Display,
+    req: &XSelectionRequestEvent,
+    payload: &DragPayload,
+    types: &[x11::xlib::Atom],
+) {
+    let data = match payload {
+        _ if !types.contains(&req.target) => None,
+        DragPayload::Files(paths) => Some(
+            paths
+                .iter()
+                .map(|p| file_uri(p))
+                .collect::<Vec<_>>()
+                .join("\r\n"),
+        ),
+        DragPayload::Text(text) => Some(text.clone()),
+    };
+    // Obsolete clients leave the property unset.
+    let property = if req.property == 0 {
+        req.target
+    } else {
+        req.property
+    };
+    if let Some(data) = &data {
+        unsafe {
+            XChangeProperty(
+                display,
+                req.requestor,
+                property,
+                req.target,
+                8,
+                PropModeReplace,
+                data.as_ptr(),
+                data.len() as _,
+            )
+        };
+    }
+    notify_requestor(display, req, if data.is_some() { property } else { 0 });
+}
+
+// Tells the requestor the conversion is done, with a property of 0 for a
+// refusal.
+fn notify_requestor(
+    display: *mut x11::xlib::Display,
+    req: &XSelectionRequestEvent,
+    property: x11::xlib::Atom,
+) {
+    let mut reply = XEvent {
+        selection: XSelectionEvent {
+            type_: SelectionNotify,
+            serial: 0,
+            send_event: x11::xlib::True,
+            display,
+            requestor: req.requestor,
+            selection: req.selection,
+            target: req.target,
+            property,
+            time: req.time,
+        },
+    };
+    unsafe {
+        XSendEvent(
+            display,
+            req.requestor,
+            x11::xlib::False,
+            NoEventMask,
+            addr_of_mut!(reply),
+        );
+        XFlush(display);
+    }
+}
+
+// How long clipboard_image waits for the owner to answer, and then for each
+// chunk of an INCR transfer.
+const CLIPBOARD_TIMEOUT: Duration = Duration::from_secs(2);
+
+// A selection owned by its own connection, which a background thread serves
+// until another client takes it over.
+pub(crate) fn set_clipboard_image(image: RgbaImage) -> Result<(), ClipboardError> {
+    let (owned_tx, owned_rx) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+        .name("nwin clipboard".into())
+        .spawn(move || {
+            let display = unsafe { XOpenDisplay(core::ptr::null()) };
+            if display.is_null() {
+                let _ = owned_tx.send(false);
+                return;
+            }
+            let window = unsafe {
+                XCreateSimpleWindow(display, XDefaultRootWindow(display), 0, 0, 1, 1, 0, 0, 0)
+            };
+            let clipboard = intern_atom(display, "CLIPBOARD");
+            unsafe { XSetSelectionOwner(display, clipboard, window, CurrentTime) };
+            let owned = unsafe { XGetSelectionOwner(display, clipboard) } == window;
+            let _ = owned_tx.send(owned);
+            if owned {
+                serve_clipboard(display, clipboard, &image);
+            }
+            unsafe { XDestroyWindow(display, window) };
+            unsafe { XCloseDisplay(display) };
+        })
+        .map_err(|_| ClipboardError::Os)?;
+    match owned_rx.recv() {
+        Ok(true) => Ok(()),
+        _ => Err(ClipboardError::Os),
+    }
+}
+
+// An INCR transfer to a requestor's property, sent a chunk at a time as the
+// requestor deletes the last one.
+struct IncrTransfer {
+    target: x11::xlib::Atom,
+    data: Rc<Vec<u8>>,
+    sent: usize,
+}
+
+// The image is only encoded once some requestor asks for that format. Runs
+// until the selection is lost and the transfers still going have finished.
+fn serve_clipboard(
+    display: *mut x11::xlib::Display,
+    clipboard: x11::xlib::Atom,
+    image: &RgbaImage,
+) {
+    let atom = |name: &str| intern_atom(display, name);
+    let (targets, png, bmp, incr) = (
+        atom("TARGETS"),
+        atom("image/png"),
+        atom("image/bmp"),
+        atom("INCR"),
+    );
+    // A quarter of the largest request, which leaves plenty of room for the
+    // rest of the ChangeProperty.
+    let chunk_size = unsafe { XMaxRequestSize(display) } as usize;
+    let mut encoded: HashMap<x11::xlib::Atom, Rc<Vec<u8>>> = HashMap::new();
+    let mut transfers: HashMap<(x11::xlib::Window, x11::xlib::Atom), IncrTransfer> = HashMap::new();
+    let mut owned = true;
+    let mut ev: XEvent = unsafe { MaybeUninit::zeroed().assume_init() };
+    while owned || !transfers.is_empty() {
+        unsafe { XNextEvent(display, addr_of_mut!(ev)) };
+        match unsafe { ev.type_ } {
+            SelectionClear if unsafe { ev.selection_clear.selection } == clipboard => owned = false,
+            SelectionRequest if owned => {
+                let req = unsafe { ev.selection_request };
+                let property = if req.property == 0 {
+                    req.target
+                } else {
+                    req.property
+                };
+                let data = if req.target == targets {
+                    let list = [targets, png, bmp];
+                    trap_errors(display, || unsafe {
+                        XChangeProperty(
+                            display,
+                            req.requestor,
+                            property,
+                            XA_ATOM,
+                            32,
+                            PropModeReplace,
+                            list.as_ptr().cast(),
+                            list.len() as _,
+                        );
+                    });
+                    notify_requestor(display, &req, property);
+                    continue;
+                } else if req.target == png {
+                    encoded
+                        .entry(png)
+                        .or_insert_with(|| Rc::new(clipboard::encode_png(image)))
+                } else if req.target == bmp {
+                    encoded
+                        .entry(bmp)
+                        .or_insert_with(|| Rc::new(clipboard::encode_bmp(image)))
+                } else {
+                    notify_requestor(display, &req, 0);
+                    continue;
+                };
+                let failed = trap_errors(display, || unsafe {
+                    if data.len() > chunk_size {
+                        XSelectInput(
+                            display,
+                            req.requestor,
+                            PropertyChangeMask | StructureNotifyMask,
+                        );
+                        let len = data.len() as c_long;
+                        XChangeProperty(
+                            display,
+                            req.requestor,
+                            property,
+                            incr,
+                            32,
+                            PropModeReplace,
+                            addr_of!(len).cast(),
+                            1,
+                        );
+                    } else {
+                        XChangeProperty(
+                            display,
+                            req.requestor,
+                            property,
+                            req.target,
+                            8,
+                            PropModeReplace,
+                            data.as_ptr(),
+                            data.len() as _,
+                        );
+                    }
+                });
+                // The requestor went away.
+                if failed.is_some() {
+                    continue;
+                }
+                if data.len() > chunk_size {
+                    transfers.insert(
+                        (req.requestor, property),
+                        IncrTransfer {
+                            target: req.target,
+                            data: data.clone(),
+                            sent: 0,
+                        },
+                    );
+                }
+                notify_requestor(display, &req, property);
+            }
+            // Refused once the selection is lost, so the requestor asks the
+            // new owner instead.
+            SelectionRequest => notify_requestor(display, unsafe { &ev.selection_request }, 0),
+            PropertyNotify => {
+                let p = unsafe { ev.property };
+                if p.state != PropertyDelete {
+                    continue;
+                }
+                let Some(t) = transfers.get_mut(&(p.window, p.atom)) else {
+                    continue;
+                };
+                // Ends with an empty chunk.
+                let chunk = &t.data[t.sent..(t.sent + chunk_size).min(t.data.len())];
+                let failed = trap_errors(display, || unsafe {
+                    XChangeProperty(
+                        display,
+                        p.window,
+                        p.atom,
+                        t.target,
+                        8,
+                        PropModeReplace,
+                        chunk.as_ptr(),
+                        chunk.len() as _,
+                    );
+                });
+                t.sent += chunk.len();
+                if chunk.is_empty() || failed.is_some() {
+                    transfers.remove(&(p.window, p.atom));
+                }
+            }
+            DestroyNotify => {
+                let window = unsafe { ev.destroy_window.window };
+                transfers.retain(|(requestor, _), _| *requestor != window);
+            }
+            _ => {}
+        }
+    }
+}
+
+// PNG first, since it keeps the alpha channel everywhere.
+pub(crate) fn clipboard_image() -> Option<RgbaImage> {
+    let display = unsafe { XOpenDisplay(core::ptr::null()) };
+    if display.is_null() {
+        return None;
+    }
+    let window =
+        unsafe { XCreateSimpleWindow(display, XDefaultRootWindow(display), 0, 0, 1, 1, 0, 0, 0) };
+    unsafe { XSelectInput(display, window, PropertyChangeMask) };
+    let clipboard = intern_atom(display, "CLIPBOARD");
+    let read = |target| read_selection(display, window, clipboard, intern_atom(display, target));
+    let image = read("image/png")
+        .and_then(|png| clipboard::decode_png(&png))
+        .or_else(|| read("image/bmp").and_then(|bmp| clipboard::decode_bmp(&bmp)));
+    unsafe { XDestroyWindow(display, window) };
+    unsafe { XCloseDisplay(display) };
+    image
+}
+
+// Converts the selection to a property on the window and reads it back,
+// following an INCR transfer if the owner starts one. None if the owner
+// refuses or stops answering.
+fn read_selection(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    selection: x11::xlib::Atom,
+    target: x11::xlib::Atom,
+) -> Option<Vec<u8>> {
+    let property = intern_atom(display, "NWIN_CLIPBOARD");
+    unsafe { XConvertSelection(display, selection, target, property, window, CurrentTime) };
+    let notify = wait_for_window_event(display, window, SelectionNotify, CLIPBOARD_TIMEOUT)?;
+    if unsafe { notify.selection.property } == 0 {
+        return None;
+    }
+    let (ty, _, data) = get_property_raw(display, window, property, true)?;
+    if ty != intern_atom(display, "INCR") {
+        return Some(data);
+    }
+    // Deleting the INCR property asked for the first chunk, and deleting
+    // each chunk asks for the next. Notifications from before a chunk was
+    // written find the property missing and are skipped.
+    let mut data = Vec::new();
+    loop {
+        let ev = wait_for_window_event(display, window, PropertyNotify, CLIPBOARD_TIMEOUT)?;
+        let p = unsafe { ev.property };
+        if p.atom != property || p.state != PropertyNewValue {
+            continue;
+        }
+        let Some((_, _, chunk)) = get_property_raw(display, window, property, true) else {
+            continue;
+        };
+        if chunk.is_empty() {
+            return Some(data);
+        }
+        data.extend(chunk);
+    }
+}
+
+fn wait_for_window_event(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    ty: c_int,
+    timeout: Duration,
+) -> Option<XEvent> {
+    let deadline = Instant::now() + timeout;
+    let mut ev: XEvent = unsafe { MaybeUninit::zeroed().assume_init() };
+    loop {
+        if unsafe { XCheckTypedWindowEvent(display, window, ty, addr_of_mut!(ev)) } != 0 {
+            return Some(ev);
+        }
+        let left = deadline.checked_duration_since(Instant::now())?;
+        let mut fd = libc::pollfd {
+            fd: unsafe { XConnectionNumber(display) },
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(addr_of_mut!(fd), 1, poll_timeout(Some(left))) };
+    }
+}
+
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &b in path.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+    uri
+}
+
+// Local file URIs become DroppedFile; everything else, including files on
+// other hosts, is passed on as DroppedUri.
+fn uri_list_events(list: &str) -> Vec<crate::WindowEvent> {
+    list.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|uri| match file_uri_path(uri) {
+            Some(path) => crate::WindowEvent::DroppedFile(path),
+            None => crate::WindowEvent::DroppedUri(uri.to_owned()),
+        })
+        .collect()
+}
+
+fn file_uri_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None;
+    }
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    Some(OsString::from_vec(decoded).into())
+}
+
+// Launchers pass the startup notification ID through the environment; it's
+// removed so child processes don't complete the launcher's sequence again.
+fn take_startup_id() -> Option<String> {
+    let id = std::env::var("DESKTOP_STARTUP_ID").ok()?;
+    std::env::remove_var("DESKTOP_STARTUP_ID");
+    (!id.is_empty()).then_some(id)
+}
+
+// Tells the launcher the application is up so it can drop the busy cursor.
+// The message is broadcast on the root window in 20-byte chunks, the first
+// tagged _NET_STARTUP_INFO_BEGIN and the rest _NET_STARTUP_INFO, ending at
+// the terminating nul.
+fn complete_startup(display: *mut x11::xlib::Display, window: x11::xlib::Window, id: &str) {
+    let mut quoted = String::with_capacity(id.len() + 2);
+    quoted.push('"');
+    for c in id.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    let mut message = format!("remove: ID={quoted}").into_bytes();
+    message.push(0);
+
+    let root = unsafe { XDefaultRootWindow(display) };
+    let begin = intern_atom(display, "_NET_STARTUP_INFO_BEGIN");
+    let info = intern_atom(display, "_NET_STARTUP_INFO");
+    for (i, chunk) in message.chunks(20).enumerate() {
+        let mut data = ClientMessageData::new();
+        for (b, c) in data.as_bytes_mut().iter_mut().zip(chunk) {
+            *b = *c as _;
+        }
+        let mut ev = XEvent {
+            client_message: XClientMessageEvent {
+                type_: ClientMessage,
+                serial: 0,
+                send_event: 0,
+                display,
+                window,
+                message_type: if i == 0 { begin } else { info },
+                format: 8,
+                data,
+            },
+        };
+        unsafe {
+            XSendEvent(
+                display,
+                root,
+                x11::xlib::False,
+                PropertyChangeMask,
+                addr_of_mut!(ev),
+            )
+        };
+    }
+    unsafe { XFlush(display) };
+}
+
+fn modify_wm_hints(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    f: impl FnOnce(&mut XWMHints),
+) {
+    unsafe {
+        let existing = XGetWMHints(display, window);
+        let mut hints = if existing.is_null() {
+            MaybeUninit::<XWMHints>::zeroed().assume_init()
+        } else {
+            let hints = *existing;
+            XFree(existing.cast());
+            hints
+        };
+        f(&mut hints);
+        XSetWMHints(display, window, addr_of_mut!(hints));
+    }
+}
+
+// Read by the WM when a window is mapped from the withdrawn state.
+fn set_initial_state(display: *mut x11::xlib::Display, window: x11::xlib::Window, iconic: bool) {
+    modify_wm_hints(display, window, |hints| {
+        hints.flags |= StateHint;
+        const NORMAL_STATE: c_int = 1;
+        const ICONIC_STATE: c_int = 3;
+        hints.initial_state = if iconic { ICONIC_STATE } else { NORMAL_STATE };
+    });
+}
+
+// The ICCCM urgency hint, which most WMs show by flashing the taskbar entry
+// until it's cleared, and _NET_WM_STATE_DEMANDS_ATTENTION, which some only
+// highlight. Critical requests set both.
+fn set_attention(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    mapped: bool,
+    attention: Option<crate::UserAttentionType>,
+) {
+    let urgent = attention == Some(crate::UserAttentionType::Critical);
+    modify_wm_hints(display, window, |hints| {
+        if urgent {
+            hints.flags |= XUrgencyHint;
+        } else {
+            hints.flags &= !XUrgencyHint;
+        }
+    });
+    set_net_wm_state(
+        display,
+        window,
+        mapped,
+        intern_atom(display, "_NET_WM_STATE_DEMANDS_ATTENTION"),
+        attention.is_some(),
+    );
+    unsafe { XFlush(display) };
+}
+
+fn intern_atom(display: *mut x11::xlib::Display, name: &str) -> x11::xlib::Atom {
+    let name_c = CString::new(name).unwrap();
+    unsafe { XInternAtom(display, name_c.as_ptr(), x11::xlib::False) }
+}
+
+fn get_property(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    property: x11::xlib::Atom,
+    delete: bool,
+) -> Vec<u8> {
+    match get_property_raw(display, window, property, delete) {
+        Some((_, 8, bytes)) => bytes,
+        _ => Vec::new(),
+    }
+}
+
+fn get_property32(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    property: x11::xlib::Atom,
+) -> Vec<c_long> {
+    match get_property_raw(display, window, property, false) {
+        Some((_, 32, bytes)) => bytes
+            .chunks_exact(size_of::<c_long>())
+            .map(|c| c_long::from_ne_bytes(c.try_into().unwrap()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// The type, format and items of the property. Format 32 items are returned as
+// longs, whatever their size on the wire.
+fn get_property_raw(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    property: x11::xlib::Atom,
+    delete: bool,
+) -> Option<(x11::xlib::Atom, i32, Vec<u8>)> {
+    let mut actual_type = 0;
+    let mut actual_format = 0;
+    let mut nitems = 0;
+    let mut bytes_after = 0;
+    let mut data: *mut u8 = core::ptr::null_mut();
+    let res = unsafe {
+        XGetWindowProperty(
+            display,
+            window,
+            property,
+            0,
+            i32::MAX as _,
+            delete as _,
+            AnyPropertyType as _,
+            addr_of_mut!(actual_type),
+            addr_of_mut!(actual_format),
+            addr_of_mut!(nitems),
+            addr_of_mut!(bytes_after),
+            addr_of_mut!(data),
+        )
+    };
+    if res != Success as i32 || data.is_null() {
+        return None;
+    }
+    let item_size = match actual_format {
+        8 => 1,
+        16 => size_of::<c_short>(),
+        32 => size_of::<c_long>(),
+        _ => 0,
+    };
+    let bytes = unsafe { slice::from_raw_parts(data, nitems as usize * item_size) }.to_vec();
+    unsafe { XFree(data.cast()) };
+    Some((actual_type, actual_format, bytes))
+}
+
+// The rate of the CRTC showing the window's centre, worked out from its mode's
+// dot clock and total (blanking included) dimensions.
+#[cfg(feature = "xrandr")]
+fn refresh_rate(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    width: u32,
+    height: u32,
+) -> Option<u32> {
+    let root = unsafe { XDefaultRootWindow(display) };
+    let (mut x, mut y, mut child) = (0, 0, 0);
+    unsafe {
+        XTranslateCoordinates(
+            display,
+            window,
+            root,
+            (width / 2) as _,
+            (height / 2) as _,
+            addr_of_mut!(x),
+            addr_of_mut!(y),
+            addr_of_mut!(child),
+        )
+    };
+
+    let res = unsafe { XRRGetScreenResourcesCurrent(display, root) };
+    if res.is_null() {
+        return None;
+    }
+    let crtcs = unsafe { slice::from_raw_parts((*res).crtcs, (*res).ncrtc as _) };
+    let modes = unsafe { slice::from_raw_parts((*res).modes, (*res).nmode as _) };
+    let mut rate = None;
+    for &crtc in crtcs {
+        let ci = unsafe { XRRGetCrtcInfo(display, res, crtc) };
+        if ci.is_null() {
+            continue;
+        }
+        let c = unsafe { *ci };
+        unsafe { XRRFreeCrtcInfo(ci) };
+        if c.mode == 0
+            || x < c.x
+            || y < c.y
+            || x >= c.x + c.width as i32
+            || y >= c.y + c.height as i32
+        {
+            continue;
+        }
+        rate = modes
+            .iter()
+            .find(|m| m.id == c.mode)
+            .and_then(mode_refresh_rate);
+        break;
+    }
+    unsafe { XRRFreeScreenResources(res) };
+    rate
+}
+
+// The core protocol has no notion of refresh rates.
+#[cfg(not(feature = "xrandr"))]
+fn refresh_rate(
+    _display: *mut x11::xlib::Display,
+    _window: x11::xlib::Window,
+    _width: u32,
+    _height: u32,
+) -> Option<u32> {
+    None
+}
+
+#[cfg(feature = "xrandr")]
+fn mode_refresh_rate(mode: &XRRModeInfo) -> Option<u32> {
+    let mut v_total = mode.vTotal as u64;
+    if mode.modeFlags & RR_DoubleScan as u64 != 0 {
+        v_total *= 2;
+    }
+    if mode.modeFlags & RR_Interlace as u64 != 0 {
+        v_total /= 2;
+    }
+    let pixels = mode.hTotal as u64 * v_total;
+    if pixels == 0 {
+        return None;
+    }
+    Some(((mode.dotClock * 1000 + pixels / 2) / pixels) as u32)
+}
+
+const XkbUseCoreKbd: c_uint = 0x0100;
+
+// Caps Lock is always the Lock modifier; the others are whichever modifier
+// the layout binds their keysym to, if any.
+fn lock_mask(display: *mut x11::xlib::Display, lock: LockKey) -> c_uint {
+    match lock {
+        LockKey::CapsLock => LockMask,
+        LockKey::NumLock => unsafe { XkbKeysymToModifiers(display, XK_Num_Lock as _) },
+        LockKey::ScrollLock => unsafe { XkbKeysymToModifiers(display, XK_Scroll_Lock as _) },
+    }
+}
+
+pub(crate) fn lock_state(lock: LockKey) -> Option<bool> {
+    let display = unsafe { XOpenDisplay(core::ptr::null()) };
+    if display.is_null() {
+        return None;
+    }
+    let mask = lock_mask(display, lock);
+    let mut state = 0;
+    let (mut root, mut child) = (0, 0);
+    let (mut root_x, mut root_y, mut x, mut y) = (0, 0, 0, 0);
+    let res = unsafe {
+        XQueryPointer(
+            display,
+            XDefaultRootWindow(display),
+            addr_of_mut!(root),
+            addr_of_mut!(child),
+            addr_of_mut!(root_x),
+            addr_of_mut!(root_y),
+            addr_of_mut!(x),
+            addr_of_mut!(y),
+            addr_of_mut!(state),
+        )
+    };
+    unsafe { XCloseDisplay(display) };
+    (mask != 0 && res != 0).then_some(state & mask != 0)
+}
+
+// Locks the modifier directly instead of faking key presses, so no key
+// events reach any client.
+pub(crate) fn set_lock_state(lock: LockKey, enabled: bool) -> Result<(), LockError> {
+    let display = unsafe { XOpenDisplay(core::ptr::null()) };
+    if display.is_null() {
+        return Err(LockError::Os);
+    }
+    let mask = lock_mask(display, lock);
+    let res = if mask == 0 {
+        Err(LockError::Unsupported)
+    } else if unsafe {
+        XkbLockModifiers(display, XkbUseCoreKbd, mask, if enabled { mask } else { 0 })
+    } == 0
+    {
+        Err(LockError::Os)
+    } else {
+        unsafe { XFlush(display) };
+        Ok(())
+    };
+    unsafe { XCloseDisplay(display) };
+    res?;
+    for (id, w) in WINDOW_INFO.write().unwrap().iter_mut() {
+        if let Some(m) = w.keys.set_lock(lock.modifier(), enabled) {
+            w.sender
+                .write()
+                .unwrap()
+                .send(WindowId(*id), crate::WindowEvent::ModifiersChanged(m));
+        }
+    }
+    Ok(())
+}
+
+// Window managers resize through ConfigureNotify alone.
+pub(crate) const REPORTS_SIZE_MOVE: bool = false;
+
+pub(crate) const WINDOW_ID_KIND: &str = "XID";
+
+pub(crate) fn init_dpi(_mode: DpiMode) -> Result<(), DpiError> {
+    Ok(())
+}
+
+// The Xft.dpi resource desktops set for their scaling, as a factor of the
+// 96 DPI baseline.
+fn xft_scale_factor(display: *mut x11::xlib::Display) -> Option<f64> {
+    let resources = unsafe { XResourceManagerString(display) };
+    if resources.is_null() {
+        return None;
+    }
+    let resources = unsafe { CStr::from_ptr(resources) }.to_string_lossy();
+    resources
+        .lines()
+        .find_map(|line| line.strip_prefix("Xft.dpi:"))
+        .and_then(|dpi| dpi.trim().parse::<f64>().ok())
+        .map(|dpi| dpi / 96.0)
+        .filter(|f| crate::dpi::validate_scale_factor(*f))
+}
+
+pub(crate) fn monitors() -> Vec<Monitor> {
+    let display = unsafe { XOpenDisplay(core::ptr::null()) };
+    if display.is_null() {
+        return Vec::new();
+    }
+    let monitors = monitors_on(display);
+    unsafe { XCloseDisplay(display) };
+    monitors
+}
+
+// Connected outputs driven by a CRTC, identified by output name.
+#[cfg(feature = "xrandr")]
+fn monitors_on(display: *mut x11::xlib::Display) -> Vec<Monitor> {
+    let root = unsafe { XDefaultRootWindow(display) };
+    let res = unsafe { XRRGetScreenResourcesCurrent(display, root) };
+    if res.is_null() {
+        return Vec::new();
+    }
+    let primary = unsafe { XRRGetOutputPrimary(display, root) };
+    let outputs = unsafe { slice::from_raw_parts((*res).outputs, (*res).noutput as _) };
+    let modes = unsafe { slice::from_raw_parts((*res).modes, (*res).nmode as _) };
+    let mut monitors = Vec::new();
+    for &output in outputs {
+        let oi = unsafe { XRRGetOutputInfo(display, res, output) };
+        if oi.is_null() {
+            continue;
+        }
+        let o = unsafe { *oi };
+        let name = unsafe { slice::from_raw_parts(o.name as *const u8, o.nameLen as _) };
+        let name = String::from_utf8_lossy(name).into_owned();
+        unsafe { XRRFreeOutputInfo(oi) };
+        if o.connection as c_int != RR_Connected || o.crtc == 0 {
+            continue;
+        }
+        let ci = unsafe { XRRGetCrtcInfo(display, res, o.crtc) };
+        if ci.is_null() {
+            continue;
+        }
+        let c = unsafe { *ci };
+        unsafe { XRRFreeCrtcInfo(ci) };
+        monitors.push(Monitor {
+            id: MonitorId(name.clone()),
+            name,
+            position: PhysicalPosition::new(c.x, c.y),
+            size: PhysicalSize::new(c.width, c.height),
+            refresh_rate_millihertz: modes
+                .iter()
+                .find(|m| m.id == c.mode)
+                .and_then(mode_refresh_rate),
+            primary: output == primary,
+        });
+    }
+    unsafe { XRRFreeScreenResources(res) };
+    monitors
+}
+
+// Without XRandR the whole screen is taken to be a single monitor.
+#[cfg(not(feature = "xrandr"))]
+fn monitors_on(display: *mut x11::xlib::Display) -> Vec<Monitor> {
+    let screen = unsafe { XDefaultScreen(display) };
+    let name = format!("screen{screen}");
+    vec![Monitor {
+        id: MonitorId(name.clone()),
+        name,
+        position: PhysicalPosition::new(0, 0),
+        size: PhysicalSize::new(
+            unsafe { x11::xlib::XDisplayWidth(display, screen) } as _,
+            unsafe { x11::xlib::XDisplayHeight(display, screen) } as _,
+        ),
+        refresh_rate_millihertz: None,
+        primary: true,
+    }]
+}
+
+#[cfg(feature = "xrandr")]
+fn update_monitors(window: x11::xlib::Window, w: &mut WindowInfo) {
+    for ev in w.monitors.update(monitors_on(w.display)) {
+        w.sender.write().unwrap().send(WindowId(window), ev);
+    }
+}
+
+fn update_refresh_rate(window: x11::xlib::Window, w: &mut WindowInfo) {
+    let rate = refresh_rate(w.display, window, w.width, w.height);
+    if let Some(rate) = w.refresh_rate.update(rate) {
+        if w.redraw.continuous() {
+            w.redraw
+                .set_interval(Some(crate::frame_interval(Some(rate))));
+        }
+        w.sender.write().unwrap().send(
+            WindowId(window),
+            crate::WindowEvent::RefreshRateChanged(rate),
+        );
+    }
+}
+
+fn xsettings_owner(display: *mut x11::xlib::Display, screen: i32) -> x11::xlib::Window {
+    unsafe {
+        XGetSelectionOwner(
+            display,
+            intern_atom(display, &format!("_XSETTINGS_S{screen}")),
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum XSetting {
+    Int(i32),
+    String(String),
+}
+
+impl XSetting {
+    fn int(&self) -> Option<i32> {
+        match self {
+            XSetting::Int(v) => Some(*v),
+            XSetting::String(_) => None,
+        }
+    }
+}
+
+fn read_xsettings(
+    display: *mut x11::xlib::Display,
+    owner: x11::xlib::Window,
+) -> HashMap<String, XSetting> {
+    if owner == 0 {
+        return HashMap::new();
+    }
+    parse_xsettings(&get_property(
+        display,
+        owner,
+        intern_atom(display, "_XSETTINGS_SETTINGS"),
+        false,
+    ))
+}
+
+// There's no light/dark setting as such, but dark themes are named that way,
+// e.g. "Adwaita-dark".
+fn theme_from_xsettings(
+    display: *mut x11::xlib::Display,
+    owner: x11::xlib::Window,
+) -> Option<Theme> {
+    theme_from_settings(&read_xsettings(display, owner))
+}
+
+fn theme_from_settings(values: &HashMap<String, XSetting>) -> Option<Theme> {
+    match values.get("Net/ThemeName")? {
+        XSetting::String(name) if name.to_lowercase().contains("dark") => Some(Theme::Dark),
+        XSetting::String(_) => Some(Theme::Light),
+        XSetting::Int(_) => None,
+    }
+}
+
+pub(crate) fn system_theme() -> Option<Theme> {
+    let display = unsafe { XOpenDisplay(core::ptr::null()) };
+    if display.is_null() {
+        return None;
+    }
+    let owner = xsettings_owner(display, unsafe { XDefaultScreen(display) });
+    let theme = theme_from_xsettings(display, owner);
+    unsafe { XCloseDisplay(display) };
+    theme
+}
+
+// Colours aren't needed, so they're skipped.
+fn parse_xsettings(data: &[u8]) -> HashMap<String, XSetting> {
+    let mut settings = HashMap::new();
+    if data.len() < 12 {
+        return settings;
+    }
+    let msb_first = data[0] == 1;
+    let card16 = |b: &[u8]| {
+        let b = [b[0], b[1]];
+        if msb_first {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    };
+    let card32 = |b: &[u8]| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if msb_first {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    };
+
+    let mut pos = 12;
+    for _ in 0..card32(&data[8..12]) {
+        let Some(header) = data.get(pos..pos + 4) else {
+            break;
+        };
+        let kind = header[0];
+        let name_len = card16(&header[2..4]) as usize;
+        let Some(name) = data.get(pos + 4..pos + 4 + name_len) else {
+            break;
+        };
+        // Skip the padded name and the last-change serial.
+        pos += 4 + ((name_len + 3) & !3) + 4;
+        match kind {
+            0 => {
+                let Some(value) = data.get(pos..pos + 4) else {
+                    break;
+                };
+                settings.insert(
+                    String::from_utf8_lossy(name).into_owned(),
+                    XSetting::Int(card32(value) as i32),
+                );
+                pos += 4;
+            }
+            1 => {
+                let Some(len) = data.get(pos..pos + 4) else {
+                    break;
+                };
+                let len = card32(len) as usize;
+                let Some(value) = data.get(pos + 4..pos + 4 + len) else {
+                    break;
+                };
+                settings.insert(
+                    String::from_utf8_lossy(name).into_owned(),
+                    XSetting::String(String::from_utf8_lossy(value).into_owned()),
+                );
+                pos += 4 + ((len + 3) & !3);
+            }
+            2 => pos += 8,
+            _ => break,
+        }
+    }
+    settings
+}
+
+pub(crate) fn system_settings() -> SystemSettings {
+    let mut settings = SystemSettings::default();
+    let display = unsafe { XOpenDisplay(core::ptr::null()) };
+    if display.is_null() {
+        return settings;
+    }
+    let owner = xsettings_owner(display, unsafe { XDefaultScreen(display) });
+    if owner != 0 {
+        settings = settings_from_xsettings(&read_xsettings(display, owner));
+    }
+    unsafe { XCloseDisplay(display) };
+    settings
+}
+
+// Whatever isn't set, or isn't usable, keeps its default.
+fn settings_from_xsettings(values: &HashMap<String, XSetting>) -> SystemSettings {
+    let mut settings = SystemSettings::default();
+    let get = |name: &str| values.get(name).and_then(XSetting::int).filter(|v| *v > 0);
+    if let Some(time) = get("Net/DoubleClickTime") {
+        settings.double_click_time = Duration::from_millis(time as _);
+    }
+    // XSETTINGS distances are measured from the first click, while the area
+    // is the full rectangle around it.
+    if let Some(distance) = get("Net/DoubleClickDistance") {
+        settings.double_click_area = (distance as u32 * 2, distance as u32 * 2);
+    }
+    if let Some(threshold) = get("Net/DndDragThreshold") {
+        settings.drag_threshold = (threshold as _, threshold as _);
+    }
+    // Net/CursorBlinkTime is the length of a whole on/off cycle.
+    if values.get("Net/CursorBlink").and_then(XSetting::int) == Some(0) {
+        settings.caret_blink_interval = None;
+    } else if let Some(time) = get("Net/CursorBlinkTime") {
+        settings.caret_blink_interval = Some(Duration::from_millis(time as u64 / 2));
+    }
+    settings.reduced_motion = values.get("Gtk/EnableAnimations").and_then(XSetting::int) == Some(0);
+    // There's no setting for it, but the high contrast themes all say so in
+    // their name.
+    settings.high_contrast = matches!(
+        values.get("Net/ThemeName"),
+        Some(XSetting::String(name)) if name.to_lowercase().contains("highcontrast")
+    );
+    settings
+}
+
+pub(crate) struct InstanceLock {
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    selection: x11::xlib::Atom,
+    attached: Option<x11::xlib::Window>,
+}
+
+pub(crate) struct Notifier {
+    display: *mut x11::xlib::Display,
+    owner: x11::xlib::Window,
+}
+
+// The first instance owns a selection named after the instance; later
+// instances find the owner and deliver their arguments through a property on
+// the owning window, announced with a `_NWIN_ACTIVATE` client message.
+pub(crate) fn claim_instance(name: &str) -> Result<Claim, InstanceError> {
+    let display = unsafe { XOpenDisplay(core::ptr::null()) };
+    if display.is_null() {
+        return Err(InstanceError::Os);
+    }
+
+    let selection = intern_atom(display, &format!("_NWIN_INSTANCE_{name}"));
+    let owner = unsafe { XGetSelectionOwner(display, selection) };
+    if owner != 0 {
+        return Ok(Claim::AlreadyRunning(crate::single_instance::Notifier {
+            inner: Notifier { display, owner },
+        }));
+    }
+
+    let window =
+        unsafe { XCreateSimpleWindow(display, XDefaultRootWindow(display), 0, 0, 1, 1, 0, 0, 0) };
+    unsafe { XSetSelectionOwner(display, selection, window, CurrentTime) };
+    let owner = unsafe { XGetSelectionOwner(display, selection) };
+    if owner != window {
+        unsafe { XDestroyWindow(display, window) };
+        // Another instance claimed it between the two checks.
+        if owner != 0 {
+            return Ok(Claim::AlreadyRunning(crate::single_instance::Notifier {
+                inner: Notifier { display, owner },
+            }));
+        }
+        unsafe { XCloseDisplay(display) };
+        return Err(InstanceError::Os);
+    }
+    NWIN_ACTIVATE.store(
+        intern_atom(display, "_NWIN_ACTIVATE"),
+        std::sync::atomic::Ordering::Relaxed,
+    );
+    unsafe { XFlush(display) };
+
+    Ok(Claim::Acquired(crate::single_instance::InstanceLock {
+        inner: InstanceLock {
+            display,
+            window,
+            selection,
+            attached: None,
+        },
+    }))
+}
+
+impl InstanceLock {
+    pub(crate) fn attach(&mut self, id: WindowId) {
+        if let Some(w) = WINDOW_INFO.read().unwrap().get(&id.0) {
+            unsafe { XSetSelectionOwner(w.display, self.selection, id.0, CurrentTime) };
+            unsafe { XFlush(w.display) };
+            self.attached = Some(id.0);
+        }
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let owner = unsafe { XGetSelectionOwner(self.display, self.selection) };
+        if owner == self.window || Some(owner) == self.attached {
+            unsafe { XSetSelectionOwner(self.display, self.selection, 0, CurrentTime) };
+        }
+        unsafe { XDestroyWindow(self.display, self.window) };
+        unsafe { XCloseDisplay(self.display) };
+    }
+}
+
+impl Notifier {
+    pub(crate) fn notify(&self, args: &[String]) -> Result<(), InstanceError> {
+        // Binary rather than text, so the property is its own type.
+        let property = intern_atom(self.display, "_NWIN_ACTIVATE_ARGS");
+        let data = crate::single_instance::encode_args(args);
+        let mut ev = XClientMessageEvent {
+            type_: ClientMessage,
+            format: 32,
+            window: self.owner,
+            message_type: intern_atom(self.display, "_NWIN_ACTIVATE"),
+            data: ClientMessageData::from([property as i64, 0, 0, 0, 0]),
+            serial: 0,
+            send_event: 0,
+            display: self.display,
+        };
+        // The owner may have quit since the claim, leaving a dead window.
+        let mut sent = 0;
+        let error = trap_errors(self.display, || unsafe {
+            XChangeProperty(
+                self.display,
+                self.owner,
+                property,
+                property,
+                8,
+                PropModeReplace,
+                data.as_ptr(),
+                data.len() as _,
+            );
+            sent = XSendEvent(
+                self.display,
+                self.owner,
+                x11::xlib::False,
+                NoEventMask,
+                addr_of_mut!(ev) as _,
+            );
+        });
+        match error {
+            Some(BadWindow) => Err(InstanceError::NotRunning),
+            Some(_) => Err(InstanceError::Os),
+            None if sent == 0 => Err(InstanceError::Os),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        unsafe { XCloseDisplay(self.display) };
+    }
+}
+
+// A window's entry goes in while it's still being created, before it has a
+// handle, so only the ones with a live handle count.
+pub(crate) fn windows() -> Vec<WindowId> {
+    WINDOW_INFO
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, info)| info.handle.strong_count() > 0)
+        .map(|(id, _)| WindowId(*id as _))
+        .collect()
+}
+
+// A pipe the loop's thread polls along with its connections. LoopWaker writes
+// a byte to it so a loop blocked in wait_for_events sees queued tasks, and
+// pump_events drains it. Both ends stay open while a waker holds on to it.
+#[derive(Debug)]
+struct LoopWake {
+    read: OwnedFd,
+    write: OwnedFd,
+}
+
+thread_local! {
+    static LOOP_WAKE: RefCell<Option<Arc<LoopWake>>> = const { RefCell::new(None) };
+}
+
+// None if the pipe couldn't be made, and waking does nothing.
+#[derive(Clone, Debug)]
+pub(crate) struct LoopWaker {
+    wake: Option<Arc<LoopWake>>,
+}
+
+impl LoopWaker {
+    pub(crate) fn wake(&self) {
+        if let Some(wake) = &self.wake {
+            // A full pipe already wakes the loop, so EAGAIN is fine.
+            unsafe { libc::write(wake.write.as_raw_fd(), [0u8].as_ptr().cast(), 1) };
+        }
+    }
+}
+
+pub(crate) fn loop_waker() -> LoopWaker {
+    LOOP_WAKE.with(|wake| {
+        let mut wake = wake.borrow_mut();
+        if wake.is_none() {
+            let mut fds = [0; 2];
+            if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } != 0 {
+                return LoopWaker { wake: None };
+            }
+            *wake = Some(Arc::new(unsafe {
+                LoopWake {
+                    read: OwnedFd::from_raw_fd(fds[0]),
+                    write: OwnedFd::from_raw_fd(fds[1]),
+                }
+            }));
+        }
+        LoopWaker { wake: wake.clone() }
+    })
+}
+
+// Blocks until one of the windows' connections (or the hotkey connection, or
+// LoopWaker's) has something to read.
+pub(crate) fn wait_for_events(ids: &HashSet<WindowId>, timeout: Option<Duration>) {
+    let Some(mut fds) = wait_fds(ids) else {
+        return;
+    };
+    let timeout = poll_timeout(timeout);
+    // With nothing to poll this is just a sleep until the next timer.
+    if !fds.is_empty() || timeout >= 0 {
+        unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, timeout) };
+    }
+}
+
+// The same wait, run later on another thread so an EventStream can sleep
+// until there's something to pump. Only the connections' descriptors cross
+// over, never the displays. None if there's nothing to wait for.
+#[cfg(feature = "async")]
+pub(crate) fn event_waiter(
+    ids: &HashSet<WindowId>,
+    timeout: Option<Duration>,
+) -> Option<Box<dyn FnOnce() + Send>> {
+    let Some(mut fds) = wait_fds(ids) else {
+        return Some(Box::new(|| {}));
+    };
+    let timeout = poll_timeout(timeout);
+    if fds.is_empty() && timeout < 0 {
+        return None;
+    }
+    Some(Box::new(move || unsafe {
+        libc::poll(fds.as_mut_ptr(), fds.len() as _, timeout);
+    }))
+}
+
+// The connections of `ids`, the hotkeys and LoopWaker to poll, flushed first,
+// or None if one already has events queued.
+fn wait_fds(ids: &HashSet<WindowId>) -> Option<Vec<libc::pollfd>> {
+    let mut displays = ids
+        .iter()
+        .filter_map(|id| WINDOW_INFO.read().unwrap().get(&id.0).map(|w| w.display))
+        .collect::<Vec<_>>();
+    HOTKEYS.with(|hotkeys| {
+        if let Some(h) = hotkeys.borrow().as_ref() {
+            displays.push(h.display);
+        }
+    });
+
+    let mut fds = Vec::with_capacity(displays.len() + 1);
+    for display in displays {
+        unsafe { XFlush(display) };
+        if unsafe { XQLength(display) } > 0 {
+            return None;
+        }
+        fds.push(libc::pollfd {
+            fd: unsafe { XConnectionNumber(display) },
+            events: libc::POLLIN,
+            revents: 0,
+        });
+    }
+    LOOP_WAKE.with(|wake| {
+        if let Some(wake) = wake.borrow().as_ref() {
+            fds.push(libc::pollfd {
+                fd: wake.read.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            });
+        }
+    });
+    Some(fds)
+}
+
+// Rounded up so a timer isn't polled just before it's due.
+fn poll_timeout(timeout: Option<Duration>) -> i32 {
+    timeout.map_or(-1, |t| {
+        t.as_nanos().div_ceil(1_000_000).min(i32::MAX as _) as i32
+    })
+}
+
+// The pixel value of an RGB color on the visual. True and direct color
+// visuals pack the channels into their masks, and whatever a 32-bit visual
+// has left over is alpha, set to opaque. The other classes need the color
+// allocated in the colormap.
+fn rgb_pixel(
+    display: *mut x11::xlib::Display,
+    info: &VisualInfo,
+    colormap: Colormap,
+    (r, g, b): (u8, u8, u8),
+) -> Option<c_ulong> {
+    fn channel(value: u8, mask: u64) -> u64 {
+        if mask == 0 {
+            return 0;
+        }
+        let shift = mask.trailing_zeros();
+        let max = mask >> shift;
+        ((value as u64 * max + 127) / 255) << shift
+    }
+
+    match info.class {
+        VisualClass::TrueColor | VisualClass::DirectColor => {
+            let mut pixel = channel(r, info.red_mask)
+                | channel(g, info.green_mask)
+                | channel(b, info.blue_mask);
+            if info.depth == 32 {
+                pixel |= 0xFFFF_FFFF & !(info.red_mask | info.green_mask | info.blue_mask);
+            }
+            Some(pixel as _)
+        }
+        _ => {
+            let mut color = XColor {
+                pixel: 0,
+                red: r as u16 * 257,
+                green: g as u16 * 257,
+                blue: b as u16 * 257,
+                flags: (DoRed | DoGreen | DoBlue) as _,
+                pad: 0,
+            };
+            (unsafe { XAllocColor(display, colormap, addr_of_mut!(color)) } != 0)
+                .then_some(color.pixel)
+        }
+    }
+}
+
+fn screen_visuals(display: *mut x11::xlib::Display, screen: i32) -> Vec<(VisualInfo, *mut Visual)> {
+    let mut template: XVisualInfo = unsafe { MaybeUninit::zeroed().assume_init() };
+    template.screen = screen;
+    let mut nitems = 0;
+    let p = unsafe {
+        XGetVisualInfo(
+            display,
+            VisualScreenMask,
+            addr_of_mut!(template),
+            addr_of_mut!(nitems),
+        )
+    };
+    if p.is_null() {
+        return Vec::new();
+    }
+    let visuals = unsafe { slice::from_raw_parts(p, nitems as _) }
+        .iter()
+        .filter_map(|v| {
+            let info = VisualInfo {
+                id: v.visualid,
+                depth: v.depth,
+                class: VisualClass::from_i32(v.class)?,
+                red_mask: v.red_mask,
+                green_mask: v.green_mask,
+                blue_mask: v.blue_mask,
+                colormap_size: v.colormap_size,
+                bits_per_rgb: v.bits_per_rgb,
+            };
+            Some((info, v.visual))
+        })
+        .collect();
+    unsafe { XFree(p.cast()) };
+    visuals
+}
+
+// An explicitly requested visual must exist. Otherwise the default visual is
+// kept unless a different depth was asked for, in which case TrueColor
+// visuals of that depth are preferred.
+fn select_visual(
+    visuals: &[VisualInfo],
+    id: Option<x11::xlib::VisualID>,
+    depth: Option<i32>,
+    default_id: x11::xlib::VisualID,
+) -> Option<usize> {
+    if let Some(id) = id {
+        return visuals.iter().position(|v| v.id == id);
+    }
+    let default = visuals.iter().position(|v| v.id == default_id);
+    match depth {
+        None => default.or((!visuals.is_empty()).then_some(0)),
+        Some(depth) => default
+            .filter(|i| visuals[*i].depth == depth)
+            .or_else(|| {
+                visuals
+                    .iter()
+                    .position(|v| v.depth == depth && v.class == VisualClass::TrueColor)
+            })
+            .or_else(|| visuals.iter().position(|v| v.depth == depth)),
+    }
+}
+
+pub fn visuals(screen: i32) -> Vec<VisualInfo> {
+    let display = unsafe { XOpenDisplay(core::ptr::null()) };
+    if display.is_null() {
+        return Vec::new();
+    }
+    let visuals = screen_visuals(display, screen)
+        .into_iter()
+        .map(|(v, _)| v)
+        .collect();
+    unsafe { XCloseDisplay(display) };
+    visuals
+}
+
+fn keysym(key: KeyboardScancode) -> Option<KeySym> {
+    let sym = match key {
+        KeyboardScancode::Esc => XK_Escape,
+        KeyboardScancode::F1 => XK_F1,
+        KeyboardScancode::F2 => XK_F2,
+        KeyboardScancode::F3 => XK_F3,
+        KeyboardScancode::F4 => XK_F4,
+        KeyboardScancode::F5 => XK_F5,
+        KeyboardScancode::F6 => XK_F6,
+        KeyboardScancode::F7 => XK_F7,
+        KeyboardScancode::F8 => XK_F8,
+        KeyboardScancode::F9 => XK_F9,
+        KeyboardScancode::F10 => XK_F10,
+        KeyboardScancode::F11 => XK_F11,
+        KeyboardScancode::F12 => XK_F12,
+        KeyboardScancode::PrtScSysRq => XK_Print,
+        KeyboardScancode::ScrLk => XK_Scroll_Lock,
+        KeyboardScancode::PauseBreak => XK_Pause,
+        KeyboardScancode::Tilde => XK_grave,
+        KeyboardScancode::Key1 => XK_1,
+        KeyboardScancode::Key2 => XK_2,
+        KeyboardScancode::Key3 => XK_3,
+        KeyboardScancode::Key4 => XK_4,
+        KeyboardScancode::Key5 => XK_5,
+        KeyboardScancode::Key6 => XK_6,
+        KeyboardScancode::Key7 => XK_7,
+        KeyboardScancode::Key8 => XK_8,
+        KeyboardScancode::Key9 => XK_9,
+        KeyboardScancode::Key0 => XK_0,
+        KeyboardScancode::Hyphen => XK_minus,
+        KeyboardScancode::Equals => XK_equal,
+        KeyboardScancode::Backspace => XK_BackSpace,
+        KeyboardScancode::Insert => XK_Insert,
+        KeyboardScancode::Home => XK_Home,
+        KeyboardScancode::PgUp => XK_Prior,
+        KeyboardScancode::NumLk => XK_Num_Lock,
+        KeyboardScancode::NumSlash => XK_KP_Divide,
+        KeyboardScancode::NumAsterisk => XK_KP_Multiply,
+        KeyboardScancode::NumHyphen => XK_KP_Subtract,
+        KeyboardScancode::Tab => XK_Tab,
+        KeyboardScancode::Q => XK_q,
+        KeyboardScancode::W => XK_w,
+        KeyboardScancode::E => XK_e,
+        KeyboardScancode::R => XK_r,
+        KeyboardScancode::T => XK_t,
+        KeyboardScancode::Y => XK_y,
+        KeyboardScancode::U => XK_u,
+        KeyboardScancode::I => XK_i,
+        KeyboardScancode::O => XK_o,
+        KeyboardScancode::P => XK_p,
+        KeyboardScancode::OpenBracket => XK_bracketleft,
+        KeyboardScancode::CloseBracket => XK_bracketright,
+        KeyboardScancode::BackSlash => XK_backslash,
+        KeyboardScancode::Del => XK_Delete,
+        KeyboardScancode::End => XK_End,
+        KeyboardScancode::PgDn => XK_Next,
+        KeyboardScancode::Num7 => XK_KP_7,
+        KeyboardScancode::Num8 => XK_KP_8,
+        KeyboardScancode::Num9 => XK_KP_9,
+        KeyboardScancode::NumPlus => XK_KP_Add,
+        KeyboardScancode::CapsLk => XK_Caps_Lock,
+        KeyboardScancode::A => XK_a,
+        KeyboardScancode::S => XK_s,
+        KeyboardScancode::D => XK_d,
+        KeyboardScancode::F => XK_f,
+        KeyboardScancode::G => XK_g,
+        KeyboardScancode::H => XK_h,
+        KeyboardScancode::J => XK_j,
+        KeyboardScancode::K => XK_k,
+        KeyboardScancode::L => XK_l,
+        KeyboardScancode::Semicolon => XK_semicolon,
+        KeyboardScancode::Apostrophe => XK_apostrophe,
+        KeyboardScancode::Enter => XK_Return,
+        KeyboardScancode::Num4 => XK_KP_4,
+        KeyboardScancode::Num5 => XK_KP_5,
+        KeyboardScancode::Num6 => XK_KP_6,
+        KeyboardScancode::LShift => XK_Shift_L,
+        KeyboardScancode::Z => XK_z,
+        KeyboardScancode::X => XK_x,
+        KeyboardScancode::C => XK_c,
+        KeyboardScancode::V => XK_v,
+        KeyboardScancode::B => XK_b,
+        KeyboardScancode::N => XK_n,
+        KeyboardScancode::M => XK_m,
+        KeyboardScancode::Comma => XK_comma,
+        KeyboardScancode::Period => XK_period,
+        KeyboardScancode::ForwardSlash => XK_slash,
+        KeyboardScancode::RShift => XK_Shift_R,
+        KeyboardScancode::ArrowUp => XK_Up,
+        KeyboardScancode::Num1 => XK_KP_1,
+        KeyboardScancode::Num2 => XK_KP_2,
+        KeyboardScancode::Num3 => XK_KP_3,
+        KeyboardScancode::NumEnter => XK_KP_Enter,
+        KeyboardScancode::LCtrl => XK_Control_L,
+        KeyboardScancode::LSys => XK_Super_L,
+        KeyboardScancode::LAlt => XK_Alt_L,
+        KeyboardScancode::Space => XK_space,
+        KeyboardScancode::RAlt => XK_Alt_R,
+        KeyboardScancode::RSys => XK_Super_R,
+        KeyboardScancode::Fn => return None,
+        KeyboardScancode::Menu => XK_Menu,
+        KeyboardScancode::RCtrl => XK_Control_R,
+        KeyboardScancode::ArrowLeft => XK_Left,
+        KeyboardScancode::ArrowDown => XK_Down,
+        KeyboardScancode::ArrowRight => XK_Right,
+        KeyboardScancode::Num0 => XK_KP_0,
+        KeyboardScancode::NumPeriod => XK_KP_Decimal,
+    };
+    Some(sym as _)
+}
+
+// The key whose keysym lives on `keycode`, so the result follows the physical
+// key rather than the shift level.
+fn scancode(display: *mut x11::xlib::Display, keycode: u32) -> Option<KeyboardScancode> {
+    crate::hotkeys::KEY_NAMES.iter().map(|(_, k)| *k).find(|k| {
+        keysym(*k).map(|sym| unsafe { XKeysymToKeycode(display, sym) } as u32) == Some(keycode)
+    })
+}
+
+// Latin-1 keysyms are their own code points and the rest of Unicode is
+// offset by 0x01000000; a few function keys stand for control characters.
+fn keysym_char(sym: KeySym) -> Option<char> {
+    let sym = u32::try_from(sym).ok()?;
+    match sym {
+        0x20..=0x7E | 0xA0..=0xFF => char::from_u32(sym),
+        0x0100_0100..=0x0110_FFFF => char::from_u32(sym - 0x0100_0000),
+        XK_BackSpace => Some('\x08'),
+        XK_Tab => Some('\t'),
+        XK_Return | XK_KP_Enter => Some('\r'),
+        XK_Escape => Some('\x1b'),
+        XK_Delete => Some('\x7f'),
+        _ => None,
+    }
+}
+
+fn modifier_mask(modifiers: Modifiers) -> u32 {
+    let mut mask = 0;
+    if modifiers.intersects(Modifiers::LSHIFT | Modifiers::RSHIFT) {
+        mask |= ShiftMask;
+    }
+    if modifiers.intersects(Modifiers::LCTRL | Modifiers::RCTRL) {
+        mask |= ControlMask;
+    }
+    if modifiers.intersects(Modifiers::LALT | Modifiers::RALT) {
+        mask |= Mod1Mask;
+    }
+    if modifiers.intersects(Modifiers::LSYS | Modifiers::RSYS) {
+        mask |= Mod4Mask;
+    }
+    mask
+}
+
+// Grabs are made for every combination of the lock modifiers so hotkeys
+// still fire with Caps Lock or Num Lock on.
+const LOCK_VARIANTS: [u32; 4] = [0, LockMask, Mod2Mask, LockMask | Mod2Mask];
+
+static GRAB_FAILED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "C" fn grab_error_handler(
+    _display: *mut x11::xlib::Display,
+    event: *mut XErrorEvent,
+) -> c_int {
+    if (*event).error_code == BadAccess {
+        GRAB_FAILED.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+    0
+}
+
+// The code of the last error trap_errors caught, or 0.
+static TRAPPED_ERROR: AtomicU8 = AtomicU8::new(0);
+
+unsafe extern "C" fn trap_error_handler(
+    _display: *mut x11::xlib::Display,
+    event: *mut XErrorEvent,
+) -> c_int {
+    TRAPPED_ERROR.store((*event).error_code, std::sync::atomic::Ordering::Relaxed);
+    0
+}
+
+// Runs requests that can fail through no fault of ours, such as ones on
+// another client's window that may be gone, and returns the code of the last
+// error they caused. Xlib's default handler would exit the process.
+fn trap_errors(display: *mut x11::xlib::Display, f: impl FnOnce()) -> Option<u8> {
+    TRAPPED_ERROR.store(0, std::sync::atomic::Ordering::Relaxed);
+    let old_handler = unsafe { XSetErrorHandler(Some(trap_error_handler)) };
+    f();
+    unsafe { XSync(display, x11::xlib::False) };
+    unsafe { XSetErrorHandler(old_handler) };
+    match TRAPPED_ERROR.load(std::sync::atomic::Ordering::Relaxed) {
+        0 => None,
+        code => Some(code),
+    }
+}
+
+struct Hotkey {
+    id: HotkeyId,
+    keycode: u32,
+    modifiers: u32,
+}
+
+struct Hotkeys {
+    display: *mut x11::xlib::Display,
+    root: x11::xlib::Window,
+    next_id: u32,
+    registered: Vec<Hotkey>,
+}
+
+thread_local! {
+    static HOTKEYS: RefCell<Option<Hotkeys>> = const { RefCell::new(None) };
+}
+
+impl Hotkeys {
+    fn new() -> Result<Self, HotkeyError> {
+        let display = unsafe { XOpenDisplay(core::ptr::null()) };
+        if display.is_null() {
+            return Err(HotkeyError::Os);
+        }
+        Ok(Self {
+            display,
+            root: unsafe { XDefaultRootWindow(display) },
+            next_id: 1,
+            registered: Vec::new(),
+        })
+    }
+
+    fn ungrab(&self, keycode: u32, modifiers: u32) {
+        for lock in LOCK_VARIANTS {
+            unsafe { XUngrabKey(self.display, keycode as _, modifiers | lock, self.root) };
+        }
+    }
+}
+
+impl Drop for Hotkeys {
+    fn drop(&mut self) {
+        for hotkey in self.registered.iter() {
+            self.ungrab(hotkey.keycode, hotkey.modifiers);
+        }
+        unsafe { XCloseDisplay(self.display) };
+    }
+}
+
+pub(crate) fn register_hotkey(combo: KeyCombo) -> Result<HotkeyId, HotkeyError> {
+    HOTKEYS.with(|hotkeys| {
+        let mut hotkeys = hotkeys.borrow_mut();
+        if hotkeys.is_none() {
+            *hotkeys = Some(Hotkeys::new()?);
+        }
+        let h = hotkeys.as_mut().unwrap();
+
+        let sym = keysym(combo.key).ok_or(HotkeyError::UnsupportedKey)?;
+        let keycode = unsafe { XKeysymToKeycode(h.display, sym) } as u32;
+        if keycode == 0 {
+            return Err(HotkeyError::UnsupportedKey);
+        }
+        let modifiers = modifier_mask(combo.modifiers);
+        if h.registered
+            .iter()
+            .any(|k| k.keycode == keycode && k.modifiers == modifiers)
+        {
+            return Err(HotkeyError::AlreadyRegistered);
+        }
+
+        // A grab held by another client fails asynchronously with BadAccess.
+        GRAB_FAILED.store(false, std::sync::atomic::Ordering::Relaxed);
+        let old_handler = unsafe { XSetErrorHandler(Some(grab_error_handler)) };
+        for lock in LOCK_VARIANTS {
+            unsafe {
+                XGrabKey(
+                    h.display,
+                    keycode as _,
+                    modifiers | lock,
+                    h.root,
+                    x11::xlib::False,
+                    GrabModeAsync,
+                    GrabModeAsync,
+                )
+            };
+        }
+        unsafe { XSync(h.display, x11::xlib::False) };
+        unsafe { XSetErrorHandler(old_handler) };
+        if GRAB_FAILED.load(std::sync::atomic::Ordering::Relaxed) {
+            h.ungrab(keycode, modifiers);
+            unsafe { XSync(h.display, x11::xlib::False) };
+            return Err(HotkeyError::AlreadyRegistered);
+        }
+
+        let id = HotkeyId(h.next_id);
+        h.next_id += 1;
+        h.registered.push(Hotkey {
+            id,
+            keycode,
+            modifiers,
+        });
+        Ok(id)
+    })
+}
+
+pub(crate) fn unregister_hotkey(id: HotkeyId) {
+    HOTKEYS.with(|hotkeys| {
+        if let Some(h) = hotkeys.borrow_mut().as_mut() {
+            if let Some(i) = h.registered.iter().position(|k| k.id == id) {
+                let hotkey = h.registered.remove(i);
+                h.ungrab(hotkey.keycode, hotkey.modifiers);
+                unsafe { XFlush(h.display) };
+            }
+        }
+    })
+}
+
+pub(crate) fn unregister_all_hotkeys() {
+    HOTKEYS.with(|hotkeys| hotkeys.borrow_mut().take());
+}
+
+pub(crate) fn next_hotkey() -> Option<HotkeyId> {
+    HOTKEYS.with(|hotkeys| {
+        let hotkeys = hotkeys.borrow();
+        let h = hotkeys.as_ref()?;
+        let mut ev: XEvent = unsafe { MaybeUninit::zeroed().assume_init() };
+        while unsafe { XPending(h.display) } > 0 {
+            unsafe { XNextEvent(h.display, addr_of_mut!(ev)) };
+            if unsafe { ev.type_ } != KeyPress {
+                continue;
+            }
+            let key = unsafe { ev.key };
+            let modifiers = key.state & !(LockMask | Mod2Mask);
+            if let Some(hotkey) = h
+                .registered
+                .iter()
+                .find(|k| k.keycode == key.keycode && k.modifiers == modifiers)
+            {
+                return Some(hotkey.id);
+            }
+        }
+        None
+    })
+}
+
+// Scales the bits selected by `mask` to 0..=255.
+fn channel(px: u64, mask: u64) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let bits = mask.count_ones();
+    let v = (px & mask) >> mask.trailing_zeros();
+    if bits >= 8 {
+        (v >> (bits - 8)) as u8
+    } else {
+        (v * 0xFF / ((1 << bits) - 1)) as u8
+    }
+}
+
+// Every window has its own display connection, so they're pumped by id. All
+// that's left are the wake-ups written by LoopWaker.
+pub(crate) fn pump_events() {
+    LOOP_WAKE.with(|wake| {
+        if let Some(wake) = wake.borrow().as_ref() {
+            let mut buf = [0u8; 64];
+            while unsafe { libc::read(wake.read.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) }
+                > 0
+            {}
+        }
+    });
+}
+
+// A panic while handling one window's events poisons only that window, which
+// reports UnrecoverableError and is no longer pumped; other windows keep
+// working.
+impl WindowIdExt for WindowId {
+    fn next_event(&self) {
+        let poisoned = WINDOW_INFO
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&self.0)
+            .is_some_and(|w| w.poisoned);
+        if poisoned {
+            return;
+        }
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| handle_next_event(*self))) {
+            poison_window(self.0, &*payload);
+        }
+    }
+
+    // Bounded by what was pending at the start, since events no branch of
+    // handle_next_event takes stay in the queue.
+    fn dispatch_pending(&self) {
+        let display = WINDOW_INFO
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&self.0)
+            .map(|w| w.display);
+        let Some(display) = display else {
+            return;
+        };
+        let pending = unsafe { XPending(display) };
+        for _ in 0..=pending {
+            self.next_event();
+        }
+    }
+
+    fn sender(&self) -> Option<Arc<RwLock<EventSender>>> {
+        WINDOW_INFO
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&self.0)
+            .map(|w| w.sender.clone())
+    }
+}
+
+fn poison_window(window: x11::xlib::Window, payload: &(dyn std::any::Any + Send)) {
+    // The panic may have happened with the lock held.
+    WINDOW_INFO.clear_poison();
+    let sender = {
+        let mut info = WINDOW_INFO.write().unwrap();
+        let Some(w) = info.get_mut(&window) else {
+            return;
+        };
+        w.poisoned = true;
+        w.sender.clone()
+    };
+    sender.clear_poison();
+    sender.write().unwrap().send(
+        WindowId(window),
+        crate::WindowEvent::UnrecoverableError(crate::panic_message(payload)),
+    );
+}
+
+// X has no setting for it, so this is the usual default elsewhere.
+const WHEEL_SCROLL_LINES: u32 = 3;
+
+// X numbers the buttons left, middle, right, then four wheel directions, then
+// the side buttons. The vertical wheel is reported as scrolling instead, and
+// the horizontal one (6 and 7) is dropped, as there's no event for it.
+fn mouse_scancode(button: c_uint) -> Option<MouseScancode> {
+    match button {
+        Button1 => Some(MouseScancode::LClick),
+        Button2 => Some(MouseScancode::MClick),
+        Button3 => Some(MouseScancode::RClick),
+        4..=7 => None,
+        n => Some(MouseScancode::from_number((n - 4).min(u8::MAX as _) as u8)),
+    }
+}
+
+// Takes on the geometry of a ConfigureNotify, returning what changed. A single
+// configure can both move and resize, and one that repeats the geometry, e.g.
+// after a restack, reports nothing.
+fn configure_events(w: &mut WindowInfo, cfg: &XConfigureEvent) -> Vec<crate::WindowEvent> {
+    let mut events = Vec::new();
+    if cfg.x != w.x || cfg.y != w.y {
+        w.x = cfg.x;
+        w.y = cfg.y;
+        events.push(crate::WindowEvent::Moved(PhysicalPosition::new(w.x, w.y)));
+    }
+    if cfg.width != w.width as _ || cfg.height != w.height as _ {
+        w.width = cfg.width as _;
+        w.height = cfg.height as _;
+        events.push(crate::WindowEvent::Resized(PhysicalSize::new(
+            w.width, w.height,
+        )));
+    }
+    events
+}
+
+fn handle_next_event(id: WindowId) {
+    let mut ev: XEvent = unsafe { MaybeUninit::zeroed().assume_init() };
+    #[cfg_attr(
+        not(feature = "accesskit"),
+        allow(unused_variables, unused_assignments)
+    )]
+    let mut focus_change = None;
+    let mut close_requested = false;
+    WINDOW_INFO.write().unwrap().entry(id.0).and_modify(|w| {
+        if w.visible && w.size_state != WindowSizeState::Minimized && w.redraw.poll(Instant::now())
+        {
+            w.sender
+                .write()
+                .unwrap()
+                .send(WindowId(id.0), crate::WindowEvent::RedrawRequested);
+        }
+
+        if w.xsettings_owner != 0
+            && unsafe {
+                XCheckTypedWindowEvent(
+                    w.display,
+                    w.xsettings_owner,
+                    PropertyNotify,
+                    addr_of_mut!(ev),
+                )
+            } == x11::xlib::True
+        {
+            w.sender
+                .write()
+                .unwrap()
+                .send(WindowId(id.0), crate::WindowEvent::SystemSettingsChanged);
+            if w.theme_preference.is_none() {
+                let theme = theme_from_xsettings(w.display, w.xsettings_owner).unwrap_or_default();
+                if theme != w.theme {
+                    w.theme = theme;
+                    w.sender
+                        .write()
+                        .unwrap()
+                        .send(WindowId(id.0), crate::WindowEvent::ThemeChanged(theme));
+                }
+            }
+        }
+
+        // Activation by the WM doesn't always move the input focus, e.g. to
+        // a window that doesn't take it, so _NET_ACTIVE_WINDOW counts too.
+        // Only selected while attention is outstanding; see update_attention.
+        let root = unsafe { XDefaultRootWindow(w.display) };
+        let mut active_changed = false;
+        while unsafe { XCheckTypedWindowEvent(w.display, root, PropertyNotify, addr_of_mut!(ev)) }
+            == x11::xlib::True
+        {
+            active_changed |=
+                unsafe { ev.property.atom } == intern_atom(w.display, "_NET_ACTIVE_WINDOW");
+        }
+        if active_changed {
+            let net_active_window = intern_atom(w.display, "_NET_ACTIVE_WINDOW");
+            let active = get_property32(w.display, root, net_active_window);
+            if active.first() == Some(&(id.0 as c_long)) {
+                w.update_attention(id.0, None);
+            }
+        }
+
+        #[cfg(feature = "xrandr")]
+        if let Some(base) = w.rr_event_base {
+            let root = unsafe { XDefaultRootWindow(w.display) };
+            let mut changed = false;
+            for ty in [base + RRScreenChangeNotify, base + RRNotify] {
+                while unsafe { XCheckTypedWindowEvent(w.display, root, ty, addr_of_mut!(ev)) }
+                    == x11::xlib::True
                 {
-                    return;
+                    unsafe { XRRUpdateConfiguration(addr_of_mut!(ev)) };
+                    changed = true;
                 }
+            }
+            if changed {
+                update_monitors(id.0, w);
+                update_refresh_rate(id.0, w);
+            }
+        }
 
-                match unsafe { ev.type_ } {
-                    DestroyNotify => {
-                        w.sender
-                            .write()
-                            .unwrap()
-                            .send(WindowId(self.0), crate::WindowEvent::CloseRequested);
-                        w.sender
-                            .write()
-                            .unwrap()
-                            .send(WindowId(self.0), crate::WindowEvent::Destroyed);
-                    }
-                    ConfigureNotify => {
-                        let cfg = unsafe { ev.configure };
-                        if cfg.x != w.x || cfg.y != w.y {
-                            w.x = cfg.x;
-                            w.y = cfg.y;
-                            w.sender.write().unwrap().send(
-                                WindowId(self.0),
-                                crate::WindowEvent::Moved(w.x as _, w.y as _),
-                            );
-                        } else if cfg.width != w.width as _ || cfg.height != w.height as _ {
-                            w.width = cfg.width as _;
-                            w.height = cfg.height as _;
-                            w.sender.write().unwrap().send(
-                                WindowId(self.0),
-                                crate::WindowEvent::Resized(w.width, w.height),
-                            );
-                        }
-                    }
-                    KeyPress => {
-                        let kp = unsafe { ev.key };
-                        w.sender.write().unwrap().send(
-                            WindowId(self.0),
-                            crate::WindowEvent::KeyDown(crate::KeyboardInput {
-                                key_code: kp.keycode as _,
-                            }),
-                        );
+        // Client messages and selection replies can't be selected for,
+        // so XCheckWindowEvent never returns them.
+        if unsafe { XCheckWindowEvent(w.display, id.0 as _, w.event_mask.bits(), addr_of_mut!(ev)) }
+            == x11::xlib::False
+            && unsafe {
+                XCheckTypedWindowEvent(w.display, id.0 as _, ClientMessage, addr_of_mut!(ev))
+            } == x11::xlib::False
+            && unsafe {
+                XCheckTypedWindowEvent(w.display, id.0 as _, SelectionNotify, addr_of_mut!(ev))
+            } == x11::xlib::False
+        {
+            return;
+        }
 
-                        let modifiers =
-                            kp.state & (ShiftMask | ControlMask | Mod1Mask | Mod4Mask | LockMask);
-                        let mut m = Modifiers::empty();
-                        if modifiers & ShiftMask != 0 {
-                            m |= Modifiers::LSHIFT;
-                        }
-                        if modifiers & ControlMask != 0 {
-                            m |= Modifiers::LCTRL;
-                        }
-                        if modifiers & Mod1Mask != 0 {
-                            m |= Modifiers::LALT;
-                        }
-                        if modifiers & Mod4Mask != 0 {
-                            m |= Modifiers::LSYS;
-                        }
-                        if modifiers & LockMask != 0 {
-                            m |= Modifiers::CAPSLOCK;
-                        }
-                        if m.contains(w.modifiers) {
-                            w.modifiers = m;
-                            w.sender
-                                .write()
-                                .unwrap()
-                                .send(WindowId(self.0), crate::WindowEvent::ModifiersChanged(m));
-                        }
-                    }
-                    KeyRelease => {
-                        let kr = unsafe { ev.key };
-                        w.sender.write().unwrap().send(
-                            WindowId(self.0),
-                            crate::WindowEvent::KeyDown(crate::KeyboardInput {
-                                key_code: kr.keycode as _,
-                            }),
-                        );
+        if unsafe { ev.type_ } == MapNotify {
+            if let Some(startup_id) = w.startup_id.take() {
+                complete_startup(w.display, id.0, &startup_id);
+            }
+            w.report_focus(id.0);
+        }
 
-                        let modifiers =
-                            kr.state & (ShiftMask | ControlMask | Mod1Mask | Mod4Mask | LockMask);
-                        let mut m = Modifiers::empty();
-                        if modifiers & ShiftMask != 0 {
-                            m |= Modifiers::LSHIFT;
-                        }
-                        if modifiers & ControlMask != 0 {
-                            m |= Modifiers::LCTRL;
-                        }
-                        if modifiers & Mod1Mask != 0 {
-                            m |= Modifiers::LALT;
-                        }
-                        if modifiers & Mod4Mask != 0 {
-                            m |= Modifiers::LSYS;
-                        }
-                        if modifiers & LockMask != 0 {
-                            m |= Modifiers::CAPSLOCK;
-                        }
-                        if m.contains(w.modifiers) {
-                            w.modifiers = m;
-                            w.sender
-                                .write()
-                                .unwrap()
-                                .send(WindowId(self.0), crate::WindowEvent::ModifiersChanged(m));
-                        }
+        match unsafe { ev.type_ } {
+            DestroyNotify => {
+                w.sender
+                    .write()
+                    .unwrap()
+                    .send(WindowId(id.0), crate::WindowEvent::Destroyed);
+            }
+            // The configured size excludes the border, matching the
+            // client area reported on win32.
+            ConfigureNotify => {
+                let cfg = unsafe { ev.configure };
+                w.border_width = cfg.border_width as _;
+                w.requested_size = None;
+                for event in configure_events(w, &cfg) {
+                    w.sender.write().unwrap().send(WindowId(id.0), event);
+                }
+                update_refresh_rate(id.0, w);
+            }
+            KeyPress => {
+                let mut kp = unsafe { ev.key };
+                w.user_time = kp.time;
+                let Some(key) = scancode(w.display, kp.keycode) else {
+                    return;
+                };
+                let transition = w.keys.key(key, true, false);
+
+                let mut buf = [0 as c_char; 8];
+                let mut sym = 0;
+                let len = unsafe {
+                    XLookupString(
+                        addr_of_mut!(kp),
+                        buf.as_mut_ptr(),
+                        buf.len() as _,
+                        addr_of_mut!(sym),
+                        core::ptr::null_mut(),
+                    )
+                };
+                // XLookupString's text is Latin-1, so anything beyond
+                // that only comes through the keysym.
+                let character = keysym_char(sym).or((len == 1).then(|| buf[0] as u8 as char));
+                let unshifted_char = keysym_char(unsafe { XLookupKeysym(addr_of_mut!(kp), 0) });
+                w.sender.write().unwrap().send(
+                    WindowId(id.0),
+                    crate::WindowEvent::KeyDown {
+                        logical_scancode: key,
+                        physical_scancode: None,
+                        character,
+                        unshifted_char,
+                        text: character.map(String::from),
+                        repeat: transition.repeat,
+                    },
+                );
+                if let Some(m) = transition.modifiers_changed {
+                    w.sender
+                        .write()
+                        .unwrap()
+                        .send(WindowId(id.0), crate::WindowEvent::ModifiersChanged(m));
+                }
+            }
+            KeyRelease => {
+                let kr = unsafe { ev.key };
+                w.user_time = kr.time;
+                // Without detectable auto-repeat, a held key sends
+                // release/press pairs with the same timestamp. Dropping
+                // the release leaves the key held, so the press that
+                // follows is reported as a repeat.
+                if !w.detectable_repeat && unsafe { XPending(w.display) } > 0 {
+                    let mut next: XEvent = unsafe { MaybeUninit::zeroed().assume_init() };
+                    unsafe { XPeekEvent(w.display, addr_of_mut!(next)) };
+                    if unsafe { next.type_ } == KeyPress
+                        && unsafe { next.key.time } == kr.time
+                        && unsafe { next.key.keycode } == kr.keycode
+                    {
+                        return;
                     }
-                    ButtonPress => {
-                        let bp = unsafe { ev.button };
-                        let button = match bp.button {
-                            Button1 => MouseButtons::LCLICK,
-                            Button2 => MouseButtons::RCLICK,
-                            Button3 => MouseButtons::MCLICK,
-                            Button4 => MouseButtons::BUTTON_4,
-                            Button5 => MouseButtons::BUTTON_5,
-                            _ => panic!(),
-                        };
-                        w.sender.write().unwrap().send(
-                            WindowId(self.0),
-                            crate::WindowEvent::MouseButtonDown(button),
-                        );
+                }
+                let Some(key) = scancode(w.display, kr.keycode) else {
+                    return;
+                };
+                let transition = w.keys.key(key, false, false);
+                w.sender.write().unwrap().send(
+                    WindowId(id.0),
+                    crate::WindowEvent::KeyUp {
+                        logical_scancode: key,
+                        physical_scancode: None,
+                    },
+                );
+                if let Some(m) = transition.modifiers_changed {
+                    w.sender
+                        .write()
+                        .unwrap()
+                        .send(WindowId(id.0), crate::WindowEvent::ModifiersChanged(m));
+                }
+            }
+            UnmapNotify => {
+                w.keyboard_grabbed = false;
+            }
+            // The last of a series of exposures; the next pump reports it.
+            Expose if unsafe { ev.expose }.count == 0 => {
+                w.redraw.request();
+            }
+            MapNotify if w.kind == WindowKind::Popup => {
+                // Override-redirect windows aren't focused by the
+                // WM, and the pointer grab reports clicks outside
+                // the popup so it can be dismissed.
+                unsafe { XSetInputFocus(w.display, id.0, RevertToParent, CurrentTime) };
+                unsafe {
+                    XGrabPointer(
+                        w.display,
+                        id.0,
+                        x11::xlib::True,
+                        (ButtonPressMask | ButtonReleaseMask | PointerMotionMask) as _,
+                        GrabModeAsync,
+                        GrabModeAsync,
+                        0,
+                        0,
+                        CurrentTime,
+                    )
+                };
+            }
+            ButtonPress => {
+                let bp = unsafe { ev.button };
+                w.user_time = bp.time;
+                if w.kind == WindowKind::Popup
+                    && (bp.x < 0 || bp.y < 0 || bp.x >= w.width as i32 || bp.y >= w.height as i32)
+                {
+                    w.sender
+                        .write()
+                        .unwrap()
+                        .send(WindowId(id.0), crate::WindowEvent::DismissRequested);
+                    return;
+                }
+                // Each wheel detent is a press/release pair of its own.
+                let ev = match bp.button {
+                    Button4 | Button5 => {
+                        let notches = if bp.button == Button4 { 1.0 } else { -1.0 };
+                        crate::wheel_scroll(notches, WHEEL_SCROLL_LINES)
                     }
-                    ButtonRelease => {
-                        let bp = unsafe { ev.button };
-                        let button = match bp.button {
-                            Button1 => MouseButtons::LCLICK,
-                            Button2 => MouseButtons::RCLICK,
-                            Button3 => MouseButtons::MCLICK,
-                            Button4 => MouseButtons::BUTTON_4,
-                            Button5 => MouseButtons::BUTTON_5,
-                            _ => panic!(),
-                        };
-                        w.sender
-                            .write()
-                            .unwrap()
-                            .send(WindowId(self.0), crate::WindowEvent::MouseButtonUp(button));
+                    button => match mouse_scancode(button) {
+                        Some(button) => crate::WindowEvent::MouseButtonDown(button),
+                        None => return,
+                    },
+                };
+                w.sender.write().unwrap().send(WindowId(id.0), ev);
+            }
+            MotionNotify => {
+                let mut m = unsafe { ev.motion };
+                // A fast pointer queues far more motion than anyone draws,
+                // so a run of it is reported once, at its last position.
+                // Only directly following events are merged, so the
+                // position is current for whatever comes after.
+                while unsafe { XPending(w.display) } > 0 {
+                    let mut next: XEvent = unsafe { MaybeUninit::zeroed().assume_init() };
+                    unsafe { XPeekEvent(w.display, addr_of_mut!(next)) };
+                    if unsafe { next.type_ } != MotionNotify
+                        || unsafe { next.motion.window } != m.window
+                    {
+                        break;
                     }
-                    FocusIn => {
-                        w.sender
-                            .write()
-                            .unwrap()
-                            .send(WindowId(self.0), crate::WindowEvent::Focused(true));
+                    unsafe { XNextEvent(w.display, addr_of_mut!(next)) };
+                    m = unsafe { next.motion };
+                }
+                let position = PhysicalPosition::new(m.x as f64, m.y as f64);
+                w.cursor_position = Some(position);
+                w.sender
+                    .write()
+                    .unwrap()
+                    .send(WindowId(id.0), crate::WindowEvent::CursorMoved(position));
+            }
+            ButtonRelease => {
+                let bp = unsafe { ev.button };
+                w.user_time = bp.time;
+                let Some(button) = mouse_scancode(bp.button) else {
+                    return;
+                };
+                w.sender
+                    .write()
+                    .unwrap()
+                    .send(WindowId(id.0), crate::WindowEvent::MouseButtonUp(button));
+            }
+            // The WM sets WM_STATE to IconicState when the window is
+            // minimized, whoever asked for it.
+            PropertyNotify => {
+                let p = unsafe { ev.property };
+                // Unmapping withdraws the window, which says nothing about
+                // the state it's shown in again.
+                if p.atom == intern_atom(w.display, "_NET_WM_STATE")
+                    && w.visible
+                    && w.fullscreen != FullscreenType::Exclusive
+                {
+                    let fullscreen = intern_atom(w.display, "_NET_WM_STATE_FULLSCREEN");
+                    w.fullscreen = if get_property32(w.display, id.0, p.atom)
+                        .contains(&(fullscreen as c_long))
+                    {
+                        FullscreenType::Borderless
+                    } else {
+                        FullscreenType::NotFullscreen
+                    };
+                }
+                if p.atom == intern_atom(w.display, "_NET_FRAME_EXTENTS") {
+                    w.frame_extents = frame_extents(w.display, id.0);
+                }
+                if p.atom == intern_atom(w.display, "WM_STATE") && w.visible {
+                    const ICONIC_STATE: c_long = 3;
+                    let iconic =
+                        get_property32(w.display, id.0, p.atom).first() == Some(&ICONIC_STATE);
+                    if iconic {
+                        w.set_size_state(id.0, WindowSizeState::Minimized);
+                    } else if w.size_state == WindowSizeState::Minimized {
+                        w.set_size_state(id.0, WindowSizeState::Other);
                     }
-                    FocusOut => {
+                }
+            }
+            // Picks up the frame of WMs that don't set _NET_FRAME_EXTENTS.
+            ReparentNotify => {
+                w.frame_extents = frame_extents(w.display, id.0);
+            }
+            FocusIn => {
+                focus_change = Some(true);
+                w.focused = true;
+                w.report_focus(id.0);
+                w.update_attention(id.0, None);
+            }
+            FocusOut => {
+                let fo = unsafe { ev.focus_change };
+                focus_change = Some(false);
+                w.focused = false;
+                w.report_focus(id.0);
+                if let Some(m) = w.keys.focus_lost() {
+                    w.sender
+                        .write()
+                        .unwrap()
+                        .send(WindowId(id.0), crate::WindowEvent::ModifiersChanged(m));
+                }
+                if w.kind == WindowKind::Popup
+                    && w.visible
+                    && fo.mode == NotifyNormal
+                    && fo.detail != NotifyInferior
+                {
+                    w.sender
+                        .write()
+                        .unwrap()
+                        .send(WindowId(id.0), crate::WindowEvent::DismissRequested);
+                }
+            }
+            ClientMessage => {
+                let cm = unsafe { ev.client_message };
+                let nwin_activate = NWIN_ACTIVATE.load(std::sync::atomic::Ordering::Relaxed);
+                if nwin_activate != 0 && cm.message_type == nwin_activate {
+                    let property = cm.data.get_long(0) as x11::xlib::Atom;
+                    let args = crate::single_instance::decode_args(&get_property(
+                        w.display, id.0, property, true,
+                    ));
+                    w.sender.write().unwrap().send(
+                        WindowId(id.0),
+                        crate::WindowEvent::ActivationRequested { args },
+                    );
+                } else if cm.message_type == WM_PROTOCOLS.load(std::sync::atomic::Ordering::Relaxed)
+                {
+                    let protocol = cm.data.get_long(0) as x11::xlib::Atom;
+                    if protocol == WM_DELETE_WINDOW.load(std::sync::atomic::Ordering::Relaxed) {
                         w.sender
                             .write()
                             .unwrap()
-                            .send(WindowId(self.0), crate::WindowEvent::Focused(false));
+                            .send(WindowId(id.0), crate::WindowEvent::CloseRequested);
+                        close_requested = true;
+                    } else if protocol == NET_WM_PING.load(std::sync::atomic::Ordering::Relaxed) {
+                        let root = unsafe { XDefaultRootWindow(w.display) };
+                        let mut reply = XEvent {
+                            client_message: XClientMessageEvent { window: root, ..cm },
+                        };
+                        unsafe {
+                            XSendEvent(
+                                w.display,
+                                root,
+                                x11::xlib::False,
+                                SubstructureNotifyMask | SubstructureRedirectMask,
+                                addr_of_mut!(reply),
+                            )
+                        };
+                        unsafe { XFlush(w.display) };
                     }
-                    ClientMessage => {
-                        let cm = unsafe { ev.client_message };
-                        if cm.data.as_longs()[0]
-                            == WM_DELETE_WINDOW.load(std::sync::atomic::Ordering::Relaxed) as _
-                        {
-                            unsafe { XDestroyWindow(w.display, self.0) };
-                            unsafe { XCloseDisplay(w.display) };
-                        }
+                } else if w.accepts_drops {
+                    handle_xdnd(w, id.0, &cm);
+                }
+            }
+            SelectionNotify => {
+                let sn = unsafe { ev.selection };
+                if let Some(source) = w.dnd_drop.take() {
+                    let data = if sn.property != 0 {
+                        get_property(w.display, id.0, sn.property, true)
+                    } else {
+                        Vec::new()
+                    };
+                    let text = String::from_utf8_lossy(&data);
+                    let events = if sn.target == intern_atom(w.display, "text/uri-list") {
+                        uri_list_events(&text)
+                    } else if text.is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![crate::WindowEvent::DroppedText(text.into_owned())]
+                    };
+                    let action = if events.is_empty() {
+                        0
+                    } else {
+                        intern_atom(w.display, "XdndActionCopy") as c_long
+                    };
+                    for event in events {
+                        w.sender.write().unwrap().send(WindowId(id.0), event);
                     }
-                    _ => {}
+                    send_client_message(
+                        w.display,
+                        source,
+                        intern_atom(w.display, "XdndFinished"),
+                        [id.0 as _, (action != 0) as _, action, 0, 0],
+                    );
                 }
-            })
-            .or_insert(WindowInfo::default());
+            }
+            _ => {}
+        }
+    });
+
+    if close_requested {
+        crate::apply_close_action(id);
+    }
+
+    // Notified after the window lock is released, since the adapter may
+    // query the window.
+    #[cfg(feature = "accesskit")]
+    if let Some(focused) = focus_change {
+        ACCESSKIT_ADAPTERS.with(|adapters| {
+            if let Some(adapter) = adapters.borrow_mut().get_mut(&id.0) {
+                adapter.window_focused(focused);
+            }
+        });
     }
 }
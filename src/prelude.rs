@@ -0,0 +1,8 @@
+// What a portable program usually needs, as `use nwin::prelude::*`. The
+// platform extension traits are left out: some of their methods share names
+// with WindowT's, which would make those calls ambiguous.
+
+pub use crate::{EventLoop, WindowBuilder, WindowEvent, WindowId, WindowT, WindowTExt};
+
+#[cfg(any(windows, all(unix, feature = "x11")))]
+pub use crate::Window;
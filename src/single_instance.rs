@@ -0,0 +1,105 @@
+use crate::{platform::platform_impl, UserAttentionType, WindowT};
+
+pub enum Claim {
+    Acquired(InstanceLock),
+    AlreadyRunning(Notifier),
+}
+
+pub struct InstanceLock {
+    pub(crate) inner: platform_impl::InstanceLock,
+}
+
+impl InstanceLock {
+    // Activation requests from other instances are delivered to the attached
+    // window as `WindowEvent::ActivationRequested` through its event loop.
+    pub fn attach(&mut self, window: &impl WindowT) {
+        self.inner.attach(window.id());
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InstanceError {
+    // No windowing backend is compiled in.
+    Unsupported,
+    // The running instance quit after the claim found it.
+    NotRunning,
+    Os,
+}
+
+pub struct Notifier {
+    pub(crate) inner: platform_impl::Notifier,
+}
+
+impl Notifier {
+    pub fn notify(&self, args: &[String]) -> Result<(), InstanceError> {
+        self.inner.notify(args)
+    }
+}
+
+pub fn claim(name: &str) -> Result<Claim, InstanceError> {
+    platform_impl::claim_instance(name)
+}
+
+pub fn present(window: &mut impl WindowT) {
+    if window.visible() == false {
+        window.show();
+    }
+    if window.minimized() {
+        window.normalize();
+    }
+    window.focus();
+    if window.focused() == false {
+        window.request_user_attention(UserAttentionType::Informational);
+    }
+}
+
+// Each argument is its length as a little-endian u32 followed by its UTF-8
+// bytes, so arguments containing NUL, and empty ones, come through intact.
+pub(crate) fn encode_args(args: &[String]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(args.iter().map(|a| 4 + a.len()).sum());
+    for arg in args {
+        bytes.extend_from_slice(&(arg.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(arg.as_bytes());
+    }
+    bytes
+}
+
+// A truncated trailing argument is dropped.
+pub(crate) fn decode_args(mut bytes: &[u8]) -> Vec<String> {
+    let mut args = Vec::new();
+    while let Some((len, rest)) = bytes.split_first_chunk::<4>() {
+        let len = u32::from_le_bytes(*len) as usize;
+        let Some(arg) = rest.get(..len) else {
+            break;
+        };
+        args.push(String::from_utf8_lossy(arg).into_owned());
+        bytes = &rest[len..];
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(args: &[&str]) {
+        let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(decode_args(&encode_args(&args)), args);
+    }
+
+    #[test]
+    fn args_round_trip() {
+        round_trip(&[]);
+        round_trip(&[""]);
+        round_trip(&["", ""]);
+        round_trip(&["--open", "file name.txt"]);
+        round_trip(&["a\0b", "\0", "ünïcödé 🎉"]);
+    }
+
+    #[test]
+    fn truncated_args_are_dropped() {
+        let bytes = encode_args(&["first".to_owned(), "second".to_owned()]);
+        assert_eq!(decode_args(&bytes[..bytes.len() - 1]), ["first"]);
+        assert_eq!(decode_args(&bytes[..2]), Vec::<String>::new());
+    }
+}
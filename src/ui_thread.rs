@@ -0,0 +1,194 @@
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    platform::platform_impl::OsError, EventLoop, Window, WindowBuilder, WindowEvent, WindowId,
+    WindowT,
+};
+
+// Windows belong to the thread that created them and their events are only
+// pumped there. This runs that thread for the application: everything that
+// touches a window is sent over as a command, and the events come back
+// through a channel.
+
+// How long an idle UI thread waits for a command before pumping the OS
+// queues again.
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+type Command = Box<dyn FnOnce(&mut UiThreadContext) + Send>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UiThreadError {
+    // The thread has exited, or exits before answering.
+    Exited,
+    Os(OsError),
+}
+
+// Owns the event loop and the windows on the UI thread. Windows created
+// through it are bound to the loop, and their events are forwarded to
+// UiThreadHandle::events.
+pub struct UiThreadContext {
+    event_loop: EventLoop,
+    windows: HashMap<WindowId, Window>,
+    exit: bool,
+}
+
+impl UiThreadContext {
+    pub fn create_window(&mut self, builder: WindowBuilder) -> Result<WindowId, OsError> {
+        let mut window = builder.build()?;
+        self.event_loop.bind(&mut window);
+        let id = window.id();
+        self.windows.insert(id, window);
+        Ok(id)
+    }
+
+    pub fn window(&mut self, id: WindowId) -> Option<&mut Window> {
+        self.windows.get_mut(&id)
+    }
+
+    // Drops the context's handle, which destroys the window unless the
+    // closure kept a clone of it.
+    pub fn destroy_window(&mut self, id: WindowId) {
+        self.windows.remove(&id);
+    }
+
+    pub fn event_loop(&mut self) -> &mut EventLoop {
+        &mut self.event_loop
+    }
+
+    // Ends the thread once the current command returns, destroying its
+    // windows.
+    pub fn exit(&mut self) {
+        self.exit = true;
+    }
+
+    fn run(&mut self, commands: Receiver<Command>, events: Sender<(WindowId, WindowEvent)>) {
+        loop {
+            let mut idle = true;
+            while let Some((id, ev)) = self.event_loop.next_event() {
+                idle = false;
+                if ev == WindowEvent::Destroyed {
+                    self.windows.remove(&id);
+                }
+                // The application may have stopped listening; only the
+                // commands decide when the thread ends.
+                let _ = events.send((id, ev));
+            }
+            if idle {
+                match commands.recv_timeout(IDLE_WAIT) {
+                    Ok(command) => command(self),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            loop {
+                match commands.try_recv() {
+                    Ok(command) => command(self),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            if self.exit {
+                return;
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct UiThreadProxy {
+    commands: Sender<Command>,
+}
+
+impl UiThreadProxy {
+    // Queues `f` to run on the UI thread without waiting for it.
+    pub fn run(
+        &self,
+        f: impl FnOnce(&mut UiThreadContext) + Send + 'static,
+    ) -> Result<(), UiThreadError> {
+        self.commands
+            .send(Box::new(f))
+            .map_err(|_| UiThreadError::Exited)
+    }
+
+    // Runs `f` on the UI thread and waits for its result.
+    pub fn call<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut UiThreadContext) -> T + Send + 'static,
+    ) -> Result<T, UiThreadError> {
+        let (tx, rx) = mpsc::channel();
+        self.run(move |cx| {
+            let _ = tx.send(f(cx));
+        })?;
+        rx.recv().map_err(|_| UiThreadError::Exited)
+    }
+
+    pub fn create_window(&self, builder: WindowBuilder) -> Result<WindowId, UiThreadError> {
+        self.call(move |cx| cx.create_window(builder))?
+            .map_err(UiThreadError::Os)
+    }
+
+    pub fn set_title(&self, id: WindowId, title: impl Into<String>) -> Result<(), UiThreadError> {
+        let title = title.into();
+        self.run(move |cx| {
+            if let Some(window) = cx.window(id) {
+                window.set_title(&title);
+            }
+        })
+    }
+
+    pub fn destroy_window(&self, id: WindowId) -> Result<(), UiThreadError> {
+        self.run(move |cx| cx.destroy_window(id))
+    }
+}
+
+pub struct UiThreadHandle {
+    proxy: UiThreadProxy,
+    events: Receiver<(WindowId, WindowEvent)>,
+    thread: JoinHandle<()>,
+}
+
+impl UiThreadHandle {
+    pub fn proxy(&self) -> UiThreadProxy {
+        self.proxy.clone()
+    }
+
+    pub fn events(&self) -> &Receiver<(WindowId, WindowEvent)> {
+        &self.events
+    }
+
+    // Asks the thread to exit and waits for it. Its windows are destroyed
+    // on the way out.
+    pub fn join(self) -> thread::Result<()> {
+        let _ = self.proxy.run(UiThreadContext::exit);
+        self.thread.join()
+    }
+}
+
+// Spawns a thread with its own event loop and runs `f` there to create the
+// initial windows. The thread keeps running until told to exit or until
+// every proxy, including the handle's, is gone.
+pub fn spawn_ui_thread(f: impl FnOnce(&mut UiThreadContext) + Send + 'static) -> UiThreadHandle {
+    let (commands, command_rx) = mpsc::channel();
+    let (event_tx, events) = mpsc::channel();
+    let thread = thread::spawn(move || {
+        let mut cx = UiThreadContext {
+            event_loop: EventLoop::new(),
+            windows: HashMap::new(),
+            exit: false,
+        };
+        f(&mut cx);
+        if !cx.exit {
+            cx.run(command_rx, event_tx);
+        }
+    });
+    UiThreadHandle {
+        proxy: UiThreadProxy { commands },
+        events,
+        thread,
+    }
+}
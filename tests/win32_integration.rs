@@ -0,0 +1,441 @@
+// Drives real windows with input synthesized through SendInput and checks what
+// comes out of the EventLoop. Input goes to the foreground window of the
+// interactive desktop, so the tests take turns, and they pass without doing
+// anything when the process has no visible window station to put windows on,
+// as under a service.
+#![cfg(windows)]
+
+use std::{
+    mem,
+    sync::{Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use nwin::{
+    clipboard, dpi::PhysicalSize, prelude::*, CloseAction, KeyboardScancode, Modifiers,
+    MouseScancode, RgbaImage, Window,
+};
+use windows::Win32::{
+    Foundation::{HANDLE, HWND, LPARAM, POINT, WPARAM},
+    Graphics::Gdi::ClientToScreen,
+    System::StationsAndDesktops::{
+        GetProcessWindowStation, GetUserObjectInformationW, UOI_FLAGS, USEROBJECTFLAGS,
+    },
+    UI::{
+        Input::KeyboardAndMouse::{
+            MapVirtualKeyW, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT,
+            KEYBD_EVENT_FLAGS, KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, MAPVK_VK_TO_VSC_EX,
+            MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP,
+            MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_XDOWN, MOUSEEVENTF_XUP,
+            MOUSEINPUT, MOUSE_EVENT_FLAGS, VIRTUAL_KEY, VK_A, VK_ESCAPE, VK_F5, VK_LEFT, VK_LSHIFT,
+            VK_MENU, VK_NUMPAD7, VK_RCONTROL, VK_RETURN, VK_SPACE, VK_Z,
+        },
+        WindowsAndMessaging::{
+            GetForegroundWindow, GetWindowTextW, IsWindow, PostMessageW, SetCursorPos,
+            SetForegroundWindow, SetWindowPos, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOZORDER, WM_CLOSE,
+            XBUTTON1, XBUTTON2,
+        },
+    },
+};
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+const WSF_VISIBLE: u32 = 0x0001;
+
+const LOCKS: Modifiers = Modifiers::CAPSLOCK
+    .union(Modifiers::NUMLOCK)
+    .union(Modifiers::SCRLOCK);
+
+fn interactive() -> bool {
+    let Ok(station) = (unsafe { GetProcessWindowStation() }) else {
+        return false;
+    };
+    let mut flags = USEROBJECTFLAGS::default();
+    let ok = unsafe {
+        GetUserObjectInformationW(
+            HANDLE(station.0),
+            UOI_FLAGS,
+            Some(&mut flags as *mut _ as *mut _),
+            mem::size_of::<USEROBJECTFLAGS>() as _,
+            None,
+        )
+    };
+    ok.as_bool() && flags.dwFlags & WSF_VISIBLE != 0
+}
+
+fn send(inputs: &[INPUT]) {
+    let sent = unsafe { SendInput(inputs, mem::size_of::<INPUT>() as _) };
+    assert_eq!(sent as usize, inputs.len(), "SendInput was blocked");
+}
+
+// Both the virtual key and the scancode are filled in, as a real keyboard
+// would, so the backend's physical and logical mappings are both exercised.
+fn key_input(vk: VIRTUAL_KEY, down: bool) -> INPUT {
+    let scancode = unsafe { MapVirtualKeyW(vk.0 as _, MAPVK_VK_TO_VSC_EX) };
+    let mut flags = KEYBD_EVENT_FLAGS(0);
+    if scancode & 0xFF00 == 0xE000 {
+        flags |= KEYEVENTF_EXTENDEDKEY;
+    }
+    if !down {
+        flags |= KEYEVENTF_KEYUP;
+    }
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: vk,
+                wScan: (scancode & 0xFF) as u16,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    }
+}
+
+fn mouse_input(flags: MOUSE_EVENT_FLAGS, data: u16) -> INPUT {
+    INPUT {
+        r#type: INPUT_MOUSE,
+        Anonymous: INPUT_0 {
+            mi: MOUSEINPUT {
+                dx: 0,
+                dy: 0,
+                mouseData: data as _,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    }
+}
+
+// A shown, foreground window with the cursor over it. Keys still held are
+// released and the window destroyed before the lock is, even if a test
+// panics, so a failure can't leave Shift stuck down for the next one.
+struct Fixture {
+    event_loop: EventLoop,
+    window: Window,
+    held: Vec<VIRTUAL_KEY>,
+    _serial: MutexGuard<'static, ()>,
+}
+
+impl Fixture {
+    fn new(title: &str) -> Option<Self> {
+        let serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        if !interactive() {
+            eprintln!("skipped: no interactive window station");
+            return None;
+        }
+        let mut window = WindowBuilder::new()
+            .with_title(title)
+            .with_inner_size(PhysicalSize::new(200, 150))
+            .with_visible(true)
+            .build()
+            .expect("failed to create a window");
+        let mut event_loop = EventLoop::new();
+        event_loop.bind(&mut window);
+        let mut fixture = Self {
+            event_loop,
+            window,
+            held: Vec::new(),
+            _serial: serial,
+        };
+
+        // Windows only hands the foreground to a process that received the
+        // last input event, which a tap of Alt takes care of.
+        let hwnd = fixture.hwnd();
+        let deadline = Instant::now() + TIMEOUT;
+        while unsafe { GetForegroundWindow() } != hwnd {
+            assert!(
+                Instant::now() < deadline,
+                "the window couldn't be brought to the foreground"
+            );
+            send(&[key_input(VK_MENU, true), key_input(VK_MENU, false)]);
+            unsafe { SetForegroundWindow(hwnd) };
+            fixture.event_loop.next_event();
+            thread::sleep(Duration::from_millis(5));
+        }
+        fixture.move_cursor(100, 75);
+        // Whatever showing and activating caused isn't under test.
+        fixture.drain();
+        Some(fixture)
+    }
+
+    fn hwnd(&self) -> HWND {
+        HWND(self.window.id().0 as _)
+    }
+
+    fn key(&mut self, vk: VIRTUAL_KEY, down: bool) {
+        self.held.retain(|&held| held != vk);
+        if down {
+            self.held.push(vk);
+        }
+        send(&[key_input(vk, down)]);
+    }
+
+    fn tap(&mut self, vk: VIRTUAL_KEY) {
+        self.key(vk, true);
+        self.key(vk, false);
+    }
+
+    // In client coordinates.
+    fn move_cursor(&self, x: i32, y: i32) {
+        let mut point = POINT { x, y };
+        unsafe {
+            ClientToScreen(self.hwnd(), &mut point);
+            SetCursorPos(point.x, point.y);
+        }
+    }
+
+    fn drain(&mut self) {
+        let until = Instant::now() + Duration::from_millis(100);
+        while Instant::now() < until {
+            if self.event_loop.next_event().is_none() {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+    }
+
+    // Skips everything before the first event `f` accepts.
+    fn expect<T>(&mut self, what: &str, mut f: impl FnMut(&WindowEvent) -> Option<T>) -> T {
+        let deadline = Instant::now() + TIMEOUT;
+        let mut seen = Vec::new();
+        while Instant::now() < deadline {
+            match self.event_loop.next_event() {
+                Some((_, event)) => {
+                    if let Some(value) = f(&event) {
+                        return value;
+                    }
+                    seen.push(event);
+                }
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        panic!("no {what} within {TIMEOUT:?}; got {seen:?}");
+    }
+
+    fn expect_key(&mut self, key: KeyboardScancode, down: bool) {
+        let physical = self.expect(
+            &format!("{key:?} {}", if down { "down" } else { "up" }),
+            |event| match *event {
+                WindowEvent::KeyDown {
+                    logical_scancode,
+                    physical_scancode,
+                    ..
+                } if down && logical_scancode == key => Some(physical_scancode),
+                WindowEvent::KeyUp {
+                    logical_scancode,
+                    physical_scancode,
+                    ..
+                } if !down && logical_scancode == key => Some(physical_scancode),
+                _ => None,
+            },
+        );
+        // SendInput carries the scancode too, so both mappings must agree.
+        assert_eq!(physical, Some(key));
+    }
+
+    fn expect_modifiers(&mut self) -> Modifiers {
+        self.expect("ModifiersChanged", |event| match *event {
+            WindowEvent::ModifiersChanged(m) => Some(m & !LOCKS),
+            _ => None,
+        })
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let releases = self
+            .held
+            .drain(..)
+            .map(|vk| key_input(vk, false))
+            .collect::<Vec<_>>();
+        if !releases.is_empty() {
+            unsafe { SendInput(&releases, mem::size_of::<INPUT>() as _) };
+        }
+    }
+}
+
+#[test]
+fn keys_map_to_scancodes() {
+    let Some(mut f) = Fixture::new("nwin keys") else {
+        return;
+    };
+    let keys = [
+        (VK_A, KeyboardScancode::A),
+        (VK_Z, KeyboardScancode::Z),
+        (VIRTUAL_KEY(b'5' as _), KeyboardScancode::Key5),
+        (VK_RETURN, KeyboardScancode::Enter),
+        (VK_ESCAPE, KeyboardScancode::Esc),
+        (VK_F5, KeyboardScancode::F5),
+        (VK_LEFT, KeyboardScancode::ArrowLeft),
+        (VK_NUMPAD7, KeyboardScancode::Num7),
+        (VK_SPACE, KeyboardScancode::Space),
+    ];
+    for (vk, key) in keys {
+        f.tap(vk);
+        f.expect_key(key, true);
+        f.expect_key(key, false);
+    }
+}
+
+#[test]
+fn modifiers_follow_their_keys() {
+    let Some(mut f) = Fixture::new("nwin modifiers") else {
+        return;
+    };
+    f.key(VK_LSHIFT, true);
+    f.expect_key(KeyboardScancode::LShift, true);
+    assert_eq!(f.expect_modifiers(), Modifiers::LSHIFT);
+    f.key(VK_RCONTROL, true);
+    f.expect_key(KeyboardScancode::RCtrl, true);
+    assert_eq!(f.expect_modifiers(), Modifiers::LSHIFT | Modifiers::RCTRL);
+    f.key(VK_RCONTROL, false);
+    assert_eq!(f.expect_modifiers(), Modifiers::LSHIFT);
+    f.key(VK_LSHIFT, false);
+    assert_eq!(f.expect_modifiers(), Modifiers::empty());
+}
+
+#[test]
+fn clicks_carry_their_button() {
+    let Some(mut f) = Fixture::new("nwin clicks") else {
+        return;
+    };
+    f.move_cursor(20, 30);
+    let position = f.expect("CursorMoved to (20, 30)", |event| match *event {
+        WindowEvent::CursorMoved(position) if (position.x, position.y) == (20.0, 30.0) => {
+            Some(position)
+        }
+        _ => None,
+    });
+    assert_eq!((position.x, position.y), (20.0, 30.0));
+
+    let buttons = [
+        (
+            MOUSEEVENTF_LEFTDOWN,
+            MOUSEEVENTF_LEFTUP,
+            0,
+            MouseScancode::LClick,
+        ),
+        (
+            MOUSEEVENTF_MIDDLEDOWN,
+            MOUSEEVENTF_MIDDLEUP,
+            0,
+            MouseScancode::MClick,
+        ),
+        (
+            MOUSEEVENTF_RIGHTDOWN,
+            MOUSEEVENTF_RIGHTUP,
+            0,
+            MouseScancode::RClick,
+        ),
+        (
+            MOUSEEVENTF_XDOWN,
+            MOUSEEVENTF_XUP,
+            XBUTTON1,
+            MouseScancode::Button4,
+        ),
+        (
+            MOUSEEVENTF_XDOWN,
+            MOUSEEVENTF_XUP,
+            XBUTTON2,
+            MouseScancode::Button5,
+        ),
+    ];
+    for (down, up, data, expected) in buttons {
+        send(&[mouse_input(down, data), mouse_input(up, data)]);
+        f.expect(&format!("{expected:?} down"), |event| {
+            (*event == WindowEvent::MouseButtonDown(expected)).then_some(())
+        });
+        f.expect(&format!("{expected:?} up"), |event| {
+            (*event == WindowEvent::MouseButtonUp(expected)).then_some(())
+        });
+    }
+}
+
+#[test]
+fn set_window_pos_reports_resized() {
+    let Some(mut f) = Fixture::new("nwin resize") else {
+        return;
+    };
+    unsafe {
+        SetWindowPos(
+            f.hwnd(),
+            HWND(0),
+            0,
+            0,
+            400,
+            300,
+            SWP_NOMOVE | SWP_NOZORDER | SWP_NOACTIVATE,
+        );
+    }
+    let size = f.expect("Resized", |event| match *event {
+        WindowEvent::Resized(size) => Some(size),
+        _ => None,
+    });
+    assert_eq!(size, f.window.inner_size());
+    assert_eq!(f.window.outer_size(), PhysicalSize::new(400, 300));
+}
+
+#[test]
+fn titles_round_trip() {
+    let Some(mut f) = Fixture::new("nwin title") else {
+        return;
+    };
+    let text = |hwnd| {
+        let mut buf = [0u16; 256];
+        let len = unsafe { GetWindowTextW(hwnd, &mut buf) };
+        String::from_utf16_lossy(&buf[..len as usize])
+    };
+    assert_eq!(text(f.hwnd()), "nwin title");
+    // Outside the BMP, to cover surrogate pairs.
+    let title = "nwin – ünïcödé 🪟";
+    f.window.set_title(title);
+    assert_eq!(text(f.hwnd()), title);
+    assert_eq!(f.window.title(), title);
+}
+
+#[test]
+fn wm_close_requests_close_and_honors_the_close_action() {
+    let Some(mut f) = Fixture::new("nwin close") else {
+        return;
+    };
+    let hwnd = f.hwnd();
+    let close = |f: &mut Fixture| {
+        unsafe { PostMessageW(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0)) };
+        f.expect("CloseRequested", |event| {
+            (*event == WindowEvent::CloseRequested).then_some(())
+        });
+    };
+
+    // By default closing is left to the application.
+    close(&mut f);
+    f.drain();
+    assert!(unsafe { IsWindow(hwnd) }.as_bool());
+
+    f.window.set_close_action(CloseAction::Destroy);
+    close(&mut f);
+    f.expect("Destroyed", |event| {
+        (*event == WindowEvent::Destroyed).then_some(())
+    });
+    assert!(!unsafe { IsWindow(hwnd) }.as_bool());
+}
+
+#[test]
+fn clipboard_images_round_trip() {
+    let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+    if !interactive() {
+        eprintln!("skipped: no interactive window station");
+        return;
+    }
+    let image = RgbaImage {
+        width: 3,
+        height: 2,
+        data: (0..24).map(|i| i * 10).collect(),
+    };
+    clipboard::set_image(image.clone()).unwrap();
+    assert_eq!(clipboard::get_image(), Some(image));
+}
@@ -0,0 +1,449 @@
+// Drives real windows with input synthesized through the XTest extension and
+// checks what comes out of the EventLoop. It needs an X server without a
+// window manager getting in the way, e.g. Xvfb, and only runs when asked to:
+//
+//     Xvfb :99 &
+//     DISPLAY=:99 NWIN_X11_TESTS=1 cargo test --test x11_integration
+//
+// Without NWIN_X11_TESTS, a display or libXtst every test passes without
+// doing anything. XTest input goes to whatever is under the pointer or has
+// focus, so the tests take turns.
+#![cfg(all(unix, feature = "x11"))]
+
+use std::{
+    ffi::{c_char, c_int, c_uint, c_ulong, c_void},
+    fs,
+    process::Command,
+    ptr,
+    sync::{Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use nwin::{
+    clipboard, dpi::PhysicalSize, prelude::*, KeyboardScancode, Modifiers, MouseScancode,
+    RgbaImage, Window,
+};
+use x11::{keysym, xlib};
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+type QueryExtension = unsafe extern "C" fn(
+    *mut xlib::Display,
+    *mut c_int,
+    *mut c_int,
+    *mut c_int,
+    *mut c_int,
+) -> c_int;
+type FakeKeyEvent = unsafe extern "C" fn(*mut xlib::Display, c_uint, c_int, c_ulong) -> c_int;
+type FakeButtonEvent = unsafe extern "C" fn(*mut xlib::Display, c_uint, c_int, c_ulong) -> c_int;
+type FakeMotionEvent =
+    unsafe extern "C" fn(*mut xlib::Display, c_int, c_int, c_int, c_ulong) -> c_int;
+
+// libXtst is loaded at run time, so building the tests doesn't need its
+// development files.
+struct XTest {
+    display: *mut xlib::Display,
+    fake_key: FakeKeyEvent,
+    fake_button: FakeButtonEvent,
+    fake_motion: FakeMotionEvent,
+}
+
+impl XTest {
+    fn open() -> Option<Self> {
+        if std::env::var_os("NWIN_X11_TESTS").is_none() {
+            eprintln!("skipped: NWIN_X11_TESTS isn't set");
+            return None;
+        }
+        let lib = unsafe { libc::dlopen(c"libXtst.so.6".as_ptr(), libc::RTLD_NOW) };
+        if lib.is_null() {
+            eprintln!("skipped: libXtst.so.6 couldn't be loaded");
+            return None;
+        }
+        let symbol = |name: &[u8]| -> *mut c_void {
+            let symbol = unsafe { libc::dlsym(lib, name.as_ptr() as *const c_char) };
+            assert!(!symbol.is_null(), "libXtst has no {:?}", name);
+            symbol
+        };
+        let query: QueryExtension =
+            unsafe { std::mem::transmute(symbol(b"XTestQueryExtension\0")) };
+        let fake_key: FakeKeyEvent = unsafe { std::mem::transmute(symbol(b"XTestFakeKeyEvent\0")) };
+        let fake_button: FakeButtonEvent =
+            unsafe { std::mem::transmute(symbol(b"XTestFakeButtonEvent\0")) };
+        let fake_motion: FakeMotionEvent =
+            unsafe { std::mem::transmute(symbol(b"XTestFakeMotionEvent\0")) };
+
+        let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+        if display.is_null() {
+            eprintln!("skipped: no X server to connect to");
+            return None;
+        }
+        let xtest = Self {
+            display,
+            fake_key,
+            fake_button,
+            fake_motion,
+        };
+        let (mut event_base, mut error_base, mut major, mut minor) = (0, 0, 0, 0);
+        let supported = unsafe {
+            query(
+                display,
+                &mut event_base,
+                &mut error_base,
+                &mut major,
+                &mut minor,
+            )
+        };
+        if supported == 0 {
+            eprintln!("skipped: the X server doesn't support XTest");
+            return None;
+        }
+        Some(xtest)
+    }
+
+    fn key(&self, sym: c_uint, down: bool) {
+        let keycode = unsafe { xlib::XKeysymToKeycode(self.display, sym as _) };
+        assert_ne!(keycode, 0, "no keycode for keysym {sym:#x}");
+        unsafe {
+            (self.fake_key)(self.display, keycode as _, down as _, 0);
+            xlib::XFlush(self.display);
+        }
+    }
+
+    fn tap(&self, sym: c_uint) {
+        self.key(sym, true);
+        self.key(sym, false);
+    }
+
+    fn button(&self, button: c_uint, down: bool) {
+        unsafe {
+            (self.fake_button)(self.display, button, down as _, 0);
+            xlib::XFlush(self.display);
+        }
+    }
+
+    // In root coordinates.
+    fn move_pointer(&self, x: i32, y: i32) {
+        unsafe {
+            (self.fake_motion)(self.display, -1, x, y, 0);
+            xlib::XFlush(self.display);
+        }
+    }
+
+    // Where the window's top left corner is in root coordinates.
+    fn origin(&self, window: xlib::Window) -> (i32, i32) {
+        let (mut x, mut y, mut child) = (0, 0, 0);
+        unsafe {
+            xlib::XTranslateCoordinates(
+                self.display,
+                window,
+                xlib::XDefaultRootWindow(self.display),
+                0,
+                0,
+                &mut x,
+                &mut y,
+                &mut child,
+            );
+        }
+        (x, y)
+    }
+
+    fn is_viewable(&self, window: xlib::Window) -> bool {
+        let mut attributes: xlib::XWindowAttributes = unsafe { std::mem::zeroed() };
+        unsafe { xlib::XGetWindowAttributes(self.display, window, &mut attributes) };
+        attributes.map_state == xlib::IsViewable
+    }
+
+    fn focus(&self, window: xlib::Window) {
+        unsafe {
+            xlib::XSetInputFocus(
+                self.display,
+                window,
+                xlib::RevertToParent,
+                xlib::CurrentTime,
+            );
+            xlib::XSync(self.display, xlib::False);
+        }
+    }
+
+    fn request_close(&self, window: xlib::Window) {
+        let atom = |name: &[u8]| unsafe {
+            xlib::XInternAtom(self.display, name.as_ptr() as *const c_char, xlib::False)
+        };
+        let mut data = xlib::ClientMessageData::new();
+        data.set_long(0, atom(b"WM_DELETE_WINDOW\0") as _);
+        data.set_long(1, xlib::CurrentTime as _);
+        let mut event = xlib::XEvent {
+            client_message: xlib::XClientMessageEvent {
+                type_: xlib::ClientMessage,
+                serial: 0,
+                send_event: xlib::True,
+                display: self.display,
+                window,
+                message_type: atom(b"WM_PROTOCOLS\0"),
+                format: 32,
+                data,
+            },
+        };
+        unsafe {
+            xlib::XSendEvent(self.display, window, xlib::False, 0, &mut event);
+            xlib::XFlush(self.display);
+        }
+    }
+}
+
+impl Drop for XTest {
+    fn drop(&mut self) {
+        unsafe { xlib::XCloseDisplay(self.display) };
+    }
+}
+
+// A shown, focused window with the pointer over it. The window is dropped
+// before the XTest connection, and the lock last, even if a test panics.
+struct Fixture {
+    event_loop: EventLoop,
+    window: Window,
+    xtest: XTest,
+    _serial: MutexGuard<'static, ()>,
+}
+
+impl Fixture {
+    fn new(title: &str) -> Option<Self> {
+        let serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let xtest = XTest::open()?;
+        let mut window = WindowBuilder::new()
+            .with_title(title)
+            .with_inner_size(PhysicalSize::new(200, 150))
+            .with_visible(true)
+            .build()
+            .expect("failed to create a window");
+        let mut event_loop = EventLoop::new();
+        event_loop.bind(&mut window);
+
+        let xid = window.id().0 as xlib::Window;
+        let deadline = Instant::now() + TIMEOUT;
+        while !xtest.is_viewable(xid) {
+            assert!(Instant::now() < deadline, "the window was never mapped");
+            event_loop.next_event();
+            thread::sleep(Duration::from_millis(5));
+        }
+        let (x, y) = xtest.origin(xid);
+        xtest.move_pointer(x + 100, y + 75);
+        xtest.focus(xid);
+        let mut fixture = Self {
+            event_loop,
+            window,
+            xtest,
+            _serial: serial,
+        };
+        // Whatever mapping and focusing caused isn't under test.
+        fixture.drain();
+        Some(fixture)
+    }
+
+    fn xid(&self) -> xlib::Window {
+        self.window.id().0 as _
+    }
+
+    fn drain(&mut self) {
+        let until = Instant::now() + Duration::from_millis(100);
+        while Instant::now() < until {
+            if self.event_loop.next_event().is_none() {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+    }
+
+    // Skips everything before the first event `f` accepts.
+    fn expect<T>(&mut self, what: &str, mut f: impl FnMut(&WindowEvent) -> Option<T>) -> T {
+        let deadline = Instant::now() + TIMEOUT;
+        let mut seen = Vec::new();
+        while Instant::now() < deadline {
+            match self.event_loop.next_event() {
+                Some((_, event)) => {
+                    if let Some(value) = f(&event) {
+                        return value;
+                    }
+                    seen.push(event);
+                }
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        panic!("no {what} within {TIMEOUT:?}; got {seen:?}");
+    }
+
+    fn expect_key(&mut self, key: KeyboardScancode, down: bool) {
+        self.expect(
+            &format!("{key:?} {}", if down { "down" } else { "up" }),
+            |event| match *event {
+                WindowEvent::KeyDown {
+                    logical_scancode, ..
+                } if down && logical_scancode == key => Some(()),
+                WindowEvent::KeyUp {
+                    logical_scancode, ..
+                } if !down && logical_scancode == key => Some(()),
+                _ => None,
+            },
+        )
+    }
+}
+
+#[test]
+fn keys_map_to_scancodes() {
+    let Some(mut f) = Fixture::new("nwin keys") else {
+        return;
+    };
+    let keys = [
+        (keysym::XK_a, KeyboardScancode::A),
+        (keysym::XK_z, KeyboardScancode::Z),
+        (keysym::XK_5, KeyboardScancode::Key5),
+        (keysym::XK_Return, KeyboardScancode::Enter),
+        (keysym::XK_Escape, KeyboardScancode::Esc),
+        (keysym::XK_F5, KeyboardScancode::F5),
+        (keysym::XK_Left, KeyboardScancode::ArrowLeft),
+        (keysym::XK_KP_7, KeyboardScancode::Num7),
+        (keysym::XK_space, KeyboardScancode::Space),
+    ];
+    for (sym, key) in keys {
+        f.xtest.tap(sym);
+        f.expect_key(key, true);
+        f.expect_key(key, false);
+    }
+}
+
+#[test]
+fn typed_keys_carry_their_text() {
+    let Some(mut f) = Fixture::new("nwin text") else {
+        return;
+    };
+    f.xtest.tap(keysym::XK_q);
+    let text = f.expect("KeyDown", |event| match event {
+        WindowEvent::KeyDown { text, .. } => Some(text.clone()),
+        _ => None,
+    });
+    assert_eq!(text.as_deref(), Some("q"));
+}
+
+#[test]
+fn modifiers_follow_their_keys() {
+    let Some(mut f) = Fixture::new("nwin modifiers") else {
+        return;
+    };
+    let changed = |f: &mut Fixture| {
+        f.expect("ModifiersChanged", |event| match *event {
+            WindowEvent::ModifiersChanged(m) => Some(m),
+            _ => None,
+        })
+    };
+    f.xtest.key(keysym::XK_Shift_L, true);
+    f.expect_key(KeyboardScancode::LShift, true);
+    assert_eq!(changed(&mut f) & !Modifiers::NUMLOCK, Modifiers::LSHIFT);
+    f.xtest.key(keysym::XK_Control_R, true);
+    assert_eq!(
+        changed(&mut f) & !Modifiers::NUMLOCK,
+        Modifiers::LSHIFT | Modifiers::RCTRL
+    );
+    f.xtest.key(keysym::XK_Control_R, false);
+    f.xtest.key(keysym::XK_Shift_L, false);
+    assert_eq!(changed(&mut f) & !Modifiers::NUMLOCK, Modifiers::LSHIFT);
+    assert_eq!(changed(&mut f) & !Modifiers::NUMLOCK, Modifiers::empty());
+}
+
+#[test]
+fn clicks_carry_their_button() {
+    let Some(mut f) = Fixture::new("nwin clicks") else {
+        return;
+    };
+    let (x, y) = f.xtest.origin(f.xid());
+    f.xtest.move_pointer(x + 20, y + 30);
+    let position = f.expect("CursorMoved", |event| match *event {
+        WindowEvent::CursorMoved(position) => Some(position),
+        _ => None,
+    });
+    assert_eq!((position.x, position.y), (20.0, 30.0));
+
+    let buttons = [
+        (1, MouseScancode::LClick),
+        (2, MouseScancode::MClick),
+        (3, MouseScancode::RClick),
+        (8, MouseScancode::Button4),
+        (9, MouseScancode::Button5),
+    ];
+    for (button, expected) in buttons {
+        f.xtest.button(button, true);
+        f.xtest.button(button, false);
+        f.expect(&format!("{expected:?} down"), |event| {
+            (*event == WindowEvent::MouseButtonDown(expected)).then_some(())
+        });
+        f.expect(&format!("{expected:?} up"), |event| {
+            (*event == WindowEvent::MouseButtonUp(expected)).then_some(())
+        });
+    }
+}
+
+#[test]
+fn wm_delete_window_requests_close() {
+    let Some(mut f) = Fixture::new("nwin close") else {
+        return;
+    };
+    f.xtest.request_close(f.xid());
+    f.expect("CloseRequested", |event| {
+        (*event == WindowEvent::CloseRequested).then_some(())
+    });
+}
+
+// Incompressible, so the PNG of a large image still needs INCR.
+fn noise_image(width: u32, height: u32) -> RgbaImage {
+    let mut seed = 1u32;
+    let data = (0..width * height * 4)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8
+        })
+        .collect();
+    RgbaImage {
+        width,
+        height,
+        data,
+    }
+}
+
+// Run by clipboard_images_round_trip in a child process, so the image is
+// fetched by another client the way a pasting application would.
+#[test]
+fn clipboard_requestor() {
+    let Some(out) = std::env::var_os("NWIN_CLIPBOARD_OUT") else {
+        return;
+    };
+    let image = clipboard::get_image().expect("no image on the clipboard");
+    let mut bytes = [image.width.to_le_bytes(), image.height.to_le_bytes()].concat();
+    bytes.extend(image.data);
+    fs::write(out, bytes).unwrap();
+}
+
+#[test]
+fn clipboard_images_round_trip() {
+    let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(_xtest) = XTest::open() else {
+        return;
+    };
+    let out = std::env::temp_dir().join(format!("nwin-clipboard-{}", std::process::id()));
+    // The larger one goes through INCR.
+    for (width, height) in [(3, 2), (512, 512)] {
+        let image = noise_image(width, height);
+        clipboard::set_image(image.clone()).unwrap();
+        let status = Command::new(std::env::current_exe().unwrap())
+            .args(["clipboard_requestor", "--exact", "--quiet"])
+            .env("NWIN_CLIPBOARD_OUT", &out)
+            .status()
+            .unwrap();
+        assert!(status.success(), "the requestor failed");
+        let bytes = fs::read(&out).unwrap();
+        let _ = fs::remove_file(&out);
+        assert_eq!(bytes[..4], width.to_le_bytes());
+        assert_eq!(bytes[4..8], height.to_le_bytes());
+        assert!(bytes[8..] == image.data, "the pixels changed");
+    }
+}